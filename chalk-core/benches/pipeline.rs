@@ -0,0 +1,134 @@
+//! Timings for tokenizing, parsing and evaluating a few kinds of input, to catch regressions.
+//! Run with `cargo bench -p chalk-core`, which prints the fastest of several runs of each phase
+
+use std::{
+    hint::black_box,
+    thread,
+    time::{Duration, Instant},
+};
+
+use chalk_core::{ast::Parser, exec::Evaluator, tokenizer::Tokenizable};
+
+/// How many times each phase is timed, keeping the fastest
+const RUNS: usize = 15;
+
+/// How much stack the benchmarks run with, since a long flat sum is as deep a tree as it is long
+const STACK_SIZE: usize = 1 << 30;
+
+/// Something to benchmark
+struct Corpus {
+    /// What it's called in the report
+    name: &'static str,
+    /// The expression
+    source: String,
+    /// Statements run before timing, binding the variables it uses
+    setup: &'static [&'static str],
+    /// How many times it's evaluated per timed run
+    evaluations: usize,
+}
+
+/// A sum of 10,000 terms, alternating integers and reals
+fn flat_sum() -> Corpus {
+    let terms: Vec<_> = (1..=10_000)
+        .map(|n| match n % 2 {
+            0 => format!("{n}.25"),
+            _ => n.to_string(),
+        })
+        .collect();
+
+    Corpus {
+        name: "flat sum",
+        source: terms.join(" + "),
+        setup: &[],
+        evaluations: 1,
+    }
+}
+
+/// An expression nested 200 parentheses deep, evaluated 1,000 times
+fn deeply_nested() -> Corpus {
+    Corpus {
+        name: "deeply nested",
+        source: format!("{}x{}", "(1 + ".repeat(200), ") * 2".repeat(200)),
+        setup: &["x = 1.5"],
+        evaluations: 1_000,
+    }
+}
+
+/// A formula reading variables bound to other formulas, evaluated 10,000 times
+fn variable_heavy() -> Corpus {
+    Corpus {
+        name: "variable heavy",
+        source: "a * b + c / d - (a + b + c + d)^2 + sqrt(c * d) + max(a, b, c, d) * e".to_string(),
+        setup: &[
+            "a = 2",
+            "b = 3.5",
+            "c = a * b + 1",
+            "d = c^2 - a / b",
+            "e = (c + d) / (a + b) - sqrt(|c - d|)",
+        ],
+        evaluations: 10_000,
+    }
+}
+
+/// The fastest of [`RUNS`] runs of `run`
+fn fastest(mut run: impl FnMut()) -> Duration {
+    (0..RUNS)
+        .map(|_| {
+            let start = Instant::now();
+            run();
+            start.elapsed()
+        })
+        .min()
+        .unwrap_or_default()
+}
+
+/// Times each phase of a corpus, printing a line for each
+fn bench(corpus: &Corpus) {
+    let mut eval = Evaluator::default();
+    eval.set_max_depth(usize::MAX);
+    for statement in corpus.setup {
+        let tokens = statement.tokenize().expect("Tokenize setup");
+        let ast = Parser::new(tokens).parse().expect("Parse setup");
+        eval.exec(&ast).expect("Run setup");
+    }
+
+    let tokenize = fastest(|| {
+        black_box(corpus.source.tokenize().expect("Tokenize"));
+    });
+
+    let tokens = corpus.source.tokenize().expect("Tokenize");
+    let parse = fastest(|| {
+        let ast = Parser::new(black_box(tokens.clone()))
+            .with_max_depth(usize::MAX)
+            .parse();
+        black_box(ast.expect("Parse"));
+    });
+
+    let ast = Parser::new(tokens)
+        .with_max_depth(usize::MAX)
+        .parse()
+        .expect("Parse");
+    let exec = fastest(|| {
+        for _ in 0..corpus.evaluations {
+            black_box(eval.exec(black_box(&ast)).expect("Evaluate"));
+        }
+    });
+
+    println!(
+        "{:<16} tokenize {:>10.1?}   parse {:>10.1?}   exec ×{:<6} {:>10.1?}",
+        corpus.name, tokenize, parse, corpus.evaluations, exec
+    );
+}
+
+fn main() {
+    let benches = thread::Builder::new()
+        .stack_size(STACK_SIZE)
+        .spawn(|| {
+            for corpus in [flat_sum(), deeply_nested(), variable_heavy()] {
+                bench(&corpus);
+            }
+        })
+        .expect("Spawn benchmark thread");
+
+    benches.join().expect("Benchmarks panicked");
+}
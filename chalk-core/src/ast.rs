@@ -1,20 +1,25 @@
 //! Abstract Syntax Tree data structures and evaluation methods
 
-use std::fmt::Display;
+use std::{error::Error, fmt::Display, ops::Range};
 
-use crate::tokenizer::Token;
+use crate::{functions, tokenizer::Token, units::Unit};
 
 /// A node in the AST
 #[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize, serde::Deserialize),
+    serde(tag = "type", content = "value", rename_all = "snake_case")
+)]
 pub enum Expr {
     /// Assignment from a variable to an expr
     Assignment(char, Box<Expr>),
     /// A variable replacement
     Variable(char),
     /// Number leaf node (integer)
-    Integer(i32),
+    Integer(i64),
     /// Number leaf node (real)
-    Real(f32),
+    Real(f64),
     /// Boolean leaf node
     Bool(bool),
     /// Binary operator node
@@ -37,61 +42,402 @@ pub enum Expr {
     Paren(Box<Expr>),
     /// Absolute value of an expression
     AbsVal(Box<Expr>),
+    /// A named constant like pi
+    Constant(Constant),
+    /// A unit like `km`, evaluating to one of it. `3km` is `3` multiplied by it
+    Unit(Unit),
+    /// A root of an equation found numerically by varying one variable
+    Solve {
+        /// The equation, or an expression taken to equal zero
+        equation: Box<Expr>,
+        /// The variable solved for
+        var: char,
+    },
+    /// A sum or product of `body` with `var` stepping through every integer from `from` to `to`
+    Reduce {
+        /// Whether the terms are summed or multiplied
+        kind: Reduction,
+        /// The index variable
+        var: char,
+        /// First value of the index
+        from: Box<Expr>,
+        /// Last value of the index, inclusive
+        to: Box<Expr>,
+        /// The term evaluated at each index
+        body: Box<Expr>,
+    },
+    /// A list of expressions, evaluated in order
+    List(Vec<Expr>),
+    /// An item of a list, counting from 1, like `x[2]`
+    Index {
+        /// The list indexed into
+        list: Box<Expr>,
+        /// Which item, counting from 1
+        index: Box<Expr>,
+    },
+    /// A call to a built in function taking any number of arguments
+    FunctionCall {
+        /// The function called
+        function: Function,
+        /// The arguments, as many as the function's arity
+        args: Vec<Expr>,
+    },
+}
+
+/// Precedence of assignments, which are only valid at the top of an expression
+const ASSIGNMENT: u8 = 0;
+/// Precedence of `&&` and `||`
+const LOGICAL: u8 = 1;
+/// Precedence of comparisons, which don't chain
+const COMPARISON: u8 = 2;
+/// Precedence of unit conversions like `x in km`
+const CONVERSION: u8 = 3;
+/// Precedence of bit shifts like `x << 2`
+const SHIFT: u8 = 4;
+/// Precedence of addition and subtraction
+const SUM: u8 = 5;
+/// Precedence of multiplication and division
+const PRODUCT: u8 = 6;
+/// Precedence of powers
+const POWER: u8 = 7;
+/// Precedence of postfix operators like factorial
+const POSTFIX: u8 = 8;
+/// Precedence of negation, which binds tighter than anything but atoms
+const PREFIX: u8 = 9;
+/// Precedence of anything that never needs wrapping, like numbers and function calls
+const ATOM: u8 = 10;
+
+/// Displays an expression as an operand, parenthesized if it binds looser than `min`
+struct Operand<'a>(&'a Expr, u8);
+
+impl Display for Operand<'_> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let Operand(expr, min) = self;
+        if expr.precedence() < *min {
+            write!(f, "({expr})")
+        } else {
+            write!(f, "{expr}")
+        }
+    }
 }
 
 impl Display for Expr {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         match self {
             Self::Variable(v) => write!(f, "{v}"),
-            Self::Assignment(v, node) => write!(f, "{v} = {node}"),
+            Self::Assignment(v, node) => write!(f, "{v} = {}", Operand(node, LOGICAL)),
+            // Keep a decimal point so the literal reads back as a real
+            Self::Real(r) if r.is_finite() && r.fract() == 0.0 => write!(f, "{r}.0"),
             Self::Real(r) => write!(f, "{r}"),
             Self::Integer(i) => write!(f, "{i}"),
             Self::Bool(b) => write!(f, "{b}"),
             Self::UnaryOp { op, node } => match op {
-                UnaryOperator::Neg => write!(f, "-{node}"),
-                UnaryOperator::Factorial => write!(f, "{node}!"),
+                UnaryOperator::Neg => write!(f, "-{}", Operand(node, PREFIX)),
+                UnaryOperator::BitNot => write!(f, "~{}", Operand(node, PREFIX)),
+                UnaryOperator::Factorial => write!(f, "{}!", Operand(node, POSTFIX)),
+                UnaryOperator::Degrees => write!(f, "{}°", Operand(node, POSTFIX)),
+                UnaryOperator::Percent => write!(f, "{}%", Operand(node, POSTFIX)),
                 UnaryOperator::Floor => write!(f, "floor({node})"),
                 UnaryOperator::Ceil => write!(f, "ceil({node})"),
                 UnaryOperator::Cos => write!(f, "cos({node})"),
                 UnaryOperator::Sin => write!(f, "sin({node})"),
                 UnaryOperator::Tan => write!(f, "tan({node})"),
                 UnaryOperator::Ln => write!(f, "ln({node})"),
+                UnaryOperator::Sqrt => write!(f, "sqrt({node})"),
 
                 UnaryOperator::ACos => write!(f, "acos({node})"),
                 UnaryOperator::ASin => write!(f, "asin({node})"),
                 UnaryOperator::ATan => write!(f, "atan({node})"),
+                UnaryOperator::Sinh => write!(f, "sinh({node})"),
+                UnaryOperator::Cosh => write!(f, "cosh({node})"),
+                UnaryOperator::Tanh => write!(f, "tanh({node})"),
+                UnaryOperator::ASinh => write!(f, "asinh({node})"),
+                UnaryOperator::ACosh => write!(f, "acosh({node})"),
+                UnaryOperator::ATanh => write!(f, "atanh({node})"),
+
+                UnaryOperator::Factorize => write!(f, "factorize({node})"),
+                UnaryOperator::Divisors => write!(f, "divisors({node})"),
+                UnaryOperator::NumDivisors => write!(f, "numdivisors({node})"),
+                UnaryOperator::SumDivisors => write!(f, "sumdivisors({node})"),
+                UnaryOperator::NthPrime => write!(f, "nthprime({node})"),
+                UnaryOperator::PrimeCount => write!(f, "primecount({node})"),
+                UnaryOperator::Totient => write!(f, "totient({node})"),
+
+                UnaryOperator::Re => write!(f, "re({node})"),
+                UnaryOperator::Im => write!(f, "im({node})"),
+                UnaryOperator::Conj => write!(f, "conj({node})"),
+                UnaryOperator::Arg => write!(f, "arg({node})"),
             },
-            Self::BinaryOp { op, left, right } => match op {
-                BinaryOperator::Gcd => write!(f, "gcd({left}, {right})"),
-                BinaryOperator::Lcm => write!(f, "lcm({left}, {right})"),
-                BinaryOperator::Eq => write!(f, "{left} == {right}"),
-                BinaryOperator::NEq => write!(f, "{left} != {right}"),
+            Self::BinaryOp { op, left, right } => {
+                let infix = |f: &mut std::fmt::Formatter<'_>, symbol: &str| {
+                    // Every operator is left associative, so only the right operand needs
+                    // wrapping at the operator's own precedence
+                    let (left_min, right_min) = match self.precedence() {
+                        COMPARISON => (CONVERSION, CONVERSION),
+                        POWER => (POWER, POSTFIX),
+                        precedence => (precedence, precedence + 1),
+                    };
+                    write!(
+                        f,
+                        "{} {symbol} {}",
+                        Operand(left, left_min),
+                        Operand(right, right_min)
+                    )
+                };
+
+                match op {
+                    BinaryOperator::Gcd => write!(f, "gcd({left}, {right})"),
+                    BinaryOperator::Lcm => write!(f, "lcm({left}, {right})"),
+                    BinaryOperator::Atan2 => write!(f, "atan2({left}, {right})"),
+                    BinaryOperator::BitAnd => write!(f, "band({left}, {right})"),
+                    BinaryOperator::BitOr => write!(f, "bor({left}, {right})"),
+                    BinaryOperator::BitXor => write!(f, "bxor({left}, {right})"),
+                    BinaryOperator::Eq => infix(f, "=="),
+                    BinaryOperator::NEq => infix(f, "!="),
+                    BinaryOperator::ApproxEq => infix(f, "~="),
+
+                    BinaryOperator::Gt => infix(f, ">"),
+                    BinaryOperator::Lt => infix(f, "<"),
+                    BinaryOperator::Gte => infix(f, ">="),
+                    BinaryOperator::Lte => infix(f, "<="),
+
+                    BinaryOperator::Or => infix(f, "||"),
+                    BinaryOperator::And => infix(f, "&&"),
+                    BinaryOperator::Convert => infix(f, "in"),
+
+                    // Units sit right next to the number they're attached to, like `3 km`
+                    BinaryOperator::Multiply if self.attaches_unit() => {
+                        write!(f, "{} {right}", Operand(left, PREFIX))
+                    }
+                    // A power written after a unit would be read as the unit's power
+                    BinaryOperator::Pow if left.attaches_unit() => {
+                        write!(f, "({left}) ^ {}", Operand(right, POSTFIX))
+                    }
+
+                    // A `%` followed by something that can't start a value is a percentage
+                    BinaryOperator::Mod => {
+                        let right = Operand(right, PRODUCT + 1).to_string();
+                        if right.starts_with(['-', '~', '|']) {
+                            write!(f, "{} % ({right})", Operand(left, PRODUCT))
+                        } else {
+                            write!(f, "{} % {right}", Operand(left, PRODUCT))
+                        }
+                    }
+
+                    _ => infix(f, &op.to_string()),
+                }
+            }
+            Self::Paren(e) => write!(f, "({e})"),
+            Self::AbsVal(e) => {
+                // Bars next to each other would read back as `||`
+                let inner = e.to_string();
+                let start = if inner.starts_with('|') { " " } else { "" };
+                let end = if inner.ends_with('|') { " " } else { "" };
+                write!(f, "|{start}{inner}{end}|")
+            }
+            Self::Constant(c) => write!(f, "{c}"),
+            Self::Unit(unit) => write!(f, "{unit}"),
+            Self::Solve { equation, var } => write!(f, "solve({equation}, {var})"),
+            Self::Reduce {
+                kind,
+                var,
+                from,
+                to,
+                body,
+            } => write!(f, "{kind}({var}, {from}, {to}, {body})"),
+            Self::FunctionCall { function, args } => {
+                // `sum(x, 1, 2, 3)` would read back as a series over `x`
+                let series = args.len() == 4
+                    && Reduction::try_from(function.signature().name).is_ok()
+                    && matches!(args[0], Self::Variable(_));
+
+                write!(f, "{function}(")?;
+                for (i, arg) in args.iter().enumerate() {
+                    if i > 0 {
+                        write!(f, ", ")?;
+                    }
+                    if i == 0 && series {
+                        write!(f, "({arg})")?;
+                    } else {
+                        write!(f, "{arg}")?;
+                    }
+                }
+                write!(f, ")")
+            }
+            Self::Index { list, index } => write!(f, "{}[{index}]", Operand(list, POSTFIX)),
+            Self::List(items) => {
+                write!(f, "[")?;
+                for (i, item) in items.iter().enumerate() {
+                    if i > 0 {
+                        write!(f, ", ")?;
+                    }
+                    write!(f, "{item}")?;
+                }
+                write!(f, "]")
+            }
+        }
+    }
+}
+
+impl Expr {
+    /// Replaces every use of a variable with another expression, without evaluating anything. The
+    /// solved for variable of `solve` and the index of `sum` and `prod` are bound by those
+    /// expressions, so uses of them inside are left alone. Displaying the result adds whatever
+    /// parentheses the replacement needs
+    pub fn substitute(&self, var: char, replacement: &Expr) -> Expr {
+        self.clone().substituted(var, replacement)
+    }
 
-                BinaryOperator::Gt => write!(f, "{left} > {right}"),
-                BinaryOperator::Lt => write!(f, "{left} < {right}"),
-                BinaryOperator::Gte => write!(f, "{left} >= {right}"),
-                BinaryOperator::Lte => write!(f, "{left} <= {right}"),
+    /// [`Expr::substitute`], reusing the nodes of an owned expression
+    pub(crate) fn substituted(self, var: char, replacement: &Expr) -> Expr {
+        match self {
+            Self::Variable(v) if v == var => replacement.clone(),
+            Self::Solve { var: unknown, .. } if unknown == var => self,
+            Self::Reduce {
+                kind,
+                var: index,
+                from,
+                to,
+                body,
+            } if index == var => Self::Reduce {
+                kind,
+                var: index,
+                from: Box::new(from.substituted(var, replacement)),
+                to: Box::new(to.substituted(var, replacement)),
+                body,
+            },
+            node => node.map_children(|child| child.substituted(var, replacement)),
+        }
+    }
 
-                BinaryOperator::Or => write!(f, "{left} || {right}"),
-                BinaryOperator::And => write!(f, "{left} && {right}"),
+    /// Whether this is a unit attached to what it measures, like `3 km`
+    pub fn attaches_unit(&self) -> bool {
+        matches!(
+            self,
+            Self::BinaryOp {
+                op: BinaryOperator::Multiply,
+                right,
+                ..
+            } if matches!(**right, Self::Unit(_))
+        )
+    }
 
-                _ => write!(f, "{left} {op} {right}"),
+    /// How tightly the expression binds when displayed, deciding where parentheses are needed
+    fn precedence(&self) -> u8 {
+        match self {
+            Self::Assignment(..) => ASSIGNMENT,
+            // Units are parsed along with the number before them, so they bind like one
+            _ if self.attaches_unit() => ATOM,
+            Self::UnaryOp { op, .. } => match op {
+                UnaryOperator::Neg | UnaryOperator::BitNot => PREFIX,
+                UnaryOperator::Factorial | UnaryOperator::Degrees | UnaryOperator::Percent => {
+                    POSTFIX
+                }
+                _ => ATOM,
             },
-            Self::Paren(e) => write!(f, "({e})"),
-            Self::AbsVal(e) => write!(f, "|{e}|"),
+            Self::Index { .. } => POSTFIX,
+            Self::BinaryOp { op, .. } => match op {
+                BinaryOperator::And | BinaryOperator::Or => LOGICAL,
+                BinaryOperator::Eq
+                | BinaryOperator::NEq
+                | BinaryOperator::ApproxEq
+                | BinaryOperator::Gt
+                | BinaryOperator::Lt
+                | BinaryOperator::Gte
+                | BinaryOperator::Lte => COMPARISON,
+                BinaryOperator::Convert => CONVERSION,
+                BinaryOperator::Shl | BinaryOperator::Shr => SHIFT,
+                BinaryOperator::Add | BinaryOperator::Subtract => SUM,
+                BinaryOperator::Multiply | BinaryOperator::Divide | BinaryOperator::Mod => PRODUCT,
+                BinaryOperator::Pow => POWER,
+                BinaryOperator::Gcd
+                | BinaryOperator::Lcm
+                | BinaryOperator::Atan2
+                | BinaryOperator::BitAnd
+                | BinaryOperator::BitOr
+                | BinaryOperator::BitXor => ATOM,
+            },
+            // Negative literals read back as negation
+            Self::Integer(i) if *i < 0 => PREFIX,
+            Self::Real(r) if r.is_sign_negative() => PREFIX,
+            Self::Variable(_)
+            | Self::Integer(_)
+            | Self::Real(_)
+            | Self::Bool(_)
+            | Self::Paren(_)
+            | Self::AbsVal(_)
+            | Self::Constant(_)
+            | Self::Unit(_)
+            | Self::Solve { .. }
+            | Self::Reduce { .. }
+            | Self::List(_)
+            | Self::FunctionCall { .. } => ATOM,
+        }
+    }
+
+    /// Renders the tree structure of the expression, one node per line with children indented
+    /// beneath their parent
+    pub fn pretty(&self) -> String {
+        let mut out = String::new();
+        self.pretty_into(0, &mut out);
+        out.pop();
+        out
+    }
+
+    /// Writes this node and its children to `out` at the given indentation depth
+    fn pretty_into(&self, depth: usize, out: &mut String) {
+        out.push_str(&"  ".repeat(depth));
+        out.push_str(&self.label());
+        out.push('\n');
+
+        for child in self.children() {
+            child.pretty_into(depth + 1, out);
+        }
+    }
+
+    /// Names this node, without its children, for views of the tree structure
+    pub(crate) fn label(&self) -> String {
+        match self {
+            Self::Assignment(v, _) => format!("Assignment {v}"),
+            Self::Variable(v) => format!("Variable {v}"),
+            Self::Integer(i) => format!("Integer {i}"),
+            Self::Real(r) => format!("Real {r}"),
+            Self::Bool(b) => format!("Bool {b}"),
+            Self::BinaryOp { op, .. } => format!("BinaryOp {op:?}"),
+            Self::UnaryOp { op, .. } => format!("UnaryOp {op:?}"),
+            Self::Paren(_) => "Paren".to_string(),
+            Self::AbsVal(_) => "AbsVal".to_string(),
+            Self::Constant(c) => format!("Constant {c}"),
+            Self::Unit(unit) => format!("Unit {unit}"),
+            Self::Solve { var, .. } => format!("Solve {var}"),
+            Self::Reduce { kind, var, .. } => format!("Reduce {kind:?} {var}"),
+            Self::List(items) => format!("List {}", items.len()),
+            Self::Index { .. } => "Index".to_string(),
+            Self::FunctionCall { function, .. } => format!("FunctionCall {function}"),
         }
     }
 }
 
 /// All unary operations
 #[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize, serde::Deserialize),
+    serde(rename_all = "snake_case")
+)]
 pub enum UnaryOperator {
     /// Natural Log
     Ln,
+    /// Square root
+    Sqrt,
     /// Negation
     Neg,
     /// Factorial
     Factorial,
+    /// Degrees to radians conversion (postfix °)
+    Degrees,
+    /// Percentage, dividing by 100 (postfix %)
+    Percent,
     /// Floor function
     Floor,
     /// Ceiling function
@@ -108,30 +454,58 @@ pub enum UnaryOperator {
     ACos,
     /// ArcSine
     ASin,
+    /// Hyperbolic sine
+    Sinh,
+    /// Hyperbolic cosine
+    Cosh,
+    /// Hyperbolic tangent
+    Tanh,
+    /// Inverse hyperbolic sine
+    ASinh,
+    /// Inverse hyperbolic cosine, defined from 1 up
+    ACosh,
+    /// Inverse hyperbolic tangent, defined strictly between -1 and 1
+    ATanh,
+    /// Prime factorization, as a list of primes in ascending order
+    Factorize,
+    /// Every positive divisor, as a list in ascending order
+    Divisors,
+    /// The number of positive divisors
+    NumDivisors,
+    /// The sum of every positive divisor
+    SumDivisors,
+    /// The nth prime, counting 2 as the first
+    NthPrime,
+    /// The number of primes up to and including a number
+    PrimeCount,
+    /// Euler's totient, counting the numbers up to a number that are coprime to it
+    Totient,
+    /// Bitwise not of an integer (prefix ~)
+    BitNot,
+    /// The real part of a complex number
+    Re,
+    /// The imaginary part of a complex number
+    Im,
+    /// The complex conjugate, flipping the sign of the imaginary part
+    Conj,
+    /// The argument of a complex number, its angle from the positive real axis in `(-pi, pi]`
+    Arg,
 }
 
 impl TryFrom<&str> for UnaryOperator {
     type Error = ();
     fn try_from(value: &str) -> Result<Self, Self::Error> {
-        match value.to_lowercase().as_str() {
-            "neg" => Ok(UnaryOperator::Neg),
-            "ln" => Ok(UnaryOperator::Ln),
-            "factorial" => Ok(UnaryOperator::Factorial),
-            "floor" => Ok(UnaryOperator::Floor),
-            "ceil" => Ok(UnaryOperator::Ceil),
-            "tan" => Ok(UnaryOperator::Tan),
-            "cos" => Ok(UnaryOperator::Cos),
-            "sin" => Ok(UnaryOperator::Sin),
-            "atan" => Ok(UnaryOperator::ATan),
-            "acos" => Ok(UnaryOperator::ACos),
-            "asin" => Ok(UnaryOperator::ASin),
-            _ => Err(()),
-        }
+        functions::lookup(Self::ALL, value).ok_or(())
     }
 }
 
 /// All binary operations
 #[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize, serde::Deserialize),
+    serde(rename_all = "snake_case")
+)]
 pub enum BinaryOperator {
     /// Adding
     Add,
@@ -141,16 +515,32 @@ pub enum BinaryOperator {
     Multiply,
     /// Dividing
     Divide,
+    /// The remainder of dividing, taking the sign of the divisor, `a % m`
+    Mod,
     /// Exponentiation
     Pow,
     /// Greatest common divisor (will coerce to integers)
     Gcd,
     /// Least common multiple (will coerce to integers)
     Lcm,
+    /// Two argument arctangent, the angle of the point `(x, y)` given as `atan2(y, x)`
+    Atan2,
+    /// Bitwise and of two integers
+    BitAnd,
+    /// Bitwise or of two integers
+    BitOr,
+    /// Bitwise exclusive or of two integers
+    BitXor,
+    /// Shifting an integer's bits left, `x << n`
+    Shl,
+    /// Shifting an integer's bits right, keeping its sign, `x >> n`
+    Shr,
     /// Equality
     Eq,
     /// Not equal
     NEq,
+    /// Equal within a relative tolerance, `a ~= b`
+    ApproxEq,
     /// Greater than
     Gt,
     /// Less than
@@ -164,29 +554,15 @@ pub enum BinaryOperator {
     And,
     /// OR
     Or,
+
+    /// Conversion of a quantity to the unit on the right, `x in km`
+    Convert,
 }
 
 impl TryFrom<&str> for BinaryOperator {
     type Error = ();
     fn try_from(value: &str) -> Result<Self, Self::Error> {
-        match value.to_lowercase().as_str() {
-            "add" => Ok(BinaryOperator::Add),
-            "subtract" | "sub" => Ok(BinaryOperator::Subtract),
-            "multiply" | "mul" => Ok(BinaryOperator::Multiply),
-            "divide" | "div" => Ok(BinaryOperator::Divide),
-            "pow" => Ok(BinaryOperator::Pow),
-            "gcd" => Ok(BinaryOperator::Gcd),
-            "lcm" => Ok(BinaryOperator::Lcm),
-            "eq" => Ok(BinaryOperator::Eq),
-            "neq" => Ok(BinaryOperator::NEq),
-            "gt" => Ok(BinaryOperator::Gt),
-            "lt" => Ok(BinaryOperator::Lt),
-            "gte" => Ok(BinaryOperator::Gte),
-            "lte" => Ok(BinaryOperator::Lte),
-            "and" => Ok(BinaryOperator::And),
-            "or" => Ok(BinaryOperator::Or),
-            _ => Err(()),
-        }
+        functions::lookup(Self::ALL, value).ok_or(())
     }
 }
 
@@ -196,63 +572,414 @@ impl Display for BinaryOperator {
             f,
             "{}",
             match self {
-                Self::Add => '+',
-                Self::Subtract => '-',
-                Self::Multiply => '*',
-                Self::Divide => '/',
-                Self::Pow => '^',
-                Self::Gt => '>',
-                Self::Lt => '<',
+                Self::Add => "+",
+                Self::Subtract => "-",
+                Self::Multiply => "*",
+                Self::Divide => "/",
+                Self::Mod => "%",
+                Self::Pow => "^",
+                Self::Gt => ">",
+                Self::Lt => "<",
                 // Todo, probably have to move this up into Expr to look better but for now we'll
                 // just do this
-                Self::Lcm => 'l',
-                Self::Gcd => 'g',
-                Self::Eq => 'e',
-                Self::NEq => 'n',
-
-                Self::Gte => 'G',
-                Self::Lte => 'L',
-
-                Self::And => '&',
-                Self::Or => '|',
+                Self::Lcm => "l",
+                Self::Gcd => "g",
+                Self::Atan2 => "a",
+                Self::BitAnd => "band",
+                Self::BitOr => "bor",
+                Self::BitXor => "bxor",
+                Self::Shl => "<<",
+                Self::Shr => ">>",
+                Self::Eq => "e",
+                Self::NEq => "n",
+                Self::ApproxEq => "~=",
+
+                Self::Gte => "G",
+                Self::Lte => "L",
+
+                Self::And => "&",
+                Self::Or => "|",
+
+                Self::Convert => "i",
             }
         )
     }
 }
 
+/// Named mathematical constants
+#[derive(Clone, Copy, Debug, PartialEq)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize, serde::Deserialize),
+    serde(rename_all = "snake_case")
+)]
+pub enum Constant {
+    /// The ratio of a circle's circumference to its diameter
+    Pi,
+}
+
+impl Constant {
+    /// Every named constant
+    pub const ALL: &[Self] = &[Self::Pi];
+
+    /// The constant's numeric value
+    pub fn value(&self) -> f64 {
+        match self {
+            Self::Pi => std::f64::consts::PI,
+        }
+    }
+}
+
+impl TryFrom<&str> for Constant {
+    type Error = ();
+    fn try_from(value: &str) -> Result<Self, Self::Error> {
+        Self::ALL
+            .iter()
+            .copied()
+            .find(|constant| constant.to_string().eq_ignore_ascii_case(value))
+            .ok_or(())
+    }
+}
+
+impl Display for Constant {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Pi => write!(f, "pi"),
+        }
+    }
+}
+
+/// Built in functions taking a fixed number of arguments
+#[derive(Clone, Copy, Debug, PartialEq)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize, serde::Deserialize),
+    serde(rename_all = "snake_case")
+)]
+pub enum Function {
+    /// `powmod(base, exp, m)`, a power taken modulo `m`
+    PowMod,
+    /// `invmod(a, m)`, the inverse of `a` modulo `m`
+    InvMod,
+    /// `rand()`, a random float in `[0, 1)`
+    Rand,
+    /// `randint(a, b)`, a random integer between `a` and `b` inclusive
+    RandInt,
+    /// `approx(a, b, tolerance)`, whether `a` and `b` are equal within a relative tolerance
+    Approx,
+    /// `hist(n)`, the result of the `n`th statement evaluated, counting from 1
+    Hist,
+    /// `pctchange(old, new)`, how many percent `new` is above or below `old`
+    PctChange,
+    /// `pctof(part, whole)`, what percent of `whole` `part` is
+    PctOf,
+    /// `applypct(value, pct)`, `value` raised or lowered by `pct` percent
+    ApplyPct,
+    /// `sum(xs...)`, the total of a list or of every argument
+    Sum,
+    /// `mean(xs...)`, the average of a list or of every argument
+    Mean,
+    /// `median(xs...)`, the middle of a list or of every argument
+    Median,
+    /// `min(xs...)`, the smallest of a list or of every argument
+    Min,
+    /// `max(xs...)`, the largest of a list or of every argument
+    Max,
+    /// `stdev(xs...)`, the sample standard deviation of a list or of every argument
+    Stdev,
+    /// `len(xs...)`, how many items a list has, or how many arguments there are
+    Len,
+    /// `range(start, end, step?)`, the integers from `start` to `end` inclusive
+    Range,
+    /// `map(f, xs)`, the function named `f` applied to every item of a list
+    Map,
+    /// `filter(f, xs)`, the items of a list the function named `f` is true for
+    Filter,
+    /// `reduce(f, init, xs)`, every item of a list folded into `init` from the left by the
+    /// function named `f`
+    Reduce,
+    /// `if(cond, then, else)`, `then` when `cond` is true and `else` when it's false, evaluating
+    /// only the one chosen
+    If,
+    /// `piecewise(cond, value, ...)`, the value after the first true condition, or the trailing
+    /// default when none are, going through conditions in order and evaluating only what's needed
+    Piecewise,
+}
+
+impl Function {
+    /// How many arguments the function takes
+    pub fn arity(&self) -> usize {
+        self.signature().arity()
+    }
+
+    /// Whether the function can give a different result each call, because it's random or
+    /// depends on what's been evaluated before, so it can't be folded away
+    pub fn is_volatile(&self) -> bool {
+        matches!(self, Self::Rand | Self::RandInt | Self::Hist)
+    }
+
+    /// Whether the first argument names a function, a variable bound to an expression of its
+    /// free variables, rather than being a value
+    pub fn takes_function(&self) -> bool {
+        matches!(self, Self::Map | Self::Filter | Self::Reduce)
+    }
+
+    /// Whether only some arguments are evaluated, depending on the others, so they can't all be
+    /// evaluated up front
+    pub fn is_lazy(&self) -> bool {
+        matches!(self, Self::If | Self::Piecewise)
+    }
+}
+
+impl TryFrom<&str> for Function {
+    type Error = ();
+    fn try_from(value: &str) -> Result<Self, Self::Error> {
+        functions::lookup(Self::ALL, value).ok_or(())
+    }
+}
+
+impl Display for Function {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.signature().name)
+    }
+}
+
+/// How the terms of a reduction are combined
+#[derive(Clone, Copy, Debug, PartialEq)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize, serde::Deserialize),
+    serde(rename_all = "snake_case")
+)]
+pub enum Reduction {
+    /// Adds the terms, empty is 0
+    Sum,
+    /// Multiplies the terms, empty is 1
+    Product,
+}
+
+impl TryFrom<&str> for Reduction {
+    type Error = ();
+    fn try_from(value: &str) -> Result<Self, Self::Error> {
+        match value.to_lowercase().as_str() {
+            "sum" => Ok(Reduction::Sum),
+            "prod" => Ok(Reduction::Product),
+            _ => Err(()),
+        }
+    }
+}
+
+impl Display for Reduction {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Sum => write!(f, "sum"),
+            Self::Product => write!(f, "prod"),
+        }
+    }
+}
+
+/// Stands in for the start of a recorded node that starts where its first child does
+const INFIX: usize = usize::MAX;
+/// Stands in for the start of a recorded node that starts at the token before its first child
+const PREFIXED: usize = usize::MAX - 1;
+
+/// Default for how many levels deep expressions may nest before parsing or executing them fails
+pub const MAX_DEPTH: usize = 256;
+
 /// A parser object for wrapping over a token span and keeping track of index during parsing
 #[derive(Clone, Debug, PartialEq)]
 pub struct Parser<'a> {
     /// All tokens in the stream
     tokens: Vec<Token<'a>>,
+    /// Source character ranges of each token, empty if the parser wasn't given any
+    spans: Vec<Range<usize>>,
     /// The current index
     current: usize,
+    /// How many absolute values the current token is directly inside of
+    abs_depth: usize,
+    /// How many factors the current token is nested inside of
+    depth: usize,
+    /// How many factors deep an expression may nest before parsing fails
+    max_depth: usize,
+    /// Source character ranges of the nodes parsed so far, children before parents. Only
+    /// recorded if the parser was given spans
+    nodes: Vec<Range<usize>>,
 }
 
-/// Generic parser error
-#[derive(Debug)]
-pub struct ParseError;
+/// A parsed expression along with the source character range of every node in it
+#[derive(Clone, Debug, PartialEq)]
+pub struct Spanned {
+    /// The parsed expression
+    pub expr: Expr,
+    /// The range of each node, in the order [`Expr::walk`] visits them, so the first is the whole
+    /// expression. Ranges are empty if the parser wasn't given spans
+    pub spans: Vec<Range<usize>>,
+}
+
+/// Why parsing failed
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum ParseErrorKind {
+    /// A token that can't appear where it was found
+    Unexpected,
+    /// The expression ended while more was expected, like `(1 +`, so more input could complete it
+    Incomplete,
+    /// The expression nests deeper than the parser's limit
+    TooDeep,
+}
+
+/// Parser error, recording which token parsing failed at
+#[derive(Clone, Debug, PartialEq)]
+pub struct ParseError {
+    /// Index of the offending token in the token stream
+    pub token: usize,
+    /// Source character range of the offending token, if the parser was given spans
+    pub span: Option<Range<usize>>,
+    /// What went wrong
+    pub kind: ParseErrorKind,
+}
 
 impl Display for ParseError {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        write!(f, "Parse error occurred :(")
+        let what = match self.kind {
+            ParseErrorKind::Unexpected => "Parse error occurred",
+            ParseErrorKind::Incomplete => "Expression ended early",
+            ParseErrorKind::TooDeep => "Expression is too deeply nested",
+        };
+        match &self.span {
+            Some(span) => write!(f, "{what} at position {} :(", span.start),
+            None => write!(f, "{what} :("),
+        }
+    }
+}
+
+impl Error for ParseError {}
+
+/// Whether a token can start a value, so a `%` before it is modulo rather than a percentage.
+/// Signs and bars aren't counted, since `50% - 1` and `|50%|` read better as percentages
+fn starts_value(token: Token<'_>) -> bool {
+    match token {
+        Token::Integer(_)
+        | Token::Real(_)
+        | Token::Variable(_)
+        | Token::Bool(_)
+        | Token::OpenParen
+        | Token::OpenBracket => true,
+        Token::Ident(ident) => ident != "in",
+        _ => false,
     }
 }
 
 impl<'a> Parser<'a> {
     /// Creates a new parser from a token span
     pub fn new(tokens: Vec<Token<'a>>) -> Self {
-        Parser { tokens, current: 0 }
+        Parser {
+            tokens,
+            spans: vec![],
+            current: 0,
+            abs_depth: 0,
+            depth: 0,
+            max_depth: MAX_DEPTH,
+            nodes: vec![],
+        }
+    }
+
+    /// Creates a new parser from tokens paired with their source ranges, so errors can report
+    /// where in the source they happened
+    pub fn with_spans(spanned: Vec<(Token<'a>, Range<usize>)>) -> Self {
+        let (tokens, spans) = spanned.into_iter().unzip();
+        Parser {
+            tokens,
+            spans,
+            current: 0,
+            abs_depth: 0,
+            depth: 0,
+            max_depth: MAX_DEPTH,
+            nodes: vec![],
+        }
+    }
+
+    /// Sets how many factors deep an expression may nest, which bounds how much stack parsing
+    /// it can use. Defaults to [`MAX_DEPTH`]
+    pub fn with_max_depth(mut self, depth: usize) -> Self {
+        self.max_depth = depth;
+        self
+    }
+
+    /// An error at the given token index, which means the expression is incomplete if that's the
+    /// end of the stream
+    fn error_at(&self, token: usize) -> ParseError {
+        let at_end = self
+            .tokens
+            .get(token)
+            .is_none_or(|token| *token == Token::EOF);
+        ParseError {
+            token,
+            span: self.spans.get(token).cloned(),
+            kind: if at_end {
+                ParseErrorKind::Incomplete
+            } else {
+                ParseErrorKind::Unexpected
+            },
+        }
+    }
+
+    /// An error at the current token for nesting past the depth limit
+    fn too_deep(&self) -> ParseError {
+        ParseError {
+            kind: ParseErrorKind::TooDeep,
+            ..self.error_at(self.current)
+        }
+    }
+
+    /// Where the current token starts in the source, or 0 if the parser wasn't given spans
+    fn start(&self) -> usize {
+        self.spans.get(self.current).map_or(0, |span| span.start)
+    }
+
+    /// Records a node made of just the last `tokens` tokens consumed
+    fn mark_tokens(&mut self, tokens: usize) {
+        if let Some(first) = self.spans.get(self.current - tokens) {
+            self.mark(first.start);
+        }
+    }
+
+    /// Records a node running from `start` to the end of the last consumed token
+    fn mark(&mut self, start: usize) {
+        if let Some(end) = self.spans.get(self.current - 1) {
+            self.nodes.push(start..end.end);
+        }
+    }
+
+    /// Records a node that starts where its first child does, like a binary operation. Leaving the
+    /// start to be filled in later saves holding it on the stack through every level of nesting
+    fn mark_infix(&mut self) {
+        self.mark(INFIX);
+    }
+
+    /// Records a factor that was just parsed. Factors with something inside them start one token
+    /// before it, like the `(` of a parenthesized expression, or otherwise are that one token
+    fn mark_prefix(&mut self, factor: &Expr) {
+        match factor {
+            Expr::List(items) if items.is_empty() => self.mark_tokens(2),
+            Expr::UnaryOp { .. } | Expr::Paren(_) | Expr::AbsVal(_) | Expr::List(_) => {
+                self.mark(PREFIXED);
+            }
+            _ => self.mark_tokens(1),
+        }
     }
 
-    /// Peeks at the next token
+    /// Peeks at the next token, treating anything past the end as EOF so token lists without one
+    /// can't read out of bounds
     fn peek(&self) -> Token<'a> {
-        self.tokens[self.current]
+        self.peek_n(0)
     }
 
-    /// Peeks at the next token plus n
+    /// Peeks at the next token plus n, treating anything past the end as EOF
     fn peek_n(&self, n: usize) -> Token<'a> {
-        self.tokens[self.current + n]
+        self.tokens
+            .get(self.current + n)
+            .copied()
+            .unwrap_or(Token::EOF)
     }
 
     /// Consumes the next token under the assertion that it is the expected input token
@@ -261,7 +988,7 @@ impl<'a> Parser<'a> {
             self.current += 1;
             Ok(())
         } else {
-            Err(ParseError)
+            Err(self.error_at(self.current))
         }
     }
 
@@ -272,26 +999,97 @@ impl<'a> Parser<'a> {
         curr
     }
 
-    /// An assignment is `variable = chained` | `chained`
+    /// Splits the `||` at the current token into two bars, where it's really the edges of
+    /// absolute values
+    fn split_or(&mut self) {
+        self.tokens[self.current] = Token::Bar;
+        self.tokens.insert(self.current + 1, Token::Bar);
+        if let Some(span) = self.spans.get(self.current).cloned() {
+            self.spans[self.current] = span.start..span.start + 1;
+            self.spans
+                .insert(self.current + 1, span.start + 1..span.end);
+        }
+    }
+
+    /// Consumes the bar closing an absolute value. The tokenizer reads bars next to each other as
+    /// `||`, so `|1 - |x||` closes two absolute values at once
+    fn close_bar(&mut self) -> Result<(), ParseError> {
+        if self.peek() == Token::Or {
+            self.split_or();
+        }
+
+        self.consume(&Token::Bar)
+    }
+
+    /// Parses a `chained` expression inside parentheses or argument lists, where any `||` is an or
+    /// again rather than the edge of an enclosing absolute value
+    fn grouped(&mut self) -> Result<Expr, ParseError> {
+        let abs_depth = std::mem::take(&mut self.abs_depth);
+        let expr = self.chained();
+        self.abs_depth = abs_depth;
+        expr
+    }
+
+    /// Parses what's inside parentheses like `grouped`, except that it may be an assignment so
+    /// they can nest, like `a = (b = 2) * 3` binding both. Anything else goes straight to
+    /// `grouped`, keeping deeply nested parentheses to as few stack frames as before
+    fn parenthesized(&mut self) -> Result<Expr, ParseError> {
+        let assigns =
+            self.peek_n(1) == Token::Assign || compound_operator(self.peek_n(1)).is_some();
+        if !matches!(self.peek(), Token::Variable(_)) || !assigns {
+            return self.grouped();
+        }
+
+        let abs_depth = std::mem::take(&mut self.abs_depth);
+        let expr = self.assignment();
+        self.abs_depth = abs_depth;
+        expr
+    }
+
+    /// An assignment is `variable = chained` | `variable (+= | -= | *= | /=) chained` | `chained`.
+    /// Compound assignments are written out in full, so `x += 1` is `x = x + 1`
     fn assignment(&mut self) -> Result<Expr, ParseError> {
-        match (self.peek(), self.peek_n(1)) {
-            (Token::Variable(v), Token::Assign) => {
-                self.advance();
-                self.advance();
+        let Token::Variable(v) = self.peek() else {
+            return self.chained();
+        };
+
+        if self.peek_n(1) == Token::Assign {
+            let start = self.start();
+            self.advance();
+            self.advance();
 
-                let expr = self.chained()?;
+            let expr = self.chained()?;
+            self.mark(start);
 
-                Ok(Expr::Assignment(v, Box::new(expr)))
-            }
-            _ => self.chained(),
+            return Ok(Expr::Assignment(v, Box::new(expr)));
         }
+
+        let Some(op) = compound_operator(self.peek_n(1)) else {
+            return self.chained();
+        };
+        let start = self.start();
+        self.advance();
+        self.mark_tokens(1);
+        self.advance();
+
+        let right = self.chained()?;
+        self.mark(start);
+        self.mark(start);
+
+        let update = Expr::BinaryOp {
+            op,
+            left: Box::new(Expr::Variable(v)),
+            right: Box::new(right),
+        };
+        Ok(Expr::Assignment(v, Box::new(update)))
     }
 
-    /// A chain is `comparison ( && | || comparison)`
+    /// A chain is `comparison ( && | || comparison)`. Directly inside an absolute value `||` is
+    /// always a pair of bars, since the absolute value of a boolean is meaningless
     fn chained(&mut self) -> Result<Expr, ParseError> {
         let mut start = self.comparison()?;
 
-        while matches!(self.peek(), Token::And | Token::Or) {
+        while self.peek() == Token::And || (self.peek() == Token::Or && self.abs_depth == 0) {
             let op = match self.advance() {
                 Token::And => BinaryOperator::And,
                 Token::Or => BinaryOperator::Or,
@@ -299,6 +1097,7 @@ impl<'a> Parser<'a> {
             };
 
             let right = self.comparison()?;
+            self.mark_infix();
 
             start = Expr::BinaryOp {
                 op,
@@ -310,17 +1109,25 @@ impl<'a> Parser<'a> {
         Ok(start)
     }
 
-    /// A chain is `expression (== | != | > | < | <= | >= expression)?`
+    /// A chain is `conversion (== | != | ~= | > | < | <= | >= conversion)?`
     fn comparison(&mut self) -> Result<Expr, ParseError> {
-        let mut start = self.expression()?;
+        let left = self.expression()?;
+        let mut start = self.conversion(left)?;
 
         if matches!(
             self.peek(),
-            Token::Eq | Token::NEq | Token::Gt | Token::Lt | Token::Gte | Token::Lte
+            Token::Eq
+                | Token::NEq
+                | Token::ApproxEq
+                | Token::Gt
+                | Token::Lt
+                | Token::Gte
+                | Token::Lte
         ) {
             let op = match self.advance() {
                 Token::Eq => BinaryOperator::Eq,
                 Token::NEq => BinaryOperator::NEq,
+                Token::ApproxEq => BinaryOperator::ApproxEq,
                 Token::Lt => BinaryOperator::Lt,
                 Token::Lte => BinaryOperator::Lte,
                 Token::Gt => BinaryOperator::Gt,
@@ -329,6 +1136,8 @@ impl<'a> Parser<'a> {
             };
 
             let right = self.expression()?;
+            let right = self.conversion(right)?;
+            self.mark_infix();
 
             start = Expr::BinaryOp {
                 op,
@@ -340,6 +1149,122 @@ impl<'a> Parser<'a> {
         Ok(start)
     }
 
+    /// A shift is `expression (<< | >> expression)*`. Takes the already parsed expression, like
+    /// `conversion` that reads it
+    fn shift(&mut self, expression: Expr) -> Result<Expr, ParseError> {
+        let mut start = expression;
+
+        while matches!(self.peek(), Token::ShiftLeft | Token::ShiftRight) {
+            let op = match self.advance() {
+                Token::ShiftLeft => BinaryOperator::Shl,
+                Token::ShiftRight => BinaryOperator::Shr,
+                _ => unreachable!(),
+            };
+            let right = self.expression()?;
+            self.mark_infix();
+
+            start = Expr::BinaryOp {
+                op,
+                left: Box::new(start),
+                right: Box::new(right),
+            }
+        }
+
+        Ok(start)
+    }
+
+    /// A conversion is `shift (in unit)*`, like `3km + 200m in m`. Takes the already parsed
+    /// expression, so conversions don't add to the stack used by every level of nesting
+    fn conversion(&mut self, expression: Expr) -> Result<Expr, ParseError> {
+        let mut start = self.shift(expression)?;
+
+        while self.peek() == Token::Ident("in") {
+            self.advance();
+            let unit_start = self.start();
+            let unit = self.unit()?;
+            self.mark(unit_start);
+            self.mark_infix();
+
+            start = Expr::BinaryOp {
+                op: BinaryOperator::Convert,
+                left: Box::new(start),
+                right: Box::new(Expr::Unit(unit)),
+            }
+        }
+
+        Ok(start)
+    }
+
+    /// A unit is `unit_power ( * | / unit_power)*`, like `km/h`. Only a unit can follow `in`, so
+    /// names that would otherwise be variables or functions are read as units here
+    fn unit(&mut self) -> Result<Unit, ParseError> {
+        let mut unit = self.unit_power()?;
+
+        while matches!(self.peek(), Token::Multiply | Token::Divide) {
+            let divides = self.advance() == Token::Divide;
+            let next = self.unit_power()?;
+            unit = self.combine_units(unit, next, divides)?;
+        }
+
+        Ok(unit)
+    }
+
+    /// A number optionally followed by its unit, `NUMBER (unit_power (/ unit_power)*)?`. Units
+    /// bind to the number before anything else, so `100 km / 2 h` divides two quantities
+    fn suffixed(&mut self, number: Expr) -> Result<Expr, ParseError> {
+        if !matches!(self.peek(), Token::Unit(_)) {
+            return Ok(number);
+        }
+
+        let unit_start = self.start();
+        let mut unit = self.unit_power()?;
+        while self.peek() == Token::Divide && matches!(self.peek_n(1), Token::Unit(_)) {
+            self.advance();
+            let next = self.unit_power()?;
+            unit = self.combine_units(unit, next, true)?;
+        }
+        self.mark(unit_start);
+        self.mark_infix();
+
+        Ok(Expr::BinaryOp {
+            op: BinaryOperator::Multiply,
+            left: Box::new(number),
+            right: Box::new(Expr::Unit(unit)),
+        })
+    }
+
+    /// Multiplies or divides a unit being parsed by the next one. Mixing units of the same
+    /// dimension, like `km*m`, has no single unit to write the result in, so it's an error
+    fn combine_units(&self, unit: Unit, next: Unit, divides: bool) -> Result<Unit, ParseError> {
+        let next = if divides { next.powi(-1) } else { next };
+        match unit.times(&next) {
+            (product, 1.0) => Ok(product),
+            _ => Err(self.error_at(self.current - 1)),
+        }
+    }
+
+    /// A unit power is `NAME (^ INTEGER)?`, like `m^2`
+    fn unit_power(&mut self) -> Result<Unit, ParseError> {
+        let unit = match self.advance() {
+            Token::Unit(symbol) | Token::Ident(symbol) => Unit::parse(symbol),
+            Token::Variable(var) => Unit::parse(var.encode_utf8(&mut [0; 4])),
+            _ => None,
+        }
+        .ok_or_else(|| self.error_at(self.current - 1))?;
+
+        if self.peek() != Token::Caret {
+            return Ok(unit);
+        }
+
+        self.advance();
+        match self.advance() {
+            Token::Integer(power) => i32::try_from(power)
+                .map(|power| unit.powi(power))
+                .map_err(|_| self.error_at(self.current - 1)),
+            _ => Err(self.error_at(self.current - 1)),
+        }
+    }
+
     /// An expression is a `term ( + | - term)* `
     fn expression(&mut self) -> Result<Expr, ParseError> {
         let mut start = self.term()?;
@@ -351,6 +1276,7 @@ impl<'a> Parser<'a> {
                 _ => unreachable!(),
             };
             let right = self.term()?;
+            self.mark_infix();
             start = Expr::BinaryOp {
                 op,
                 left: Box::new(start),
@@ -361,40 +1287,22 @@ impl<'a> Parser<'a> {
         Ok(start)
     }
 
-    /// A term is a `power ( * | / power)*`
+    /// A term is a `power ( * | / power)*`. A power followed directly by a variable, function,
+    /// number or parenthesis is multiplied by it, so `3x^2` is `3 * x ^ 2`. `in` starts a
+    /// conversion instead
     fn term(&mut self) -> Result<Expr, ParseError> {
         let mut start = self.power()?;
 
-        while matches!(
-            self.peek(),
-            Token::Divide | Token::Multiply | Token::OpenParen | Token::Variable(_)
-        ) {
-            let mut paren_mul = false;
-            let op = match self.peek() {
-                Token::Divide => {
-                    self.advance();
-                    BinaryOperator::Divide
-                }
-                Token::Multiply => {
-                    self.advance();
-                    BinaryOperator::Multiply
-                }
-                Token::OpenParen => {
-                    self.advance();
-                    paren_mul = true;
-                    BinaryOperator::Multiply
-                }
-                Token::Variable(_) => BinaryOperator::Multiply,
-                _ => unreachable!(),
-            };
-
-            let right = if paren_mul {
-                let r = self.chained()?;
+        while let Some(op) = self.product_operator() {
+            // A parenthesis straight after a power is consumed along with the operator
+            let right = if self.tokens[self.current - 1] == Token::OpenParen {
+                let r = self.grouped()?;
                 self.consume(&Token::CloseParen)?;
                 r
             } else {
                 self.power()?
             };
+            self.mark_infix();
 
             start = Expr::BinaryOp {
                 op,
@@ -406,6 +1314,24 @@ impl<'a> Parser<'a> {
         Ok(start)
     }
 
+    /// Consumes the operator joining the next power onto a term, if there is one. Kept out of
+    /// `term` so matching it doesn't add to the stack used by every level of nesting
+    fn product_operator(&mut self) -> Option<BinaryOperator> {
+        let op = match self.peek() {
+            Token::Divide => BinaryOperator::Divide,
+            Token::Percent => BinaryOperator::Mod,
+            Token::Multiply | Token::OpenParen => BinaryOperator::Multiply,
+            Token::Variable(_) | Token::Integer(_) | Token::Real(_) => {
+                return Some(BinaryOperator::Multiply);
+            }
+            Token::Ident(ident) if ident != "in" => return Some(BinaryOperator::Multiply),
+            _ => return None,
+        };
+
+        self.advance();
+        Some(op)
+    }
+
     /// A power is a `factor (^ factor)*`
     fn power(&mut self) -> Result<Expr, ParseError> {
         let mut start = self.factorial()?;
@@ -413,6 +1339,7 @@ impl<'a> Parser<'a> {
         while self.peek() == Token::Caret {
             self.advance();
             let exponent = self.factorial()?;
+            self.mark_infix();
             start = Expr::BinaryOp {
                 op: BinaryOperator::Pow,
                 left: Box::new(start),
@@ -423,70 +1350,266 @@ impl<'a> Parser<'a> {
         Ok(start)
     }
 
-    /// A factorial is `factor (!)?`
+    /// A factorial is `factor (! | ° | % | "[" expression "]")*`. A `%` followed by a value is
+    /// modulo instead, left for `term`
     fn factorial(&mut self) -> Result<Expr, ParseError> {
         let mut start = self.factor()?;
-        while self.peek() == Token::Exclamation {
-            self.advance();
-            start = Expr::UnaryOp {
-                op: UnaryOperator::Factorial,
-                node: Box::new(start),
-            }
+        while self.at_postfix() {
+            start = self.postfix(start)?;
         }
 
         Ok(start)
     }
 
-    /// A factor is `NUMBER | "(" expression ")" | "|" expression "|" | - factor`
+    /// Whether the current token is a postfix operator or index. Kept out of `factorial` so
+    /// looking past a `%` doesn't add to the stack used by every level of nesting
+    fn at_postfix(&self) -> bool {
+        match self.peek() {
+            Token::Exclamation | Token::Degree | Token::OpenBracket => true,
+            Token::Percent => !starts_value(self.peek_n(1)),
+            _ => false,
+        }
+    }
+
+    /// Applies the postfix operator or index starting at the current token to `operand`. Takes
+    /// the already parsed operand, so indexing doesn't add to the stack used by every level of
+    /// nesting
+    fn postfix(&mut self, operand: Expr) -> Result<Expr, ParseError> {
+        let op = match self.advance() {
+            Token::Exclamation => UnaryOperator::Factorial,
+            Token::Degree => UnaryOperator::Degrees,
+            Token::Percent => UnaryOperator::Percent,
+            _ => {
+                let index = self.grouped()?;
+                self.consume(&Token::CloseBracket)?;
+                self.mark_infix();
+                return Ok(Expr::Index {
+                    list: Box::new(operand),
+                    index: Box::new(index),
+                });
+            }
+        };
+        self.mark_infix();
+
+        Ok(Expr::UnaryOp {
+            op,
+            node: Box::new(operand),
+        })
+    }
+
+    /// A factor is `NUMBER | "(" expression ")" | "[" expressions "]" | "|" expression "|" |
+    /// "-" factor | "~" factor`. Every nested expression passes through here, so this is where
+    /// the depth limit is enforced
     fn factor(&mut self) -> Result<Expr, ParseError> {
-        match self.advance() {
-            Token::Minus => Ok(Expr::UnaryOp {
-                op: UnaryOperator::Neg,
+        if self.depth >= self.max_depth {
+            return Err(self.too_deep());
+        }
+
+        self.depth += 1;
+        let factor = self.nested_factor();
+        self.depth -= 1;
+        factor
+    }
+
+    /// Parses a factor once the depth limit has been checked
+    fn nested_factor(&mut self) -> Result<Expr, ParseError> {
+        let factor = match self.advance() {
+            token @ (Token::Minus | Token::Tilde) => Expr::UnaryOp {
+                op: if token == Token::Minus {
+                    UnaryOperator::Neg
+                } else {
+                    UnaryOperator::BitNot
+                },
                 node: Box::new(self.factor()?),
-            }),
-            Token::Real(n) => Ok(Expr::Real(n)),
-            Token::Integer(i) => Ok(Expr::Integer(i)),
-            Token::Bool(b) => Ok(Expr::Bool(b)),
+            },
+            Token::Real(n) => {
+                self.mark_tokens(1);
+                return self.suffixed(Expr::Real(n));
+            }
+            Token::Integer(i) => {
+                self.mark_tokens(1);
+                return self.suffixed(Expr::Integer(i));
+            }
+            Token::Bool(b) => Expr::Bool(b),
             Token::OpenParen => {
-                let inner = self.chained()?;
+                let inner = self.parenthesized()?;
                 self.consume(&Token::CloseParen)?;
-                Ok(Expr::Paren(Box::new(inner)))
+                Expr::Paren(Box::new(inner))
             }
             Token::Bar => {
-                let inner = self.chained()?;
-                self.consume(&Token::Bar)?;
-                Ok(Expr::AbsVal(Box::new(inner)))
+                self.abs_depth += 1;
+                let inner = self.chained();
+                self.abs_depth -= 1;
+                let inner = inner?;
+                self.close_bar()?;
+                Expr::AbsVal(Box::new(inner))
+            }
+            Token::Or => {
+                // Two bars opening nested absolute values, like `||x| - 1|`
+                self.current -= 1;
+                self.split_or();
+                return self.factor();
             }
 
-            Token::Variable(v) => Ok(Expr::Variable(v)),
+            Token::OpenBracket => Expr::List(self.arguments(&Token::CloseBracket)?),
+            Token::Variable(v) => Expr::Variable(v),
 
-            Token::Ident(ident) => {
-                if let Ok(op) = BinaryOperator::try_from(ident) {
-                    self.consume(&Token::OpenParen)?;
-                    let l = self.chained()?;
-                    self.consume(&Token::Comma)?;
-                    let r = self.chained()?;
-                    self.consume(&Token::CloseParen)?;
+            Token::Ident(ident) => return self.identifier(ident),
+            _ => return Err(self.error_at(self.current - 1)),
+        };
 
-                    Ok(Expr::BinaryOp {
-                        op,
-                        left: Box::new(l),
-                        right: Box::new(r),
-                    })
-                } else if let Ok(op) = UnaryOperator::try_from(ident) {
-                    self.consume(&Token::OpenParen)?;
-                    let node = self.chained()?;
-                    self.consume(&Token::CloseParen)?;
-
-                    Ok(Expr::UnaryOp {
-                        op,
-                        node: Box::new(node),
+        self.mark_prefix(&factor);
+        Ok(factor)
+    }
+
+    /// Parses a factor starting with an identifier, a constant, function or run of variables
+    fn identifier(&mut self, ident: &'a str) -> Result<Expr, ParseError> {
+        let from = self
+            .spans
+            .get(self.current - 1)
+            .map_or(0, |span| span.start);
+        if let Ok(constant) = Constant::try_from(ident) {
+            self.mark(from);
+            Ok(Expr::Constant(constant))
+        } else if ident == "solve" {
+            self.consume(&Token::OpenParen)?;
+            let equation = self.grouped()?;
+            self.consume(&Token::Comma)?;
+            let Token::Variable(var) = self.advance() else {
+                return Err(self.error_at(self.current - 1));
+            };
+            self.consume(&Token::CloseParen)?;
+            self.mark(from);
+
+            Ok(Expr::Solve {
+                equation: Box::new(equation),
+                var,
+            })
+        } else if let Ok(kind) = Reduction::try_from(ident) {
+            self.consume(&Token::OpenParen)?;
+            let first = self.nodes.len();
+            let args = self.arguments(&Token::CloseParen)?;
+
+            match <[Expr; 4]>::try_from(args) {
+                Ok([Expr::Variable(var), lower, upper, body]) => {
+                    // The variable is bound rather than a node of its own
+                    if first < self.nodes.len() {
+                        self.nodes.remove(first);
+                    }
+                    self.mark(from);
+                    Ok(Expr::Reduce {
+                        kind,
+                        var,
+                        from: Box::new(lower),
+                        to: Box::new(upper),
+                        body: Box::new(body),
                     })
-                } else {
-                    Err(ParseError)
                 }
+                // Anything else is a call, like `sum` of a list
+                Ok(args) => self.call(ident, args.into(), from),
+                Err(args) => self.call(ident, args, from),
             }
-            _ => Err(ParseError),
+        } else if Function::try_from(ident).is_ok() {
+            self.consume(&Token::OpenParen)?;
+            let args = self.arguments(&Token::CloseParen)?;
+            self.call(ident, args, from)
+        } else if let Ok(op) = BinaryOperator::try_from(ident) {
+            self.consume(&Token::OpenParen)?;
+            let l = self.grouped()?;
+            self.consume(&Token::Comma)?;
+            let r = self.grouped()?;
+            self.consume(&Token::CloseParen)?;
+            self.mark(from);
+
+            Ok(Expr::BinaryOp {
+                op,
+                left: Box::new(l),
+                right: Box::new(r),
+            })
+        } else if let Ok(op) = UnaryOperator::try_from(ident) {
+            let node = if self.peek() == Token::OpenParen {
+                self.advance();
+                let node = self.grouped()?;
+                self.consume(&Token::CloseParen)?;
+                node
+            } else {
+                self.bare_argument()?
+            };
+            self.mark(from);
+
+            Ok(Expr::UnaryOp {
+                op,
+                node: Box::new(node),
+            })
+        } else if ident.chars().all(char::is_alphabetic) && self.peek() != Token::OpenParen {
+            // Variables written next to each other, like `xy`. A word followed by
+            // arguments is more likely a misspelled function, so that stays an error
+            // Each variable is a single character of the identifier
+            let mut vars = ident.chars().map(Expr::Variable);
+            let first = vars.next().expect("Identifiers aren't empty");
+            if !self.spans.is_empty() {
+                self.nodes.push(from..from + 1);
+            }
+            Ok(vars.enumerate().fold(first, |left, (i, right)| {
+                if !self.spans.is_empty() {
+                    self.nodes.push(from + i + 1..from + i + 2);
+                    self.nodes.push(from..from + i + 2);
+                }
+                Expr::BinaryOp {
+                    op: BinaryOperator::Multiply,
+                    left: Box::new(left),
+                    right: Box::new(right),
+                }
+            }))
+        } else {
+            Err(self.error_at(self.current - 1))
+        }
+    }
+
+    /// Parses the argument of a unary function written without parentheses, like `sin 2`. The
+    /// argument is a power, so `sin x^2` is `sin(x ^ 2)` and `sin 2 * x` is `sin(2) * x`. An
+    /// argument multiplied by juxtaposition, like `sin 2x` or `sin xy`, could be read either way,
+    /// so that's an error rather than a guess
+    fn bare_argument(&mut self) -> Result<Expr, ParseError> {
+        if let Token::Ident(word) = self.peek()
+            && is_variable_run(word)
+        {
+            return Err(self.error_at(self.current));
+        }
+
+        let argument = self.power()?;
+        match self.peek() {
+            Token::Variable(_) | Token::Integer(_) | Token::Real(_) | Token::OpenParen => {
+                Err(self.error_at(self.current))
+            }
+            Token::Ident(ident) if ident != "in" => Err(self.error_at(self.current)),
+            _ => Ok(argument),
+        }
+    }
+
+    /// Parses comma separated expressions up to and including `close`, which may be none at all
+    fn arguments(&mut self, close: &Token<'a>) -> Result<Vec<Expr>, ParseError> {
+        let mut args = vec![];
+        if &self.peek() != close {
+            args.push(self.grouped()?);
+            while self.peek() == Token::Comma {
+                self.advance();
+                args.push(self.grouped()?);
+            }
+        }
+        self.consume(close)?;
+
+        Ok(args)
+    }
+
+    /// A call to the function named `ident` starting at `from`, once its arguments are parsed
+    fn call(&mut self, ident: &str, args: Vec<Expr>, from: usize) -> Result<Expr, ParseError> {
+        match Function::try_from(ident) {
+            Ok(function) if function.signature().takes(args.len()) => {
+                self.mark(from);
+                Ok(Expr::FunctionCall { function, args })
+            }
+            _ => Err(self.error_at(self.current - 1)),
         }
     }
 
@@ -497,6 +1620,67 @@ impl<'a> Parser<'a> {
 
         Ok(expr)
     }
+
+    /// Parses the current token span into an AST along with where each node came from in the
+    /// source. The parser needs spans, from [`Parser::with_spans`], for the ranges to be known
+    pub fn parse_spanned(&mut self) -> Result<Spanned, ParseError> {
+        self.nodes.clear();
+        let expr = self.parse()?;
+
+        let mut spans = Vec::with_capacity(self.nodes.len());
+        let mut next = 0;
+        reorder(&expr, &self.nodes, &self.spans, &mut next, &mut spans);
+        debug_assert!(self.nodes.is_empty() || next == self.nodes.len());
+
+        Ok(Spanned { expr, spans })
+    }
+}
+
+/// The operator a compound assignment token applies, like adding for `+=`
+fn compound_operator(token: Token<'_>) -> Option<BinaryOperator> {
+    match token {
+        Token::PlusAssign => Some(BinaryOperator::Add),
+        Token::MinusAssign => Some(BinaryOperator::Subtract),
+        Token::MultiplyAssign => Some(BinaryOperator::Multiply),
+        Token::DivideAssign => Some(BinaryOperator::Divide),
+        _ => None,
+    }
+}
+
+/// Whether an identifier is a run of variables multiplied together, like `xy`, rather than the
+/// name of a constant or function
+fn is_variable_run(word: &str) -> bool {
+    word.chars().count() > 1
+        && Constant::try_from(word).is_err()
+        && !functions::all().any(|signature| signature.accepts(word))
+}
+
+/// Appends the ranges of `expr`'s nodes in the order [`Expr::walk`] visits them, reading them
+/// from `recorded`, where children come before parents, starting at `next`. Starts left to be
+/// filled in are found from the first child and the source range of each token
+fn reorder(
+    expr: &Expr,
+    recorded: &[Range<usize>],
+    tokens: &[Range<usize>],
+    next: &mut usize,
+    spans: &mut Vec<Range<usize>>,
+) {
+    let slot = spans.len();
+    spans.push(0..0);
+    for child in expr.children() {
+        reorder(child, recorded, tokens, next, spans);
+    }
+
+    let mut span = recorded.get(*next).cloned().unwrap_or_default();
+    let child = spans.get(slot + 1).map_or(0, |child| child.start);
+    if span.start == INFIX {
+        span.start = child;
+    } else if span.start == PREFIXED {
+        let token = tokens.partition_point(|token| token.start < child);
+        span.start = tokens[token.saturating_sub(1)].start;
+    }
+    spans[slot] = span;
+    *next += 1;
 }
 
 #[cfg(test)]
@@ -516,27 +1700,574 @@ mod tests {
         let mut parser = Parser::new(tokens);
         let ast = parser.parse().expect("Parsing failed");
 
-        let expected = Expr::BinaryOp {
-            op: BinaryOperator::Subtract,
-            left: Box::new(Expr::BinaryOp {
-                op: BinaryOperator::Multiply,
-                left: Box::new(Expr::Paren(Box::new(Expr::BinaryOp {
-                    op: BinaryOperator::Add,
-                    left: Box::new(Expr::Integer(1)),
-                    right: Box::new(Expr::Integer(2)),
-                }))),
-                right: Box::new(Expr::Integer(3)),
-            }),
-            right: Box::new(Expr::BinaryOp {
-                op: BinaryOperator::Divide,
-                left: Box::new(Expr::Integer(4)),
-                right: Box::new(Expr::Integer(2)),
-            }),
-        };
+        let expected = Expr::paren(Expr::int(1) + 2) * 3 - Expr::div(4, 2);
 
         assert_eq!(ast, expected);
     }
 
+    /// An integer inside `depth` parentheses
+    fn nested(depth: usize) -> String {
+        format!("{}1{}", "(".repeat(depth), ")".repeat(depth))
+    }
+
+    #[test]
+    fn depth_limit() {
+        // The integer itself is the innermost factor
+        let parse = |source: &str| Parser::new(source.tokenize().expect("Tokenize")).parse();
+        assert!(parse(&nested(MAX_DEPTH - 1)).is_ok());
+        assert_eq!(
+            parse(&nested(MAX_DEPTH)).map_err(|err| (err.kind, err.token)),
+            Err((ParseErrorKind::TooDeep, MAX_DEPTH))
+        );
+        assert_eq!(
+            parse(&"-".repeat(MAX_DEPTH * 4)).map_err(|err| err.kind),
+            Err(ParseErrorKind::TooDeep)
+        );
+
+        let source = nested(10);
+        let tokens = source.tokenize_spanned().expect("Tokenize");
+        let err = Parser::with_spans(tokens)
+            .with_max_depth(10)
+            .parse()
+            .expect_err("Too deep");
+        assert_eq!(
+            err.to_string(),
+            "Expression is too deeply nested at position 10 :("
+        );
+
+        // A huge input fails instead of overflowing the stack
+        assert!(parse(&nested(100_000)).is_err());
+    }
+
+    /// The source text of every node in an expression, in the order [`Expr::walk`] visits them
+    fn node_sources(source: &str) -> Vec<&str> {
+        let tokens = source.tokenize_spanned().expect("Tokenize");
+        let spanned = Parser::with_spans(tokens)
+            .parse_spanned()
+            .unwrap_or_else(|_| panic!("`{source}` should parse"));
+
+        let mut nodes = 0;
+        spanned.expr.walk(&mut |_| nodes += 1);
+        assert_eq!(spanned.spans.len(), nodes, "{source}");
+
+        spanned
+            .spans
+            .into_iter()
+            .map(|span| &source[span])
+            .collect()
+    }
+
+    #[test]
+    fn node_spans() {
+        assert_eq!(
+            node_sources("(1+2)*3"),
+            ["(1+2)*3", "(1+2)", "1+2", "1", "2", "3"]
+        );
+        assert_eq!(
+            node_sources("x = -2xy!"),
+            ["x = -2xy!", "-2xy!", "-2", "2", "xy!", "xy", "x", "y"]
+        );
+        assert_eq!(
+            node_sources("x *= y + 1"),
+            ["x *= y + 1", "x *= y + 1", "x", "y + 1", "y", "1"]
+        );
+        assert_eq!(
+            node_sources("sum(i, 1, n, |i|) in km"),
+            [
+                "sum(i, 1, n, |i|) in km",
+                "sum(i, 1, n, |i|)",
+                "1",
+                "n",
+                "|i|",
+                "i",
+                "km"
+            ]
+        );
+        assert_eq!(
+            node_sources("3.5 < [1, 2][0] && ||x| - 1| == 2"),
+            [
+                "3.5 < [1, 2][0] && ||x| - 1| == 2",
+                "3.5 < [1, 2][0]",
+                "3.5",
+                "[1, 2][0]",
+                "[1, 2]",
+                "1",
+                "2",
+                "0",
+                "||x| - 1| == 2",
+                "||x| - 1|",
+                "|x| - 1",
+                "|x|",
+                "x",
+                "1",
+                "2",
+            ]
+        );
+        assert_eq!(
+            node_sources("solve(2x == 4, x) + max(1, 2) + gcd(5, sqrt(3)) << 1"),
+            [
+                "solve(2x == 4, x) + max(1, 2) + gcd(5, sqrt(3)) << 1",
+                "solve(2x == 4, x) + max(1, 2) + gcd(5, sqrt(3))",
+                "solve(2x == 4, x) + max(1, 2)",
+                "solve(2x == 4, x)",
+                "2x == 4",
+                "2x",
+                "2",
+                "x",
+                "4",
+                "max(1, 2)",
+                "1",
+                "2",
+                "gcd(5, sqrt(3))",
+                "5",
+                "sqrt(3)",
+                "3",
+                "1",
+            ]
+        );
+        assert_eq!(
+            node_sources("2 sin x^2"),
+            ["2 sin x^2", "2", "sin x^2", "x^2", "x", "2"]
+        );
+
+        // Without spans there's nothing to say where nodes came from
+        let tokens = "1 + 2".tokenize().expect("Tokenize");
+        let spanned = Parser::new(tokens).parse_spanned().expect("Parse");
+        assert_eq!(spanned.spans, [0..0, 0..0, 0..0]);
+    }
+
+    #[test]
+    fn parse_error_positions() {
+        let tokens = "(1 + ) * 2".tokenize_spanned().expect("Tokenize");
+        let err = Parser::with_spans(tokens).parse().expect_err("Parse");
+
+        assert_eq!(
+            err,
+            ParseError {
+                token: 3,
+                span: Some(5..6),
+                kind: ParseErrorKind::Unexpected,
+            }
+        );
+
+        let tokens = "1 +".tokenize().expect("Tokenize");
+        let err = Parser::new(tokens).parse().expect_err("Parse");
+
+        assert_eq!(
+            err,
+            ParseError {
+                token: 2,
+                span: None,
+                kind: ParseErrorKind::Incomplete,
+            }
+        );
+    }
+
+    #[test]
+    fn incomplete_expressions() {
+        let kind = |expression: &str| {
+            let tokens = expression.tokenize().expect("Tokenize");
+            Parser::new(tokens).parse().map_err(|err| err.kind)
+        };
+
+        for incomplete in ["(1 +", "1 + ", "gcd(4,", "|x", "sum(i, 1", "x =", "3 in"] {
+            assert_eq!(
+                kind(incomplete),
+                Err(ParseErrorKind::Incomplete),
+                "{incomplete}"
+            );
+        }
+        for unexpected in ["1 + )", "(1 +) * 2", "gcd(4 5)", "1 ) 2"] {
+            assert_eq!(
+                kind(unexpected),
+                Err(ParseErrorKind::Unexpected),
+                "{unexpected}"
+            );
+        }
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn serde_round_trip() {
+        let tokens = "y = |2^(x - 1) / (3.5 + 1)|! >= 4 || x != 1"
+            .tokenize()
+            .expect("Tokenize");
+        let ast = Parser::new(tokens).parse().expect("Parse");
+
+        let json = serde_json::to_string(&ast).expect("Serialize");
+        let restored: Expr = serde_json::from_str(&json).expect("Deserialize");
+        assert_eq!(restored, ast);
+
+        let sum = Expr::BinaryOp {
+            op: BinaryOperator::Add,
+            left: Box::new(Expr::Integer(1)),
+            right: Box::new(Expr::Variable('x')),
+        };
+        assert_eq!(
+            serde_json::to_string(&sum).expect("Serialize"),
+            r#"{"type":"binary_op","value":{"op":"add","left":{"type":"integer","value":1},"right":{"type":"variable","value":"x"}}}"#
+        );
+    }
+
+    /// A small xorshift generator, so random tests are reproducible without extra dependencies
+    struct Rng(u64);
+
+    impl Rng {
+        /// A random number below `n`
+        fn below(&mut self, n: u64) -> u64 {
+            self.0 ^= self.0 << 13;
+            self.0 ^= self.0 >> 7;
+            self.0 ^= self.0 << 17;
+            self.0 % n
+        }
+
+        /// A random expression at most `depth` levels deep
+        fn expr(&mut self, depth: u64) -> Expr {
+            let small = |rng: &mut Self| Expr::Integer(rng.below(6) as i64 + 1);
+            if depth == 0 {
+                return match self.below(5) {
+                    0 => Expr::Variable(['x', 'y'][self.below(2) as usize]),
+                    1 => Expr::Real(self.below(20) as f64 / 4.0 - 2.0),
+                    2 => Expr::Constant(Constant::Pi),
+                    3 => Expr::Integer(self.below(7) as i64 - 3),
+                    _ => small(self),
+                };
+            }
+
+            let node = |rng: &mut Self| Box::new(rng.expr(depth - 1));
+            let unary = |op, node| Expr::UnaryOp { op, node };
+            let binary = |op, left, right| Expr::BinaryOp { op, left, right };
+
+            match self.below(13) {
+                0 => unary(UnaryOperator::Neg, node(self)),
+                1 => unary(UnaryOperator::Sin, node(self)),
+                2 => unary(UnaryOperator::Percent, node(self)),
+                3 => Expr::Paren(node(self)),
+                4 => Expr::AbsVal(node(self)),
+                5 => unary(UnaryOperator::Factorial, Box::new(small(self))),
+                6 => binary(
+                    BinaryOperator::Gcd,
+                    Box::new(small(self)),
+                    Box::new(small(self)),
+                ),
+                7 => unary(UnaryOperator::BitNot, node(self)),
+                _ => {
+                    let op = [
+                        BinaryOperator::Add,
+                        BinaryOperator::Subtract,
+                        BinaryOperator::Multiply,
+                        BinaryOperator::Divide,
+                        BinaryOperator::Pow,
+                        BinaryOperator::Lt,
+                        BinaryOperator::Eq,
+                        BinaryOperator::And,
+                        BinaryOperator::Shl,
+                        BinaryOperator::Mod,
+                    ][self.below(10) as usize]
+                        .clone();
+                    let left = self.expr(depth - 1);
+                    let right = self.expr(depth - 1);
+                    binary(op, Box::new(left), Box::new(right))
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn display_round_trips() {
+        let mut executor = Evaluator::default();
+        executor.set('x', Expr::Real(1.5));
+        executor.set('y', Expr::Integer(-2));
+
+        let mut rng = Rng(0x5eed_c4a1);
+        for _ in 0..500 {
+            let expr = rng.expr(4);
+            let shown = expr.to_string();
+            let tokens = shown.tokenize().expect("Tokenize");
+            let reparsed = Parser::new(tokens)
+                .parse()
+                .unwrap_or_else(|_| panic!("`{shown}` should parse"));
+
+            let original = executor.exec(&expr).map(|res| res.to_string());
+            let reread = executor.exec(&reparsed).map(|res| res.to_string());
+            assert_eq!(original, reread, "`{shown}` from {expr:?}");
+            assert_eq!(reparsed.to_string(), shown);
+        }
+    }
+
+    #[test]
+    fn display_parenthesizes_by_precedence() {
+        let product = (Expr::int(1) + 2) * 3;
+        assert_eq!(product.to_string(), "(1 + 2) * 3");
+
+        let negated = -Expr::unary(UnaryOperator::Factorial, 3);
+        assert_eq!(negated.to_string(), "-(3!)");
+
+        let tokens = "(1 + 2) * ((3)) - 4 / (5 - 6)"
+            .tokenize()
+            .expect("Tokenize");
+        let ast = Parser::new(tokens).parse().expect("Parse");
+        assert_eq!(ast.to_string(), "(1 + 2) * ((3)) - 4 / (5 - 6)");
+    }
+
+    #[test]
+    fn bitwise_operators() {
+        for (source, shown) in [
+            ("1 << 2 + 3", "1 << 2 + 3"),
+            ("(1 << 2) + 3", "(1 << 2) + 3"),
+            ("x >> 1 << 2", "x >> 1 << 2"),
+            ("x >> (1 << 2)", "x >> (1 << 2)"),
+            ("1 << 3 == 8", "1 << 3 == 8"),
+            ("x ~= ~y", "x ~= ~y"),
+            ("~x^2", "~x ^ 2"),
+            ("shl(x, 1)", "x << 1"),
+            ("bnot(band(x, 3))", "~band(x, 3)"),
+            ("bxor(bor(1, 2), 3)", "bxor(bor(1, 2), 3)"),
+        ] {
+            let tokens = source.tokenize().expect("Tokenize");
+            let ast = Parser::new(tokens).parse().expect("Parse");
+            assert_eq!(ast.to_string(), shown, "{source}");
+        }
+
+        let tokens = "1 < < 2".tokenize().expect("Tokenize");
+        assert!(Parser::new(tokens).parse().is_err());
+    }
+
+    #[test]
+    fn lists() {
+        for (source, shown) in [
+            ("[1, x, 2 + 3]", "[1, x, 2 + 3]"),
+            ("[]", "[]"),
+            ("[[1], [2, 3]]", "[[1], [2, 3]]"),
+            ("mean([1, 2], 3)", "mean([1, 2], 3)"),
+            ("sum(i, 1, 10, i^2)", "sum(i, 1, 10, i ^ 2)"),
+            ("sum(1, 2, 3)", "sum(1, 2, 3)"),
+            ("sum([i, 1, 10, i^2])", "sum([i, 1, 10, i ^ 2])"),
+            ("sum((x), 1, 2, 3)", "sum((x), 1, 2, 3)"),
+            ("x[2]^2", "x[2] ^ 2"),
+            ("x[i + 1][2]!", "x[i + 1][2]!"),
+            ("range(1, 9, 2)[n]", "range(1, 9, 2)[n]"),
+            ("[1, 2][1]", "[1, 2][1]"),
+        ] {
+            let tokens = source.tokenize().expect("Tokenize");
+            let ast = Parser::new(tokens).parse().expect("Parse");
+            assert_eq!(ast.to_string(), shown, "{source}");
+        }
+
+        // A call to `sum` that looks like a series still reads back as a call
+        let call = Expr::FunctionCall {
+            function: Function::Sum,
+            args: vec![
+                Expr::Variable('x'),
+                Expr::Integer(1),
+                Expr::Integer(2),
+                Expr::Integer(3),
+            ],
+        };
+        let shown = call.to_string();
+        let tokens = shown.tokenize().expect("Tokenize");
+        let reparsed = Parser::new(tokens).parse().expect("Parse");
+        assert!(matches!(reparsed, Expr::FunctionCall { .. }), "{reparsed}");
+
+        for invalid in [
+            "[1, 2",
+            "[1,]",
+            "mean()",
+            "prod(1, 2)",
+            "gcd([1, 2])",
+            "x[]",
+            "x[1, 2]",
+            "range(1)",
+            "range(1, 2, 3, 4)",
+        ] {
+            let tokens = invalid.tokenize().expect("Tokenize");
+            assert!(Parser::new(tokens).parse().is_err(), "{invalid}");
+        }
+    }
+
+    #[cfg(feature = "units")]
+    #[test]
+    fn units_and_conversions() {
+        for source in [
+            "3 km + 200 m in m",
+            "60 mi/h * 2 h",
+            "x in km/h == 2 m/s",
+            "-2.5 m^2 / (3 s) ^ 2",
+            "2 ^ 3 kg",
+        ] {
+            let tokens = source.tokenize().expect("Tokenize");
+            let ast = Parser::new(tokens).parse().expect("Parse");
+            assert_eq!(ast.to_string(), source);
+        }
+
+        let tokens = "100 km / 2 h".tokenize().expect("Tokenize");
+        let ast = Parser::new(tokens).parse().expect("Parse");
+        assert_eq!(
+            ast.pretty(),
+            "BinaryOp Divide\n  BinaryOp Multiply\n    Integer 100\n    Unit km\n  BinaryOp Multiply\n    Integer 2\n    Unit h"
+        );
+
+        for invalid in ["x in", "x in frob", "x in km*m", "x in m^y"] {
+            let tokens = invalid.tokenize().expect("Tokenize");
+            assert!(Parser::new(tokens).parse().is_err(), "{invalid}");
+        }
+    }
+
+    #[test]
+    fn constants_and_sqrt() {
+        let tokens = "2pi == 2 * pi && sqrt(9) == 3"
+            .tokenize()
+            .expect("Tokenize");
+        let ast = Parser::new(tokens).parse().expect("Parse");
+
+        let mut executor = Evaluator::default();
+        assert_eq!(
+            executor.exec(&ast).expect("Execute"),
+            EvalResult::Bool(true)
+        );
+        assert_eq!(Expr::Constant(Constant::Pi).to_string(), "pi".to_string());
+    }
+
+    #[test]
+    fn function_calls() {
+        let parse =
+            |expression: &str| Parser::new(expression.tokenize().expect("Tokenize")).parse();
+
+        let ast = parse("powmod(2, 1 + 9, 10^3)").expect("Parse");
+        assert_eq!(ast.to_string(), "powmod(2, 1 + 9, 10 ^ 3)");
+        assert_eq!(ast.pretty().lines().next(), Some("FunctionCall powmod"));
+
+        assert_eq!(
+            parse("invmod(3, 7)"),
+            Ok(Expr::FunctionCall {
+                function: Function::InvMod,
+                args: vec![Expr::Integer(3), Expr::Integer(7)],
+            })
+        );
+
+        for wrong_arity in ["powmod(2, 3)", "invmod()", "invmod(1, 2, 3)", "invmod(1,)"] {
+            assert!(parse(wrong_arity).is_err(), "{wrong_arity}");
+        }
+    }
+
+    #[test]
+    fn compound_assignment() {
+        let parse = |expression: &str| {
+            Parser::new(expression.tokenize().expect("Tokenize"))
+                .parse()
+                .map(|ast| ast.to_string())
+        };
+
+        for (expression, shown) in [
+            ("x += 1", "x = x + 1"),
+            ("x -= y", "x = x - y"),
+            ("x *= 1 + 2", "x = x * (1 + 2)"),
+            ("x /= 2", "x = x / 2"),
+            ("y = (x += 1) * 2", "y = (x = x + 1) * 2"),
+        ] {
+            assert_eq!(parse(expression), Ok(shown.to_string()), "{expression}");
+        }
+
+        // Only a variable can be updated
+        for expression in ["2 += 1", "x + 1 += 1", "sin += 1", "x += "] {
+            assert!(parse(expression).is_err(), "{expression}");
+        }
+    }
+
+    #[test]
+    fn calls_without_parentheses() {
+        let parse = |expression: &str| {
+            Parser::new(expression.tokenize().expect("Tokenize"))
+                .parse()
+                .map(|ast| ast.to_string())
+        };
+
+        for (expression, shown) in [
+            ("sin 0.5", "sin(0.5)"),
+            ("sqrt 2", "sqrt(2)"),
+            ("sin x^2", "sin(x ^ 2)"),
+            ("sqrt 4!", "sqrt(4!)"),
+            ("sin 2 * x", "sin(2) * x"),
+            ("sin x / 2", "sin(x) / 2"),
+            ("sin x + 1", "sin(x) + 1"),
+            ("2 sin x", "2 * sin(x)"),
+            ("sin -x", "sin(-x)"),
+            ("sin cos x", "sin(cos(x))"),
+            ("ln pi", "ln(pi)"),
+            ("sin 30°", "sin(30°)"),
+            ("sin(2)x", "sin(2) * x"),
+            ("sin x == 0", "sin(x) == 0"),
+        ] {
+            assert_eq!(parse(expression).as_deref(), Ok(shown), "{expression}");
+        }
+
+        // Implicit products after the argument are ambiguous, and binary functions need parentheses
+        for ambiguous in [
+            "sin 2x",
+            "sin xy",
+            "sin x y",
+            "sin 2 (3)",
+            "sin x cos x",
+            "gcd 4, 6",
+            "atan2 1",
+            "sin",
+        ] {
+            assert!(parse(ambiguous).is_err(), "{ambiguous}");
+        }
+    }
+
+    #[test]
+    fn missing_eof() {
+        assert!(Parser::new(vec![]).parse().is_err());
+        assert!(
+            Parser::new(vec![Token::Integer(1), Token::Plus])
+                .parse()
+                .is_err()
+        );
+        assert_eq!(
+            Parser::new(vec![Token::Integer(1)]).parse(),
+            Ok(Expr::Integer(1))
+        );
+    }
+
+    #[test]
+    fn substituting() {
+        let parse = |expression: &str| {
+            Parser::new(expression.tokenize().expect("Tokenize"))
+                .parse()
+                .expect("Parse")
+        };
+        let substituted = |expression, replacement| {
+            parse(expression)
+                .substitute('x', &parse(replacement))
+                .to_string()
+        };
+
+        assert_eq!(substituted("x^2 + x", "(y+1)"), "(y + 1) ^ 2 + (y + 1)");
+        assert_eq!(substituted("x + x^2", "y+1"), "y + 1 + (y + 1) ^ 2");
+        assert_eq!(substituted("2x - y", "y - 1"), "2 * (y - 1) - y");
+        assert_eq!(
+            substituted("sum(x, 1, x, x) + solve(x == 2, x)", "n"),
+            "sum(x, 1, n, x) + solve(x == 2, x)",
+            "bound variables are left alone"
+        );
+        assert_eq!(substituted("y * z", "2"), "y * z");
+
+        for (expression, replacement) in [("x^2 + x", "y + 1"), ("2 / x - x!", "y * 2")] {
+            let mut bound = Evaluator::default();
+            bound.set('y', Expr::Integer(3));
+            bound.set('x', parse(replacement));
+
+            let mut unbound = Evaluator::default();
+            unbound.set('y', Expr::Integer(3));
+            assert_eq!(
+                unbound.exec(&parse(expression).substitute('x', &parse(replacement))),
+                bound.exec(&parse(expression)),
+                "{expression}"
+            );
+        }
+    }
+
     #[test]
     fn evaluating_ast() {
         let test = Expr::Paren(Box::new(Expr::BinaryOp {
@@ -561,6 +2292,42 @@ mod tests {
         assert_eq!(printed, "(1 + 2.5)")
     }
 
+    #[test]
+    fn pretty_power_chain() {
+        let tokens = "2 ^ 3 ^ 2".tokenize().expect("Tokenize stream");
+        let ast = Parser::new(tokens).parse().expect("Failed to parse");
+
+        assert_eq!(
+            ast.pretty(),
+            "BinaryOp Pow\n  BinaryOp Pow\n    Integer 2\n    Integer 3\n  Integer 2"
+        );
+    }
+
+    #[test]
+    fn pretty_nesting() {
+        let tokens = "y = -|x| + floor(2.5)!"
+            .tokenize()
+            .expect("Tokenize stream");
+        let ast = Parser::new(tokens).parse().expect("Failed to parse");
+
+        let expected = "\
+Assignment y
+  BinaryOp Add
+    UnaryOp Neg
+      AbsVal
+        Variable x
+    UnaryOp Factorial
+      UnaryOp Floor
+        Real 2.5";
+
+        assert_eq!(ast.pretty(), expected);
+    }
+
+    #[test]
+    fn pretty_leaf() {
+        assert_eq!(Expr::Bool(true).pretty(), "Bool true");
+    }
+
     #[test]
     fn full_run_through() {
         let tokens = "1 + 1 - (2 * 4)".tokenize().expect("Tokenize stream");
@@ -598,6 +2365,94 @@ mod tests {
         assert_eq!(executor.exec(&ast).expect("Eval"), EvalResult::Float(6.0));
     }
 
+    #[test]
+    fn adjacent_absolute_values() {
+        let run = |executor: &mut Evaluator, expression: &str| {
+            let tokens = expression.tokenize_spanned().expect("Tokenize");
+            let ast = Parser::with_spans(tokens)
+                .parse()
+                .unwrap_or_else(|_| panic!("`{expression}` should parse"));
+            executor.exec(&ast).expect("Eval")
+        };
+
+        let mut executor = Evaluator::default();
+        assert!(matches!(
+            run(&mut executor, "|3 - 5|"),
+            EvalResult::Integer(2)
+        ));
+
+        executor.set('a', Expr::Integer(-3));
+        executor.set('b', Expr::Integer(4));
+        assert!(matches!(
+            run(&mut executor, "|a| * |b|"),
+            EvalResult::Integer(12)
+        ));
+        assert!(matches!(
+            run(&mut executor, "|a|*|b|"),
+            EvalResult::Integer(12)
+        ));
+
+        executor.set('x', Expr::Real(0.5));
+        executor.set('y', Expr::Integer(-2));
+        assert_eq!(
+            run(&mut executor, "|x| > 1 || |y| > 1"),
+            EvalResult::Bool(true)
+        );
+        assert_eq!(run(&mut executor, "|x|>1||y>1"), EvalResult::Bool(false));
+
+        // `||` opening or closing two absolute values at once
+        assert_eq!(run(&mut executor, "||y| - 5|"), EvalResult::Integer(3));
+        assert_eq!(run(&mut executor, "|1 - |y||"), EvalResult::Integer(1));
+        assert_eq!(run(&mut executor, "||y| - |b||"), EvalResult::Integer(2));
+
+        // Grouping brings back ors inside absolute values
+        let tokens = "|(x > 1 || y > 1)|".tokenize().expect("Tokenize");
+        assert!(Parser::new(tokens).parse().is_ok());
+        let tokens = "|x > 1 || y > 1|".tokenize().expect("Tokenize");
+        assert!(Parser::new(tokens).parse().is_err());
+    }
+
+    #[test]
+    fn implicit_multiplication() {
+        let mut executor = Evaluator::default();
+        executor.set('x', Expr::Integer(3));
+        executor.set('y', Expr::Integer(4));
+
+        let cases = [
+            ("3x", "3 * x", 9.0),
+            ("3x^2", "3 * x ^ 2", 27.0),
+            ("x^2y", "x ^ 2 * y", 36.0),
+            ("(1+1)(2+2)", "(1 + 1) * (2 + 2)", 8.0),
+            ("2(x+1)(x-1)", "2 * (x + 1) * (x - 1)", 16.0),
+            ("2cos(0)", "2 * cos(0)", 2.0),
+            ("xy", "x * y", 12.0),
+            ("2xy", "2 * (x * y)", 24.0),
+            ("x y", "x * y", 12.0),
+            ("(2)3", "(2) * 3", 6.0),
+            ("x!y", "x! * y", 24.0),
+            ("2 - 3", "2 - 3", -1.0),
+            ("2 -3x", "2 - 3 * x", -7.0),
+            ("-2x", "-2 * x", -6.0),
+        ];
+
+        for (expression, shown, expected) in cases {
+            let tokens = expression.tokenize().expect("Tokenize");
+            let ast = Parser::new(tokens)
+                .parse()
+                .unwrap_or_else(|_| panic!("`{expression}` should parse"));
+            assert_eq!(ast.to_string(), shown, "{expression}");
+            assert_eq!(
+                executor.exec(&ast).expect("Eval"),
+                EvalResult::Float(expected),
+                "{expression}"
+            );
+        }
+
+        // Unknown words followed by arguments are still errors rather than products
+        let tokens = "sinn(x)".tokenize().expect("Tokenize");
+        assert!(Parser::new(tokens).parse().is_err());
+    }
+
     #[test]
     fn exponentiation_simple() {
         let tokens = "3 ^ 2".tokenize().expect("Tokenize stream");
@@ -663,6 +2518,50 @@ mod tests {
         assert_eq!(executor.exec(&ast).expect("Eval"), EvalResult::Integer(60));
     }
 
+    #[test]
+    fn degrees() {
+        let tokens = "sin(90°)".tokenize().expect("Tokenize stream");
+        let mut parser = Parser::new(tokens);
+        let ast = parser.parse().expect("Failed to parse");
+        let mut executor = Evaluator::default();
+        let res = executor.exec(&ast).expect("Eval").float().expect("Float");
+        assert!((res - 1.0).abs() < 1e-6);
+    }
+
+    #[test]
+    fn percent() {
+        let tokens = "50% * 200 == 100".tokenize().expect("Tokenize stream");
+        let mut parser = Parser::new(tokens);
+        let ast = parser.parse().expect("Failed to parse");
+        let mut executor = Evaluator::default();
+        assert_eq!(executor.exec(&ast).expect("Eval"), EvalResult::Bool(true));
+        assert_eq!(format!("{ast}"), "50% * 200 == 100");
+    }
+
+    #[test]
+    fn modulo() {
+        let cases = [
+            ("10 % 3", EvalResult::Integer(1)),
+            ("-7 % 3", EvalResult::Integer(2)),
+            ("7 % (-3)", EvalResult::Integer(-2)),
+            ("7.5 % 2", EvalResult::Rational(3, 2)),
+            ("mod(10, 4)", EvalResult::Integer(2)),
+            ("50% - 1", EvalResult::Rational(-1, 2)),
+        ];
+        for (source, expected) in cases {
+            let tokens = source.tokenize().expect("Tokenize stream");
+            let mut parser = Parser::new(tokens);
+            let ast = parser.parse().expect("Failed to parse");
+            let mut executor = Evaluator::default();
+            assert_eq!(executor.exec(&ast).expect("Eval"), expected, "{source}");
+        }
+
+        let tokens = "10 % 3 * 2".tokenize().expect("Tokenize stream");
+        let mut parser = Parser::new(tokens);
+        let ast = parser.parse().expect("Failed to parse");
+        assert_eq!(format!("{ast}"), "10 % 3 * 2");
+    }
+
     #[test]
     fn floor() {
         let tokens = "floor(2 - 0.0001)".tokenize().expect("Tokenize stream");
@@ -759,4 +2658,39 @@ mod tests {
 
         assert_eq!(executor.exec(&ast).expect("Eval"), EvalResult::Integer(100));
     }
+
+    #[test]
+    fn nested_assign() {
+        let tokens = "a = (b = 2) * 3".tokenize().expect("Tokenize stream");
+        let ast = Parser::new(tokens).parse().expect("Failed to parse");
+        assert_eq!(
+            ast,
+            Expr::Assignment(
+                'a',
+                Box::new(Expr::mul(
+                    Expr::Paren(Box::new(Expr::Assignment('b', Box::new(Expr::Integer(2))))),
+                    3
+                ))
+            )
+        );
+        assert_eq!(ast.to_string(), "a = (b = 2) * 3");
+
+        let mut executor = Evaluator::default();
+        assert_eq!(executor.exec(&ast), Ok(EvalResult::Integer(6)));
+        assert_eq!(executor.get('b'), Some(&Expr::Integer(2)));
+        assert_eq!(executor.value('a'), Some(Ok(EvalResult::Integer(6))));
+
+        // Binding a variable isn't depending on it
+        assert!(!executor.depends_on(&ast, 'b'));
+        let tokens = "(c = d + 1) + 1".tokenize().expect("Tokenize stream");
+        let ast = Parser::new(tokens).parse().expect("Failed to parse");
+        assert!(executor.depends_on(&ast, 'd'));
+        assert!(!executor.depends_on(&ast, 'c'));
+
+        // Only parentheses hold assignments, anywhere else `=` is still an error
+        for source in ["a = b = 2", "1 + b = 2", "sqrt(b = 2)", "(1 = 2)"] {
+            let tokens = source.tokenize().expect("Tokenize stream");
+            assert!(Parser::new(tokens).parse().is_err(), "{source}");
+        }
+    }
 }
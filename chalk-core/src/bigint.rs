@@ -0,0 +1,276 @@
+//! Arbitrary precision integers, taking over once integer results outgrow an `i64`
+
+use std::cmp::Ordering;
+
+use num_bigint::BigInt;
+use num_integer::Integer;
+use num_traits::{Signed, ToPrimitive, Zero};
+
+use crate::{ast::BinaryOperator, exec::EvalResult, math::rational};
+
+/// Most bits an exact power may have, anything larger is left to floats
+const MAX_POWER_BITS: u64 = 1 << 20;
+/// Largest factorial computed exactly, anything larger is left to floats
+const MAX_FACTORIAL: u64 = 10_000;
+
+impl EvalResult {
+    /// Wraps a big integer, narrowing it back to an `Integer` if it fits
+    pub fn big(value: BigInt) -> Self {
+        value.to_i64().map_or(Self::BigInt(value), Self::Integer)
+    }
+
+    /// The result as a big integer, if it's a whole number
+    fn as_big(&self) -> Option<BigInt> {
+        match self {
+            Self::Integer(i) => Some(BigInt::from(*i)),
+            Self::BigInt(big) => Some(big.clone()),
+            _ => None,
+        }
+    }
+
+    /// The result as a big fraction, if it's a finite number. Floats are converted exactly
+    fn big_fraction(&self) -> Option<(BigInt, BigInt)> {
+        match self {
+            Self::Rational(numerator, denominator) => {
+                Some((BigInt::from(*numerator), BigInt::from(*denominator)))
+            }
+            Self::Float(f) if f.is_finite() => {
+                let (mantissa, exponent) = rational::decompose(*f);
+                let mantissa = BigInt::from(mantissa);
+                if exponent >= 0 {
+                    Some((mantissa << exponent, BigInt::from(1)))
+                } else {
+                    Some((mantissa, BigInt::from(1) << exponent.unsigned_abs()))
+                }
+            }
+            _ => Some((self.as_big()?, BigInt::from(1))),
+        }
+    }
+}
+
+/// A big integer's nearest float, infinite past the range of an `f64`
+pub(crate) fn to_float(big: &BigInt) -> f64 {
+    big.to_f64().unwrap_or(if big.is_negative() {
+        f64::NEG_INFINITY
+    } else {
+        f64::INFINITY
+    })
+}
+
+/// Applies a binary operation to two whole numbers with arbitrary precision, if it has an exact
+/// whole result. `gcd` and `lcm` are only taken over once an operand is already big
+pub(crate) fn binary(
+    op: &BinaryOperator,
+    left: &EvalResult,
+    right: &EvalResult,
+) -> Option<EvalResult> {
+    let any_big = matches!(left, EvalResult::BigInt(_)) || matches!(right, EvalResult::BigInt(_));
+    let handled = match op {
+        BinaryOperator::Add
+        | BinaryOperator::Subtract
+        | BinaryOperator::Multiply
+        | BinaryOperator::Divide
+        | BinaryOperator::Mod
+        | BinaryOperator::Pow => true,
+        BinaryOperator::Gcd | BinaryOperator::Lcm => any_big,
+        _ => false,
+    };
+    if !handled {
+        return None;
+    }
+
+    let (left, right) = (left.as_big()?, right.as_big()?);
+    let result = match op {
+        BinaryOperator::Add => left + right,
+        BinaryOperator::Subtract => left - right,
+        BinaryOperator::Multiply => left * right,
+        BinaryOperator::Divide => {
+            if right.is_zero() {
+                return None;
+            }
+            let (quotient, remainder) = left.div_rem(&right);
+            if !remainder.is_zero() {
+                return None;
+            }
+            quotient
+        }
+        BinaryOperator::Mod => {
+            if right.is_zero() {
+                return None;
+            }
+            left.mod_floor(&right)
+        }
+        BinaryOperator::Pow => {
+            let exponent = right.to_u32()?;
+            if left.bits().saturating_mul(u64::from(exponent)) > MAX_POWER_BITS {
+                return None;
+            }
+            left.pow(exponent)
+        }
+        _ if left.is_negative() || right.is_negative() => return None,
+        BinaryOperator::Gcd => left.gcd(&right),
+        _ => left.lcm(&right),
+    };
+
+    Some(EvalResult::big(result))
+}
+
+/// The least common multiple of two integers whose product overflows a `u64`
+pub(crate) fn lcm(a: u64, b: u64) -> EvalResult {
+    EvalResult::big(BigInt::from(a).lcm(&BigInt::from(b)))
+}
+
+/// Negates a whole number whose negation doesn't fit an `i64`
+pub(crate) fn negate(value: &EvalResult) -> Option<EvalResult> {
+    match value {
+        EvalResult::Integer(i64::MIN) | EvalResult::BigInt(_) => {
+            Some(EvalResult::big(-value.as_big()?))
+        }
+        _ => None,
+    }
+}
+
+/// The absolute value of a whole number whose absolute value doesn't fit an `i64`
+pub(crate) fn abs(value: &EvalResult) -> Option<EvalResult> {
+    match value {
+        EvalResult::Integer(i64::MIN) | EvalResult::BigInt(_) => {
+            Some(EvalResult::big(value.as_big()?.abs()))
+        }
+        _ => None,
+    }
+}
+
+/// `n!` computed exactly, if it isn't unreasonably large
+pub(crate) fn factorial(n: u64) -> Option<EvalResult> {
+    if n > MAX_FACTORIAL {
+        return None;
+    }
+
+    Some(EvalResult::big((1..=n).map(BigInt::from).product()))
+}
+
+/// Orders two exact results when either is big
+pub(crate) fn cmp(left: &EvalResult, right: &EvalResult) -> Option<Ordering> {
+    if !matches!(left, EvalResult::BigInt(_)) && !matches!(right, EvalResult::BigInt(_)) {
+        return None;
+    }
+
+    // Infinities are past every big integer
+    match (left, right) {
+        (EvalResult::Float(f), _) if f.is_infinite() => return Some(f.total_cmp(&0.0)),
+        (_, EvalResult::Float(f)) if f.is_infinite() => return Some(0f64.total_cmp(f)),
+        _ => {}
+    }
+
+    let (ln, ld) = left.big_fraction()?;
+    let (rn, rd) = right.big_fraction()?;
+    Some((ln * rd).cmp(&(rn * ld)))
+}
+
+/// Big integers as decimal strings in serialized results, rather than the limbs they're stored
+/// in, which mean nothing to a JSON consumer
+#[cfg(feature = "serde")]
+pub(crate) mod decimal {
+    use num_bigint::BigInt;
+    use serde::{Deserialize, Deserializer, Serializer, de::Error};
+
+    /// Writes a big integer as a decimal string
+    pub fn serialize<S: Serializer>(big: &BigInt, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_str(big)
+    }
+
+    /// Reads a big integer back from a decimal string
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<BigInt, D::Error> {
+        String::deserialize(deserializer)?
+            .parse()
+            .map_err(D::Error::custom)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use num_bigint::BigInt;
+
+    use crate::{eval_str, exec::EvalResult};
+
+    /// A big integer from its decimal digits
+    fn big(digits: &str) -> EvalResult {
+        EvalResult::BigInt(digits.parse::<BigInt>().expect("Digits"))
+    }
+
+    #[test]
+    fn promotes_on_overflow() {
+        assert_eq!(
+            eval_str("50!"),
+            Ok(big(
+                "30414093201713378043612608166064768844377641568960512000000000000"
+            ))
+        );
+        assert_eq!(
+            eval_str("2^128"),
+            Ok(big("340282366920938463463374607431768211456"))
+        );
+        assert_eq!(
+            eval_str("2^128").expect("Evaluate").to_string(),
+            "340282366920938463463374607431768211456"
+        );
+        assert_eq!(
+            eval_str("9223372036854775807 + 1"),
+            Ok(big("9223372036854775808"))
+        );
+        assert_eq!(eval_str("-(2^63) * -1"), Ok(big("9223372036854775808")));
+    }
+
+    #[test]
+    fn narrows_back_down() {
+        assert_eq!(eval_str("2^70 / 2^65"), Ok(EvalResult::Integer(32)));
+        assert_eq!(eval_str("2^70 - 2^70"), Ok(EvalResult::Integer(0)));
+        assert_eq!(eval_str("gcd(2^70, 2^65)"), Ok(big("36893488147419103232")));
+        assert_eq!(eval_str("lcm(2^70, 3)"), Ok(big("3541774862152233910272")));
+        assert_eq!(eval_str("2^70 % 3"), Ok(EvalResult::Integer(1)));
+        assert_eq!(eval_str("-(2^70) % 3"), Ok(EvalResult::Integer(2)));
+    }
+
+    #[test]
+    fn comparisons_and_floats() {
+        assert_eq!(eval_str("2^100 > 2^99"), Ok(EvalResult::Bool(true)));
+        assert_eq!(eval_str("2^100 == 2^100"), Ok(EvalResult::Bool(true)));
+        assert_eq!(eval_str("2^64 > 1/3"), Ok(EvalResult::Bool(true)));
+        assert_eq!(
+            eval_str("2^64 < 99999999999999999999.5"),
+            Ok(EvalResult::Bool(true))
+        );
+        assert_eq!(
+            eval_str("2^64 == 18446744073709551616.0"),
+            Ok(EvalResult::Bool(true))
+        );
+        assert_eq!(
+            eval_str("2^64 + 1 > 18446744073709551616.0"),
+            Ok(EvalResult::Bool(true))
+        );
+        assert_eq!(
+            eval_str("2^64 + 1 == 18446744073709551616.0"),
+            Ok(EvalResult::Bool(false))
+        );
+        assert_eq!(eval_str("2^2000 < 1/0"), Ok(EvalResult::Bool(true)));
+        assert_eq!(eval_str("-(2^2000) > -1/0"), Ok(EvalResult::Bool(true)));
+
+        // Anything inexact loses precision by going through a float
+        assert_eq!(
+            eval_str("2^100 / 3"),
+            Ok(EvalResult::Float(2f64.powi(100) / 3.0))
+        );
+        assert_eq!(
+            eval_str("2^100 + 0.5"),
+            Ok(EvalResult::Float(2f64.powi(100)))
+        );
+        assert_eq!(
+            eval_str("sqrt(2^128)"),
+            Ok(EvalResult::Float(2f64.powi(64)))
+        );
+        assert_eq!(
+            eval_str("2^2000 * 1.0"),
+            Ok(EvalResult::Float(f64::INFINITY))
+        );
+    }
+}
@@ -0,0 +1,214 @@
+//! Ergonomic construction of expressions, the supported way for library users to build trees
+//! without going through the parser. Every constructor takes anything that converts into an
+//! `Expr`, and the arithmetic operators are overloaded, so
+//! `Expr::var('x') * 2 + 1` is the same tree as parsing `x * 2 + 1`:
+//!
+//! ```
+//! use chalk_core::ast::Expr;
+//!
+//! let expr = Expr::pow(Expr::var('x'), 2) - Expr::var('x') * 3 + 1.5;
+//! assert_eq!(expr.to_string(), "x ^ 2 - x * 3 + 1.5");
+//! ```
+//!
+//! No parentheses are added to the tree, displaying it adds whatever precedence needs
+
+use std::ops::{Add, Div, Mul, Neg, Sub};
+
+use crate::ast::{BinaryOperator, Constant, Expr, UnaryOperator};
+
+impl Expr {
+    /// An integer literal
+    pub fn int(i: i64) -> Self {
+        Expr::Integer(i)
+    }
+
+    /// A real literal
+    pub fn real(f: f64) -> Self {
+        Expr::Real(f)
+    }
+
+    /// A variable
+    pub fn var(var: char) -> Self {
+        Expr::Variable(var)
+    }
+
+    /// A named constant
+    pub fn constant(constant: Constant) -> Self {
+        Expr::Constant(constant)
+    }
+
+    /// Any binary operation
+    pub fn binary(op: BinaryOperator, left: impl Into<Expr>, right: impl Into<Expr>) -> Self {
+        Expr::BinaryOp {
+            op,
+            left: Box::new(left.into()),
+            right: Box::new(right.into()),
+        }
+    }
+
+    /// Any unary operation, including functions of one argument like `sin`
+    pub fn unary(op: UnaryOperator, node: impl Into<Expr>) -> Self {
+        Expr::UnaryOp {
+            op,
+            node: Box::new(node.into()),
+        }
+    }
+
+    /// `left + right`
+    pub fn add(left: impl Into<Expr>, right: impl Into<Expr>) -> Self {
+        Self::binary(BinaryOperator::Add, left, right)
+    }
+
+    /// `left - right`
+    pub fn sub(left: impl Into<Expr>, right: impl Into<Expr>) -> Self {
+        Self::binary(BinaryOperator::Subtract, left, right)
+    }
+
+    /// `left * right`
+    pub fn mul(left: impl Into<Expr>, right: impl Into<Expr>) -> Self {
+        Self::binary(BinaryOperator::Multiply, left, right)
+    }
+
+    /// `left / right`
+    pub fn div(left: impl Into<Expr>, right: impl Into<Expr>) -> Self {
+        Self::binary(BinaryOperator::Divide, left, right)
+    }
+
+    /// `base ^ exponent`
+    pub fn pow(base: impl Into<Expr>, exponent: impl Into<Expr>) -> Self {
+        Self::binary(BinaryOperator::Pow, base, exponent)
+    }
+
+    /// `-node`
+    pub fn neg(node: impl Into<Expr>) -> Self {
+        Self::unary(UnaryOperator::Neg, node)
+    }
+
+    /// `(node)`, only needed to keep parentheses the parser would have kept
+    pub fn paren(node: impl Into<Expr>) -> Self {
+        Expr::Paren(Box::new(node.into()))
+    }
+
+    /// `|node|`
+    pub fn abs(node: impl Into<Expr>) -> Self {
+        Expr::AbsVal(Box::new(node.into()))
+    }
+
+    /// `var = node`
+    pub fn assign(var: char, node: impl Into<Expr>) -> Self {
+        Expr::Assignment(var, Box::new(node.into()))
+    }
+}
+
+impl From<i64> for Expr {
+    fn from(i: i64) -> Self {
+        Expr::Integer(i)
+    }
+}
+
+impl From<i32> for Expr {
+    fn from(i: i32) -> Self {
+        Expr::Integer(i64::from(i))
+    }
+}
+
+impl From<f64> for Expr {
+    fn from(f: f64) -> Self {
+        Expr::Real(f)
+    }
+}
+
+impl From<f32> for Expr {
+    fn from(f: f32) -> Self {
+        Expr::Real(f64::from(f))
+    }
+}
+
+impl From<bool> for Expr {
+    fn from(b: bool) -> Self {
+        Expr::Bool(b)
+    }
+}
+
+impl<R: Into<Expr>> Add<R> for Expr {
+    type Output = Expr;
+    fn add(self, rhs: R) -> Self::Output {
+        Expr::binary(BinaryOperator::Add, self, rhs)
+    }
+}
+
+impl<R: Into<Expr>> Sub<R> for Expr {
+    type Output = Expr;
+    fn sub(self, rhs: R) -> Self::Output {
+        Expr::binary(BinaryOperator::Subtract, self, rhs)
+    }
+}
+
+impl<R: Into<Expr>> Mul<R> for Expr {
+    type Output = Expr;
+    fn mul(self, rhs: R) -> Self::Output {
+        Expr::binary(BinaryOperator::Multiply, self, rhs)
+    }
+}
+
+impl<R: Into<Expr>> Div<R> for Expr {
+    type Output = Expr;
+    fn div(self, rhs: R) -> Self::Output {
+        Expr::binary(BinaryOperator::Divide, self, rhs)
+    }
+}
+
+impl Neg for Expr {
+    type Output = Expr;
+    fn neg(self) -> Self::Output {
+        Expr::unary(UnaryOperator::Neg, self)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        ast::{Constant, Expr, UnaryOperator},
+        exec::{EvalResult, Evaluator},
+        pipeline::testing::parse,
+    };
+
+    #[test]
+    fn matches_the_parser() {
+        let x = || Expr::var('x');
+        let cases = [
+            (x() * 2 + 1, "x * 2 + 1"),
+            (Expr::add(1, x()), "1 + x"),
+            (-Expr::paren(x() - 3) / 2.5, "-(x - 3) / 2.5"),
+            (Expr::pow(x(), Expr::int(2)) * 3, "x ^ 2 * 3"),
+            (Expr::abs(Expr::sub(1, x())), "|1 - x|"),
+            (Expr::assign('y', Expr::mul(2, x())), "y = 2 * x"),
+            (
+                Expr::unary(UnaryOperator::Sin, Expr::constant(Constant::Pi) / 2),
+                "sin(pi / 2)",
+            ),
+        ];
+
+        for (built, source) in cases {
+            assert_eq!(built, parse(source), "{source}");
+        }
+    }
+
+    #[test]
+    fn display_adds_needed_parentheses() {
+        let built = (Expr::var('x') + 1) * (Expr::var('x') - 1);
+        assert_eq!(built.to_string(), "(x + 1) * (x - 1)");
+
+        let mut eval = Evaluator::default();
+        eval.set('x', Expr::from(3));
+        assert_eq!(eval.exec(&built), Ok(EvalResult::Integer(8)));
+    }
+
+    #[test]
+    fn conversions() {
+        assert_eq!(Expr::from(3i32), Expr::Integer(3));
+        assert_eq!(Expr::from(0.5f32), Expr::Real(0.5));
+        assert_eq!(Expr::from(true), Expr::Bool(true));
+        assert_eq!(Expr::neg(1.5), -Expr::real(1.5));
+    }
+}
@@ -0,0 +1,207 @@
+//! Remembering what variables evaluate to, so a variable read over and over is only evaluated
+//! again once it or something it depends on is rebound. Values drawn from `rand` or recalled with
+//! `hist` can change between reads, so variables depending on them are never cached
+
+use std::collections::{BTreeSet, HashMap};
+
+use crate::{
+    ast::Expr,
+    exec::{EvalResult, Evaluator},
+};
+
+/// How well the variable cache has been doing
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct CacheStats {
+    /// Reads of a bound variable answered from the cache
+    pub hits: u64,
+    /// Reads of a bound variable that had to evaluate it
+    pub misses: u64,
+    /// How many variables have a value cached right now
+    pub entries: usize,
+}
+
+/// A variable's value along with every variable it was evaluated through
+#[derive(Clone, Debug)]
+struct Cached {
+    /// What the variable evaluated to
+    value: EvalResult,
+    /// The variables, bound or not, that the value depends on
+    dependencies: BTreeSet<char>,
+}
+
+/// Values of variables evaluated since they, or anything they depend on, were last bound
+#[derive(Clone, Debug, Default)]
+pub(crate) struct ValueCache {
+    /// Each cached variable's value
+    values: HashMap<char, Cached>,
+    /// Reads answered from the cache
+    hits: u64,
+    /// Reads that had to evaluate
+    misses: u64,
+}
+
+impl Evaluator {
+    /// How many reads of bound variables the cache has answered and how many it's holding
+    pub fn cache_stats(&self) -> CacheStats {
+        CacheStats {
+            hits: self.cache.hits,
+            misses: self.cache.misses,
+            entries: self.cache.values.len(),
+        }
+    }
+
+    /// The cached value of a bound variable, if it has one. Nothing is read from the cache while
+    /// a trace is taken, so every step of evaluating the variable is still recorded
+    pub(crate) fn cached(&mut self, var: char) -> Option<EvalResult> {
+        if self.tracing() {
+            return None;
+        }
+
+        match self.cache.values.get(&var) {
+            Some(cached) => {
+                self.cache.hits += 1;
+                Some(cached.value.clone())
+            }
+            None => {
+                self.cache.misses += 1;
+                None
+            }
+        }
+    }
+
+    /// Caches what a variable just evaluated to, unless something it goes through is volatile
+    pub(crate) fn cache_value(&mut self, var: char, value: &EvalResult) {
+        let deps = self.dependencies(&Expr::Variable(var));
+        let volatile = deps
+            .bound
+            .iter()
+            .filter_map(|var| self.ctx.get(var))
+            .any(|expr| {
+                let mut volatile = false;
+                expr.walk(&mut |node| {
+                    volatile |= matches!(node, Expr::FunctionCall { function, .. } if function.is_volatile())
+                });
+                volatile
+            });
+        if volatile {
+            return;
+        }
+
+        let dependencies = deps.bound.into_iter().chain(deps.free).collect();
+        self.cache.values.insert(
+            var,
+            Cached {
+                value: value.clone(),
+                dependencies,
+            },
+        );
+    }
+
+    /// Forgets the cached value of a variable that's been rebound, along with those of every
+    /// variable depending on it
+    pub(crate) fn invalidate(&mut self, var: char) {
+        self.cache
+            .values
+            .retain(|cached, entry| *cached != var && !entry.dependencies.contains(&var));
+    }
+
+    /// Forgets every cached value, for when something every value could depend on has changed,
+    /// like the angle mode
+    pub(crate) fn invalidate_all(&mut self) {
+        self.cache.values.clear();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        ast::Expr,
+        exec::{AngleMode, EvalResult, Evaluator, RuntimeError},
+        pipeline::testing::{parse, run},
+    };
+
+    use super::CacheStats;
+
+    #[test]
+    fn invalidating_dependents() {
+        let mut eval = Evaluator::default();
+        eval.set('x', Expr::Integer(2));
+        eval.set('y', Expr::var('x') * Expr::Integer(3));
+        eval.set('z', Expr::Integer(5));
+        eval.set('w', Expr::var('y') + Expr::Integer(1));
+
+        assert_eq!(run(&mut eval, "w + z"), Ok(EvalResult::Integer(12)));
+        assert_eq!(
+            eval.cache_stats(),
+            CacheStats {
+                hits: 0,
+                misses: 4,
+                entries: 4
+            }
+        );
+        assert_eq!(run(&mut eval, "w"), Ok(EvalResult::Integer(7)));
+        assert_eq!(eval.cache_stats().hits, 1);
+
+        // Everything going through x is forgotten, and z isn't
+        run(&mut eval, "x = 10").expect("Rebind");
+        assert_eq!(eval.cache_stats().entries, 1);
+        assert_eq!(eval.value('z'), Some(Ok(EvalResult::Integer(5))));
+        assert_eq!(eval.cache_stats().hits, 2);
+        assert_eq!(eval.value('w'), Some(Ok(EvalResult::Integer(31))));
+        assert_eq!(eval.value('y'), Some(Ok(EvalResult::Integer(30))));
+        assert_eq!(eval.cache_stats().hits, 3);
+
+        eval.remove('x');
+        assert_eq!(eval.value('w'), Some(Err(RuntimeError::Invalid)));
+        eval.set('x', 1.into());
+        assert_eq!(eval.value('w'), Some(Ok(EvalResult::Integer(4))));
+
+        eval.clear();
+        assert_eq!(eval.cache_stats().entries, 0);
+    }
+
+    #[test]
+    fn temporary_bindings() {
+        let mut eval = Evaluator::default();
+        run(&mut eval, "x = 3").expect("Bind");
+        run(&mut eval, "y = x^2").expect("Bind");
+        assert_eq!(eval.value('y'), Some(Ok(EvalResult::Integer(9))));
+
+        // The cached 9 isn't used while x is bound to something else, and what y is then isn't
+        // kept past the binding
+        let y = parse("y");
+        for point in 0..5 {
+            assert_eq!(
+                eval.exec_with(&y, &[('x', EvalResult::Integer(point))]),
+                Ok(EvalResult::Integer(point * point))
+            );
+        }
+        let samples = eval.sample(&y, 'x', 0.0, 2.0, 3).expect("Sample");
+        assert_eq!(samples, [0.0, 1.0, 4.0]);
+        assert_eq!(eval.value('y'), Some(Ok(EvalResult::Integer(9))));
+
+        // Sums bind their index the same way
+        assert_eq!(
+            run(&mut eval, "sum(x, 1, 3, y)"),
+            Ok(EvalResult::Integer(14))
+        );
+        assert_eq!(eval.value('y'), Some(Ok(EvalResult::Integer(9))));
+    }
+
+    #[test]
+    fn uncached_values() {
+        let mut eval = Evaluator::default();
+        eval.seed(7);
+        run(&mut eval, "r = rand()").expect("Bind");
+        run(&mut eval, "s = r + 1").expect("Bind");
+        let first = eval.value('s');
+        assert_ne!(eval.value('s'), first);
+        assert_eq!(eval.cache_stats().entries, 0);
+
+        // Changing the angle mode changes what trig functions give
+        run(&mut eval, "a = sin(90)").expect("Bind");
+        assert_ne!(eval.value('a'), Some(Ok(EvalResult::Float(1.0))));
+        eval.set_angle_mode(AngleMode::Degrees);
+        assert_eq!(eval.value('a'), Some(Ok(EvalResult::Float(1.0))));
+    }
+}
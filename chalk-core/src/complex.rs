@@ -0,0 +1,348 @@
+//! Complex numbers like `3 + 4i`, for when results leave the real line. In complex mode `i` is
+//! the imaginary unit unless it's bound, and operations that have no real result, like
+//! `sqrt(-4)` or `(-1)^0.5`, give their principal complex value instead of failing
+
+use std::{cmp::Ordering, fmt::Formatter};
+
+use crate::{
+    ast::{BinaryOperator, UnaryOperator},
+    exec::{EvalResult, Evaluator, RuntimeError},
+};
+
+/// How small a part may be next to the other, relative to it, before it's taken to be nothing
+/// but representation error
+const NOISE: f64 = 1e-15;
+/// Largest whole exponent raised to by repeated multiplication, which keeps `i^2` exact
+const MAX_EXACT_POWER: f64 = 1024.0;
+
+/// A complex number as `(real, imaginary)`
+type Parts = (f64, f64);
+
+impl EvalResult {
+    /// Wraps a complex number, narrowing it to a float if it's real. A part that's no bigger than
+    /// representation error next to the other is dropped, so `e^(i*pi)` is exactly -1
+    pub fn complex(re: f64, im: f64) -> Self {
+        let noise = re.abs().max(im.abs()) * NOISE;
+        let re = if re.abs() <= noise { 0.0 } else { re };
+        if im.abs() <= noise {
+            Self::Float(re)
+        } else {
+            Self::Complex(re, im)
+        }
+    }
+
+    /// The result as a complex number, if it's a number at all
+    fn parts(&self) -> Option<Parts> {
+        match self {
+            Self::Complex(re, im) => Some((*re, *im)),
+            _ => Some((self.float().ok()?, 0.0)),
+        }
+    }
+}
+
+impl Evaluator {
+    /// Whether `i` is the imaginary unit and operations leaving the reals give complex results
+    pub fn complex(&self) -> bool {
+        self.complex
+    }
+
+    /// Turns complex mode on or off. Complex results already bound to variables are unaffected
+    pub fn set_complex(&mut self, complex: bool) {
+        self.complex = complex;
+        self.invalidate_all();
+    }
+
+    /// In complex mode, turns the operand of a square root or logarithm of a negative number
+    /// complex, so the complex version of the operation takes over
+    pub(crate) fn widen_unary(&self, op: &UnaryOperator, value: EvalResult) -> EvalResult {
+        match (op, value.float()) {
+            (UnaryOperator::Sqrt | UnaryOperator::Ln, Ok(x)) if self.complex && x < 0.0 => {
+                EvalResult::Complex(x, 0.0)
+            }
+            _ => value,
+        }
+    }
+
+    /// In complex mode, turns a negative base raised to a fractional power complex, so it gives
+    /// the principal root rather than failing or picking the real one
+    pub(crate) fn widen_base(
+        &self,
+        op: &BinaryOperator,
+        base: EvalResult,
+        exponent: &EvalResult,
+    ) -> EvalResult {
+        match (op, base.float(), exponent.float()) {
+            (BinaryOperator::Pow, Ok(x), Ok(power))
+                if self.complex && x < 0.0 && power.fract() != 0.0 =>
+            {
+                EvalResult::Complex(x, 0.0)
+            }
+            _ => base,
+        }
+    }
+}
+
+/// Applies an arithmetic operation where either side is complex. Anything but arithmetic is left
+/// to the real versions, which reject complex numbers
+pub(crate) fn binary(
+    op: &BinaryOperator,
+    left: &EvalResult,
+    right: &EvalResult,
+) -> Option<Result<EvalResult, RuntimeError>> {
+    if !is_complex(left) && !is_complex(right) {
+        return None;
+    }
+
+    let (Some(l), Some(r)) = (left.parts(), right.parts()) else {
+        return Some(Err(RuntimeError::Invalid));
+    };
+    let result = match op {
+        BinaryOperator::Add => Ok((l.0 + r.0, l.1 + r.1)),
+        BinaryOperator::Subtract => Ok((l.0 - r.0, l.1 - r.1)),
+        BinaryOperator::Multiply => Ok(mul(l, r)),
+        BinaryOperator::Divide => div(l, r),
+        BinaryOperator::Pow => pow(l, r),
+        _ => return None,
+    };
+
+    Some(result.map(|(re, im)| EvalResult::complex(re, im)))
+}
+
+/// Applies a unary operation to a complex number
+pub(crate) fn unary(op: &UnaryOperator, re: f64, im: f64) -> Result<EvalResult, RuntimeError> {
+    let (re, im) = match op {
+        UnaryOperator::Neg => (-re, -im),
+        UnaryOperator::Sqrt => sqrt((re, im)),
+        UnaryOperator::Ln => ln((re, im)),
+        UnaryOperator::Conj => (re, -im),
+        UnaryOperator::Re => return Ok(EvalResult::Float(re)),
+        UnaryOperator::Im => return Ok(EvalResult::Float(im)),
+        UnaryOperator::Arg => return Ok(EvalResult::Float(im.atan2(re))),
+        _ => return Err(RuntimeError::Invalid),
+    };
+
+    Ok(EvalResult::complex(re, im))
+}
+
+/// The distance of a complex number from 0
+pub(crate) fn modulus(re: f64, im: f64) -> EvalResult {
+    EvalResult::Float(re.hypot(im))
+}
+
+/// Compares two results where either is complex. Complex numbers can be equal, but have no order
+pub(crate) fn cmp(
+    left: &EvalResult,
+    right: &EvalResult,
+) -> Option<Result<Option<Ordering>, RuntimeError>> {
+    if !is_complex(left) && !is_complex(right) {
+        return None;
+    }
+
+    Some(match (left.parts(), right.parts()) {
+        (Some(l), Some(r)) if l == r => Ok(Some(Ordering::Equal)),
+        _ => Err(RuntimeError::Invalid),
+    })
+}
+
+/// Whether two results where either is complex are within a relative tolerance of each other,
+/// measured by the distance between them
+pub(crate) fn approx_eq(
+    left: &EvalResult,
+    right: &EvalResult,
+    epsilon: f64,
+) -> Option<Result<bool, RuntimeError>> {
+    if !is_complex(left) && !is_complex(right) {
+        return None;
+    }
+
+    Some(match (left.parts(), right.parts()) {
+        (Some(l), Some(r)) => {
+            let distance = (l.0 - r.0).hypot(l.1 - r.1);
+            Ok(distance <= epsilon * l.0.hypot(l.1).max(r.0.hypot(r.1)))
+        }
+        _ => Err(RuntimeError::Invalid),
+    })
+}
+
+/// Writes a complex number like `3+4i`, leaving out a real part of 0 and a coefficient of 1, so
+/// `i` and `2-i` rather than `0+1i` and `2-1i`
+pub(crate) fn fmt(f: &mut Formatter<'_>, re: f64, im: f64) -> std::fmt::Result {
+    let coefficient = if im.abs() == 1.0 {
+        String::new()
+    } else {
+        EvalResult::Float(im.abs()).to_string()
+    };
+    let sign = if im.is_sign_negative() { "-" } else { "+" };
+
+    if re == 0.0 {
+        write!(f, "{}{coefficient}i", sign.trim_start_matches('+'))
+    } else {
+        write!(f, "{}{sign}{coefficient}i", EvalResult::Float(re))
+    }
+}
+
+/// Whether a result is complex
+fn is_complex(value: &EvalResult) -> bool {
+    matches!(value, EvalResult::Complex(..))
+}
+
+/// The product of two complex numbers
+fn mul((a, b): Parts, (c, d): Parts) -> Parts {
+    (a * c - b * d, a * d + b * c)
+}
+
+/// The quotient of two complex numbers, an error if the divisor is 0
+fn div((a, b): Parts, (c, d): Parts) -> Result<Parts, RuntimeError> {
+    let denominator = c * c + d * d;
+    if denominator == 0.0 {
+        return Err(RuntimeError::Invalid);
+    }
+    Ok(((a * c + b * d) / denominator, (b * c - a * d) / denominator))
+}
+
+/// The principal square root, the one with a non-negative real part
+fn sqrt((re, im): Parts) -> Parts {
+    let modulus = re.hypot(im);
+    let root_re = ((modulus + re) / 2.0).sqrt();
+    let root_im = ((modulus - re) / 2.0).sqrt();
+    (root_re, root_im.copysign(im))
+}
+
+/// The principal natural logarithm, with its imaginary part in `(-pi, pi]`
+fn ln((re, im): Parts) -> Parts {
+    (re.hypot(im).ln(), im.atan2(re))
+}
+
+/// `e` raised to a complex number
+fn exp((re, im): Parts) -> Parts {
+    let magnitude = re.exp();
+    (magnitude * im.cos(), magnitude * im.sin())
+}
+
+/// A complex number raised to a complex power. Whole powers are multiplied out so they stay
+/// exact where they can, anything else goes through the principal logarithm
+fn pow(base: Parts, exponent: Parts) -> Result<Parts, RuntimeError> {
+    let (power, imaginary) = exponent;
+    if imaginary != 0.0 || power.fract() != 0.0 || power.abs() > MAX_EXACT_POWER {
+        return non_zero(base).map(|base| exp(mul(exponent, ln(base))));
+    }
+
+    let mut result = (1.0, 0.0);
+    let mut square = base;
+    let mut remaining = power.abs() as u32;
+    while remaining > 0 {
+        if remaining % 2 == 1 {
+            result = mul(result, square);
+        }
+        square = mul(square, square);
+        remaining /= 2;
+    }
+
+    if power < 0.0 {
+        div((1.0, 0.0), result)
+    } else {
+        Ok(result)
+    }
+}
+
+/// A base that has a logarithm, since 0 to a complex power is undefined
+fn non_zero(base: Parts) -> Result<Parts, RuntimeError> {
+    if base == (0.0, 0.0) {
+        Err(RuntimeError::Invalid)
+    } else {
+        Ok(base)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        exec::{EvalResult, Evaluator, RuntimeError},
+        pipeline::testing::run,
+    };
+
+    /// An evaluator in complex mode
+    fn complex() -> Evaluator {
+        let mut eval = Evaluator::default();
+        eval.set_complex(true);
+        eval
+    }
+
+    #[test]
+    fn arithmetic() {
+        let mut eval = complex();
+
+        for (expression, expected) in [
+            ("i", "i"),
+            ("-i", "-i"),
+            ("3 + 4 * i", "3+4i"),
+            ("(1 + 2 * i) * (3 - i)", "5+5i"),
+            ("(1 + i) / (1 - i)", "i"),
+            ("2 - i - 2", "-i"),
+            ("1.5 * i - 1", "-1+1.5i"),
+            ("(1 + i) ^ 2", "2i"),
+            ("i ^ -1", "-i"),
+            ("sqrt(-4)", "2i"),
+            ("sqrt(2 * i)", "1+i"),
+            ("(-1) ^ 0.5", "i"),
+            ("ln(-1) == pi * i", "true"),
+            ("2.718281828459045 ^ (i * pi)", "-1"),
+            ("|3 + 4 * i|", "5"),
+            ("re(3 - 4 * i)", "3"),
+            ("im(3 - 4 * i)", "-4"),
+            ("conj(3 - 4 * i)", "3+4i"),
+            ("arg(i) == pi / 2", "true"),
+            (
+                "arg(-2)",
+                &EvalResult::Float(std::f64::consts::PI).to_string(),
+            ),
+            ("re(2) + im(2)", "2"),
+        ] {
+            assert_eq!(
+                run(&mut eval, expression).map(|result| result.to_string()),
+                Ok(expected.to_string()),
+                "{expression}"
+            );
+        }
+    }
+
+    #[test]
+    fn i_squared() {
+        let mut eval = complex();
+
+        assert_eq!(run(&mut eval, "i^2 == -1"), Ok(EvalResult::Bool(true)));
+        assert_eq!(run(&mut eval, "i^2"), Ok(EvalResult::Integer(-1)));
+        assert_eq!(
+            run(&mut eval, "i^0.5 ~= (1 + i) / sqrt(2)"),
+            Ok(true.into())
+        );
+        assert_eq!(run(&mut eval, "i == i"), Ok(EvalResult::Bool(true)));
+        assert_eq!(run(&mut eval, "i != 1"), Ok(EvalResult::Bool(true)));
+        assert_eq!(run(&mut eval, "i < 1"), Err(RuntimeError::Invalid));
+        assert_eq!(run(&mut eval, "sin(i)"), Err(RuntimeError::Invalid));
+        assert_eq!(run(&mut eval, "i / 0"), Err(RuntimeError::Invalid));
+
+        // A bound `i` shadows the imaginary unit
+        assert_eq!(
+            run(&mut eval, "sum(i, 1, 3, i)"),
+            Ok(EvalResult::Integer(6))
+        );
+        run(&mut eval, "i = 2").expect("Assign");
+        assert_eq!(run(&mut eval, "i^2"), Ok(EvalResult::Integer(4)));
+    }
+
+    #[test]
+    fn mode_off() {
+        let mut eval = Evaluator::default();
+
+        assert_eq!(run(&mut eval, "i"), Err(RuntimeError::Invalid));
+        assert_eq!(run(&mut eval, "(-1) ^ 0.5"), Err(RuntimeError::Invalid));
+        assert_eq!(run(&mut eval, "(-8) ^ (1/3)"), Ok(EvalResult::Float(-2.0)));
+        assert_eq!(
+            run(&mut eval, "sqrt(-4)"),
+            Err(RuntimeError::OutOfDomain("sqrt"))
+        );
+        assert_eq!(run(&mut eval, "re(2.5)"), Ok(EvalResult::Float(2.5)));
+        assert_eq!(run(&mut eval, "im(2.5)"), Ok(EvalResult::Integer(0)));
+    }
+}
@@ -0,0 +1,257 @@
+//! Fixed-point decimals for money, where `0.1 + 0.2` has to be exactly `0.30`. In decimal mode
+//! numbers written with a decimal point are kept as a whole number of their last decimal place,
+//! and every exact result that isn't whole is rounded to that place with banker's rounding, so
+//! `10 / 3` is `3.33` at two places. Operations that can't stay exact, like `sqrt` and `sin`,
+//! give floats unless the mode is strict, when they fail instead
+
+use std::{cmp::Ordering, fmt::Formatter};
+
+use crate::{
+    exec::{EvalResult, Evaluator, NumericMode, RuntimeError},
+    math::rational::{self, Fraction},
+};
+
+/// Most decimal places a decimal may keep, which leaves room for amounts in the hundreds of
+/// trillions
+pub const MAX_SCALE: u8 = 4;
+
+/// The mantissa of 1 with `scale` decimal places
+fn one(scale: u8) -> i64 {
+    10i64.pow(u32::from(scale.min(MAX_SCALE)))
+}
+
+/// A decimal as an exact fraction in lowest terms
+pub(crate) fn fraction(mantissa: i64, scale: u8) -> Option<Fraction> {
+    rational::reduce(mantissa, one(scale))
+}
+
+/// A decimal as the nearest float
+pub(crate) fn to_float(mantissa: i64, scale: u8) -> f64 {
+    mantissa as f64 / one(scale) as f64
+}
+
+/// Writes a decimal with every one of its places, like `0.30`
+pub(crate) fn fmt(f: &mut Formatter<'_>, mantissa: i64, scale: u8) -> std::fmt::Result {
+    let sign = if mantissa < 0 { "-" } else { "" };
+    let one = one(scale).unsigned_abs();
+    let (whole, part) = (mantissa.unsigned_abs() / one, mantissa.unsigned_abs() % one);
+    match scale {
+        0 => write!(f, "{sign}{whole}"),
+        _ => write!(
+            f,
+            "{sign}{whole}.{part:0width$}",
+            width = usize::from(scale)
+        ),
+    }
+}
+
+impl EvalResult {
+    /// Rounds an exact fraction to a decimal with `scale` places, capped at [`MAX_SCALE`], taking
+    /// halves to whichever neighbour is even. `None` if it's too large to fit
+    pub fn decimal((numerator, denominator): Fraction, scale: u8) -> Option<Self> {
+        let scale = scale.min(MAX_SCALE);
+        let (numerator, denominator) = rational::reduce(numerator, denominator)?;
+        let scaled = i128::from(numerator) * i128::from(one(scale));
+        let denominator = i128::from(denominator);
+
+        let (quotient, remainder) = (
+            scaled.div_euclid(denominator),
+            scaled.rem_euclid(denominator),
+        );
+        let rounded = match (2 * remainder).cmp(&denominator) {
+            Ordering::Less => quotient,
+            Ordering::Greater => quotient + 1,
+            Ordering::Equal => quotient + (quotient & 1),
+        };
+        i64::try_from(rounded)
+            .ok()
+            .map(|mantissa| Self::Decimal(mantissa, scale))
+    }
+}
+
+impl Evaluator {
+    /// How numbers with a decimal point and results that aren't whole are represented
+    pub fn numeric_mode(&self) -> NumericMode {
+        self.numeric_mode
+    }
+
+    /// Sets how numbers with a decimal point and results that aren't whole are represented, with
+    /// scales past [`MAX_SCALE`] capped at it. Results already bound to variables are unaffected
+    /// until they're next evaluated
+    pub fn set_numeric_mode(&mut self, mode: NumericMode) {
+        self.numeric_mode = match mode {
+            NumericMode::Decimal { scale, strict } => NumericMode::Decimal {
+                scale: scale.min(MAX_SCALE),
+                strict,
+            },
+            NumericMode::Float => NumericMode::Float,
+        };
+        self.invalidate_all();
+    }
+
+    /// A number written with a decimal point, rounded to a decimal in decimal mode. One too large
+    /// to be a decimal stays a float, which strict decimal mode then rejects
+    pub(crate) fn real(&self, n: f64) -> EvalResult {
+        let NumericMode::Decimal { scale, .. } = self.numeric_mode else {
+            return EvalResult::Float(n);
+        };
+
+        let scaled = (n * one(scale) as f64).round_ties_even();
+        if scaled.is_finite() && scaled.abs() < i64::MAX as f64 {
+            EvalResult::Decimal(scaled as i64, scale)
+        } else {
+            EvalResult::Float(n)
+        }
+    }
+
+    /// Brings a result into decimal mode, rounding fractions and decimals to its places and
+    /// rejecting floats if it's strict. Anything fits float mode as it is
+    pub(crate) fn settle(&self, result: EvalResult) -> Result<EvalResult, RuntimeError> {
+        let NumericMode::Decimal { scale, strict } = self.numeric_mode else {
+            return Ok(result);
+        };
+
+        match result {
+            EvalResult::Rational(..) | EvalResult::Decimal(..) => {
+                match result
+                    .exact()
+                    .and_then(|fraction| EvalResult::decimal(fraction, scale))
+                {
+                    Some(decimal) => Ok(decimal),
+                    None if strict => Err(RuntimeError::Inexact),
+                    None => Ok(EvalResult::Float(result.float()?)),
+                }
+            }
+            EvalResult::Float(_) | EvalResult::Complex(..) | EvalResult::Quantity { .. }
+                if strict =>
+            {
+                Err(RuntimeError::Inexact)
+            }
+            EvalResult::List(items) => items
+                .into_iter()
+                .map(|item| self.settle(item))
+                .collect::<Result<_, _>>()
+                .map(EvalResult::List),
+            result => Ok(result),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        exec::{EvalResult, Evaluator, NumericMode, RuntimeError},
+        pipeline::testing::run,
+    };
+
+    use super::MAX_SCALE;
+
+    /// An evaluator in decimal mode with two places
+    fn cents(strict: bool) -> Evaluator {
+        let mut eval = Evaluator::default();
+        eval.set_numeric_mode(NumericMode::Decimal { scale: 2, strict });
+        eval
+    }
+
+    #[test]
+    fn exact_arithmetic() {
+        let mut eval = cents(true);
+        assert_eq!(
+            run(&mut eval, "0.1 + 0.2").map(|sum| sum.to_string()),
+            Ok("0.30".to_string())
+        );
+        assert_eq!(
+            run(&mut eval, "0.1 + 0.2 == 0.3"),
+            Ok(EvalResult::Bool(true))
+        );
+        assert_eq!(
+            run(&mut eval, "0.1 * 3 - 0.3 == 0"),
+            Ok(EvalResult::Bool(true))
+        );
+
+        for (expression, shown) in [
+            ("19.99 * 3", "59.97"),
+            ("10 / 3", "3.33"),
+            ("-10 / 3", "-3.33"),
+            ("2 / 3", "0.67"),
+            // Halves round to the even neighbour
+            ("1 / 8", "0.12"),
+            ("3 / 8", "0.38"),
+            ("-1 / 8", "-0.12"),
+            ("0.125", "0.12"),
+            ("0.5 * 4", "2"),
+            ("|-2.5|", "2.50"),
+            ("floor(2.75)", "2"),
+            ("[0.1, 1 / 4]", "[0.10, 0.25]"),
+        ] {
+            assert_eq!(
+                run(&mut eval, expression).map(|result| result.to_string()),
+                Ok(shown.to_string()),
+                "{expression}"
+            );
+        }
+
+        // Rounding happens after every operation, like it would on an invoice
+        assert_eq!(
+            run(&mut eval, "10 / 3 * 3").map(|result| result.to_string()),
+            Ok("9.99".to_string())
+        );
+    }
+
+    #[test]
+    fn inexact_operations() {
+        let mut strict = cents(true);
+        for expression in ["sqrt(2)", "sin(0.5)", "pi * 2", "2^0.5"] {
+            assert_eq!(
+                run(&mut strict, expression),
+                Err(RuntimeError::Inexact),
+                "{expression}"
+            );
+        }
+
+        let mut lenient = cents(false);
+        assert_eq!(
+            run(&mut lenient, "sin(0.5)"),
+            Ok(EvalResult::Float(0.5f64.sin()))
+        );
+        assert_eq!(
+            run(&mut lenient, "sqrt(2) + 0.01"),
+            Ok(EvalResult::Float(2f64.sqrt() + 0.01))
+        );
+    }
+
+    #[test]
+    fn scales() {
+        let mut eval = Evaluator::default();
+        eval.set_numeric_mode(NumericMode::Decimal {
+            scale: 9,
+            strict: false,
+        });
+        assert_eq!(
+            eval.numeric_mode(),
+            NumericMode::Decimal {
+                scale: MAX_SCALE,
+                strict: false
+            }
+        );
+        assert_eq!(
+            run(&mut eval, "1 / 3").map(|result| result.to_string()),
+            Ok("0.3333".to_string())
+        );
+
+        eval.set_numeric_mode(NumericMode::Decimal {
+            scale: 0,
+            strict: false,
+        });
+        assert_eq!(
+            run(&mut eval, "5 / 2").map(|result| result.to_string()),
+            Ok("2".to_string())
+        );
+        assert_eq!(EvalResult::decimal((i64::MAX, 1), 2), None);
+
+        // Float mode is untouched
+        eval.set_numeric_mode(NumericMode::Float);
+        assert_eq!(run(&mut eval, "0.5"), Ok(EvalResult::Float(0.5)));
+        assert_eq!(run(&mut eval, "1 / 4"), Ok(EvalResult::Rational(1, 4)));
+    }
+}
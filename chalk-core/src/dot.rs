@@ -0,0 +1,100 @@
+//! Views of an expression's syntax tree, for teaching how parsing works and debugging precedence
+
+use std::fmt::Write;
+
+use crate::ast::Expr;
+
+impl Expr {
+    /// Renders the syntax tree as a Graphviz digraph, suitable for piping into `dot -Tpng`. Nodes
+    /// are numbered in the order they're written, so identical subtrees still get their own
+    pub fn to_dot(&self) -> String {
+        let mut out = String::from("digraph {\n  node [shape=box];\n");
+        self.dot_into(&mut 0, &mut out);
+        out.push('}');
+        out
+    }
+
+    /// Writes this node and its children to `out`, numbering them from `next`. Returns this
+    /// node's ID
+    fn dot_into(&self, next: &mut usize, out: &mut String) -> usize {
+        let id = *next;
+        *next += 1;
+
+        let label = self.label().replace('\\', "\\\\").replace('"', "\\\"");
+        let _ = writeln!(out, "  n{id} [label=\"{label}\"];");
+        for child in self.children() {
+            let child = child.dot_into(next, out);
+            let _ = writeln!(out, "  n{id} -> n{child};");
+        }
+
+        id
+    }
+
+    /// Renders the syntax tree with lines drawn from each node to its children, like `tree` draws
+    /// directories
+    pub fn to_tree_string(&self) -> String {
+        let mut out = self.label();
+        self.tree_into("", &mut out);
+        out
+    }
+
+    /// Writes the children of this node to `out`, each line starting with `prefix`
+    fn tree_into(&self, prefix: &str, out: &mut String) {
+        let children = self.children();
+        for (i, child) in children.iter().enumerate() {
+            let last = i + 1 == children.len();
+            let (branch, rest) = if last {
+                ("└── ", "    ")
+            } else {
+                ("├── ", "│   ")
+            };
+
+            let _ = write!(out, "\n{prefix}{branch}{}", child.label());
+            child.tree_into(&format!("{prefix}{rest}"), out);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::pipeline::testing::parse;
+
+    #[test]
+    fn dot() {
+        let expected = r#"digraph {
+  node [shape=box];
+  n0 [label="BinaryOp Multiply"];
+  n1 [label="Paren"];
+  n2 [label="BinaryOp Add"];
+  n3 [label="Integer 1"];
+  n2 -> n3;
+  n4 [label="Integer 2"];
+  n2 -> n4;
+  n1 -> n2;
+  n0 -> n1;
+  n5 [label="Integer 3"];
+  n0 -> n5;
+}"#;
+        assert_eq!(parse("(1+2)*3").to_dot(), expected);
+
+        // Identical subtrees are still separate nodes
+        let dot = parse("(x + 1) * (x + 1)").to_dot();
+        assert_eq!(dot.matches("[label=").count(), 9);
+        assert_eq!(dot.matches(" -> ").count(), 8);
+        assert!(dot.contains("n8 [label=\"Integer 1\"]"));
+    }
+
+    #[test]
+    fn tree_string() {
+        let expected = "\
+BinaryOp Add
+├── FunctionCall powmod
+│   ├── Integer 2
+│   ├── Variable x
+│   └── Integer 7
+└── UnaryOp Neg
+    └── Variable y";
+        assert_eq!(parse("powmod(2, x, 7) + -y").to_tree_string(), expected);
+        assert_eq!(parse("2").to_tree_string(), "Integer 2");
+    }
+}
@@ -1,135 +1,1279 @@
 //! AST Execution/Evaluation
 
-use std::{collections::HashMap, error::Error, fmt::Display};
+use std::{
+    cmp::Ordering,
+    collections::{BTreeSet, HashMap},
+    error::Error,
+    fmt::Display,
+    sync::Arc,
+};
 
+#[cfg(feature = "bigint")]
+use crate::bigint;
 use crate::{
-    ast::{BinaryOperator, Expr, UnaryOperator},
-    math::{gcd::gcd, lcm::lcm},
+    ast::{BinaryOperator, Expr, Function, MAX_DEPTH, Reduction, UnaryOperator},
+    cache::ValueCache,
+    complex, decimal,
+    history::{History, HistoryEntry},
+    list,
+    math::{
+        divisors::{divisors_from, num_divisors_from, sum_divisors_from, totient_from},
+        gcd::gcd,
+        lcm::lcm,
+        modular::{inv_mod, pow_mod},
+        prime::{PrimeFactorizable, factorize_within, nth_prime, prime_count},
+        random::Rng,
+        rational::{self, Fraction},
+    },
+    stats,
+    trace::{StepKind, TraceStep},
+    units::{self, Unit},
 };
 
-/// A runtime type error
-#[derive(Debug, Clone, Copy)]
-pub struct RuntimeError;
+/// Most terms a sum or product may have
+const MAX_REDUCTION_STEPS: i64 = 1_000_000;
+
+/// The relative tolerance `~=` compares within unless another is set
+pub const DEFAULT_EPSILON: f64 = 1e-9;
+
+/// An error while evaluating an expression
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum RuntimeError {
+    /// An operation got values it can't work with, or a variable wasn't bound
+    Invalid,
+    /// The expression, including whatever its variables are bound to, nests too deeply
+    TooDeep,
+    /// Evaluation took more steps than the evaluator's fuel allows
+    FuelExhausted,
+    /// Quantities were added, compared or converted between units measuring different things,
+    /// like `1km + 1h`
+    DimensionMismatch,
+    /// A function divided by zero, like `pctof(1, 0)`
+    DivisionByZero(Function),
+    /// A list was indexed outside of its items, which count from 1
+    IndexOutOfBounds {
+        /// The index asked for
+        index: i64,
+        /// How many items the list has
+        len: usize,
+    },
+    /// A function was given a value it isn't defined for, like `acos(2)`, named by what it's
+    /// called
+    OutOfDomain(&'static str),
+    /// Arithmetic had no defined result, like `0/0` or `inf - inf`
+    Indeterminate,
+    /// A variable would have been bound to an infinite or undefined value
+    NonFinite(char),
+    /// A condition of a function like `if` wasn't true or false, since numbers aren't truthy
+    NotACondition(Function),
+    /// None of the conditions of a function like `piecewise` were true, and it had no default
+    Unmatched(Function),
+    /// An operation couldn't give a result exact to the decimal places of strict decimal mode,
+    /// like `sqrt(2)`
+    Inexact,
+    /// A variable was updated in terms of itself, like `x += 1`, before it had a value
+    Unbound(char),
+    /// A sum or product had more terms than it may, like `sum(i, 1, 10^7, i)`
+    TooManySteps(Reduction),
+}
 
 impl Display for RuntimeError {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        write!(
-            f,
-            "Runtime error occurred :( your types are probably not good"
-        )
+        match self {
+            RuntimeError::Invalid => write!(
+                f,
+                "Runtime error occurred :( your types are probably not good"
+            ),
+            RuntimeError::TooDeep => write!(f, "Expression is too deeply nested :("),
+            RuntimeError::FuelExhausted => write!(f, "Expression took too long to evaluate :("),
+            RuntimeError::DimensionMismatch => {
+                write!(f, "Those units don't measure the same thing :(")
+            }
+            RuntimeError::DivisionByZero(function) => {
+                write!(f, "`{function}` divided by zero :(")
+            }
+            RuntimeError::IndexOutOfBounds { index, len } => {
+                write!(
+                    f,
+                    "Index {index} is out of bounds for a list of {len} items :("
+                )
+            }
+            RuntimeError::OutOfDomain(name) => {
+                write!(f, "`{name}` isn't defined for that value :(")
+            }
+            RuntimeError::Indeterminate => {
+                write!(f, "That has no defined result, like 0/0 or inf - inf :(")
+            }
+            RuntimeError::NonFinite(var) => {
+                write!(
+                    f,
+                    "`{var}` can't be bound to an infinite or undefined value :("
+                )
+            }
+            RuntimeError::NotACondition(function) => {
+                write!(f, "`{function}` needs conditions that are true or false :(")
+            }
+            RuntimeError::Unmatched(function) => {
+                write!(f, "None of the conditions of `{function}` were true :(")
+            }
+            RuntimeError::Inexact => {
+                write!(f, "That can't be worked out exactly in decimal mode :(")
+            }
+            RuntimeError::Unbound(var) => {
+                write!(f, "`{var}` has no value to update yet :(")
+            }
+            RuntimeError::TooManySteps(kind) => {
+                write!(
+                    f,
+                    "`{kind}` can have at most {MAX_REDUCTION_STEPS} terms :("
+                )
+            }
+        }
     }
 }
 
 impl Error for RuntimeError {}
 
+impl RuntimeError {
+    /// Whether the error stands in for a NaN result, which plotting and solving treat as a point
+    /// with no value rather than a failure
+    pub fn is_undefined(&self) -> bool {
+        matches!(self, Self::OutOfDomain(_) | Self::Indeterminate)
+    }
+}
+
+/// Whether `var` is used in `ast` itself, rather than only through what other variables are bound
+/// to. The solved for variable of `solve` and the index of `sum` and `prod` are their own
+fn mentions(ast: &Expr, var: char) -> bool {
+    match ast {
+        Expr::Variable(v) => *v == var,
+        Expr::Solve { var: unknown, .. } if *unknown == var => false,
+        Expr::Reduce {
+            var: index,
+            from,
+            to,
+            ..
+        } if *index == var => mentions(from, var) || mentions(to, var),
+        _ => ast.children().into_iter().any(|child| mentions(child, var)),
+    }
+}
+
+/// The variables an expression depends on, followed transitively through bound variables
+#[derive(Clone, Debug, Default, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub struct Dependencies {
+    /// Variables that aren't bound to anything
+    pub free: BTreeSet<char>,
+    /// Variables bound to an expression
+    pub bound: BTreeSet<char>,
+}
+
+/// The unit trig functions take angles in and inverse trig functions give them in
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub enum AngleMode {
+    /// Radians, where a full turn is `2pi`
+    #[default]
+    Radians,
+    /// Degrees, where a full turn is 360
+    Degrees,
+}
+
+impl TryFrom<&str> for AngleMode {
+    type Error = ();
+    fn try_from(value: &str) -> Result<Self, Self::Error> {
+        match value.to_lowercase().as_str() {
+            "rad" | "radians" => Ok(AngleMode::Radians),
+            "deg" | "degrees" => Ok(AngleMode::Degrees),
+            _ => Err(()),
+        }
+    }
+}
+
+/// How numbers written with a decimal point, and results that aren't whole, are represented
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub enum NumericMode {
+    /// Floats, with integers divided into exact fractions
+    #[default]
+    Float,
+    /// Fixed-point decimals with `scale` places, rounding every exact result to them. Operations
+    /// that can't stay exact, like `sqrt` and `sin`, give floats, or fail when `strict`
+    Decimal {
+        /// How many decimal places are kept, up to [`decimal::MAX_SCALE`]
+        scale: u8,
+        /// Whether operations that can't stay exact fail rather than giving floats
+        strict: bool,
+    },
+}
+
 /// Struct for executing ASTs
-#[derive(Clone, Debug, Default)]
+#[derive(Clone, Debug)]
 pub struct Evaluator {
-    pub(crate) ctx: HashMap<char, Expr>,
+    /// Variable context mapping each bound variable to its expression, shared so reading a
+    /// variable doesn't copy what it's bound to
+    pub(crate) ctx: HashMap<char, Arc<Expr>>,
+    /// The unit of angles in trig functions
+    angle_mode: AngleMode,
+    /// How many nodes deep the expression currently being executed is
+    depth: usize,
+    /// How many nodes deep an expression may be before executing it fails
+    max_depth: usize,
+    /// The steps evaluated so far, while a trace is being taken
+    pub(crate) trace: Option<Vec<TraceStep>>,
+    /// Where `rand` and `randint` draw from
+    rng: Rng,
+    /// The relative tolerance `~=` compares within
+    epsilon: f64,
+    /// The statements evaluated so far, which `hist` recalls from
+    history: History,
+    /// How many steps a single evaluation may take, or `None` for no limit
+    fuel: Option<u64>,
+    /// How many steps the evaluation under way has taken
+    burned: u64,
+    /// Formulas stored by name, which unlike variables are never substituted into expressions
+    pub(crate) formulas: HashMap<String, Expr>,
+    /// Whether `i` is the imaginary unit and operations leaving the reals give complex results
+    pub(crate) complex: bool,
+    /// Whether operations may give NaN and variables may be bound to non-finite values
+    allow_non_finite: bool,
+    /// How numbers with a decimal point and results that aren't whole are represented
+    pub(crate) numeric_mode: NumericMode,
+    /// What variables evaluated to, kept until they or anything they depend on are rebound
+    pub(crate) cache: ValueCache,
+}
+
+impl Default for Evaluator {
+    fn default() -> Self {
+        Self {
+            ctx: HashMap::new(),
+            angle_mode: AngleMode::default(),
+            depth: 0,
+            max_depth: MAX_DEPTH,
+            trace: None,
+            rng: Rng::default(),
+            epsilon: DEFAULT_EPSILON,
+            history: History::default(),
+            fuel: None,
+            burned: 0,
+            formulas: HashMap::new(),
+            complex: false,
+            allow_non_finite: false,
+            numeric_mode: NumericMode::Float,
+            cache: ValueCache::default(),
+        }
+    }
 }
 
 impl Evaluator {
+    /// How many nodes deep an expression may be, counting through bound variables, before
+    /// executing it fails with [`RuntimeError::TooDeep`]
+    pub fn max_depth(&self) -> usize {
+        self.max_depth
+    }
+
+    /// Sets how deeply expressions may nest, which bounds how much stack executing one can use.
+    /// Cached values are forgotten, since a variable read from the cache isn't followed at all
+    pub fn set_max_depth(&mut self, depth: usize) {
+        self.max_depth = depth;
+        self.invalidate_all();
+    }
+
+    /// How many steps a single evaluation may take before failing with
+    /// [`RuntimeError::FuelExhausted`], or `None` for no limit
+    pub fn fuel(&self) -> Option<u64> {
+        self.fuel
+    }
+
+    /// Sets how many steps a single evaluation may take. Every node executed is a step, as is
+    /// every term of a sum or product and every point sampled. `None`, the default, is no limit
+    pub fn set_fuel(&mut self, fuel: Option<u64>) {
+        self.fuel = fuel;
+    }
+
+    /// Takes a step of the evaluation under way, failing once the fuel has run out
+    fn burn(&mut self) -> Result<(), RuntimeError> {
+        self.burned += 1;
+        match self.fuel {
+            Some(fuel) if self.burned > fuel => Err(RuntimeError::FuelExhausted),
+            _ => Ok(()),
+        }
+    }
+
+    /// Refills the fuel, unless it's being called from inside an evaluation already under way
+    fn refuel(&mut self) {
+        if self.depth == 0 {
+            self.burned = 0;
+        }
+    }
+
+    /// The unit of angles in trig functions
+    pub fn angle_mode(&self) -> AngleMode {
+        self.angle_mode
+    }
+
+    /// Sets the unit of angles in trig functions, which affects nothing else
+    pub fn set_angle_mode(&mut self, mode: AngleMode) {
+        self.angle_mode = mode;
+        self.invalidate_all();
+    }
+
+    /// Whether operations may give NaN and variables may be bound to infinities and NaN
+    pub fn allows_non_finite(&self) -> bool {
+        self.allow_non_finite
+    }
+
+    /// Sets whether operations follow IEEE semantics, giving NaN where they have no defined
+    /// result and letting variables be bound to non-finite values. Off by default, so `acos(2)`
+    /// and `0/0` fail instead of quietly making every comparison with them false
+    pub fn allow_non_finite(&mut self, allow: bool) {
+        self.allow_non_finite = allow;
+        self.invalidate_all();
+    }
+
+    /// The relative tolerance `~=` compares within
+    pub fn epsilon(&self) -> f64 {
+        self.epsilon
+    }
+
+    /// Sets the relative tolerance `~=` compares within. A negative or NaN tolerance leaves only
+    /// equal values approximately equal
+    pub fn set_epsilon(&mut self, epsilon: f64) {
+        self.epsilon = epsilon;
+        self.invalidate_all();
+    }
+
+    /// The statements evaluated so far that `hist` can recall, oldest first
+    pub fn history(&self) -> &[HistoryEntry] {
+        self.history.entries()
+    }
+
+    /// Records a statement that evaluated successfully in the history, returning its number
+    pub fn remember(&mut self, source: impl Into<String>, result: EvalResult) -> usize {
+        self.history.push(source.into(), result)
+    }
+
+    /// Seeds the generator behind `rand` and `randint`, so the same seed always draws the same
+    /// sequence
+    pub fn seed(&mut self, seed: u64) {
+        self.rng = Rng::seeded(seed);
+    }
+
+    /// Gets the expression currently bound to a variable
+    pub fn get(&self, var: char) -> Option<&Expr> {
+        self.ctx.get(&var).map(AsRef::as_ref)
+    }
+
+    /// Binds a variable to an expression, returning the previous binding if there was one
+    pub fn set(&mut self, var: char, expr: Expr) -> Option<Expr> {
+        self.invalidate(var);
+        self.ctx
+            .insert(var, Arc::new(expr))
+            .map(Arc::unwrap_or_clone)
+    }
+
+    /// Unbinds a variable, returning its expression if it was bound
+    pub fn remove(&mut self, var: char) -> Option<Expr> {
+        self.invalidate(var);
+        self.ctx.remove(&var).map(Arc::unwrap_or_clone)
+    }
+
+    /// Unbinds every variable
+    pub fn clear(&mut self) {
+        self.invalidate_all();
+        self.ctx.clear()
+    }
+
+    /// All bound variables and their expressions, sorted by variable name
+    pub fn vars(&self) -> Vec<(char, &Expr)> {
+        let mut vars: Vec<_> = self
+            .ctx
+            .iter()
+            .map(|(var, expr)| (*var, expr.as_ref()))
+            .collect();
+        vars.sort_by_key(|(var, _)| *var);
+        vars
+    }
+
+    /// Evaluates the expression currently bound to a variable, or `None` if it isn't bound
+    pub fn value(&mut self, var: char) -> Option<Result<EvalResult, RuntimeError>> {
+        self.ctx
+            .contains_key(&var)
+            .then(|| self.exec(&Expr::Variable(var)))
+    }
+
+    /// What an assignment to `var` binds it to. Uses of `var` itself, like in `x = x + 1` or
+    /// `x += 1`, are replaced by the value it has now, so the variable is updated rather than
+    /// bound to itself, while the rest stays lazy like any binding
+    fn updating(&mut self, var: char, node: &Expr) -> Result<Expr, RuntimeError> {
+        if !mentions(node, var) {
+            return Ok(node.clone());
+        }
+
+        let current = self.value(var).unwrap_or(Err(RuntimeError::Unbound(var)))?;
+        Ok(node.substitute(var, &Expr::from(current)))
+    }
+
+    /// Evaluates every bound variable, sorted by variable name. A variable whose expression
+    /// fails to evaluate doesn't stop the others from being evaluated
+    pub fn values(&mut self) -> Vec<(char, Result<EvalResult, RuntimeError>)> {
+        let mut vars: Vec<_> = self.ctx.keys().copied().collect();
+        vars.sort();
+
+        vars.into_iter()
+            .filter_map(|var| Some((var, self.value(var)?)))
+            .collect()
+    }
+
     /// Checks if an AST depends on a variable
     pub fn depends_on(&self, ast: &Expr, dep: char) -> bool {
         match ast {
-            Expr::AbsVal(expr) => self.depends_on(expr, dep),
-            Expr::UnaryOp { op: _, node } => self.depends_on(node, dep),
             Expr::Variable(var) => {
-                if var == &dep {
-                    true
-                } else if let Some(sub_ast) = self.ctx.get(var) {
-                    self.depends_on(sub_ast, dep)
-                } else {
-                    false
+                *var == dep
+                    || self
+                        .ctx
+                        .get(var)
+                        .is_some_and(|sub_ast| self.depends_on(sub_ast, dep))
+            }
+            // The solved for or index variable is bound by the expression itself
+            Expr::Solve { var, .. } if *var == dep => false,
+            Expr::Reduce { var, from, to, .. } if *var == dep => {
+                self.depends_on(from, dep) || self.depends_on(to, dep)
+            }
+            _ => ast
+                .children()
+                .into_iter()
+                .any(|child| self.depends_on(child, dep)),
+        }
+    }
+
+    /// Every variable an AST depends on, following bound variables through the context
+    pub fn dependencies(&self, ast: &Expr) -> Dependencies {
+        let mut deps = Dependencies::default();
+        self.collect_dependencies(ast, &mut deps);
+        deps
+    }
+
+    /// Adds the variables an AST depends on to `deps`
+    fn collect_dependencies(&self, ast: &Expr, deps: &mut Dependencies) {
+        match ast {
+            Expr::Variable(var) => match self.ctx.get(var) {
+                // Bound variables are only followed the first time they're seen, so cyclic
+                // bindings can't loop forever
+                Some(expr) => {
+                    if deps.bound.insert(*var) {
+                        self.collect_dependencies(expr, deps)
+                    }
                 }
+                None => {
+                    deps.free.insert(*var);
+                }
+            },
+            Expr::Assignment(_, node)
+            | Expr::UnaryOp { op: _, node }
+            | Expr::Paren(node)
+            | Expr::AbsVal(node) => self.collect_dependencies(node, deps),
+            Expr::BinaryOp { op: _, left, right }
+            | Expr::Index {
+                list: left,
+                index: right,
+            } => {
+                self.collect_dependencies(left, deps);
+                self.collect_dependencies(right, deps);
             }
-            Expr::BinaryOp { op: _, left, right } => {
-                self.depends_on(left, dep) || self.depends_on(right, dep)
+            Expr::Solve { equation, var } => self.collect_shadowed(equation, *var, deps),
+            Expr::Reduce {
+                var,
+                from,
+                to,
+                body,
+                ..
+            } => {
+                self.collect_dependencies(from, deps);
+                self.collect_dependencies(to, deps);
+                self.collect_shadowed(body, *var, deps);
             }
-            Expr::Paren(node) => self.depends_on(node, dep),
-            _ => false,
+            // The named function's free variables are its parameters, so only the variables it's
+            // bound through are depended on
+            Expr::FunctionCall { function, args } if function.takes_function() => {
+                let mut inner = Dependencies::default();
+                for (i, arg) in args.iter().enumerate() {
+                    self.collect_dependencies(arg, if i == 0 { &mut inner } else { deps });
+                }
+                deps.bound.extend(inner.bound);
+            }
+            Expr::List(items) | Expr::FunctionCall { args: items, .. } => {
+                for item in items {
+                    self.collect_dependencies(item, deps);
+                }
+            }
+            Expr::Integer(_)
+            | Expr::Real(_)
+            | Expr::Bool(_)
+            | Expr::Constant(_)
+            | Expr::Unit(_) => {}
+        }
+    }
+
+    /// Adds the variables an AST depends on to `deps`, leaving out `var` since it's bound by the
+    /// surrounding expression and shadows any outer binding
+    fn collect_shadowed(&self, ast: &Expr, var: char, deps: &mut Dependencies) {
+        let mut inner = Dependencies::default();
+        self.collect_dependencies(ast, &mut inner);
+        inner.free.remove(&var);
+        inner.bound.remove(&var);
+
+        deps.free.append(&mut inner.free);
+        deps.bound.append(&mut inner.bound);
+    }
+
+    /// Replaces every use of a variable with another expression like [`Expr::substitute`], but
+    /// first replaces every other bound variable with what it's bound to, so uses of `var` inside
+    /// their expressions are replaced too. Variables bound to themselves, directly or not, fail
+    /// with [`RuntimeError::TooDeep`]
+    pub fn substitute(
+        &self,
+        ast: &Expr,
+        var: char,
+        replacement: &Expr,
+    ) -> Result<Expr, RuntimeError> {
+        Ok(self
+            .inline(ast.clone(), &mut vec![var], 0)?
+            .substituted(var, replacement))
+    }
+
+    /// Replaces bound variables with what they're bound to, other than those in `kept`, having
+    /// already followed `depth` bindings
+    fn inline(&self, ast: Expr, kept: &mut Vec<char>, depth: usize) -> Result<Expr, RuntimeError> {
+        // Shadowing variables are kept until the end of the expression binding them
+        let shadowed = |kept: &mut Vec<char>, var, node: Box<Expr>| {
+            kept.push(var);
+            let inlined = self.inline(*node, kept, depth);
+            kept.pop();
+            inlined.map(Box::new)
+        };
+
+        match ast {
+            Expr::Variable(var) if !kept.contains(&var) => match self.ctx.get(&var) {
+                Some(_) if depth >= self.max_depth => Err(RuntimeError::TooDeep),
+                Some(bound) => self.inline(bound.as_ref().clone(), kept, depth + 1),
+                None => Ok(ast),
+            },
+            Expr::Solve { equation, var } => Ok(Expr::Solve {
+                equation: shadowed(kept, var, equation)?,
+                var,
+            }),
+            Expr::Reduce {
+                kind,
+                var,
+                from,
+                to,
+                body,
+            } => Ok(Expr::Reduce {
+                kind,
+                var,
+                from: Box::new(self.inline(*from, kept, depth)?),
+                to: Box::new(self.inline(*to, kept, depth)?),
+                body: shadowed(kept, var, body)?,
+            }),
+            node => node.try_map_children(&mut |child| self.inline(child, kept, depth)),
+        }
+    }
+
+    /// Evaluates an AST at `steps` evenly spaced points from `start` to `end` inclusive, with `var`
+    /// bound to each point in turn. Non-finite results become NaN, and any previous binding of
+    /// `var` is restored afterwards. The AST is compiled once where it can be, rather than walked
+    /// at every point
+    pub fn sample(
+        &mut self,
+        ast: &Expr,
+        var: char,
+        start: f64,
+        end: f64,
+        steps: usize,
+    ) -> Result<Vec<f64>, RuntimeError> {
+        let step = if steps > 1 {
+            (end - start) / (steps - 1) as f64
+        } else {
+            0.0
+        };
+
+        // Every point shares one tank of fuel, rather than each being its own evaluation
+        self.refuel();
+        self.depth += 1;
+        let compiled = self.compile(ast, &[var]);
+        let samples = (0..steps)
+            .map(|i| {
+                self.burn()?;
+                let point = EvalResult::Float(start + step * i as f64);
+                let result = match &compiled {
+                    Some(compiled) => compiled.run(&[point]),
+                    None => self.exec_with(ast, &[(var, point)]),
+                };
+                match result {
+                    Ok(EvalResult::Float(f)) if !f.is_finite() => Ok(f64::NAN),
+                    Err(err) if err.is_undefined() => Ok(f64::NAN),
+                    result => result?.float(),
+                }
+            })
+            .collect();
+        self.depth -= 1;
+        samples
+    }
+
+    /// Executes an AST with some variables bound only for this call. The bindings shadow any
+    /// outer binding of the same variables, including inside the expressions other variables are
+    /// bound to, and the context is left exactly as it was afterwards whether or not evaluation
+    /// succeeds. Assignments made to a shadowed variable are discarded along with the binding
+    pub fn exec_with(
+        &mut self,
+        ast: &Expr,
+        bindings: &[(char, EvalResult)],
+    ) -> Result<EvalResult, RuntimeError> {
+        let bindings = bindings
+            .iter()
+            .map(|(var, value)| (*var, Expr::from(value.clone())));
+        self.with_bindings(bindings, |eval| eval.exec(ast))
+    }
+
+    /// Runs `f` with variables temporarily bound, restoring every previous binding afterwards.
+    /// Cached values depending on the variables are forgotten going in and coming out, so none
+    /// from outside are used while they're bound and none from inside outlive the bindings
+    pub(crate) fn with_bindings<T>(
+        &mut self,
+        bindings: impl IntoIterator<Item = (char, Expr)>,
+        f: impl FnOnce(&mut Self) -> T,
+    ) -> T {
+        let previous: Vec<_> = bindings
+            .into_iter()
+            .map(|(var, expr)| {
+                self.invalidate(var);
+                (var, self.ctx.insert(var, Arc::new(expr)))
+            })
+            .collect();
+        let result = f(self);
+
+        // Restoring in reverse handles a variable being bound more than once
+        for (var, expr) in previous.into_iter().rev() {
+            self.invalidate(var);
+            match expr {
+                Some(expr) => self.ctx.insert(var, expr),
+                None => self.ctx.remove(&var),
+            };
         }
+
+        result
     }
 
-    /// Executes an AST
+    /// Executes an AST. Variables bound to themselves, directly or not, fail once they've been
+    /// followed past the depth limit instead of recursing forever, and evaluations taking more
+    /// steps than the fuel allows fail rather than hanging
     pub fn exec(&mut self, ast: &Expr) -> Result<EvalResult, RuntimeError> {
+        if self.depth >= self.max_depth {
+            return Err(RuntimeError::TooDeep);
+        }
+        self.refuel();
+        self.burn()?;
+
+        self.depth += 1;
+        let result = self.exec_node(ast).and_then(|result| self.settle(result));
+        self.depth -= 1;
+        result
+    }
+
+    /// Executes a statement, telling a binding apart from an expression that only gives a value.
+    /// Only an assignment at the top is a binding, those nested inside an expression just give
+    /// their value to it
+    pub fn exec_statement(&mut self, ast: &Expr) -> Result<ExecOutcome, RuntimeError> {
+        let value = self.exec(ast)?;
+        Ok(match ast {
+            Expr::Assignment(name, _) => ExecOutcome::Assigned { name: *name, value },
+            _ => ExecOutcome::Value(value),
+        })
+    }
+
+    /// Executes a single node, recursing back through `exec` for its children
+    fn exec_node(&mut self, ast: &Expr) -> Result<EvalResult, RuntimeError> {
         match ast {
             Expr::Variable(v) => {
                 if let Some(e) = self.ctx.get(v).cloned() {
-                    self.exec(&e)
+                    if let Some(value) = self.cached(*v) {
+                        return Ok(value);
+                    }
+                    let result = self.exec(&e);
+                    if let Ok(value) = &result {
+                        self.cache_value(*v, value);
+                    }
+                    self.record(StepKind::Substitution, Some(Expr::Variable(*v)), &result);
+                    result
+                } else if self.complex && *v == 'i' {
+                    Ok(EvalResult::Complex(0.0, 1.0))
                 } else {
-                    Err(RuntimeError)
+                    Err(RuntimeError::Invalid)
                 }
             }
             Expr::Assignment(v, node) => {
-                let entry = self.ctx.entry(*v).or_insert(Expr::Integer(0));
-                *entry = *node.clone();
-                self.exec(node)
+                let node = self.updating(*v, node)?;
+                let node = Arc::new(node);
+                self.invalidate(*v);
+                let previous = self.ctx.insert(*v, Arc::clone(&node));
+                let result = match self.exec(&node) {
+                    Ok(value) if !self.allow_non_finite && !value.is_finite() => {
+                        Err(RuntimeError::NonFinite(*v))
+                    }
+                    Ok(value) => return Ok(value),
+                    Err(err) => Err(err),
+                };
+
+                // A new variable bound to an expression of variables that aren't bound yet, like
+                // `f = x^2`, stays bound for when they are or to be applied with `map`. Otherwise
+                // a variable is never left bound to something without a value, so a failed
+                // assignment, including one closing a cycle, leaves it as it was
+                if previous.is_none()
+                    && result == Err(RuntimeError::Invalid)
+                    && !self.dependencies(&node).free.is_empty()
+                {
+                    return result;
+                }
+                self.invalidate(*v);
+                match previous {
+                    Some(expr) => self.ctx.insert(*v, expr),
+                    None => self.ctx.remove(v),
+                };
+                result
             }
-            Expr::Real(n) => Ok(EvalResult::Float(*n)),
+            Expr::Real(n) => Ok(self.real(*n)),
             Expr::Integer(i) => Ok(EvalResult::Integer(*i)),
             Expr::Bool(b) => Ok(EvalResult::Bool(*b)),
             Expr::Paren(inner) => self.exec(inner),
             Expr::BinaryOp { op, left, right } => {
+                // Attaching a unit to a number, like `3 km`, isn't worth a step of its own
+                let attaches_unit = ast.attaches_unit();
                 let left = self.exec(left)?;
+                if let Some(decided) = op.short_circuits(&left)? {
+                    return Ok(EvalResult::Bool(decided));
+                }
                 let right = self.exec(right)?;
-                op.eval(left, right)
+                let left = self.widen_base(op, left, &right);
+                let step = (self.tracing() && !attaches_unit)
+                    .then(|| Expr::binary(op.clone(), left.clone(), right.clone()));
+                let result = defined(
+                    op.eval_in(left, right, self.angle_mode, self.epsilon),
+                    self.allow_non_finite,
+                    RuntimeError::Indeterminate,
+                );
+                self.record(StepKind::Reduction, step, &result);
+                result
+            }
+            Expr::UnaryOp { op, node } => {
+                let value = self.exec(node)?;
+                let value = self.widen_unary(op, value);
+                let step = self
+                    .tracing()
+                    .then(|| Expr::unary(op.clone(), value.clone()));
+                let result = if op.factorizes() {
+                    self.factorized(op, &value)
+                } else {
+                    defined(
+                        op.eval_in(value, self.angle_mode),
+                        self.allow_non_finite,
+                        RuntimeError::OutOfDomain(op.name()),
+                    )
+                };
+                self.record(StepKind::Reduction, step, &result);
+                result
+            }
+            Expr::AbsVal(expr) => {
+                let value = self.exec(expr)?;
+                let step = self.tracing().then(|| Expr::abs(value.clone()));
+                let result = Self::abs(value);
+                self.record(StepKind::Reduction, step, &result);
+                result
+            }
+            Expr::Constant(c) => Ok(EvalResult::Float(c.value())),
+            Expr::Unit(unit) => Ok(units::quantity(1.0, unit.clone())),
+            Expr::Solve { equation, var } => {
+                let result = self.untraced(|eval| eval.solve(equation, *var));
+                self.record(
+                    StepKind::Reduction,
+                    self.tracing().then(|| ast.clone()),
+                    &result,
+                );
+                result
+            }
+            Expr::Reduce {
+                kind,
+                var,
+                from,
+                to,
+                body,
+            } => {
+                let result = self.untraced(|eval| eval.reduce(*kind, *var, from, to, body));
+                self.record(
+                    StepKind::Reduction,
+                    self.tracing().then(|| ast.clone()),
+                    &result,
+                );
+                result
+            }
+            Expr::List(items) => items
+                .iter()
+                .map(|item| self.exec(item))
+                .collect::<Result<_, _>>()
+                .map(EvalResult::List),
+            Expr::Index { list, index } => {
+                let (list, index) = (self.exec(list)?, self.exec(index)?);
+                let step = self.tracing().then(|| Expr::Index {
+                    list: Box::new(list.clone().into()),
+                    index: Box::new(index.clone().into()),
+                });
+                let result = list::index(&list, &index);
+                self.record(StepKind::Reduction, step, &result);
+                result
+            }
+            Expr::FunctionCall { function, args } if function.takes_function() => {
+                self.apply(*function, args)
+            }
+            Expr::FunctionCall { function, args } if function.is_lazy() => {
+                self.branch(*function, args)
+            }
+            Expr::FunctionCall { function, args } => {
+                let args: Vec<EvalResult> = args
+                    .iter()
+                    .map(|arg| self.exec(arg))
+                    .collect::<Result<_, _>>()?;
+                let step = self.tracing().then(|| Expr::FunctionCall {
+                    function: *function,
+                    args: args.iter().cloned().map(Expr::from).collect(),
+                });
+                let result = match function {
+                    Function::Hist => self.history.recall(&args[0]),
+                    _ => function.eval(&args, &mut self.rng),
+                };
+                self.record(StepKind::Reduction, step, &result);
+                result
+            }
+        }
+    }
+
+    /// Evaluates an operation that [factorizes](UnaryOperator::factorizes) its operand, burning
+    /// fuel while trial dividing so a large prime runs out of it rather than hanging
+    fn factorized(
+        &mut self,
+        op: &UnaryOperator,
+        value: &EvalResult,
+    ) -> Result<EvalResult, RuntimeError> {
+        let factors = factorize_within(value.positive()?, || self.burn())?;
+        op.of_factors(factors)
+    }
+
+    /// The absolute value of a result
+    pub(crate) fn abs(value: EvalResult) -> Result<EvalResult, RuntimeError> {
+        #[cfg(feature = "bigint")]
+        if let Some(abs) = bigint::abs(&value) {
+            return Ok(abs);
+        }
+        match value {
+            EvalResult::Float(f) => Ok(EvalResult::Float(f.abs())),
+            EvalResult::Quantity { value, unit } => Ok(units::quantity(value.abs(), unit)),
+            EvalResult::Complex(re, im) => Ok(complex::modulus(re, im)),
+            value => value.exact_or_float(
+                |(numerator, denominator)| Some((numerator.checked_abs()?, denominator)),
+                f64::abs,
+            ),
+        }
+    }
+
+    /// Evaluates `if` or `piecewise`, whose arguments are condition and value pairs optionally
+    /// followed by a default. Conditions are tried in order and only the value of the first true
+    /// one is evaluated, or the default if none are
+    fn branch(&mut self, function: Function, args: &[Expr]) -> Result<EvalResult, RuntimeError> {
+        let mut cases = args.chunks_exact(2);
+        for case in cases.by_ref() {
+            // Numbers aren't truthy, a condition has to be a comparison or other bool
+            let holds = self.exec(&case[0])?;
+            if holds
+                .bool()
+                .or(Err(RuntimeError::NotACondition(function)))?
+            {
+                return self.exec(&case[1]);
             }
-            Expr::UnaryOp { op, node } => op.eval(self.exec(node)?),
-            Expr::AbsVal(expr) => Ok(EvalResult::Float(f32::abs(self.exec(expr)?.float()?))),
         }
+
+        match cases.remainder() {
+            [default] => self.exec(default),
+            _ => Err(RuntimeError::Unmatched(function)),
+        }
+    }
+
+    /// Combines `body` evaluated with `var` bound to every integer from `from` to `to`. Reversed
+    /// bounds give the empty sum or product
+    fn reduce(
+        &mut self,
+        kind: Reduction,
+        var: char,
+        from: &Expr,
+        to: &Expr,
+        body: &Expr,
+    ) -> Result<EvalResult, RuntimeError> {
+        let from = self.exec(from)?.int()?;
+        let to = self.exec(to)?.int()?;
+        if i128::from(to) - i128::from(from) >= i128::from(MAX_REDUCTION_STEPS) {
+            return Err(RuntimeError::TooManySteps(kind));
+        }
+
+        let mut total = match kind {
+            Reduction::Sum => EvalResult::Integer(0),
+            Reduction::Product => EvalResult::Integer(1),
+        };
+        for index in from..=to {
+            self.burn()?;
+            let term = self.exec_with(body, &[(var, EvalResult::Integer(index))])?;
+            total = kind.combine(total, term)?;
+        }
+
+        Ok(total)
+    }
+}
+
+/// Fails with `error` if an operation gave NaN, unless NaN is allowed, so it can't quietly poison
+/// everything computed from it
+pub(crate) fn defined(
+    result: Result<EvalResult, RuntimeError>,
+    allow_nan: bool,
+    error: RuntimeError,
+) -> Result<EvalResult, RuntimeError> {
+    match result {
+        Ok(value) if !allow_nan && value.is_nan() => Err(error),
+        result => result,
     }
 }
 
-/// All results an AST may have
-#[derive(Debug, Clone, Copy)]
+/// All results an AST may have. Numbers compare by value across variants, so `1 == 1.0`, while
+/// bools and lists are only ever equal to their own kind. There's no `Eq` or `Hash`, since a NaN
+/// float isn't equal to itself and `1` and `1.0` would need to hash the same
+#[derive(Debug, Clone)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize, serde::Deserialize),
+    serde(tag = "type", content = "value", rename_all = "snake_case")
+)]
 pub enum EvalResult {
     /// An integer
-    Integer(i32),
+    Integer(i64),
     /// A float
-    Float(f32),
+    Float(f64),
     /// A bool
     Bool(bool),
+    /// An exact fraction as `(numerator, denominator)`, always in lowest terms with a denominator
+    /// above 1
+    Rational(i64, i64),
+    /// An integer too large for an `i64`, never holding one that would fit. Serialized as a
+    /// decimal string, since JSON numbers can't hold it exactly
+    #[cfg(feature = "bigint")]
+    #[cfg_attr(feature = "serde", serde(with = "bigint::decimal"))]
+    BigInt(num_bigint::BigInt),
+    /// A list of results, which only `==` and `!=` accept
+    List(Vec<EvalResult>),
+    /// A measurement in some unit, like `3.2 km`. Never dimensionless, those are plain floats
+    Quantity {
+        /// How many of the unit
+        value: f64,
+        /// What it's measured in
+        unit: Unit,
+    },
+    /// A complex number as `(real, imaginary)`, only ever given in complex mode and never with an
+    /// imaginary part of 0
+    Complex(f64, f64),
+    /// A fixed-point decimal as `(mantissa, scale)`, counting in the last of `scale` decimal
+    /// places, so `(30, 2)` is 0.30. Only ever given in decimal mode
+    Decimal(i64, u8),
+}
+
+impl From<EvalResult> for Expr {
+    fn from(value: EvalResult) -> Self {
+        match value {
+            EvalResult::Integer(i) => Expr::Integer(i),
+            EvalResult::Float(f) => Expr::Real(f),
+            EvalResult::Bool(b) => Expr::Bool(b),
+            EvalResult::Rational(numerator, denominator) => Expr::BinaryOp {
+                op: BinaryOperator::Divide,
+                left: Box::new(Expr::Integer(numerator)),
+                right: Box::new(Expr::Integer(denominator)),
+            },
+            // Literals only hold an `i64`, so big integers are kept as their nearest float
+            #[cfg(feature = "bigint")]
+            EvalResult::BigInt(big) => Expr::Real(bigint::to_float(&big)),
+            EvalResult::List(items) => Expr::List(items.into_iter().map(Expr::from).collect()),
+            // Whole values read back as integers, they're floats again once the unit is attached
+            EvalResult::Quantity { value, unit } => {
+                let value = if value.fract() == 0.0 && value.abs() < i64::MAX as f64 {
+                    Expr::Integer(value as i64)
+                } else {
+                    Expr::Real(value)
+                };
+                Expr::mul(value, Expr::Unit(unit))
+            }
+            // Reads back as the same decimal in decimal mode with at least as many places
+            decimal @ EvalResult::Decimal(..) => Expr::Real(decimal.float().unwrap_or(f64::NAN)),
+            // Only reads back as the same number in complex mode, with `i` unbound
+            EvalResult::Complex(re, im) => Expr::BinaryOp {
+                op: BinaryOperator::Add,
+                left: Box::new(Expr::Real(re)),
+                right: Box::new(Expr::mul(Expr::Real(im), Expr::Variable('i'))),
+            },
+        }
+    }
 }
 
 impl PartialEq for EvalResult {
     fn eq(&self, other: &Self) -> bool {
-        match (*self, *other) {
-            (Self::Integer(i1), Self::Integer(i2)) => i1 == i2,
-            (Self::Integer(i1), Self::Float(f1)) => i1 as f32 == f1,
-            (Self::Float(f1), Self::Integer(i1)) => f1 == i1 as f32,
-            (Self::Float(f1), Self::Float(f2)) => f1 == f2,
+        match (self, other) {
             (Self::Bool(b1), Self::Bool(b2)) => b1 == b2,
-            _ => false,
+            (Self::List(l1), Self::List(l2)) => l1 == l2,
+            (Self::Bool(_), _) | (_, Self::Bool(_)) | (Self::List(_), _) | (_, Self::List(_)) => {
+                false
+            }
+            _ => self.compare(other) == Ok(Some(Ordering::Equal)),
+        }
+    }
+}
+
+/// Numbers are ordered by value across variants, and lists element by element. Bools are only
+/// comparable when they're equal, matching the language where `true < false` is an error
+impl PartialOrd for EvalResult {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        match (self, other) {
+            (Self::Bool(b1), Self::Bool(b2)) => (b1 == b2).then_some(Ordering::Equal),
+            (Self::List(l1), Self::List(l2)) => l1.partial_cmp(l2),
+            (Self::Bool(_), _) | (_, Self::Bool(_)) | (Self::List(_), _) | (_, Self::List(_)) => {
+                None
+            }
+            _ => self.compare(other).ok().flatten(),
         }
     }
 }
 
+impl From<i64> for EvalResult {
+    fn from(i: i64) -> Self {
+        Self::Integer(i)
+    }
+}
+
+impl From<i32> for EvalResult {
+    fn from(i: i32) -> Self {
+        Self::Integer(i64::from(i))
+    }
+}
+
+impl From<f64> for EvalResult {
+    fn from(f: f64) -> Self {
+        Self::Float(f)
+    }
+}
+
+impl From<f32> for EvalResult {
+    fn from(f: f32) -> Self {
+        Self::Float(f64::from(f))
+    }
+}
+
+impl From<bool> for EvalResult {
+    fn from(b: bool) -> Self {
+        Self::Bool(b)
+    }
+}
+
+/// Whole numbers, including whole floats like `2.0`
+impl TryFrom<EvalResult> for i64 {
+    type Error = RuntimeError;
+    fn try_from(value: EvalResult) -> Result<Self, Self::Error> {
+        value.int()
+    }
+}
+
+/// Whole numbers that fit an `i32`, including whole floats like `2.0`
+impl TryFrom<EvalResult> for i32 {
+    type Error = RuntimeError;
+    fn try_from(value: EvalResult) -> Result<Self, Self::Error> {
+        i32::try_from(value.int()?).map_err(|_| RuntimeError::Invalid)
+    }
+}
+
+/// Any number, rounded to the nearest float
+impl TryFrom<EvalResult> for f64 {
+    type Error = RuntimeError;
+    fn try_from(value: EvalResult) -> Result<Self, Self::Error> {
+        value.float()
+    }
+}
+
+/// Any number, rounded to the nearest `f32`
+impl TryFrom<EvalResult> for f32 {
+    type Error = RuntimeError;
+    fn try_from(value: EvalResult) -> Result<Self, Self::Error> {
+        Ok(value.float()? as f32)
+    }
+}
+
+impl TryFrom<EvalResult> for bool {
+    type Error = RuntimeError;
+    fn try_from(value: EvalResult) -> Result<Self, Self::Error> {
+        value.bool()
+    }
+}
+
 impl EvalResult {
-    /// Gets the result assuming it to be an int, asserting it so through a runtime error
-    pub fn int(&self) -> Result<i32, RuntimeError> {
+    /// Builds the result of an exact fraction, an integer if it's whole. Falls back to a float if
+    /// the fraction is invalid
+    pub fn fraction(fraction: Option<Fraction>, fallback: f64) -> Self {
+        match fraction {
+            Some((numerator, 1)) => Self::Integer(numerator),
+            Some((numerator, denominator)) => Self::Rational(numerator, denominator),
+            None => Self::Float(fallback),
+        }
+    }
+
+    /// Wraps an unsigned integer, which needs a wider type if it's too large for an `i64`
+    pub fn unsigned(value: u64) -> Self {
+        match i64::try_from(value) {
+            Ok(i) => Self::Integer(i),
+            #[cfg(feature = "bigint")]
+            Err(_) => Self::BigInt(value.into()),
+            #[cfg(not(feature = "bigint"))]
+            Err(_) => Self::Float(value as f64),
+        }
+    }
+
+    /// The result as an exact fraction, if it's an integer, rational or decimal
+    pub fn exact(&self) -> Option<Fraction> {
         match self {
-            Self::Integer(i) => Ok(*i),
-            Self::Float(f) if f.round() == *f => Ok(*f as i32),
-            _ => Err(RuntimeError),
+            Self::Integer(i) => Some((*i, 1)),
+            Self::Rational(numerator, denominator) => Some((*numerator, *denominator)),
+            Self::Decimal(mantissa, scale) => decimal::fraction(*mantissa, *scale),
+            _ => None,
+        }
+    }
+
+    /// Applies an operation exactly if the result is exact and the operation succeeds, otherwise
+    /// applies the float version
+    fn exact_or_float(
+        &self,
+        exact: impl FnOnce(Fraction) -> Option<Fraction>,
+        float: impl FnOnce(f64) -> f64,
+    ) -> Result<EvalResult, RuntimeError> {
+        let value = float(self.float()?);
+        match self.exact() {
+            Some(fraction) => Ok(Self::fraction(exact(fraction), value)),
+            None => Ok(EvalResult::Float(value)),
+        }
+    }
+
+    /// Orders two numeric results, exactly unless both are floats. `None` if either is NaN
+    pub(crate) fn compare(&self, other: &Self) -> Result<Option<Ordering>, RuntimeError> {
+        if let Some(ordering) = complex::cmp(self, other) {
+            return ordering;
+        }
+        if let Some(ordering) = units::cmp(self, other) {
+            return ordering;
+        }
+        #[cfg(feature = "bigint")]
+        if let Some(ordering) = bigint::cmp(self, other) {
+            return Ok(Some(ordering));
+        }
+
+        match (self, self.exact(), other, other.exact()) {
+            (_, Some(left), _, Some(right)) => Ok(Some(rational::cmp(left, right))),
+            (_, Some(left), Self::Float(right), _) => Ok(rational::cmp_float(left, *right)),
+            (Self::Float(left), _, _, Some(right)) => {
+                Ok(rational::cmp_float(right, *left).map(Ordering::reverse))
+            }
+            _ => Ok(self.float()?.partial_cmp(&other.float()?)),
+        }
+    }
+
+    /// Gets the result assuming it to be an int, asserting it so through a runtime error
+    pub fn int(&self) -> Result<i64, RuntimeError> {
+        match (self, self.exact()) {
+            (Self::Integer(i), _) => Ok(*i),
+            (Self::Float(f), _) if f.round() == *f => Ok(*f as i64),
+            (Self::Decimal(..), Some((whole, 1))) => Ok(whole),
+            _ => Err(RuntimeError::Invalid),
         }
     }
 
     /// Gets the result assuming it to be an unsigned int, asserting it so through a runtime error
-    pub fn uint(&self) -> Result<u32, RuntimeError> {
+    pub fn uint(&self) -> Result<u64, RuntimeError> {
+        match self {
+            Self::Integer(i) if *i >= 0 => Ok(*i as u64),
+            Self::Float(f) if f.round() == *f && *f >= 0.0 => Ok(*f as u64),
+            Self::Decimal(..) => u64::try_from(self.int()?).map_err(|_| RuntimeError::Invalid),
+            _ => Err(RuntimeError::Invalid),
+        }
+    }
+
+    /// Gets the magnitude of the result assuming it to be an int, for operations like `gcd` that
+    /// don't care about its sign
+    fn magnitude(&self) -> Result<u64, RuntimeError> {
+        match self {
+            Self::Float(f) if f.round() == *f => Ok(f.abs() as u64),
+            _ => self.int().map(i64::unsigned_abs),
+        }
+    }
+
+    /// Gets the result assuming it to be a positive int, asserting it so through a runtime error
+    fn positive(&self) -> Result<u64, RuntimeError> {
+        match self.uint()? {
+            0 => Err(RuntimeError::Invalid),
+            n => Ok(n),
+        }
+    }
+
+    /// Gets the result as the integer bitwise operations work on. Unlike [`Self::int`], whole
+    /// floats are an error too, since their bits aren't an integer's
+    fn bits(&self) -> Result<i64, RuntimeError> {
         match self {
-            Self::Integer(i) if *i >= 0 => Ok(*i as u32),
-            Self::Float(f) if f.round() == *f && *f >= 0.0 => Ok(*f as u32),
-            _ => Err(RuntimeError),
+            Self::Integer(i) => Ok(*i),
+            _ => Err(RuntimeError::Invalid),
         }
     }
 
+    /// Gets the result as how far to shift an integer's bits. Shifting by the whole width or more
+    /// would leave nothing of the value, so it's an error like a negative count
+    fn shift_count(&self) -> Result<u32, RuntimeError> {
+        u32::try_from(self.bits()?)
+            .ok()
+            .filter(|&count| count < i64::BITS)
+            .ok_or(RuntimeError::Invalid)
+    }
+
     /// Gets the result assuming it to be an int, asserting it so through a runtime error
-    pub fn float(&self) -> Result<f32, RuntimeError> {
+    pub fn float(&self) -> Result<f64, RuntimeError> {
         match self {
             Self::Float(f) => Ok(*f),
-            Self::Integer(i) => Ok(*i as f32),
-            _ => Err(RuntimeError),
+            Self::Integer(i) => Ok(*i as f64),
+            Self::Rational(numerator, denominator) => Ok(*numerator as f64 / *denominator as f64),
+            Self::Decimal(mantissa, scale) => Ok(decimal::to_float(*mantissa, *scale)),
+            #[cfg(feature = "bigint")]
+            Self::BigInt(big) => Ok(bigint::to_float(big)),
+            _ => Err(RuntimeError::Invalid),
+        }
+    }
+
+    /// Whether the result is NaN, or a complex number or quantity with a NaN part
+    pub fn is_nan(&self) -> bool {
+        match self {
+            Self::Float(f) | Self::Quantity { value: f, .. } => f.is_nan(),
+            Self::Complex(re, im) => re.is_nan() || im.is_nan(),
+            _ => false,
+        }
+    }
+
+    /// Whether every number in the result is neither infinite nor NaN
+    pub fn is_finite(&self) -> bool {
+        match self {
+            Self::Float(f) | Self::Quantity { value: f, .. } => f.is_finite(),
+            Self::Complex(re, im) => re.is_finite() && im.is_finite(),
+            Self::List(items) => items.iter().all(Self::is_finite),
+            _ => true,
         }
     }
 
@@ -137,7 +1281,7 @@ impl EvalResult {
     pub fn bool(&self) -> Result<bool, RuntimeError> {
         match self {
             Self::Bool(b) => Ok(*b),
-            _ => Err(RuntimeError),
+            _ => Err(RuntimeError::Invalid),
         }
     }
 }
@@ -147,22 +1291,193 @@ impl Display for EvalResult {
         match self {
             Self::Bool(b) => write!(f, "{b}"),
             Self::Integer(i) => write!(f, "{i}"),
+            // Rounding to 15 significant digits hides representation error, so 0.1 + 0.2 shows
+            // as 0.3
+            Self::Float(fl) if fl.is_finite() => {
+                let rounded: f64 = format!("{fl:.14e}").parse().unwrap_or(*fl);
+                write!(f, "{rounded}")
+            }
             Self::Float(fl) => write!(f, "{fl}"),
+            Self::Rational(numerator, denominator) => write!(f, "{numerator}/{denominator}"),
+            #[cfg(feature = "bigint")]
+            Self::BigInt(big) => write!(f, "{big}"),
+            Self::Quantity { value, unit } => write!(f, "{} {unit}", Self::Float(*value)),
+            Self::Complex(re, im) => complex::fmt(f, *re, *im),
+            Self::Decimal(mantissa, scale) => decimal::fmt(f, *mantissa, *scale),
+            Self::List(items) => {
+                write!(f, "[")?;
+                for (i, item) in items.iter().enumerate() {
+                    if i > 0 {
+                        write!(f, ", ")?;
+                    }
+                    write!(f, "{item}")?;
+                }
+                write!(f, "]")
+            }
+        }
+    }
+}
+
+/// What executing a statement did, either giving a value or binding one to a variable
+#[derive(Debug, Clone, PartialEq)]
+pub enum ExecOutcome {
+    /// An expression gave a value
+    Value(EvalResult),
+    /// An assignment bound a variable to an expression, which gave the value
+    Assigned {
+        /// The variable bound
+        name: char,
+        /// What the expression bound gave
+        value: EvalResult,
+    },
+}
+
+impl ExecOutcome {
+    /// The value given, whether it was bound or not
+    pub fn value(&self) -> &EvalResult {
+        match self {
+            Self::Value(value) | Self::Assigned { value, .. } => value,
+        }
+    }
+
+    /// Takes the value given, whether it was bound or not
+    pub fn into_value(self) -> EvalResult {
+        match self {
+            Self::Value(value) | Self::Assigned { value, .. } => value,
+        }
+    }
+
+    /// The variable bound, if the statement was an assignment
+    pub fn assigned(&self) -> Option<char> {
+        match self {
+            Self::Assigned { name, .. } => Some(*name),
+            Self::Value(_) => None,
         }
     }
 }
 
 impl UnaryOperator {
-    /// Evaluates a left and right value with relation to the current operation
+    /// Whether the operation works on the prime factors of a positive integer, which take up to
+    /// its square root in trial divisions to find
+    pub fn factorizes(&self) -> bool {
+        matches!(
+            self,
+            Self::Factorize
+                | Self::Divisors
+                | Self::NumDivisors
+                | Self::SumDivisors
+                | Self::Totient
+        )
+    }
+
+    /// Evaluates an operation that [factorizes](Self::factorizes) from the prime factors of its
+    /// operand
+    pub(crate) fn of_factors(&self, factors: Vec<u64>) -> Result<EvalResult, RuntimeError> {
+        let list = |items: Vec<u64>| {
+            EvalResult::List(items.into_iter().map(EvalResult::unsigned).collect())
+        };
+        match self {
+            Self::Factorize => Ok(list(factors)),
+            Self::Divisors => Ok(list(divisors_from(factors))),
+            Self::NumDivisors => Ok(EvalResult::unsigned(num_divisors_from(factors))),
+            Self::SumDivisors => u64::try_from(sum_divisors_from(factors))
+                .map(EvalResult::unsigned)
+                .map_err(|_| RuntimeError::Invalid),
+            Self::Totient => Ok(EvalResult::unsigned(totient_from(factors))),
+            _ => Err(RuntimeError::Invalid),
+        }
+    }
+
+    /// Evaluates a value with trig functions taking, and inverse trig functions giving, angles
+    /// in `mode`. Postfix degrees convert to `mode`, so they're left alone in degrees mode
+    pub fn eval_in(&self, expr: EvalResult, mode: AngleMode) -> Result<EvalResult, RuntimeError> {
+        if mode == AngleMode::Radians {
+            return self.eval(expr);
+        }
+
+        match self {
+            Self::Degrees => Ok(expr),
+            Self::Sin | Self::Cos | Self::Tan => {
+                self.eval(EvalResult::Float(expr.float()?.to_radians()))
+            }
+            Self::ASin | Self::ACos | Self::ATan => {
+                Ok(EvalResult::Float(self.eval(expr)?.float()?.to_degrees()))
+            }
+            _ => self.eval(expr),
+        }
+    }
+
+    /// Evaluates a left and right value with relation to the current operation. Raising a negative
+    /// number to a fractional power gives the real root for fractions with an odd denominator,
+    /// so `(-8)^(1/3)` is -2, and errors otherwise
     pub fn eval(&self, expr: EvalResult) -> Result<EvalResult, RuntimeError> {
+        if let EvalResult::Quantity { value, unit } = &expr {
+            return units::unary(self, *value, unit);
+        }
+        if let EvalResult::Complex(re, im) = expr {
+            return complex::unary(self, re, im);
+        }
+
         match self {
-            Self::Neg => Ok(EvalResult::Float(-(expr.float()?))),
+            Self::Neg => {
+                #[cfg(feature = "bigint")]
+                if let Some(negated) = bigint::negate(&expr) {
+                    return Ok(negated);
+                }
+                expr.exact_or_float(
+                    |(numerator, denominator)| Some((numerator.checked_neg()?, denominator)),
+                    |f| -f,
+                )
+            }
             Self::Factorial => {
-                let expr = expr.uint()?;
-                Ok(EvalResult::Integer((1..=(expr)).product::<u32>() as i32))
+                let n = match expr.int()? {
+                    negative if negative < 0 => return Err(RuntimeError::OutOfDomain(self.name())),
+                    _ => expr.uint()?,
+                };
+                let exact = (1..=n).try_fold(1i64, |product, i| {
+                    product.checked_mul(i64::try_from(i).ok()?)
+                });
+
+                if let Some(exact) = exact {
+                    return Ok(EvalResult::Integer(exact));
+                }
+                #[cfg(feature = "bigint")]
+                if let Some(big) = bigint::factorial(n) {
+                    return Ok(big);
+                }
+
+                // Otherwise the product only fits in a float, which overflows to infinity at 171!
+                let mut product = 1.0;
+                for i in 1..=n {
+                    product *= i as f64;
+                    if product.is_infinite() {
+                        break;
+                    }
+                }
+                Ok(EvalResult::Float(product))
+            }
+            Self::Degrees => Ok(EvalResult::Float(expr.float()?.to_radians())),
+            Self::Percent => {
+                expr.exact_or_float(|fraction| rational::div(fraction, (100, 1)), |f| f / 100.0)
             }
-            Self::Floor => Ok(EvalResult::Integer(expr.float()?.floor() as i32)),
-            Self::Ceil => Ok(EvalResult::Integer(expr.float()?.ceil() as i32)),
+            Self::Floor => match expr {
+                EvalResult::Rational(numerator, denominator) => Ok(EvalResult::fraction(
+                    Some((numerator.div_euclid(denominator), 1)),
+                    expr.float()?.floor(),
+                )),
+                #[cfg(feature = "bigint")]
+                big @ EvalResult::BigInt(_) => Ok(big),
+                _ => Ok(EvalResult::Integer(expr.float()?.floor() as i64)),
+            },
+            Self::Ceil => match expr {
+                EvalResult::Rational(numerator, denominator) => Ok(EvalResult::fraction(
+                    Some((numerator.div_euclid(denominator) + 1, 1)),
+                    expr.float()?.ceil(),
+                )),
+                #[cfg(feature = "bigint")]
+                big @ EvalResult::BigInt(_) => Ok(big),
+                _ => Ok(EvalResult::Integer(expr.float()?.ceil() as i64)),
+            },
             Self::Cos => Ok(EvalResult::Float(expr.float()?.cos())),
             Self::Sin => Ok(EvalResult::Float(expr.float()?.sin())),
             Self::Tan => Ok(EvalResult::Float(expr.float()?.tan())),
@@ -171,44 +1486,340 @@ impl UnaryOperator {
             Self::ASin => Ok(EvalResult::Float(expr.float()?.asin())),
             Self::ATan => Ok(EvalResult::Float(expr.float()?.atan())),
             Self::Ln => Ok(EvalResult::Float(expr.float()?.ln())),
+            Self::Sqrt => Ok(EvalResult::Float(expr.float()?.sqrt())),
+
+            Self::Sinh => Ok(EvalResult::Float(expr.float()?.sinh())),
+            Self::Cosh => Ok(EvalResult::Float(expr.float()?.cosh())),
+            Self::Tanh => Ok(EvalResult::Float(expr.float()?.tanh())),
+            Self::ASinh => Ok(EvalResult::Float(expr.float()?.asinh())),
+            Self::ACosh => match expr.float()? {
+                x if x >= 1.0 => Ok(EvalResult::Float(x.acosh())),
+                _ => Err(RuntimeError::Invalid),
+            },
+            Self::ATanh => match expr.float()? {
+                x if x.abs() < 1.0 => Ok(EvalResult::Float(x.atanh())),
+                _ => Err(RuntimeError::Invalid),
+            },
+
+            Self::Factorize
+            | Self::Divisors
+            | Self::NumDivisors
+            | Self::SumDivisors
+            | Self::Totient => self.of_factors(expr.positive()?.prime_factorize()),
+            Self::NthPrime => nth_prime(expr.positive()?)
+                .map(EvalResult::unsigned)
+                .ok_or(RuntimeError::Invalid),
+            Self::PrimeCount => prime_count(expr.positive()?)
+                .map(EvalResult::unsigned)
+                .ok_or(RuntimeError::Invalid),
+            Self::BitNot => Ok(EvalResult::Integer(!expr.bits()?)),
+
+            // A real number is its own real part and conjugate
+            Self::Re | Self::Conj => expr.float().map(|_| expr),
+            Self::Im => expr.float().map(|_| EvalResult::Integer(0)),
+            Self::Arg => Ok(EvalResult::Float(0f64.atan2(expr.float()?))),
+        }
+    }
+}
+
+impl Function {
+    /// Evaluates the function on its arguments, which must match its arity. Random functions draw
+    /// from `rng`
+    pub fn eval(&self, args: &[EvalResult], rng: &mut Rng) -> Result<EvalResult, RuntimeError> {
+        if !self.signature().takes(args.len()) {
+            return Err(RuntimeError::Invalid);
+        }
+
+        match self {
+            Self::PowMod => {
+                let (base, exp, m) = (args[0].int()?, args[1].int()?, args[2].int()?);
+                // A negative exponent is a power of the inverse
+                let base = if exp < 0 {
+                    inv_mod(base, m).ok_or(RuntimeError::Invalid)?
+                } else {
+                    base
+                };
+                pow_mod(base, exp.unsigned_abs(), m)
+                    .map(EvalResult::Integer)
+                    .ok_or(RuntimeError::Invalid)
+            }
+            Self::InvMod => inv_mod(args[0].int()?, args[1].int()?)
+                .map(EvalResult::Integer)
+                .ok_or(RuntimeError::Invalid),
+            Self::Rand => Ok(EvalResult::Float(rng.float())),
+            Self::RandInt => rng
+                .int(args[0].int()?, args[1].int()?)
+                .map(EvalResult::Integer)
+                .ok_or(RuntimeError::Invalid),
+            Self::Approx => approx_eq(&args[0], &args[1], args[2].float()?).map(EvalResult::Bool),
+            // Recalling needs the evaluator's history, so the evaluator handles it itself
+            Self::Hist => Err(RuntimeError::Invalid),
+            // Measured against the size of the old value, so growing is always positive
+            Self::PctChange => {
+                let (old, new) = (args[0].float()?, args[1].float()?);
+                self.percent(new - old, old.abs())
+            }
+            Self::PctOf => self.percent(args[0].float()?, args[1].float()?),
+            Self::Sum => stats::sum(stats::values(args)?),
+            Self::Mean => stats::mean(stats::values(args)?),
+            Self::Median => stats::median(stats::values(args)?),
+            Self::Min => stats::min(stats::values(args)?),
+            Self::Max => stats::max(stats::values(args)?),
+            Self::Stdev => stats::stdev(stats::values(args)?),
+            Self::Len => Ok(stats::len(stats::values(args)?)),
+            // The function named by the first argument needs the evaluator, so it handles these
+            Self::Map | Self::Filter | Self::Reduce => Err(RuntimeError::Invalid),
+            // Only the chosen branch is evaluated, so the evaluator handles these itself
+            Self::If | Self::Piecewise => Err(RuntimeError::Invalid),
+            Self::Range => {
+                let step = args.get(2).map_or(Ok(1), EvalResult::int)?;
+                list::range(args[0].int()?, args[1].int()?, step)
+            }
+            Self::ApplyPct => Ok(EvalResult::Float(
+                args[0].float()? * (1.0 + args[1].float()? / 100.0),
+            )),
+        }
+    }
+
+    /// `part` as a percentage of `whole`, failing with this function's name if `whole` is 0
+    fn percent(&self, part: f64, whole: f64) -> Result<EvalResult, RuntimeError> {
+        if whole == 0.0 {
+            return Err(RuntimeError::DivisionByZero(*self));
+        }
+        Ok(EvalResult::Float(part / whole * 100.0))
+    }
+}
+
+/// Whether two numbers are equal within a relative tolerance, so
+/// `|a - b| <= epsilon * max(|a|, |b|)`. Being relative, the same tolerance suits both `1e20` and
+/// `1e-20`, but nothing but 0 itself is approximately 0. Equal values always are, even
+/// infinities. Quantities are compared in the same unit, and bools and lists are an error
+fn approx_eq(left: &EvalResult, right: &EvalResult, epsilon: f64) -> Result<bool, RuntimeError> {
+    let not_number =
+        |value: &EvalResult| matches!(value, EvalResult::Bool(_) | EvalResult::List(_));
+    if not_number(left) || not_number(right) {
+        return Err(RuntimeError::Invalid);
+    }
+    if left == right {
+        return Ok(true);
+    }
+    if let Some(approx) = complex::approx_eq(left, right, epsilon) {
+        return approx;
+    }
+
+    let (a, b) = match units::same_unit(left, right) {
+        Some(values) => values?,
+        None => (left.float()?, right.float()?),
+    };
+    Ok((a - b).abs() <= epsilon * a.abs().max(b.abs()))
+}
+
+impl Reduction {
+    /// Adds or multiplies a term into a running total, staying exact until it would overflow
+    pub fn combine(&self, total: EvalResult, term: EvalResult) -> Result<EvalResult, RuntimeError> {
+        match self {
+            Self::Sum => BinaryOperator::Add.eval(total, term),
+            Self::Product => BinaryOperator::Multiply.eval(total, term),
         }
     }
 }
 
 impl BinaryOperator {
+    /// The result of `&&` or `||` if the left side alone decides it, so the right side is never
+    /// evaluated, like `false && x`. Errors if the left side of either isn't a bool
+    pub fn short_circuits(&self, left: &EvalResult) -> Result<Option<bool>, RuntimeError> {
+        match self {
+            Self::And => Ok((!left.bool()?).then_some(false)),
+            Self::Or => Ok(left.bool()?.then_some(true)),
+            _ => Ok(None),
+        }
+    }
+
+    /// Evaluates a left and right value with `atan2` giving its angle in `mode` and `~=`
+    /// comparing within the relative tolerance `epsilon`
+    pub fn eval_in(
+        &self,
+        left: EvalResult,
+        right: EvalResult,
+        mode: AngleMode,
+        epsilon: f64,
+    ) -> Result<EvalResult, RuntimeError> {
+        match (self, mode) {
+            (Self::Atan2, AngleMode::Degrees) => Ok(EvalResult::Float(
+                self.eval(left, right)?.float()?.to_degrees(),
+            )),
+            (Self::ApproxEq, _) => approx_eq(&left, &right, epsilon).map(EvalResult::Bool),
+            _ => self.eval(left, right),
+        }
+    }
+
     /// Evaluates a left and right value with relation to the current operation
     pub fn eval(&self, left: EvalResult, right: EvalResult) -> Result<EvalResult, RuntimeError> {
+        // Integers and rationals stay exact unless the result overflows or divides by zero, in
+        // which case the float version below is used
+        if let (Some(l), Some(r)) = (left.exact(), right.exact()) {
+            let exact = match self {
+                Self::Add => rational::add(l, r),
+                Self::Subtract => rational::sub(l, r),
+                Self::Multiply => rational::mul(l, r),
+                Self::Divide => rational::div(l, r),
+                Self::Mod => rational::rem(l, r),
+                Self::Pow if r.1 == 1 => rational::pow(l, r.0),
+                _ => None,
+            };
+
+            match exact {
+                Some((numerator, 1)) => return Ok(EvalResult::Integer(numerator)),
+                Some((numerator, denominator)) => {
+                    return Ok(EvalResult::Rational(numerator, denominator));
+                }
+                None => {}
+            }
+        }
+
+        if let Some(result) = units::binary(self, &left, &right) {
+            return result;
+        }
+        if let Some(result) = complex::binary(self, &left, &right) {
+            return result;
+        }
+
+        // Whole numbers that overflow an `i64` carry on as big integers
+        #[cfg(feature = "bigint")]
+        if let Some(result) = bigint::binary(self, &left, &right) {
+            return Ok(result);
+        }
+
         match self {
             Self::Add => Ok(EvalResult::Float(left.float()? + right.float()?)),
             Self::Divide => Ok(EvalResult::Float(left.float()? / right.float()?)),
             Self::Multiply => Ok(EvalResult::Float(left.float()? * right.float()?)),
             Self::Subtract => Ok(EvalResult::Float(left.float()? - right.float()?)),
-            Self::Pow => Ok(EvalResult::Float(left.float()?.powf(right.float()?))),
-            Self::Gcd => Ok(EvalResult::Integer(gcd(left.uint()?, right.uint()?))),
-            Self::Lcm => Ok(EvalResult::Integer(lcm(left.uint()?, right.uint()?))),
+            Self::Mod => {
+                let (a, m) = (left.float()?, right.float()?);
+                Ok(EvalResult::Float(a - m * (a / m).floor()))
+            }
+            Self::Pow => {
+                let (base, exponent) = (left.float()?, right.float()?);
+                if base >= 0.0 || !exponent.is_finite() || exponent.fract() == 0.0 {
+                    return Ok(EvalResult::Float(base.powf(exponent)));
+                }
+
+                // A negative base only has a real power if the exponent is a fraction with an
+                // odd denominator, like the cube root in `(-8)^(1/3)`
+                match right.exact() {
+                    Some((numerator, denominator)) if denominator % 2 != 0 => {
+                        let magnitude = (-base).powf(exponent);
+                        Ok(EvalResult::Float(if numerator % 2 == 0 {
+                            magnitude
+                        } else {
+                            -magnitude
+                        }))
+                    }
+                    _ => Err(RuntimeError::Invalid),
+                }
+            }
+            Self::Atan2 => Ok(EvalResult::Float(left.float()?.atan2(right.float()?))),
+            Self::Gcd => Ok(EvalResult::unsigned(gcd(
+                left.magnitude()?,
+                right.magnitude()?,
+            ))),
+            Self::BitAnd => Ok(EvalResult::Integer(left.bits()? & right.bits()?)),
+            Self::BitOr => Ok(EvalResult::Integer(left.bits()? | right.bits()?)),
+            Self::BitXor => Ok(EvalResult::Integer(left.bits()? ^ right.bits()?)),
+            Self::Shl => {
+                // Shifted in a wider integer so bits pushed off the top are an overflow, rather
+                // than quietly lost
+                let shifted = i128::from(left.bits()?) << right.shift_count()?;
+                i64::try_from(shifted)
+                    .map(EvalResult::Integer)
+                    .map_err(|_| RuntimeError::Invalid)
+            }
+            Self::Shr => Ok(EvalResult::Integer(left.bits()? >> right.shift_count()?)),
+            Self::Lcm => {
+                let (a, b) = (left.magnitude()?, right.magnitude()?);
+                match lcm(a, b) {
+                    Some(lcm) => Ok(EvalResult::unsigned(lcm)),
+                    #[cfg(feature = "bigint")]
+                    None => Ok(bigint::lcm(a, b)),
+                    #[cfg(not(feature = "bigint"))]
+                    None => Err(RuntimeError::Invalid),
+                }
+            }
 
             // Boolean operations
             Self::Eq => Ok(EvalResult::Bool(left == right)),
             Self::NEq => Ok(EvalResult::Bool(left != right)),
-            Self::Gt => Ok(EvalResult::Bool(left.float()? > right.float()?)),
-            Self::Gte => Ok(EvalResult::Bool(left.float()? >= right.float()?)),
-            Self::Lt => Ok(EvalResult::Bool(left.float()? < right.float()?)),
-            Self::Lte => Ok(EvalResult::Bool(left.float()? <= right.float()?)),
+            // Without an evaluator's setting to go by, `~=` uses the default tolerance
+            Self::ApproxEq => approx_eq(&left, &right, DEFAULT_EPSILON).map(EvalResult::Bool),
+            Self::Gt => Ok(EvalResult::Bool(
+                left.compare(&right)?.is_some_and(Ordering::is_gt),
+            )),
+            Self::Gte => Ok(EvalResult::Bool(
+                left.compare(&right)?.is_some_and(Ordering::is_ge),
+            )),
+            Self::Lt => Ok(EvalResult::Bool(
+                left.compare(&right)?.is_some_and(Ordering::is_lt),
+            )),
+            Self::Lte => Ok(EvalResult::Bool(
+                left.compare(&right)?.is_some_and(Ordering::is_le),
+            )),
 
             Self::And => Ok(EvalResult::Bool(left.bool()? && right.bool()?)),
             Self::Or => Ok(EvalResult::Bool(left.bool()? || right.bool()?)),
+
+            // Plain numbers have no unit to convert from
+            Self::Convert => Err(RuntimeError::Invalid),
         }
     }
 }
 
 #[cfg(test)]
 mod tests {
+    use std::{cmp::Ordering, collections::BTreeSet};
+
     use crate::{
-        ast::{Expr, Parser},
-        exec::Evaluator,
+        ast::{Expr, Function, MAX_DEPTH, Parser, Reduction},
+        exec::{AngleMode, Dependencies, EvalResult, Evaluator, ExecOutcome, RuntimeError},
+        math::random::Rng,
+        pipeline::testing::{parse, run},
         tokenizer::Tokenizable,
     };
 
+    /// What random inputs are built from, separated by spaces: single characters, including
+    /// multi-byte ones that slicing by character index would break on, plus the names, keywords and
+    /// extreme numbers the parser and evaluator treat specially
+    const PIECES: &str = "0 1 2 9 . -1 0.5 9223372036854775807 4611686018427387904 x y i t e é π 中 \
+        ° ÷ + - * / ^ ! % | || & && = == < >= ( ) [ ] , # pi true sin ln sqrt floor atan2 gcd \
+        invmod powmod factorize divisors rand randint sum prod solve in km h mph";
+
+    #[test]
+    fn arbitrary_input_never_panics() {
+        let pieces: Vec<_> = PIECES.split_whitespace().chain([" ", "\n"]).collect();
+        let mut rng = Rng::seeded(0);
+
+        for _ in 0..20_000 {
+            let len = rng.int(1, 14).unwrap();
+            let input: String = (0..len)
+                .map(|_| pieces[rng.int(0, pieces.len() as i64 - 1).unwrap() as usize])
+                .collect();
+
+            let Ok(tokens) = input.tokenize_spanned() else {
+                continue;
+            };
+            let Ok(ast) = Parser::with_spans(tokens).parse() else {
+                continue;
+            };
+
+            let mut eval = Evaluator::default();
+            eval.set('y', Expr::Integer(2));
+            let _ = ast.to_string();
+            if let Ok(result) = eval.exec(&ast) {
+                let _ = result.to_string();
+            }
+        }
+    }
+
     #[test]
     fn complex_dependency() {
         let tokens = "y = 3x + 5".tokenize().expect("Tokenize stream");
@@ -216,7 +1827,7 @@ mod tests {
         let ast = parser.parse().expect("Failed to parse");
 
         let mut eval = Evaluator::default();
-        eval.ctx.insert('x', Expr::Integer(0));
+        eval.set('x', Expr::Integer(0));
         eval.exec(&ast).expect("Eval");
 
         let tokens = "cos(y)".tokenize().expect("Tokenize stream");
@@ -227,6 +1838,279 @@ mod tests {
         assert!(!eval.depends_on(&ast, 'f'));
     }
 
+    #[test]
+    fn statement_outcomes() {
+        let mut eval = Evaluator::default();
+        let mut run = |source: &str| {
+            let ast = parse(source);
+            eval.exec_statement(&ast).expect("Eval")
+        };
+
+        assert_eq!(
+            run("x = 4"),
+            ExecOutcome::Assigned {
+                name: 'x',
+                value: EvalResult::Integer(4)
+            }
+        );
+        assert_eq!(run("x + 1"), ExecOutcome::Value(EvalResult::Integer(5)));
+
+        // Only the outermost assignment is the statement's binding, and its value is still usable
+        let chained = run("y = (z = 2) * x");
+        assert_eq!(chained.assigned(), Some('y'));
+        assert_eq!(chained.value(), &EvalResult::Integer(8));
+        assert_eq!(run("z"), ExecOutcome::Value(EvalResult::Integer(2)));
+    }
+
+    #[test]
+    fn failed_assignments() {
+        let mut eval = Evaluator::default();
+        run(&mut eval, "x = 5").expect("Assign");
+
+        // Failing for any reason leaves the previous binding
+        for statement in ["x = 1/0", "x = [1, 2][5]", "x = true + 1"] {
+            assert!(run(&mut eval, statement).is_err(), "{statement}");
+            assert_eq!(eval.get('x'), Some(&Expr::Integer(5)), "{statement}");
+        }
+        assert_eq!(run(&mut eval, "x = x * 2 + 1"), Ok(EvalResult::Integer(11)));
+        assert_eq!(eval.value('x'), Some(Ok(EvalResult::Integer(11))));
+
+        // Or no binding at all
+        assert!(run(&mut eval, "n = 1/0").is_err());
+        assert!(run(&mut eval, "n = sqrt(true)").is_err());
+        assert_eq!(eval.get('n'), None);
+
+        // Expressions of unbound variables are kept for later, but not cycles through them
+        assert_eq!(run(&mut eval, "f = g + 1"), Err(RuntimeError::Invalid));
+        assert_eq!(run(&mut eval, "g = f * 2"), Err(RuntimeError::TooDeep));
+        assert_eq!(eval.get('g'), None);
+        assert!(eval.get('f').is_some());
+        run(&mut eval, "g = 3").expect("Assign");
+        assert_eq!(eval.value('f'), Some(Ok(EvalResult::Integer(4))));
+
+        // Only new variables, one already bound keeps its value
+        let bound = eval.get('x').cloned();
+        assert_eq!(run(&mut eval, "x = x + q"), Err(RuntimeError::Invalid));
+        assert_eq!(eval.get('x').cloned(), bound);
+        run(&mut eval, "q = 1").expect("Assign");
+        assert_eq!(eval.value('x'), Some(Ok(EvalResult::Integer(11))));
+    }
+
+    #[test]
+    fn compound_assignment() {
+        let mut eval = Evaluator::default();
+        assert_eq!(run(&mut eval, "t += 1"), Err(RuntimeError::Unbound('t')));
+        assert!(eval.vars().is_empty());
+        assert_eq!(
+            RuntimeError::Unbound('t').to_string(),
+            "`t` has no value to update yet :("
+        );
+
+        for (statement, value) in [
+            ("t = 10", EvalResult::Integer(10)),
+            ("t += 5", EvalResult::Integer(15)),
+            ("t -= 3", EvalResult::Integer(12)),
+            ("t *= 1 + 1", EvalResult::Integer(24)),
+            ("t /= 5", EvalResult::Rational(24, 5)),
+            ("t = t + 1", EvalResult::Rational(29, 5)),
+        ] {
+            assert_eq!(run(&mut eval, statement), Ok(value), "{statement}");
+        }
+
+        // The variable's value is captured when it's updated, while the rest stays lazy
+        run(&mut eval, "y = 1").expect("Bind y");
+        run(&mut eval, "x = y").expect("Bind x");
+        assert_eq!(run(&mut eval, "x += y"), Ok(EvalResult::Integer(2)));
+        assert_eq!(
+            eval.ctx.get(&'x').map(ToString::to_string),
+            Some("1 + y".into())
+        );
+        run(&mut eval, "y = 10").expect("Rebind y");
+        assert_eq!(run(&mut eval, "x"), Ok(EvalResult::Integer(11)));
+
+        // Updates don't nest deeper each time, so accumulating never runs out of depth
+        for _ in 0..MAX_DEPTH * 2 {
+            run(&mut eval, "y += 1").expect("Accumulate");
+        }
+        assert_eq!(
+            eval.ctx.get(&'y').map(ToString::to_string),
+            Some("521 + 1".into())
+        );
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn serde_round_trip() {
+        for result in [
+            EvalResult::Integer(-3),
+            EvalResult::Float(2.5),
+            EvalResult::Bool(true),
+        ] {
+            let json = serde_json::to_string(&result).expect("Serialize");
+            let restored: EvalResult = serde_json::from_str(&json).expect("Deserialize");
+            assert_eq!(restored, result);
+        }
+
+        assert_eq!(
+            serde_json::to_string(&EvalResult::Float(2.5)).expect("Serialize"),
+            r#"{"type":"float","value":2.5}"#
+        );
+
+        #[cfg(feature = "bigint")]
+        {
+            let big = EvalResult::big(num_bigint::BigInt::from(u64::MAX) * 3);
+            let json = serde_json::to_string(&big).expect("Serialize");
+            assert_eq!(json, r#"{"type":"big_int","value":"55340232221128654845"}"#);
+            let restored: EvalResult = serde_json::from_str(&json).expect("Deserialize");
+            assert_eq!(restored, big);
+        }
+    }
+
+    #[test]
+    fn full_dependencies() {
+        let tokens = "y = 3x + 5".tokenize().expect("Tokenize stream");
+        let mut parser = Parser::new(tokens);
+        let ast = parser.parse().expect("Failed to parse");
+
+        let mut eval = Evaluator::default();
+        eval.set('x', Expr::Integer(0));
+        eval.exec(&ast).expect("Eval");
+
+        let tokens = "cos(y) + z * |a|".tokenize().expect("Tokenize stream");
+        let mut parser = Parser::new(tokens);
+        let ast = parser.parse().expect("Failed to parse");
+
+        assert_eq!(
+            eval.dependencies(&ast),
+            Dependencies {
+                free: BTreeSet::from(['a', 'z']),
+                bound: BTreeSet::from(['x', 'y']),
+            }
+        );
+    }
+
+    #[test]
+    fn cyclic_dependencies() {
+        let mut eval = Evaluator::default();
+        eval.set('a', Expr::Variable('b'));
+        eval.set('b', Expr::Variable('a'));
+
+        assert_eq!(
+            eval.dependencies(&Expr::Variable('a')),
+            Dependencies {
+                free: BTreeSet::new(),
+                bound: BTreeSet::from(['a', 'b']),
+            }
+        );
+    }
+
+    #[test]
+    fn substituting_through_bindings() {
+        let mut eval = Evaluator::default();
+        eval.set('y', parse("x + 1"));
+        eval.set('x', Expr::Integer(10));
+        eval.set('f', parse("sum(i, 1, 3, i * y)"));
+
+        let substituted = eval
+            .substitute(&parse("2y + z"), 'x', &parse("t^2"))
+            .expect("Substitute");
+        assert_eq!(substituted.to_string(), "2 * (t ^ 2 + 1) + z");
+        assert_eq!(
+            eval.substitute(&parse("f"), 'x', &Expr::Integer(2))
+                .map(|expr| expr.to_string()),
+            Ok("sum(i, 1, 3, i * (2 + 1))".to_string())
+        );
+
+        // Evaluates the same as binding the variable, leaving other variables alone
+        eval.set('t', Expr::Integer(3));
+        let bound = eval.exec_with(&parse("2y + f"), &[('x', EvalResult::Integer(9))]);
+        let substituted = eval
+            .substitute(&parse("2y + f"), 'x', &parse("t^2"))
+            .expect("Substitute");
+        assert_eq!(eval.exec(&substituted), bound);
+        assert_eq!(eval.get('x'), Some(&Expr::Integer(10)));
+
+        eval.set('a', Expr::Variable('b'));
+        eval.set('b', Expr::Variable('a'));
+        assert_eq!(
+            eval.substitute(&parse("a + x"), 'x', &Expr::Integer(1)),
+            Err(RuntimeError::TooDeep)
+        );
+    }
+
+    #[test]
+    fn sampling() {
+        let ast = parse("x^2");
+
+        let mut eval = Evaluator::default();
+        eval.set('x', Expr::Integer(7));
+
+        assert_eq!(
+            eval.sample(&ast, 'x', -2.0, 2.0, 5).expect("Sample"),
+            [4.0, 1.0, 0.0, 1.0, 4.0]
+        );
+        assert_eq!(eval.get('x'), Some(&Expr::Integer(7)));
+
+        assert_eq!(eval.sample(&ast, 'x', 3.0, 9.0, 1).expect("Sample"), [9.0]);
+        assert!(
+            eval.sample(&ast, 'x', 0.0, 1.0, 0)
+                .expect("Sample")
+                .is_empty()
+        );
+    }
+
+    #[test]
+    fn sampling_non_finite_points() {
+        let ast = parse("1 / x");
+
+        let mut eval = Evaluator::default();
+        let samples = eval.sample(&ast, 'x', -1.0, 1.0, 3).expect("Sample");
+
+        assert_eq!(samples[0], -1.0);
+        assert!(samples[1].is_nan());
+        assert_eq!(samples[2], 1.0);
+        assert_eq!(eval.get('x'), None);
+
+        let ast = parse("x > 0");
+        assert!(eval.sample(&ast, 'x', -1.0, 1.0, 3).is_err());
+    }
+
+    #[test]
+    fn context_accessors() {
+        let mut eval = Evaluator::default();
+        eval.set('y', Expr::Integer(2));
+        eval.set('x', Expr::Bool(true));
+
+        assert_eq!(eval.get('x'), Some(&Expr::Bool(true)));
+        assert_eq!(
+            eval.vars(),
+            [('x', &Expr::Bool(true)), ('y', &Expr::Integer(2))]
+        );
+
+        assert_eq!(eval.remove('x'), Some(Expr::Bool(true)));
+        assert_eq!(eval.get('x'), None);
+
+        eval.clear();
+        assert!(eval.vars().is_empty());
+    }
+
+    #[test]
+    fn evaluating_bound_values() {
+        let mut eval = Evaluator::default();
+        eval.set('y', Expr::var('x') + 1);
+
+        assert_eq!(eval.value('z'), None);
+        assert!(matches!(eval.value('y'), Some(Err(_))));
+
+        eval.set('x', Expr::Integer(2));
+        eval.set('b', Expr::Variable('q'));
+
+        let values = eval.values();
+        assert_eq!(values[1], ('x', Ok(EvalResult::Integer(2))));
+        assert_eq!(values[2], ('y', Ok(EvalResult::Integer(3))));
+        assert!(matches!(values[0], ('b', Err(_))));
+    }
+
     #[test]
     fn depends_on() {
         let tokens = "15 + (30 / 100x)".tokenize().expect("Tokenize stream");
@@ -238,4 +2122,967 @@ mod tests {
         assert!(eval.depends_on(&ast, 'x'));
         assert!(!eval.depends_on(&ast, 'f'));
     }
+
+    #[test]
+    fn sums_and_products() {
+        let mut eval = Evaluator::default();
+
+        assert_eq!(
+            run(&mut eval, "sum(i, 1, 10, i)"),
+            Ok(EvalResult::Integer(55))
+        );
+        assert_eq!(
+            run(&mut eval, "prod(k, 1, 5, k)"),
+            Ok(EvalResult::Integer(120))
+        );
+        assert_eq!(
+            run(&mut eval, "sum(i, 1, 3, i^2)"),
+            Ok(EvalResult::Float(14.0))
+        );
+        assert_eq!(
+            run(&mut eval, "sum(i, 5, 1, i)"),
+            Ok(EvalResult::Integer(0))
+        );
+        assert_eq!(
+            run(&mut eval, "prod(i, 5, 1, i)"),
+            Ok(EvalResult::Integer(1))
+        );
+
+        // 21! overflows an i64, so the product carries on as a float
+        #[cfg(not(feature = "bigint"))]
+        assert_eq!(
+            run(&mut eval, "prod(k, 1, 21, k)"),
+            Ok(EvalResult::Float(51_090_942_171_709_440_000.0))
+        );
+    }
+
+    #[test]
+    fn sums_use_outer_variables() {
+        let mut eval = Evaluator::default();
+        eval.set('a', Expr::Integer(2));
+        eval.set('i', Expr::Integer(100));
+
+        assert_eq!(
+            run(&mut eval, "sum(i, 1, 4, a * i) + i"),
+            Ok(EvalResult::Float(120.0))
+        );
+        assert_eq!(eval.get('i'), Some(&Expr::Integer(100)));
+        assert_eq!(
+            run(&mut eval, "sum(i, 1, a + 1, 1)"),
+            Ok(EvalResult::Integer(3))
+        );
+
+        let ast = parse("sum(i, 1, n, i * a)");
+        let deps = eval.dependencies(&ast);
+        assert_eq!(deps.free, BTreeSet::from(['n']));
+        assert_eq!(deps.bound, BTreeSet::from(['a']));
+    }
+
+    #[test]
+    fn bad_reductions() {
+        let mut eval = Evaluator::default();
+
+        assert_eq!(
+            run(&mut eval, "sum(i, 1, 2.5, i)"),
+            Err(RuntimeError::Invalid)
+        );
+        assert_eq!(
+            run(&mut eval, "sum(i, true, 2, i)"),
+            Err(RuntimeError::Invalid)
+        );
+        assert_eq!(
+            run(&mut eval, "sum(i, 1, 1000001, i)"),
+            Err(RuntimeError::TooManySteps(Reduction::Sum))
+        );
+        assert_eq!(
+            run(&mut eval, "sum(i, 1, 10^7, i)"),
+            Err(RuntimeError::TooManySteps(Reduction::Sum))
+        );
+        assert_eq!(
+            RuntimeError::TooManySteps(Reduction::Product).to_string(),
+            "`prod` can have at most 1000000 terms :("
+        );
+        assert_eq!(
+            run(&mut eval, "sum(i, 1, 2, i > 1)"),
+            Err(RuntimeError::Invalid)
+        );
+    }
+
+    #[test]
+    fn exec_with_shadows_outer_bindings() {
+        let mut eval = Evaluator::default();
+        eval.set('x', Expr::Integer(1));
+        let assign = parse("y = x * 10");
+        eval.exec(&assign).expect("Assign y");
+
+        let y = Expr::Variable('y');
+        assert_eq!(
+            eval.exec_with(&y, &[('x', EvalResult::Integer(4))]),
+            Ok(EvalResult::Float(40.0))
+        );
+        assert_eq!(eval.exec(&y), Ok(EvalResult::Float(10.0)));
+        assert_eq!(eval.get('x'), Some(&Expr::Integer(1)));
+
+        // A later binding of the same variable wins, and both are undone
+        let bindings = [('z', EvalResult::Integer(2)), ('z', EvalResult::Bool(true))];
+        assert_eq!(
+            eval.exec_with(&Expr::Variable('z'), &bindings),
+            Ok(EvalResult::Bool(true))
+        );
+        assert_eq!(eval.get('z'), None);
+    }
+
+    #[test]
+    fn exec_with_restores_after_errors() {
+        let mut eval = Evaluator::default();
+        eval.set('a', Expr::Integer(3));
+
+        let ast = parse("a = b + 1");
+        let bindings = [('a', EvalResult::Float(0.5)), ('c', EvalResult::Integer(7))];
+
+        assert_eq!(eval.exec_with(&ast, &bindings), Err(RuntimeError::Invalid));
+        assert_eq!(eval.vars(), [('a', &Expr::Integer(3))]);
+    }
+
+    #[test]
+    fn nested_exec_with() {
+        let mut eval = Evaluator::default();
+        let ast = parse("sum(i, 1, n, i * k) + i");
+
+        let bindings = [
+            ('n', EvalResult::Integer(3)),
+            ('k', EvalResult::Integer(2)),
+            ('i', EvalResult::Integer(100)),
+        ];
+        assert_eq!(
+            eval.exec_with(&ast, &bindings),
+            Ok(EvalResult::Float(112.0))
+        );
+        assert!(eval.vars().is_empty());
+    }
+
+    #[test]
+    fn rationals_stay_exact() {
+        let mut eval = Evaluator::default();
+
+        assert_eq!(run(&mut eval, "1/3 * 3 == 1"), Ok(EvalResult::Bool(true)));
+        assert_eq!(run(&mut eval, "2/4"), Ok(EvalResult::Rational(1, 2)));
+        assert_eq!(run(&mut eval, "2/4").unwrap().to_string(), "1/2");
+        assert_eq!(
+            run(&mut eval, "sqrt(1/4) == 0.5"),
+            Ok(EvalResult::Bool(true))
+        );
+
+        assert_eq!(run(&mut eval, "1/2 + 1/3"), Ok(EvalResult::Rational(5, 6)));
+        assert_eq!(run(&mut eval, "(2/3)^-2"), Ok(EvalResult::Rational(9, 4)));
+        assert_eq!(run(&mut eval, "-(1/2) - 1/2"), Ok(EvalResult::Integer(-1)));
+        assert_eq!(run(&mut eval, "|-3/4|"), Ok(EvalResult::Rational(3, 4)));
+        assert_eq!(run(&mut eval, "50%"), Ok(EvalResult::Rational(1, 2)));
+        assert_eq!(run(&mut eval, "floor(-1/2)"), Ok(EvalResult::Integer(-1)));
+        assert_eq!(run(&mut eval, "ceil(7/2)"), Ok(EvalResult::Integer(4)));
+        assert_eq!(run(&mut eval, "6/3"), Ok(EvalResult::Integer(2)));
+    }
+
+    #[test]
+    fn rationals_degrade_to_floats() {
+        let mut eval = Evaluator::default();
+
+        assert_eq!(run(&mut eval, "1/2 + 0.25"), Ok(EvalResult::Float(0.75)));
+        assert_eq!(run(&mut eval, "4^(1/2)"), Ok(EvalResult::Float(2.0)));
+        assert_eq!(run(&mut eval, "1/0"), Ok(EvalResult::Float(f64::INFINITY)));
+        #[cfg(not(feature = "bigint"))]
+        assert_eq!(run(&mut eval, "2^70"), Ok(EvalResult::Float(2f64.powi(70))));
+    }
+
+    #[test]
+    fn negative_bases() {
+        let mut eval = Evaluator::default();
+
+        assert_eq!(run(&mut eval, "(-2)^3"), Ok(EvalResult::Integer(-8)));
+        assert_eq!(run(&mut eval, "(-2)^2"), Ok(EvalResult::Integer(4)));
+        assert_eq!(run(&mut eval, "2^-2 == 0.25"), Ok(EvalResult::Bool(true)));
+        assert_eq!(run(&mut eval, "(-2)^-3"), Ok(EvalResult::Rational(-1, 8)));
+        assert_eq!(run(&mut eval, "(-1.5)^2"), Ok(EvalResult::Float(2.25)));
+
+        // Odd roots of negative numbers are real, even roots aren't
+        assert_eq!(run(&mut eval, "(-8)^(1/3)"), Ok(EvalResult::Float(-2.0)));
+        assert_eq!(run(&mut eval, "(-1)^(2/3)"), Ok(EvalResult::Float(1.0)));
+        assert_eq!(run(&mut eval, "(-4)^0.5"), Err(RuntimeError::Invalid));
+        assert_eq!(run(&mut eval, "(-4)^(1/2)"), Err(RuntimeError::Invalid));
+        assert_eq!(run(&mut eval, "(-8)^0.3"), Err(RuntimeError::Invalid));
+    }
+
+    #[test]
+    fn gcd_and_lcm() {
+        let mut eval = Evaluator::default();
+
+        assert_eq!(run(&mut eval, "gcd(0, 0)"), Ok(EvalResult::Integer(0)));
+        assert_eq!(run(&mut eval, "lcm(0, 5)"), Ok(EvalResult::Integer(0)));
+        assert_eq!(
+            run(&mut eval, "lcm(600851475143, 1000003)"),
+            Ok(EvalResult::Integer(600_851_475_143 * 1_000_003))
+        );
+
+        // Only the magnitudes matter, so the sign of either doesn't
+        for (expression, expected) in [
+            ("gcd(-8, 12)", 4),
+            ("gcd(8, -12)", 4),
+            ("gcd(-8, -12)", 4),
+            ("gcd(-5, 0)", 5),
+            ("gcd(-6.0, 9)", 3),
+            ("lcm(-4, 6)", 12),
+            ("lcm(4, -6)", 12),
+            ("lcm(-4, -6)", 12),
+            ("lcm(-3, 0)", 0),
+        ] {
+            assert_eq!(
+                run(&mut eval, expression),
+                Ok(EvalResult::Integer(expected)),
+                "{expression}"
+            );
+        }
+        assert_eq!(
+            run(&mut eval, "gcd(-9223372036854775807, 0)"),
+            Ok(EvalResult::Integer(i64::MAX))
+        );
+        assert_eq!(run(&mut eval, "gcd(3.5, 2)"), Err(RuntimeError::Invalid));
+        assert_eq!(run(&mut eval, "lcm(2, -0.5)"), Err(RuntimeError::Invalid));
+
+        // Coprime, so the lcm is their product and overflows a u64
+        let overflowing = "lcm(9223372036854775807, 9223372036854775806)";
+        #[cfg(not(feature = "bigint"))]
+        assert_eq!(run(&mut eval, overflowing), Err(RuntimeError::Invalid));
+        #[cfg(feature = "bigint")]
+        assert_eq!(
+            run(&mut eval, overflowing).map(|lcm| lcm.to_string()),
+            Ok("85070591730234615838173535747377725442".to_string())
+        );
+    }
+
+    #[test]
+    fn divisor_functions() {
+        let mut eval = Evaluator::default();
+        let list = |items: &[i64]| {
+            Ok(EvalResult::List(
+                items.iter().copied().map(EvalResult::Integer).collect(),
+            ))
+        };
+
+        assert_eq!(run(&mut eval, "factorize(100)"), list(&[2, 2, 5, 5]));
+        assert_eq!(
+            run(&mut eval, "factorize(100)").unwrap().to_string(),
+            "[2, 2, 5, 5]"
+        );
+        assert_eq!(run(&mut eval, "factorize(1)"), list(&[]));
+        assert_eq!(run(&mut eval, "divisors(28)"), list(&[1, 2, 4, 7, 14, 28]));
+        assert_eq!(
+            run(&mut eval, "numdivisors(28)"),
+            Ok(EvalResult::Integer(6))
+        );
+        assert_eq!(
+            run(&mut eval, "sumdivisors(28) == 56"),
+            Ok(EvalResult::Bool(true))
+        );
+        assert_eq!(
+            run(&mut eval, "factorize(12) == factorize(2 * 6)"),
+            Ok(EvalResult::Bool(true))
+        );
+
+        assert_eq!(
+            run(&mut eval, "totient(10) == 4 && totient(97) == 96"),
+            Ok(EvalResult::Bool(true))
+        );
+        assert_eq!(run(&mut eval, "totient(1)"), Ok(EvalResult::Integer(1)));
+
+        for bad in [
+            "factorize(0)",
+            "divisors(-4)",
+            "sumdivisors(2.5)",
+            "totient(0)",
+        ] {
+            assert_eq!(run(&mut eval, bad), Err(RuntimeError::Invalid), "{bad}");
+        }
+        // Lists are only compared for equality
+        for bad in ["factorize(4) + 1", "-divisors(6)", "divisors(6) > 1"] {
+            assert_eq!(run(&mut eval, bad), Err(RuntimeError::Invalid), "{bad}");
+        }
+    }
+
+    #[test]
+    fn prime_functions() {
+        let mut eval = Evaluator::default();
+
+        assert_eq!(
+            run(&mut eval, "nthprime(100)"),
+            Ok(EvalResult::Integer(541))
+        );
+        assert_eq!(
+            run(&mut eval, "primecount(1000)"),
+            Ok(EvalResult::Integer(168))
+        );
+        assert_eq!(
+            run(&mut eval, "nthprime(1) == 2"),
+            Ok(EvalResult::Bool(true))
+        );
+
+        for bad in [
+            "nthprime(0)",
+            "nthprime(-3)",
+            "nthprime(2.5)",
+            "primecount(0)",
+            "nthprime(10000001)",
+            "primecount(10^12)",
+        ] {
+            assert_eq!(run(&mut eval, bad), Err(RuntimeError::Invalid), "{bad}");
+        }
+    }
+
+    #[test]
+    fn modular_functions() {
+        let mut eval = Evaluator::default();
+
+        assert_eq!(
+            run(&mut eval, "powmod(2, 10, 1000) == 24"),
+            Ok(EvalResult::Bool(true))
+        );
+        assert_eq!(
+            run(&mut eval, "powmod(2, 64, 1000000007)"),
+            Ok(EvalResult::Integer(582_344_008))
+        );
+        assert_eq!(
+            run(&mut eval, "invmod(3, 7) == 5"),
+            Ok(EvalResult::Bool(true))
+        );
+        assert_eq!(
+            run(&mut eval, "powmod(3, -1, 7)"),
+            Ok(EvalResult::Integer(5))
+        );
+
+        for bad in ["invmod(2, 4)", "powmod(2, 3, 0)", "invmod(1.5, 7)"] {
+            assert_eq!(run(&mut eval, bad), Err(RuntimeError::Invalid), "{bad}");
+        }
+    }
+
+    #[test]
+    fn approximate_equality() {
+        let mut eval = Evaluator::default();
+
+        for (expression, expected) in [
+            ("0.1 + 0.2 == 0.3", false),
+            ("0.1 + 0.2 ~= 0.3", true),
+            ("1 ~= 1.1", false),
+            ("1/3 ~= 0.333333333333", true),
+            ("10^20 ~= 10^20 + 10^5", true),
+            ("0 ~= 10^-20", false),
+            ("0 ~= 0", true),
+            ("approx(1, 1.1, 0.1)", true),
+            ("approx(1, 1.1, 0.01)", false),
+            ("approx(2, 2, -1)", true),
+        ] {
+            assert_eq!(
+                run(&mut eval, expression),
+                Ok(EvalResult::Bool(expected)),
+                "{expression}"
+            );
+        }
+
+        for bad in [
+            "true ~= true",
+            "1 ~= false",
+            "divisors(6) ~= 1",
+            "approx(1, 1, true)",
+        ] {
+            assert_eq!(run(&mut eval, bad), Err(RuntimeError::Invalid), "{bad}");
+        }
+
+        eval.set_epsilon(0.1);
+        assert_eq!(eval.epsilon(), 0.1);
+        assert_eq!(run(&mut eval, "1 ~= 1.1"), Ok(EvalResult::Bool(true)));
+        assert_eq!(run(&mut eval, "1 ~= 1.2"), Ok(EvalResult::Bool(false)));
+        assert_eq!(run(&mut eval, "1 == 1.1"), Ok(EvalResult::Bool(false)));
+    }
+
+    #[test]
+    fn percentages() {
+        let mut eval = Evaluator::default();
+
+        for (expression, expected) in [
+            ("pctchange(80, 100)", 25.0),
+            ("pctchange(100, 80)", -20.0),
+            ("pctchange(-50, -25)", 50.0),
+            ("pctchange(3, 3)", 0.0),
+            ("pctof(30, 120)", 25.0),
+            ("pctof(-3, 4)", -75.0),
+            ("pctof(0, 7)", 0.0),
+            ("applypct(80, -15)", 68.0),
+            ("applypct(80, 15)", 92.0),
+            ("applypct(-40, 50)", -60.0),
+            ("applypct(80, 0)", 80.0),
+        ] {
+            assert_eq!(
+                run(&mut eval, expression),
+                Ok(EvalResult::Float(expected)),
+                "{expression}"
+            );
+        }
+
+        let err = run(&mut eval, "pctchange(0, 5)").expect_err("Old value is 0");
+        assert_eq!(err, RuntimeError::DivisionByZero(Function::PctChange));
+        assert_eq!(err.to_string(), "`pctchange` divided by zero :(");
+        assert_eq!(
+            run(&mut eval, "pctof(5, 0)"),
+            Err(RuntimeError::DivisionByZero(Function::PctOf))
+        );
+        assert_eq!(
+            run(&mut eval, "applypct(true, 5)"),
+            Err(RuntimeError::Invalid)
+        );
+    }
+
+    #[test]
+    fn conditionals() {
+        let mut eval = Evaluator::default();
+        run(&mut eval, "f = if(x < 0, -x, x)").expect_err("x isn't bound");
+        for (x, expected) in [(-3, 3), (0, 0), (4, 4)] {
+            eval.set('x', Expr::Integer(x));
+            assert_eq!(
+                run(&mut eval, "f"),
+                Ok(EvalResult::Integer(expected)),
+                "{x}"
+            );
+        }
+
+        // The branch not taken is never evaluated, so it can't fail or bind anything
+        assert_eq!(
+            run(&mut eval, "if(1 / 0 > 0, 1, sqrt(-1))"),
+            Ok(EvalResult::Integer(1))
+        );
+        assert_eq!(
+            run(&mut eval, "if(1 > 2, (y = 1 / 0), q)"),
+            Err(RuntimeError::Invalid)
+        );
+        assert_eq!(eval.get('y'), None);
+        assert_eq!(
+            run(&mut eval, "if(false, sqrt(-1), (y = 2)) + 1"),
+            Ok(EvalResult::Integer(3))
+        );
+        assert_eq!(eval.get('y'), Some(&Expr::Integer(2)));
+        assert_eq!(
+            run(&mut eval, "if(true, 1, sqrt(-1))"),
+            Ok(EvalResult::Integer(1))
+        );
+        assert_eq!(
+            run(&mut eval, "if(false, 1, sqrt(-1))"),
+            Err(RuntimeError::OutOfDomain("sqrt"))
+        );
+
+        // Numbers aren't truthy
+        assert_eq!(
+            run(&mut eval, "if(1, 2, 3)"),
+            Err(RuntimeError::NotACondition(Function::If))
+        );
+        assert_eq!(
+            run(&mut eval, "if(x, 2, 3)"),
+            Err(RuntimeError::NotACondition(Function::If))
+        );
+    }
+
+    #[test]
+    fn piecewise() {
+        let mut eval = Evaluator::default();
+        run(
+            &mut eval,
+            "t = piecewise(x <= 10, 0, x <= 40, (x - 10) / 5, 6 + (x - 40) / 2)",
+        )
+        .expect_err("x isn't bound");
+        for (x, expected) in [(5, 0), (10, 0), (25, 3), (40, 6), (50, 11)] {
+            eval.set('x', Expr::Integer(x));
+            assert_eq!(
+                run(&mut eval, "t"),
+                Ok(EvalResult::Integer(expected)),
+                "{x}"
+            );
+        }
+
+        // Later conditions and values aren't evaluated once one holds
+        assert_eq!(
+            run(&mut eval, "piecewise(true, 1, q, sqrt(-1), 2)"),
+            Ok(EvalResult::Integer(1))
+        );
+        assert_eq!(
+            run(&mut eval, "piecewise(false, q, 1 > 2, q, 3)"),
+            Ok(EvalResult::Integer(3))
+        );
+
+        // Without a default, nothing matching is an error
+        assert_eq!(
+            run(&mut eval, "piecewise(x < 0, -1, x > 100, 1)"),
+            Err(RuntimeError::Unmatched(Function::Piecewise))
+        );
+        assert_eq!(
+            run(&mut eval, "piecewise(x > 0, 1, x < 0, -1)"),
+            Ok(EvalResult::Integer(1))
+        );
+        let err = run(&mut eval, "piecewise(false, 1, 2, 3, 4)").expect_err("2 isn't a bool");
+        assert_eq!(err, RuntimeError::NotACondition(Function::Piecewise));
+        assert_eq!(
+            err.to_string(),
+            "`piecewise` needs conditions that are true or false :("
+        );
+        assert_eq!(
+            RuntimeError::Unmatched(Function::Piecewise).to_string(),
+            "None of the conditions of `piecewise` were true :("
+        );
+        let tokens = "piecewise(true, 1)".tokenize().expect("Tokenize stream");
+        assert!(
+            Parser::new(tokens).parse().is_err(),
+            "needs a condition, value and more"
+        );
+    }
+
+    #[test]
+    fn bitwise_operators() {
+        let mut eval = Evaluator::default();
+        eval.set('x', Expr::Integer(6));
+
+        for (expression, expected) in [
+            ("band(255, 15)", 15),
+            ("bor(x, 8)", 14),
+            ("bxor(x, 3)", 5),
+            ("~x", -7),
+            ("bnot(-1)", 0),
+            ("1 << 4", 16),
+            ("shl(1, 4)", 16),
+            ("-16 >> 2", -4),
+            ("shr(x, 1)", 3),
+            ("1 << 2 + 3", 32),
+            ("1 << 62", 1 << 62),
+            ("x >> 63", 0),
+            ("band(x, ~1) << 1 >> 2", 3),
+        ] {
+            assert_eq!(
+                run(&mut eval, expression),
+                Ok(EvalResult::Integer(expected)),
+                "{expression}"
+            );
+        }
+        assert_eq!(run(&mut eval, "1 << 3 > 7"), Ok(EvalResult::Bool(true)));
+
+        for bad in [
+            "band(1.0, 1)",
+            "bor(1, true)",
+            "~0.5",
+            "~(1/2)",
+            "1 << 64",
+            "1 >> 64",
+            "1 << -1",
+            "1 << 63",
+            "3 << 62",
+            "1 << 2.0",
+        ] {
+            assert_eq!(run(&mut eval, bad), Err(RuntimeError::Invalid), "{bad}");
+        }
+    }
+
+    #[test]
+    fn random_functions() {
+        let draws = |seed| {
+            let mut eval = Evaluator::default();
+            eval.seed(seed);
+            ["rand()", "randint(1, 6)", "rand() + randint(-10, 10)"]
+                .map(|expression| run(&mut eval, expression).unwrap())
+        };
+        assert_eq!(draws(1), draws(1));
+        assert_ne!(draws(1), draws(2));
+
+        let mut eval = Evaluator::default();
+        eval.seed(1234);
+        for _ in 0..1000 {
+            let Ok(EvalResult::Float(f)) = run(&mut eval, "rand()") else {
+                panic!("rand gives floats");
+            };
+            assert!((0.0..1.0).contains(&f));
+
+            let Ok(EvalResult::Integer(i)) = run(&mut eval, "randint(-2, 2)") else {
+                panic!("randint gives integers");
+            };
+            assert!((-2..=2).contains(&i));
+        }
+
+        assert_eq!(run(&mut eval, "randint(3, 3)"), Ok(EvalResult::Integer(3)));
+        for bad in ["randint(6, 1)", "randint(1, 2.5)"] {
+            assert_eq!(run(&mut eval, bad), Err(RuntimeError::Invalid), "{bad}");
+        }
+    }
+
+    #[test]
+    fn hyperbolic_functions() {
+        let mut eval = Evaluator::default();
+        let close = |eval: &mut Evaluator, expression: &str, expected: f64| {
+            let found = run(eval, expression).and_then(|res| res.float()).unwrap();
+            assert!((found - expected).abs() < 1e-9, "{expression} gave {found}");
+        };
+
+        for x in ["0", "0.5", "-2", "3.7"] {
+            close(&mut eval, &format!("cosh({x})^2 - sinh({x})^2"), 1.0);
+            close(
+                &mut eval,
+                &format!("tanh({x}) - sinh({x}) / cosh({x})"),
+                0.0,
+            );
+            close(&mut eval, &format!("asinh(sinh({x})) - {x}"), 0.0);
+        }
+        close(&mut eval, "acosh(cosh(2))", 2.0);
+        close(&mut eval, "atanh(tanh(0.5))", 0.5);
+        close(&mut eval, "atan2(1, 1)", std::f64::consts::FRAC_PI_4);
+        close(&mut eval, "atan2(1, -1)", 3.0 * std::f64::consts::FRAC_PI_4);
+
+        for bad in ["acosh(0.5)", "atanh(1)", "atanh(-3)"] {
+            assert_eq!(run(&mut eval, bad), Err(RuntimeError::Invalid), "{bad}");
+        }
+    }
+
+    #[test]
+    fn angle_modes() {
+        let mut eval = Evaluator::default();
+        let close = |eval: &mut Evaluator, expression: &str, expected: f64| {
+            let found = run(eval, expression).and_then(|res| res.float()).unwrap();
+            assert!((found - expected).abs() < 1e-9, "{expression} gave {found}");
+        };
+
+        close(&mut eval, "sin(90)", 90f64.sin());
+        close(&mut eval, "acos(0)", std::f64::consts::FRAC_PI_2);
+        close(&mut eval, "tan(45)", 45f64.tan());
+        close(&mut eval, "sin(90°)", 1.0);
+
+        eval.set_angle_mode(AngleMode::Degrees);
+        close(&mut eval, "sin(90)", 1.0);
+        close(&mut eval, "acos(0)", 90.0);
+        close(&mut eval, "tan(45)", 1.0);
+        close(&mut eval, "sin(90°)", 1.0);
+        close(&mut eval, "atan2(1, 1)", 45.0);
+
+        // Nothing but trig is affected
+        close(&mut eval, "sinh(1)", 1f64.sinh());
+        close(&mut eval, "90 * 2", 180.0);
+        close(&mut eval, "sqrt(pi^2)", std::f64::consts::PI);
+
+        assert_eq!(AngleMode::try_from("deg"), Ok(AngleMode::Degrees));
+        assert_eq!(AngleMode::try_from("Radians"), Ok(AngleMode::Radians));
+        assert_eq!(AngleMode::try_from("grad"), Err(()));
+    }
+
+    #[test]
+    fn comparing_across_types() {
+        let mut eval = Evaluator::default();
+
+        assert_eq!(run(&mut eval, "1/3 > 1/4"), Ok(EvalResult::Bool(true)));
+        assert_eq!(run(&mut eval, "1/3 < 0.34"), Ok(EvalResult::Bool(true)));
+        assert_eq!(run(&mut eval, "1/2 == 0.5"), Ok(EvalResult::Bool(true)));
+        assert_eq!(run(&mut eval, "2/3 >= 1"), Ok(EvalResult::Bool(false)));
+        assert_eq!(run(&mut eval, "1/2 == true"), Ok(EvalResult::Bool(false)));
+        assert_eq!(run(&mut eval, "1/2 < true"), Err(RuntimeError::Invalid));
+    }
+
+    #[test]
+    fn comparing_exactly() {
+        let mut eval = Evaluator::default();
+
+        let cases = [
+            ("16777217 > 16777216", true),
+            ("9007199254740993 > 9007199254740992", true),
+            ("9223372036854775807 != 9223372036854775806", true),
+            ("-9223372036854775807 < -9223372036854775806", true),
+            // Both sides round to the same float, but aren't equal
+            ("9007199254740993 == 9007199254740992.0", false),
+            ("9007199254740993 > 9007199254740992.0", true),
+            ("9007199254740992.0 < 9007199254740993", true),
+            ("9007199254740992 == 9007199254740992.0", true),
+            ("3 == 3.0", true),
+            ("1/4 == 0.25", true),
+            ("1/3 == 0.3333333333333333", false),
+            ("0.1 > 1/10", true),
+            ("2 >= 2.0", true),
+        ];
+        for (expression, expected) in cases {
+            assert_eq!(
+                run(&mut eval, expression),
+                Ok(EvalResult::Bool(expected)),
+                "{expression}"
+            );
+        }
+
+        assert_eq!(run(&mut eval, "1 > true"), Err(RuntimeError::Invalid));
+        assert_eq!(run(&mut eval, "false <= 0.5"), Err(RuntimeError::Invalid));
+        assert_eq!(run(&mut eval, "true < false"), Err(RuntimeError::Invalid));
+        assert_eq!(run(&mut eval, "1 == true"), Ok(EvalResult::Bool(false)));
+    }
+
+    #[test]
+    fn depth_limit() {
+        let nested = |depth: usize| (0..depth).fold(Expr::int(1), |expr, _| Expr::neg(expr));
+        let mut eval = Evaluator::default();
+
+        // The integer itself is the innermost node
+        assert_eq!(
+            eval.exec(&nested(MAX_DEPTH - 1)),
+            Ok(EvalResult::Integer(-1))
+        );
+        assert_eq!(eval.exec(&nested(MAX_DEPTH)), Err(RuntimeError::TooDeep));
+
+        eval.set_max_depth(8);
+        assert_eq!(eval.exec(&nested(7)), Ok(EvalResult::Integer(-1)));
+        assert_eq!(eval.exec(&nested(8)), Err(RuntimeError::TooDeep));
+        assert_eq!(
+            eval.exec(&nested(0)),
+            Ok(EvalResult::Integer(1)),
+            "depth is reset"
+        );
+
+        // Depth is counted through bound variables, so cycles fail rather than recursing forever
+        let mut eval = Evaluator::default();
+        assert_eq!(run(&mut eval, "x = y + 1"), Err(RuntimeError::Invalid));
+        assert_eq!(run(&mut eval, "y = x * 2"), Err(RuntimeError::TooDeep));
+        assert_eq!(
+            RuntimeError::TooDeep.to_string(),
+            "Expression is too deeply nested :("
+        );
+    }
+
+    #[test]
+    fn undefined_results() {
+        let mut eval = Evaluator::default();
+        assert!(!eval.allows_non_finite());
+
+        assert_eq!(
+            run(&mut eval, "acos(2)"),
+            Err(RuntimeError::OutOfDomain("acos"))
+        );
+        assert_eq!(
+            RuntimeError::OutOfDomain("acos").to_string(),
+            "`acos` isn't defined for that value :("
+        );
+        assert_eq!(
+            run(&mut eval, "asin(-1.5) + 1"),
+            Err(RuntimeError::OutOfDomain("asin"))
+        );
+        assert_eq!(run(&mut eval, "0 / 0.0"), Err(RuntimeError::Indeterminate));
+        assert_eq!(
+            run(&mut eval, "1 / 0.0 - 1 / 0.0"),
+            Err(RuntimeError::Indeterminate)
+        );
+        assert_eq!(
+            run(&mut eval, "1 / 0.0"),
+            Ok(EvalResult::Float(f64::INFINITY))
+        );
+
+        // Variables are never bound to anything non-finite, and keep what they had instead. An
+        // undefined value keeps the error saying why it's undefined
+        assert_eq!(run(&mut eval, "x = 0/0"), Err(RuntimeError::Indeterminate));
+        assert_eq!(
+            run(&mut eval, "x = acos(2)"),
+            Err(RuntimeError::OutOfDomain("acos"))
+        );
+        assert_eq!(eval.get('x'), None);
+        run(&mut eval, "y = 2").expect("Assign");
+        assert_eq!(
+            run(&mut eval, "y = 1 / 0.0"),
+            Err(RuntimeError::NonFinite('y'))
+        );
+        assert_eq!(eval.get('y'), Some(&Expr::Integer(2)));
+
+        // Plotting and solving treat undefined points as gaps rather than failing
+        let ast = parse("sqrt(x)");
+        let samples = eval.sample(&ast, 'x', -1.0, 1.0, 3).expect("Sample");
+        assert!(samples[0].is_nan());
+        assert_eq!(samples[1..], [0.0, 1.0]);
+        assert_eq!(
+            run(&mut eval, "solve(sqrt(x) == 2, x)"),
+            Ok(EvalResult::Float(4.0))
+        );
+
+        eval.allow_non_finite(true);
+        assert!(run(&mut eval, "acos(2)").unwrap().is_nan());
+        assert!(run(&mut eval, "x = 0/0").unwrap().is_nan());
+        assert!(eval.get('x').is_some());
+        assert_eq!(run(&mut eval, "x == x"), Ok(EvalResult::Bool(false)));
+    }
+
+    #[test]
+    fn fuel_limit() {
+        let mut eval = Evaluator::default();
+        assert_eq!(eval.fuel(), None);
+
+        // Factorizing a large prime burns fuel as it trial divides
+        eval.set_fuel(Some(100));
+        assert_eq!(
+            run(&mut eval, "factorize(1000000007)"),
+            Err(RuntimeError::FuelExhausted)
+        );
+        assert_eq!(
+            run(&mut eval, "totient(2^40)"),
+            Ok(EvalResult::Integer(1 << 39))
+        );
+
+        // Two operators and three numbers
+        eval.set_fuel(Some(5));
+        assert_eq!(run(&mut eval, "1 + 2 * 3"), Ok(EvalResult::Integer(7)));
+        assert_eq!(
+            run(&mut eval, "1 + 2 * 3"),
+            Ok(EvalResult::Integer(7)),
+            "fuel is refilled"
+        );
+        eval.set_fuel(Some(4));
+        assert_eq!(
+            run(&mut eval, "1 + 2 * 3"),
+            Err(RuntimeError::FuelExhausted)
+        );
+
+        // Every term of a sum takes a step, not just the nodes written out
+        eval.set_fuel(Some(10_000));
+        assert_eq!(
+            run(&mut eval, "sum(i, 1, 999999, i)"),
+            Err(RuntimeError::FuelExhausted)
+        );
+        assert_eq!(
+            run(&mut eval, "sum(i, 1, 1000, i)"),
+            Ok(EvalResult::Integer(500500))
+        );
+
+        // As does every point sampled, even when the expression is compiled
+        let ast = parse("x");
+        eval.set_fuel(Some(5));
+        assert_eq!(
+            eval.sample(&ast, 'x', 0.0, 1.0, 6),
+            Err(RuntimeError::FuelExhausted)
+        );
+        assert!(eval.sample(&ast, 'x', 0.0, 1.0, 5).is_ok());
+
+        eval.set_fuel(None);
+        assert_eq!(
+            run(&mut eval, "sum(i, 1, 20000, 1)"),
+            Ok(EvalResult::Integer(20000))
+        );
+        assert_eq!(
+            RuntimeError::FuelExhausted.to_string(),
+            "Expression took too long to evaluate :("
+        );
+    }
+
+    #[test]
+    fn ordering_results() {
+        let mut results = vec![
+            EvalResult::Float(2.5),
+            EvalResult::Integer(-3),
+            EvalResult::Rational(1, 3),
+            EvalResult::Integer(2),
+            EvalResult::Float(f64::NEG_INFINITY),
+            EvalResult::Float(0.25),
+        ];
+        results.sort_by(|a, b| a.partial_cmp(b).expect("Numbers are comparable"));
+        assert_eq!(
+            results,
+            [
+                EvalResult::Float(f64::NEG_INFINITY),
+                EvalResult::Integer(-3),
+                EvalResult::Float(0.25),
+                EvalResult::Rational(1, 3),
+                EvalResult::Integer(2),
+                EvalResult::Float(2.5),
+            ]
+        );
+
+        assert!(EvalResult::Integer(1) < EvalResult::Float(1.5));
+        assert!(EvalResult::Rational(1, 2) >= EvalResult::Float(0.5));
+        assert_eq!(
+            EvalResult::Bool(true).partial_cmp(&EvalResult::Bool(true)),
+            Some(Ordering::Equal)
+        );
+        assert_eq!(
+            EvalResult::Bool(true).partial_cmp(&EvalResult::Bool(false)),
+            None
+        );
+        assert_eq!(
+            EvalResult::Bool(false).partial_cmp(&EvalResult::Integer(0)),
+            None
+        );
+        assert_eq!(
+            EvalResult::Float(f64::NAN).partial_cmp(&EvalResult::Float(f64::NAN)),
+            None
+        );
+        assert!(
+            EvalResult::List(vec![1.into(), 2.into()]) < EvalResult::List(vec![1.into(), 3.into()])
+        );
+    }
+
+    #[test]
+    fn converting_results() {
+        assert_eq!(EvalResult::from(3i32), EvalResult::Integer(3));
+        assert_eq!(EvalResult::from(0.5f32), EvalResult::Float(0.5));
+        assert_eq!(EvalResult::from(true), EvalResult::Bool(true));
+
+        assert_eq!(i32::try_from(EvalResult::Integer(-7)), Ok(-7));
+        assert_eq!(i32::try_from(EvalResult::Float(4.0)), Ok(4));
+        assert_eq!(i64::try_from(EvalResult::Integer(1 << 40)), Ok(1 << 40));
+        assert_eq!(f32::try_from(EvalResult::Rational(1, 4)), Ok(0.25));
+        assert_eq!(f64::try_from(EvalResult::Integer(3)), Ok(3.0));
+        assert_eq!(bool::try_from(EvalResult::Bool(false)), Ok(false));
+
+        assert_eq!(
+            i32::try_from(EvalResult::Integer(1 << 40)),
+            Err(RuntimeError::Invalid)
+        );
+        assert_eq!(
+            i32::try_from(EvalResult::Float(1.5)),
+            Err(RuntimeError::Invalid)
+        );
+        assert_eq!(
+            i32::try_from(EvalResult::Rational(1, 2)),
+            Err(RuntimeError::Invalid)
+        );
+        assert_eq!(
+            f32::try_from(EvalResult::Bool(true)),
+            Err(RuntimeError::Invalid)
+        );
+        assert_eq!(
+            bool::try_from(EvalResult::Integer(1)),
+            Err(RuntimeError::Invalid)
+        );
+        assert_eq!(
+            f64::try_from(EvalResult::List(vec![])),
+            Err(RuntimeError::Invalid)
+        );
+    }
+
+    #[test]
+    fn wide_numbers() {
+        let mut eval = Evaluator::default();
+
+        assert_eq!(
+            run(&mut eval, "2^40"),
+            Ok(EvalResult::Integer(1_099_511_627_776))
+        );
+        assert_eq!(
+            run(&mut eval, "20!"),
+            Ok(EvalResult::Integer(2_432_902_008_176_640_000))
+        );
+        assert_eq!(run(&mut eval, "0.1 + 0.2").unwrap().to_string(), "0.3");
+        assert_eq!(
+            run(&mut eval, "1.0 / 3").unwrap().to_string(),
+            "0.333333333333333"
+        );
+        #[cfg(not(feature = "bigint"))]
+        assert_eq!(run(&mut eval, "171!"), Ok(EvalResult::Float(f64::INFINITY)));
+        assert_eq!(
+            run(&mut eval, "10001!"),
+            Ok(EvalResult::Float(f64::INFINITY))
+        );
+
+        // Negative integers have no factorial, and other numbers aren't integers at all
+        assert_eq!(
+            run(&mut eval, "(-3)!"),
+            Err(RuntimeError::OutOfDomain("factorial"))
+        );
+        assert_eq!(run(&mut eval, "2.5!"), Err(RuntimeError::Invalid));
+    }
 }
@@ -0,0 +1,781 @@
+//! Configurable display of evaluation results
+
+use crate::exec::{DEFAULT_EPSILON, EvalResult};
+
+/// Largest denominator floats are shown with when fractions are asked for
+pub const MAX_DISPLAY_DENOMINATOR: u32 = 10_000;
+
+/// How far a fraction from [`EvalResult::to_fraction`] may be from the value, relative to the
+/// value once it's larger than 1
+const FRACTION_TOLERANCE: f64 = 1e-6;
+
+/// Most terms of the continued fraction looked at before settling on an approximation
+const MAX_TERMS: usize = 64;
+
+/// Integers smaller than this in magnitude are shown in full whatever the notation
+pub const PLAIN_BELOW: u64 = 10_000;
+
+/// How floats are rendered once rounded to a precision
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub enum FloatStyle {
+    /// Trailing zeros (and a trailing decimal point) are stripped
+    #[default]
+    Trimmed,
+    /// Exactly `precision` decimal places are always shown
+    Fixed,
+}
+
+/// How numbers are written out
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub enum Notation {
+    /// Every digit in full, like `1099511627776`
+    #[default]
+    Plain,
+    /// One digit before the point and a power of ten, like `1.099511627776e12`
+    Scientific,
+    /// Like scientific, but with a power of ten that's a multiple of 3, like `12.345e3`
+    Engineering,
+}
+
+impl TryFrom<&str> for Notation {
+    type Error = ();
+    fn try_from(value: &str) -> Result<Self, Self::Error> {
+        match value.to_lowercase().as_str() {
+            "plain" => Ok(Notation::Plain),
+            "sci" | "scientific" => Ok(Notation::Scientific),
+            "eng" | "engineering" => Ok(Notation::Engineering),
+            _ => Err(()),
+        }
+    }
+}
+
+/// What goes between groups of three digits when they're grouped
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub enum DigitSeparator {
+    /// `1,234,567`
+    #[default]
+    Comma,
+    /// `1_234_567`
+    Underscore,
+    /// `1 234 567`
+    Space,
+}
+
+impl DigitSeparator {
+    /// The character put between groups
+    pub fn char(&self) -> char {
+        match self {
+            Self::Comma => ',',
+            Self::Underscore => '_',
+            Self::Space => ' ',
+        }
+    }
+}
+
+impl TryFrom<&str> for DigitSeparator {
+    type Error = ();
+    fn try_from(value: &str) -> Result<Self, Self::Error> {
+        match value.to_lowercase().as_str() {
+            "," | "comma" => Ok(DigitSeparator::Comma),
+            "_" | "underscore" => Ok(DigitSeparator::Underscore),
+            " " | "space" => Ok(DigitSeparator::Space),
+            _ => Err(()),
+        }
+    }
+}
+
+/// Options controlling how an [`EvalResult`] is displayed
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct FormatOptions {
+    /// Decimal places floats are rounded to, or `None` for the shortest exact representation
+    pub precision: Option<usize>,
+    /// How rounded floats are rendered
+    pub style: FloatStyle,
+    /// Whether exact fractions are shown as decimals. They always are once a precision is set,
+    /// unless they're approximated
+    pub decimal: bool,
+    /// Whether exact fractions are followed by their decimal approximation, like `1/2 ≈ 0.5`,
+    /// which is rounded to the precision. Ignored when fractions are shown as decimals
+    pub approximate: bool,
+    /// Whether floats are shown as fractions, like `0.75` as `3/4`, when one with a denominator
+    /// up to [`MAX_DISPLAY_DENOMINATOR`] is equal to within [`DEFAULT_EPSILON`]
+    pub fraction: bool,
+    /// How numbers are written out. Integers below [`PLAIN_BELOW`] are always shown in full
+    pub notation: Notation,
+    /// What separates groups of three digits in the whole part of integers and floats, or `None`
+    /// to not group them. Only ever for display, grouped output doesn't tokenize back
+    pub group: Option<DigitSeparator>,
+}
+
+impl FormatOptions {
+    /// Rounds floats to a number of decimal places, stripping trailing zeros
+    pub fn precision(precision: usize) -> Self {
+        Self {
+            precision: Some(precision),
+            style: FloatStyle::Trimmed,
+            decimal: false,
+            approximate: false,
+            fraction: false,
+            notation: Notation::Plain,
+            group: None,
+        }
+    }
+
+    /// Rounds floats to exactly a number of decimal places
+    pub fn fixed(precision: usize) -> Self {
+        Self {
+            precision: Some(precision),
+            style: FloatStyle::Fixed,
+            decimal: false,
+            approximate: false,
+            fraction: false,
+            notation: Notation::Plain,
+            group: None,
+        }
+    }
+
+    /// Writes a number out in the notation, unless it's plain or the number is zero or not finite
+    fn exponent(&self, f: f64) -> Option<String> {
+        if self.notation == Notation::Plain || f == 0.0 || !f.is_finite() {
+            return None;
+        }
+
+        // Without a precision, 15 significant digits hide representation error like `Display` does
+        let mut out = format!("{f:.*e}", self.precision.unwrap_or(14));
+        if self.style == FloatStyle::Trimmed || self.precision.is_none() {
+            let (mantissa, exponent) = out.split_once('e').unwrap_or((&out, "0"));
+            let mantissa = mantissa.trim_end_matches('0').trim_end_matches('.');
+            out = format!("{mantissa}e{exponent}");
+        }
+
+        Some(match self.notation {
+            Notation::Engineering => engineering(&out),
+            _ => out,
+        })
+    }
+}
+
+/// Moves the point of a number in scientific notation right until the exponent is a multiple of 3
+fn engineering(scientific: &str) -> String {
+    let (mantissa, exponent) = scientific.split_once('e').unwrap_or((scientific, "0"));
+    let exponent: i32 = exponent.parse().unwrap_or(0);
+    let shift = exponent.rem_euclid(3) as usize;
+
+    let (sign, mantissa) = match mantissa.strip_prefix('-') {
+        Some(mantissa) => ("-", mantissa),
+        None => ("", mantissa),
+    };
+    let mut digits = mantissa.replace('.', "");
+    while digits.len() <= shift {
+        digits.push('0');
+    }
+
+    let (whole, fraction) = digits.split_at(shift + 1);
+    let point = if fraction.is_empty() { "" } else { "." };
+    format!("{sign}{whole}{point}{fraction}e{}", exponent - shift as i32)
+}
+
+impl EvalResult {
+    /// Displays the result according to the format options. Integers and bools are unaffected
+    pub fn format_with(&self, options: &FormatOptions) -> String {
+        if options.approximate
+            && !options.decimal
+            && let Some((exact, approx)) = self.exact_and_approx()
+        {
+            return format!("{exact} ≈ {}", Self::Float(approx).format_with(options));
+        }
+
+        if options.fraction
+            && let Self::Float(f) = self
+            && let Some((numerator, denominator)) = self.to_fraction(MAX_DISPLAY_DENOMINATOR)
+            && denominator > 1
+            && (numerator as f64 / denominator as f64 - f).abs() <= DEFAULT_EPSILON * f.abs()
+        {
+            return format!("{numerator}/{denominator}");
+        }
+
+        if let Some(out) = self.in_notation(options) {
+            return out;
+        }
+
+        let out = match (self, options.precision) {
+            (Self::Float(f), Some(precision)) => format_float(*f, precision, options.style),
+            (Self::Quantity { value, unit }, Some(precision)) => {
+                format!("{} {unit}", format_float(*value, precision, options.style))
+            }
+            (Self::Rational(..), Some(_)) => {
+                Self::Float(self.float().unwrap_or(f64::NAN)).format_with(options)
+            }
+            (Self::Rational(..), None) if options.decimal => {
+                Self::Float(self.float().unwrap_or(f64::NAN)).to_string()
+            }
+            (Self::List(items), _) => {
+                // Approximating every item would bury the list, so only whole results are
+                let options = FormatOptions {
+                    approximate: false,
+                    ..*options
+                };
+                let items: Vec<String> = items
+                    .iter()
+                    .map(|item| item.format_with(&options))
+                    .collect();
+                format!("[{}]", items.join(", "))
+            }
+            _ => self.to_string(),
+        };
+
+        match options.group {
+            Some(separator) if self.is_groupable() => group_digits(&out, separator.char()),
+            _ => out,
+        }
+    }
+
+    /// The result written exactly along with its decimal approximation, if it has an exact form
+    /// that isn't already a decimal. Only fractions do, integers are shown once as they are and
+    /// floats are already approximate
+    pub fn exact_and_approx(&self) -> Option<(String, f64)> {
+        match self {
+            Self::Rational(..) => Some((self.to_string(), self.float().ok()?)),
+            _ => None,
+        }
+    }
+
+    /// Whether the result is a number whose digits can be grouped
+    fn is_groupable(&self) -> bool {
+        match self {
+            Self::Integer(_) | Self::Float(_) | Self::Decimal(..) => true,
+            #[cfg(feature = "bigint")]
+            Self::BigInt(_) => true,
+            _ => false,
+        }
+    }
+
+    /// The result written out in the options' notation, or `None` if it's shown as usual
+    fn in_notation(&self, options: &FormatOptions) -> Option<String> {
+        match self {
+            Self::Integer(i) if i.unsigned_abs() < PLAIN_BELOW => None,
+            Self::Quantity { value, unit } => Some(format!("{} {unit}", options.exponent(*value)?)),
+            Self::Float(_) | Self::Integer(_) | Self::Rational(..) => {
+                options.exponent(self.float().ok()?)
+            }
+            #[cfg(feature = "bigint")]
+            Self::BigInt(_) => options.exponent(self.float().ok()?),
+            _ => None,
+        }
+    }
+}
+
+impl EvalResult {
+    /// The closest fraction to a number with a denominator up to `max_denominator`, found from
+    /// its continued fraction, as `(numerator, denominator)` in lowest terms. `None` if it isn't a
+    /// number, or no such fraction is within a millionth of it
+    pub fn to_fraction(&self, max_denominator: u32) -> Option<(i64, u64)> {
+        let max_denominator = u64::from(max_denominator);
+        match *self {
+            Self::Integer(i) if max_denominator > 0 => Some((i, 1)),
+            Self::Rational(numerator, denominator)
+                if denominator.unsigned_abs() <= max_denominator =>
+            {
+                Some((numerator, denominator.unsigned_abs()))
+            }
+            Self::Float(_) | Self::Rational(..) => {
+                let value = self.float().ok()?;
+                let (numerator, denominator) = approximate(value.abs(), max_denominator)?;
+                let error = (numerator as f64 / denominator as f64 - value.abs()).abs();
+                if error > FRACTION_TOLERANCE * value.abs().max(1.0) {
+                    return None;
+                }
+
+                let numerator = i64::try_from(numerator).ok()?;
+                Some((
+                    if value < 0.0 { -numerator } else { numerator },
+                    denominator,
+                ))
+            }
+            _ => None,
+        }
+    }
+}
+
+/// The convergent or semiconvergent of a non-negative float's continued fraction closest to it
+/// with a denominator up to `max_denominator`
+fn approximate(value: f64, max_denominator: u64) -> Option<(u64, u64)> {
+    if !value.is_finite() || max_denominator == 0 {
+        return None;
+    }
+
+    // The two most recent convergents, starting from the conventional 0/1 and 1/0
+    let (mut p0, mut q0, mut p1, mut q1) = (0u64, 1u64, 1u64, 0u64);
+    let mut rest = value;
+    for _ in 0..MAX_TERMS {
+        let term = rest.floor();
+        if term >= u64::MAX as f64 {
+            break;
+        }
+        let term = term as u64;
+
+        let next = term
+            .checked_mul(q1)
+            .and_then(|q| q.checked_add(q0))
+            .filter(|&q| q <= max_denominator)
+            .zip(term.checked_mul(p1).and_then(|p| p.checked_add(p0)));
+        let Some((q2, p2)) = next else {
+            // The largest step towards the next convergent that still fits may be closer
+            let steps = (max_denominator - q0) / q1;
+            let (ps, qs) = (p0 + steps * p1, q0 + steps * q1);
+            let distance = |p: u64, q: u64| (p as f64 / q as f64 - value).abs();
+            if distance(ps, qs) < distance(p1, q1) {
+                return Some((ps, qs));
+            }
+            break;
+        };
+
+        (p0, q0, p1, q1) = (p1, q1, p2, q2);
+        let fraction = rest - rest.floor();
+        if fraction == 0.0 {
+            break;
+        }
+        rest = 1.0 / fraction;
+    }
+
+    Some((p1, q1))
+}
+
+/// Puts a separator between every three digits of the whole part of a number, counting from the
+/// point
+fn group_digits(number: &str, separator: char) -> String {
+    let (sign, unsigned) = match number.strip_prefix('-') {
+        Some(unsigned) => ("-", unsigned),
+        None => ("", number),
+    };
+    let whole_len = unsigned
+        .find(|c: char| !c.is_ascii_digit())
+        .unwrap_or(unsigned.len());
+    let (whole, rest) = unsigned.split_at(whole_len);
+
+    let mut out = sign.to_string();
+    for (i, digit) in whole.chars().enumerate() {
+        if i > 0 && (whole_len - i) % 3 == 0 {
+            out.push(separator);
+        }
+        out.push(digit);
+    }
+    out + rest
+}
+
+/// Rounds a float to `precision` decimal places in the given style
+fn format_float(f: f64, precision: usize, style: FloatStyle) -> String {
+    if !f.is_finite() {
+        return f.to_string();
+    }
+
+    let mut out = format!("{f:.precision$}");
+
+    if style == FloatStyle::Trimmed && out.contains('.') {
+        out.truncate(out.trim_end_matches('0').trim_end_matches('.').len());
+    }
+
+    // Rounding a small negative number shouldn't leave a lone minus sign on zero
+    if out
+        .trim_start_matches('-')
+        .chars()
+        .all(|c| c == '0' || c == '.')
+    {
+        out = out.trim_start_matches('-').to_string();
+    }
+
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        exec::EvalResult,
+        units::{self, Unit},
+    };
+
+    use super::{DigitSeparator, FloatStyle, FormatOptions, Notation};
+
+    #[test]
+    fn default_is_display() {
+        let options = FormatOptions::default();
+        for result in [
+            EvalResult::Float(1.0 / 3.0),
+            EvalResult::Integer(-12),
+            EvalResult::Bool(true),
+        ] {
+            assert_eq!(result.format_with(&options), result.to_string());
+        }
+    }
+
+    #[test]
+    fn trimmed_precision() {
+        let cases = [
+            (1.0 / 3.0, 3, "0.333"),
+            (2.5, 4, "2.5"),
+            (2.0, 2, "2"),
+            (-1.23456, 2, "-1.23"),
+            (0.999, 2, "1"),
+            (1e-7, 3, "0"),
+            (-1e-7, 3, "0"),
+            (1.5e-5, 6, "0.000015"),
+            (1e10, 2, "10000000000"),
+            (123.456, 0, "123"),
+        ];
+
+        for (f, precision, expected) in cases {
+            assert_eq!(
+                EvalResult::Float(f).format_with(&FormatOptions::precision(precision)),
+                expected,
+                "{f} at precision {precision}"
+            );
+        }
+    }
+
+    #[test]
+    fn fixed_precision() {
+        let cases = [
+            (1.0 / 3.0, 3, "0.333"),
+            (2.5, 4, "2.5000"),
+            (2.0, 2, "2.00"),
+            (-1e-7, 3, "0.000"),
+            (1e10, 1, "10000000000.0"),
+        ];
+
+        for (f, precision, expected) in cases {
+            assert_eq!(
+                EvalResult::Float(f).format_with(&FormatOptions::fixed(precision)),
+                expected,
+                "{f} at precision {precision}"
+            );
+        }
+    }
+
+    #[test]
+    fn rationals() {
+        let third = EvalResult::Rational(1, 3);
+        let decimal = FormatOptions {
+            decimal: true,
+            ..FormatOptions::default()
+        };
+
+        assert_eq!(third.format_with(&FormatOptions::default()), "1/3");
+        assert_eq!(third.format_with(&decimal), "0.333333333333333");
+        assert_eq!(third.format_with(&FormatOptions::precision(2)), "0.33");
+        assert_eq!(
+            EvalResult::Rational(-5, 2).format_with(&FormatOptions::fixed(2)),
+            "-2.50"
+        );
+    }
+
+    #[test]
+    fn lists_format_each_item() {
+        let list = EvalResult::List(vec![
+            EvalResult::Integer(2),
+            EvalResult::Float(0.126),
+            EvalResult::Rational(1, 3),
+        ]);
+
+        assert_eq!(
+            list.format_with(&FormatOptions::default()),
+            "[2, 0.126, 1/3]"
+        );
+        assert_eq!(
+            list.format_with(&FormatOptions::fixed(2)),
+            "[2, 0.13, 0.33]"
+        );
+    }
+
+    #[test]
+    fn fractions() {
+        let cases = [
+            (EvalResult::Float(0.5), 1000, Some((1, 2))),
+            (EvalResult::Float(0.75), 1000, Some((3, 4))),
+            (EvalResult::Float(0.333333), 1000, Some((1, 3))),
+            (EvalResult::Float(-2.125), 1000, Some((-17, 8))),
+            (EvalResult::Float(0.1 + 0.2), 10, Some((3, 10))),
+            (
+                EvalResult::Float(std::f64::consts::PI),
+                1000,
+                Some((355, 113)),
+            ),
+            (EvalResult::Float(std::f64::consts::PI), 100, None),
+            (EvalResult::Float(2f64.sqrt()), 100, None),
+            (EvalResult::Integer(-4), 1, Some((-4, 1))),
+            (EvalResult::Rational(-2, 3), 5, Some((-2, 3))),
+            (EvalResult::Rational(1, 3001), 100, None),
+            (EvalResult::Float(f64::NAN), 100, None),
+            (EvalResult::Bool(true), 100, None),
+        ];
+
+        for (result, max_denominator, expected) in cases {
+            assert_eq!(
+                result.to_fraction(max_denominator),
+                expected,
+                "{result} up to {max_denominator}"
+            );
+        }
+    }
+
+    #[test]
+    fn fraction_display() {
+        let fraction = FormatOptions {
+            fraction: true,
+            ..FormatOptions::default()
+        };
+
+        for (f, expected) in [
+            (0.75, "3/4"),
+            (-0.1 - 0.2, "-3/10"),
+            (1.0 / 7.0, "1/7"),
+            (2.0, "2"),
+            // 355/113 is close, but not close enough to show in its place
+            (std::f64::consts::PI, "3.14159265358979"),
+            (0.333333, "0.333333"),
+        ] {
+            assert_eq!(EvalResult::Float(f).format_with(&fraction), expected, "{f}");
+        }
+        assert_eq!(
+            EvalResult::List(vec![EvalResult::Float(0.5), EvalResult::Integer(3)])
+                .format_with(&fraction),
+            "[1/2, 3]"
+        );
+    }
+
+    #[test]
+    fn notations() {
+        let scientific = FormatOptions {
+            notation: Notation::Scientific,
+            ..FormatOptions::default()
+        };
+        let engineering = FormatOptions {
+            notation: Notation::Engineering,
+            ..FormatOptions::default()
+        };
+
+        let cases = [
+            (
+                EvalResult::Integer(1 << 40),
+                "1.099511627776e12",
+                "1.099511627776e12",
+            ),
+            (EvalResult::Integer(12345), "1.2345e4", "12.345e3"),
+            (EvalResult::Integer(-250_000), "-2.5e5", "-250e3"),
+            (EvalResult::Integer(9999), "9999", "9999"),
+            (EvalResult::Integer(-42), "-42", "-42"),
+            (EvalResult::Float(0.00042), "4.2e-4", "420e-6"),
+            (EvalResult::Float(-0.5), "-5e-1", "-500e-3"),
+            (EvalResult::Float(0.1 + 0.2), "3e-1", "300e-3"),
+            (EvalResult::Float(6.02e23), "6.02e23", "602e21"),
+            (EvalResult::Float(1.5), "1.5e0", "1.5e0"),
+            (EvalResult::Float(0.0), "0", "0"),
+            (EvalResult::Float(f64::NEG_INFINITY), "-inf", "-inf"),
+            (EvalResult::Rational(1, 8), "1.25e-1", "125e-3"),
+            (EvalResult::Bool(true), "true", "true"),
+        ];
+
+        for (result, sci, eng) in cases {
+            assert_eq!(
+                result.format_with(&scientific),
+                sci,
+                "{result} in scientific"
+            );
+            assert_eq!(
+                result.format_with(&engineering),
+                eng,
+                "{result} in engineering"
+            );
+            assert_eq!(
+                result.format_with(&FormatOptions::default()),
+                result.to_string(),
+                "{result} in plain"
+            );
+        }
+    }
+
+    #[test]
+    fn notation_precision() {
+        let cases = [
+            (
+                Notation::Scientific,
+                2,
+                FloatStyle::Trimmed,
+                123456.0,
+                "1.23e5",
+            ),
+            (
+                Notation::Scientific,
+                3,
+                FloatStyle::Trimmed,
+                0.0015,
+                "1.5e-3",
+            ),
+            (
+                Notation::Scientific,
+                3,
+                FloatStyle::Fixed,
+                0.0015,
+                "1.500e-3",
+            ),
+            (
+                Notation::Engineering,
+                2,
+                FloatStyle::Trimmed,
+                123456.0,
+                "123e3",
+            ),
+            (
+                Notation::Engineering,
+                4,
+                FloatStyle::Fixed,
+                12345.0,
+                "12.345e3",
+            ),
+            (Notation::Engineering, 0, FloatStyle::Fixed, -0.05, "-50e-3"),
+            (
+                Notation::Engineering,
+                1,
+                FloatStyle::Trimmed,
+                999_999.0,
+                "1e6",
+            ),
+        ];
+
+        for (notation, precision, style, f, expected) in cases {
+            let options = FormatOptions {
+                precision: Some(precision),
+                style,
+                notation,
+                ..FormatOptions::default()
+            };
+            assert_eq!(
+                EvalResult::Float(f).format_with(&options),
+                expected,
+                "{f} in {notation:?} to {precision}"
+            );
+        }
+        assert_eq!(
+            units::quantity(1500.0, Unit::parse("m").expect("Unit")).format_with(&FormatOptions {
+                notation: Notation::Engineering,
+                ..FormatOptions::default()
+            }),
+            "1.5e3 m"
+        );
+    }
+
+    #[test]
+    fn grouping_digits() {
+        let grouped = |separator| FormatOptions {
+            group: Some(separator),
+            ..FormatOptions::default()
+        };
+        let comma = grouped(DigitSeparator::Comma);
+
+        let cases = [
+            (EvalResult::Integer(1_234_567), "1,234,567"),
+            (EvalResult::Integer(-1000), "-1,000"),
+            (EvalResult::Integer(999), "999"),
+            (EvalResult::Integer(0), "0"),
+            (EvalResult::Float(1234567.89), "1,234,567.89"),
+            (EvalResult::Float(-12345.678), "-12,345.678"),
+            (EvalResult::Float(0.00012), "0.00012"),
+            (EvalResult::Float(f64::INFINITY), "inf"),
+            (EvalResult::Rational(1234, 5), "1234/5"),
+            (
+                EvalResult::List(vec![EvalResult::Integer(1000), EvalResult::Bool(true)]),
+                "[1,000, true]",
+            ),
+        ];
+        for (result, expected) in cases {
+            assert_eq!(result.format_with(&comma), expected, "{result}");
+        }
+
+        let million = EvalResult::Integer(1_000_000);
+        assert_eq!(
+            million.format_with(&grouped(DigitSeparator::Underscore)),
+            "1_000_000"
+        );
+        assert_eq!(
+            million.format_with(&grouped(DigitSeparator::Space)),
+            "1 000 000"
+        );
+
+        // Grouping applies after rounding to a precision
+        for (options, expected) in [
+            (FormatOptions::precision(2), "-1,234,567.89"),
+            (FormatOptions::fixed(3), "-1,234,567.891"),
+            (FormatOptions::precision(0), "-1,234,568"),
+        ] {
+            let options = FormatOptions {
+                group: Some(DigitSeparator::Comma),
+                ..options
+            };
+            assert_eq!(
+                EvalResult::Float(-1234567.891).format_with(&options),
+                expected
+            );
+        }
+        assert_eq!(
+            EvalResult::Rational(12345, 2).format_with(&FormatOptions {
+                group: Some(DigitSeparator::Comma),
+                ..FormatOptions::fixed(2)
+            }),
+            "6,172.50"
+        );
+    }
+
+    #[test]
+    fn approximating_fractions() {
+        let approximate = FormatOptions {
+            approximate: true,
+            ..FormatOptions::default()
+        };
+
+        assert_eq!(
+            EvalResult::Rational(1, 2).format_with(&approximate),
+            "1/2 ≈ 0.5"
+        );
+        assert_eq!(
+            EvalResult::Rational(-1, 3).format_with(&approximate),
+            "-1/3 ≈ -0.333333333333333"
+        );
+        assert_eq!(
+            EvalResult::Rational(1, 3).format_with(&FormatOptions {
+                approximate: true,
+                ..FormatOptions::precision(3)
+            }),
+            "1/3 ≈ 0.333"
+        );
+        assert_eq!(
+            EvalResult::List(vec![EvalResult::Rational(1, 2)]).format_with(&approximate),
+            "[1/2]"
+        );
+
+        // Anything without an exact form is shown once
+        assert_eq!(EvalResult::Float(0.1).format_with(&approximate), "0.1");
+        assert_eq!(EvalResult::Integer(2).format_with(&approximate), "2");
+        assert_eq!(EvalResult::Bool(true).format_with(&approximate), "true");
+
+        // Decimals take over entirely
+        let decimal = FormatOptions {
+            decimal: true,
+            ..approximate
+        };
+        assert_eq!(EvalResult::Rational(1, 2).format_with(&decimal), "0.5");
+        assert_eq!(
+            EvalResult::Rational(1, 2).exact_and_approx(),
+            Some(("1/2".to_string(), 0.5))
+        );
+    }
+
+    #[test]
+    fn non_floats_untouched() {
+        let options = FormatOptions::fixed(3);
+        assert_eq!(EvalResult::Integer(7).format_with(&options), "7");
+        assert_eq!(EvalResult::Bool(false).format_with(&options), "false");
+        assert_eq!(
+            EvalResult::Float(f64::INFINITY).format_with(&options),
+            "inf"
+        );
+    }
+}
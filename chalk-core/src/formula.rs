@@ -0,0 +1,114 @@
+//! Formulas stored by name, kept apart from variables so they're only ever evaluated when asked
+//! for by name, with whatever variables they need bound just for that evaluation
+
+use crate::{
+    ast::Expr,
+    exec::{EvalResult, Evaluator, RuntimeError},
+};
+
+impl Evaluator {
+    /// Stores a formula under a name, returning the formula it replaced if there was one
+    pub fn store_formula(&mut self, name: impl Into<String>, formula: Expr) -> Option<Expr> {
+        self.formulas.insert(name.into(), formula)
+    }
+
+    /// The formula stored under a name, if there is one
+    pub fn recall_formula(&self, name: &str) -> Option<&Expr> {
+        self.formulas.get(name)
+    }
+
+    /// Removes the formula stored under a name, returning it if there was one
+    pub fn remove_formula(&mut self, name: &str) -> Option<Expr> {
+        self.formulas.remove(name)
+    }
+
+    /// Every stored formula and its name, sorted by name
+    pub fn formulas(&self) -> Vec<(&str, &Expr)> {
+        let mut formulas: Vec<_> = self
+            .formulas
+            .iter()
+            .map(|(name, formula)| (name.as_str(), formula))
+            .collect();
+        formulas.sort_by_key(|(name, _)| *name);
+        formulas
+    }
+
+    /// Evaluates the formula stored under a name with some variables bound for this evaluation
+    /// only, like [`Evaluator::exec_with`]. Returns `None` if no formula has that name
+    pub fn eval_formula_with(
+        &mut self,
+        name: &str,
+        bindings: &[(char, EvalResult)],
+    ) -> Option<Result<EvalResult, RuntimeError>> {
+        let formula = self.formulas.get(name)?.clone();
+        Some(self.exec_with(&formula, bindings))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        ast::Expr,
+        exec::{EvalResult, Evaluator, RuntimeError},
+        pipeline::testing::parse,
+    };
+
+    #[test]
+    fn storing_and_recalling() {
+        let mut eval = Evaluator::default();
+        assert_eq!(eval.store_formula("quad", parse("x^2 + 3x + 1")), None);
+        assert_eq!(eval.store_formula("area", parse("w * h")), None);
+
+        assert_eq!(eval.recall_formula("quad"), Some(&parse("x^2 + 3x + 1")));
+        assert_eq!(eval.recall_formula("cube"), None);
+        let names: Vec<_> = eval.formulas().into_iter().map(|(name, _)| name).collect();
+        assert_eq!(names, ["area", "quad"]);
+
+        // Formulas aren't variables, so nothing else can refer to them
+        assert!(eval.vars().is_empty());
+        assert_eq!(eval.exec(&parse("q + 1")), Err(RuntimeError::Invalid));
+
+        assert_eq!(
+            eval.store_formula("area", parse("w * h / 2")),
+            Some(parse("w * h"))
+        );
+        assert_eq!(eval.remove_formula("area"), Some(parse("w * h / 2")));
+        assert_eq!(eval.recall_formula("area"), None);
+    }
+
+    #[test]
+    fn evaluating_with_bindings() {
+        let mut eval = Evaluator::default();
+        eval.store_formula("quad", parse("x^2 + 3x + 1"));
+        eval.store_formula("area", parse("w * h"));
+        eval.set('w', Expr::Integer(10));
+
+        for (x, expected) in [(2, 11), (0, 1), (-1, -1)] {
+            assert_eq!(
+                eval.eval_formula_with("quad", &[('x', EvalResult::Integer(x))]),
+                Some(Ok(EvalResult::Integer(expected))),
+                "{x}"
+            );
+        }
+        // Bound variables are used unless a binding shadows them, and stay bound afterwards
+        assert_eq!(
+            eval.eval_formula_with("area", &[('h', EvalResult::Integer(3))]),
+            Some(Ok(EvalResult::Integer(30)))
+        );
+        assert_eq!(
+            eval.eval_formula_with(
+                "area",
+                &[('w', EvalResult::Integer(2)), ('h', EvalResult::Integer(3))]
+            ),
+            Some(Ok(EvalResult::Integer(6)))
+        );
+        assert_eq!(eval.get('w'), Some(&Expr::Integer(10)));
+        assert_eq!(eval.get('h'), None);
+
+        assert_eq!(
+            eval.eval_formula_with("area", &[]),
+            Some(Err(RuntimeError::Invalid))
+        );
+        assert_eq!(eval.eval_formula_with("cube", &[]), None);
+    }
+}
@@ -0,0 +1,545 @@
+//! The registry of everything callable by name, like `gcd(a, b)` or `sin(x)`. Parsing looks names
+//! up here, so anything listed is understood and anything understood is listed, which keeps help
+//! output and autocompletion from drifting out of date
+
+use std::fmt::Display;
+
+use crate::ast::{BinaryOperator, Function, UnaryOperator};
+
+/// How a named function is called and what it does
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Signature {
+    /// The name it's usually called by
+    pub name: &'static str,
+    /// Other names it can be called by
+    pub aliases: &'static [&'static str],
+    /// Names for its arguments, only used for display
+    pub params: &'static [&'static str],
+    /// A one line description
+    pub description: &'static str,
+}
+
+impl Signature {
+    /// A signature without any aliases
+    const fn new(
+        name: &'static str,
+        params: &'static [&'static str],
+        description: &'static str,
+    ) -> Self {
+        Self {
+            name,
+            aliases: &[],
+            params,
+            description,
+        }
+    }
+
+    /// Adds other names the function can be called by
+    const fn aliased(self, aliases: &'static [&'static str]) -> Self {
+        Self { aliases, ..self }
+    }
+
+    /// How many arguments the function takes, or the fewest if some are optional or repeated
+    pub fn arity(&self) -> usize {
+        self.params
+            .iter()
+            .filter(|param| !param.ends_with('?'))
+            .count()
+    }
+
+    /// Whether the last parameter can be repeated, which is written like `xs...`
+    pub fn is_variadic(&self) -> bool {
+        self.params
+            .last()
+            .is_some_and(|param| param.ends_with("..."))
+    }
+
+    /// Whether the function can be called with `count` arguments. Optional parameters, written
+    /// like `step?`, come last
+    pub fn takes(&self, count: usize) -> bool {
+        count >= self.arity() && (count <= self.params.len() || self.is_variadic())
+    }
+
+    /// Whether the function can be called by a name, ignoring case
+    pub fn accepts(&self, name: &str) -> bool {
+        self.name.eq_ignore_ascii_case(name)
+            || self
+                .aliases
+                .iter()
+                .any(|alias| alias.eq_ignore_ascii_case(name))
+    }
+}
+
+impl Display for Signature {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}({})", self.name, self.params.join(", "))
+    }
+}
+
+#[cfg(feature = "serde")]
+impl serde::Serialize for Signature {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        use serde::ser::SerializeStruct;
+
+        let mut state = serializer.serialize_struct("Signature", 5)?;
+        state.serialize_field("name", self.name)?;
+        state.serialize_field("aliases", self.aliases)?;
+        state.serialize_field("arity", &self.arity())?;
+        state.serialize_field("params", self.params)?;
+        state.serialize_field("description", self.description)?;
+        state.end()
+    }
+}
+
+impl UnaryOperator {
+    /// Every unary operation callable by name. Postfix-only operations like `°` aren't included
+    pub const ALL: &[(Self, Signature)] = &[
+        (Self::Sqrt, Signature::new("sqrt", &["x"], "square root")),
+        (Self::Ln, Signature::new("ln", &["x"], "natural logarithm")),
+        (Self::Floor, Signature::new("floor", &["x"], "round down")),
+        (Self::Ceil, Signature::new("ceil", &["x"], "round up")),
+        (Self::Neg, Signature::new("neg", &["x"], "-x")),
+        (Self::Factorial, Signature::new("factorial", &["n"], "n!")),
+        (Self::Sin, Signature::new("sin", &["x"], "sine")),
+        (Self::Cos, Signature::new("cos", &["x"], "cosine")),
+        (Self::Tan, Signature::new("tan", &["x"], "tangent")),
+        (Self::ASin, Signature::new("asin", &["x"], "inverse sine")),
+        (Self::ACos, Signature::new("acos", &["x"], "inverse cosine")),
+        (
+            Self::ATan,
+            Signature::new("atan", &["x"], "inverse tangent"),
+        ),
+        (
+            Self::Sinh,
+            Signature::new("sinh", &["x"], "hyperbolic sine"),
+        ),
+        (
+            Self::Cosh,
+            Signature::new("cosh", &["x"], "hyperbolic cosine"),
+        ),
+        (
+            Self::Tanh,
+            Signature::new("tanh", &["x"], "hyperbolic tangent"),
+        ),
+        (
+            Self::ASinh,
+            Signature::new("asinh", &["x"], "inverse hyperbolic sine"),
+        ),
+        (
+            Self::ACosh,
+            Signature::new("acosh", &["x"], "inverse hyperbolic cosine"),
+        ),
+        (
+            Self::ATanh,
+            Signature::new("atanh", &["x"], "inverse hyperbolic tangent"),
+        ),
+        (
+            Self::Factorize,
+            Signature::new("factorize", &["n"], "the prime factors of n, as a list"),
+        ),
+        (
+            Self::Divisors,
+            Signature::new("divisors", &["n"], "the divisors of n, as a list"),
+        ),
+        (
+            Self::NumDivisors,
+            Signature::new("numdivisors", &["n"], "how many divisors n has"),
+        ),
+        (
+            Self::SumDivisors,
+            Signature::new("sumdivisors", &["n"], "the sum of the divisors of n"),
+        ),
+        (
+            Self::Totient,
+            Signature::new(
+                "totient",
+                &["n"],
+                "how many numbers up to n are coprime to it",
+            ),
+        ),
+        (
+            Self::NthPrime,
+            Signature::new("nthprime", &["n"], "the nth prime, starting from 2"),
+        ),
+        (
+            Self::PrimeCount,
+            Signature::new("primecount", &["n"], "how many primes there are up to n"),
+        ),
+        (
+            Self::BitNot,
+            Signature::new("bnot", &["n"], "~n, every bit of n flipped"),
+        ),
+        (Self::Re, Signature::new("re", &["z"], "the real part of z")),
+        (
+            Self::Im,
+            Signature::new("im", &["z"], "the imaginary part of z"),
+        ),
+        (
+            Self::Conj,
+            Signature::new("conj", &["z"], "the complex conjugate of z"),
+        ),
+        (
+            Self::Arg,
+            Signature::new("arg", &["z"], "the angle of z from the positive real axis"),
+        ),
+    ];
+}
+
+impl UnaryOperator {
+    /// The name the operation is called by, or its symbol if it's only ever postfix
+    pub fn name(&self) -> &'static str {
+        match self {
+            Self::Degrees => "°",
+            Self::Percent => "%",
+            _ => Self::ALL
+                .iter()
+                .find_map(|(op, signature)| (op == self).then_some(signature.name))
+                .expect("Every operation that isn't postfix only is registered"),
+        }
+    }
+}
+
+impl BinaryOperator {
+    /// Every binary operation callable by name, including the named forms of operators like `+`
+    pub const ALL: &[(Self, Signature)] = &[
+        (
+            Self::Gcd,
+            Signature::new("gcd", &["a", "b"], "greatest common divisor"),
+        ),
+        (
+            Self::Lcm,
+            Signature::new("lcm", &["a", "b"], "least common multiple"),
+        ),
+        (
+            Self::Atan2,
+            Signature::new("atan2", &["y", "x"], "the angle of the point (x, y)"),
+        ),
+        (
+            Self::BitAnd,
+            Signature::new("band", &["a", "b"], "the bits set in both a and b"),
+        ),
+        (
+            Self::BitOr,
+            Signature::new("bor", &["a", "b"], "the bits set in either a or b"),
+        ),
+        (
+            Self::BitXor,
+            Signature::new(
+                "bxor",
+                &["a", "b"],
+                "the bits set in exactly one of a and b",
+            ),
+        ),
+        (Self::Shl, Signature::new("shl", &["a", "n"], "a << n")),
+        (Self::Shr, Signature::new("shr", &["a", "n"], "a >> n")),
+        (Self::Add, Signature::new("add", &["a", "b"], "a + b")),
+        (
+            Self::Subtract,
+            Signature::new("subtract", &["a", "b"], "a - b").aliased(&["sub"]),
+        ),
+        (
+            Self::Multiply,
+            Signature::new("multiply", &["a", "b"], "a * b").aliased(&["mul"]),
+        ),
+        (
+            Self::Divide,
+            Signature::new("divide", &["a", "b"], "a / b").aliased(&["div"]),
+        ),
+        (Self::Mod, Signature::new("mod", &["a", "m"], "a % m")),
+        (Self::Pow, Signature::new("pow", &["a", "b"], "a ^ b")),
+        (Self::Eq, Signature::new("eq", &["a", "b"], "a == b")),
+        (Self::NEq, Signature::new("neq", &["a", "b"], "a != b")),
+        (Self::Gt, Signature::new("gt", &["a", "b"], "a > b")),
+        (Self::Lt, Signature::new("lt", &["a", "b"], "a < b")),
+        (Self::Gte, Signature::new("gte", &["a", "b"], "a >= b")),
+        (Self::Lte, Signature::new("lte", &["a", "b"], "a <= b")),
+        (Self::And, Signature::new("and", &["a", "b"], "a && b")),
+        (Self::Or, Signature::new("or", &["a", "b"], "a || b")),
+    ];
+}
+
+impl Function {
+    /// Every function taking some other number of arguments
+    pub const ALL: &[(Self, Signature)] = &[
+        (
+            Self::PowMod,
+            Signature::new("powmod", &["b", "e", "m"], "b ^ e modulo m"),
+        ),
+        (
+            Self::InvMod,
+            Signature::new("invmod", &["a", "m"], "the inverse of a modulo m"),
+        ),
+        (
+            Self::Rand,
+            Signature::new("rand", &[], "a random number from 0 up to but excluding 1"),
+        ),
+        (
+            Self::RandInt,
+            Signature::new(
+                "randint",
+                &["a", "b"],
+                "a random integer from a to b inclusive",
+            ),
+        ),
+        (
+            Self::Approx,
+            Signature::new(
+                "approx",
+                &["a", "b", "tolerance"],
+                "whether a and b are equal within a relative tolerance",
+            ),
+        ),
+        (
+            Self::Hist,
+            Signature::new("hist", &["n"], "the result of the nth statement evaluated"),
+        ),
+        (
+            Self::PctChange,
+            Signature::new(
+                "pctchange",
+                &["old", "new"],
+                "the percent change from old to new",
+            ),
+        ),
+        (
+            Self::PctOf,
+            Signature::new("pctof", &["part", "whole"], "part as a percent of whole"),
+        ),
+        (
+            Self::ApplyPct,
+            Signature::new(
+                "applypct",
+                &["value", "pct"],
+                "value increased by pct percent, or decreased if pct is negative",
+            ),
+        ),
+        (
+            Self::Sum,
+            Signature::new("sum", &["xs..."], "the total of a list or the arguments"),
+        ),
+        (
+            Self::Mean,
+            Signature::new("mean", &["xs..."], "the average of a list or the arguments"),
+        ),
+        (
+            Self::Median,
+            Signature::new(
+                "median",
+                &["xs..."],
+                "the middle of a list or the arguments, averaging the middle two of an even count",
+            ),
+        ),
+        (
+            Self::Min,
+            Signature::new("min", &["xs..."], "the smallest of a list or the arguments"),
+        ),
+        (
+            Self::Max,
+            Signature::new("max", &["xs..."], "the largest of a list or the arguments"),
+        ),
+        (
+            Self::Stdev,
+            Signature::new(
+                "stdev",
+                &["xs..."],
+                "the sample standard deviation of a list or the arguments",
+            ),
+        ),
+        (
+            Self::Len,
+            Signature::new(
+                "len",
+                &["xs..."],
+                "how many items a list or the arguments have",
+            ),
+        ),
+        (
+            Self::Range,
+            Signature::new(
+                "range",
+                &["start", "end", "step?"],
+                "the integers from start to end inclusive, counting by step or else 1",
+            ),
+        ),
+        (
+            Self::Map,
+            Signature::new("map", &["f", "xs"], "f applied to every item of a list"),
+        ),
+        (
+            Self::Filter,
+            Signature::new("filter", &["f", "xs"], "the items of a list f is true for"),
+        ),
+        (
+            Self::Reduce,
+            Signature::new(
+                "reduce",
+                &["f", "init", "xs"],
+                "every item of a list folded into init by f, from the left",
+            ),
+        ),
+        (
+            Self::If,
+            Signature::new(
+                "if",
+                &["cond", "then", "else"],
+                "then if cond is true and else if it's false, evaluating only that one",
+            ),
+        ),
+        (
+            Self::Piecewise,
+            Signature::new(
+                "piecewise",
+                &["cond", "value", "more..."],
+                "the value after the first true condition, or the default after the last pair",
+            ),
+        ),
+    ];
+
+    /// How the function is called
+    pub fn signature(&self) -> &'static Signature {
+        Self::ALL
+            .iter()
+            .find_map(|(function, signature)| (function == self).then_some(signature))
+            .expect("Every function is registered")
+    }
+}
+
+/// Finds the entry in a registry callable by a name
+pub(crate) fn lookup<T: Clone>(registry: &[(T, Signature)], name: &str) -> Option<T> {
+    registry
+        .iter()
+        .find_map(|(item, signature)| signature.accepts(name).then(|| item.clone()))
+}
+
+/// Every function callable by name, in the order they're listed in help
+pub fn all() -> impl Iterator<Item = &'static Signature> {
+    let unary = UnaryOperator::ALL.iter().map(|(_, signature)| signature);
+    let binary = BinaryOperator::ALL.iter().map(|(_, signature)| signature);
+    let functions = Function::ALL.iter().map(|(_, signature)| signature);
+    unary.chain(binary).chain(functions)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashSet;
+
+    use crate::ast::{BinaryOperator, Function, UnaryOperator};
+
+    use super::all;
+
+    /// Every name the operators were accepted by before the registry existed, so dropping one
+    /// from the registry is caught
+    const NAMES: &[&str] = &[
+        "neg",
+        "ln",
+        "sqrt",
+        "factorial",
+        "floor",
+        "ceil",
+        "tan",
+        "cos",
+        "sin",
+        "atan",
+        "acos",
+        "asin",
+        "sinh",
+        "cosh",
+        "tanh",
+        "asinh",
+        "acosh",
+        "atanh",
+        "factorize",
+        "divisors",
+        "numdivisors",
+        "sumdivisors",
+        "nthprime",
+        "primecount",
+        "totient",
+        "bnot",
+        "add",
+        "subtract",
+        "sub",
+        "multiply",
+        "mul",
+        "divide",
+        "div",
+        "pow",
+        "gcd",
+        "lcm",
+        "atan2",
+        "band",
+        "bor",
+        "bxor",
+        "shl",
+        "shr",
+        "eq",
+        "neq",
+        "gt",
+        "lt",
+        "gte",
+        "lte",
+        "and",
+        "or",
+        "powmod",
+        "invmod",
+        "rand",
+        "randint",
+        "approx",
+        "hist",
+        "pctchange",
+        "pctof",
+        "applypct",
+        "sum",
+        "mean",
+        "median",
+        "min",
+        "max",
+        "stdev",
+        "len",
+        "range",
+        "map",
+        "filter",
+        "reduce",
+    ];
+
+    #[test]
+    fn registry_matches_parsing() {
+        let parses = |name: &str| {
+            UnaryOperator::try_from(name).is_ok()
+                || BinaryOperator::try_from(name).is_ok()
+                || Function::try_from(name).is_ok()
+        };
+
+        let mut registered = HashSet::new();
+        for signature in all() {
+            for name in std::iter::once(&signature.name).chain(signature.aliases) {
+                assert!(registered.insert(*name), "`{name}` is registered twice");
+                assert!(parses(name), "`{name}` is registered but doesn't parse");
+                assert!(parses(&name.to_uppercase()), "`{name}` is case sensitive");
+            }
+            assert!(!signature.description.is_empty(), "{signature}");
+        }
+
+        for name in NAMES {
+            assert!(
+                registered.contains(name),
+                "`{name}` parses but isn't registered"
+            );
+        }
+        assert!(!parses("frobnicate"));
+    }
+
+    #[test]
+    fn arities() {
+        for (op, signature) in UnaryOperator::ALL {
+            assert_eq!(signature.arity(), 1, "{op:?}");
+        }
+        for (op, signature) in BinaryOperator::ALL {
+            assert_eq!(signature.arity(), 2, "{op:?}");
+        }
+        assert_eq!(Function::PowMod.arity(), 3);
+        assert_eq!(Function::Rand.arity(), 0);
+        assert_eq!(Function::Rand.signature().to_string(), "rand()");
+        assert_eq!(Function::InvMod.signature().to_string(), "invmod(a, m)");
+    }
+}
@@ -0,0 +1,127 @@
+//! A bounded record of the statements evaluated so far, so earlier results can be recalled
+
+use crate::exec::{EvalResult, RuntimeError};
+
+/// How many entries are kept before the oldest are forgotten
+pub const MAX_HISTORY: usize = 1000;
+
+/// A statement that evaluated successfully, along with what it evaluated to
+#[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub struct HistoryEntry {
+    /// The entry's position counting from 1, which stays the same as older entries are forgotten
+    pub number: usize,
+    /// The statement as it was written
+    pub source: String,
+    /// What the statement evaluated to
+    pub result: EvalResult,
+}
+
+/// The most recent [`MAX_HISTORY`] entries, oldest first
+#[derive(Clone, Debug, Default)]
+pub(crate) struct History {
+    /// The entries still remembered
+    entries: Vec<HistoryEntry>,
+    /// How many entries have ever been added
+    added: usize,
+}
+
+impl History {
+    /// Every remembered entry, oldest first
+    pub(crate) fn entries(&self) -> &[HistoryEntry] {
+        &self.entries
+    }
+
+    /// Adds an entry, forgetting the oldest if there are too many. Returns the entry's number
+    pub(crate) fn push(&mut self, source: String, result: EvalResult) -> usize {
+        self.added += 1;
+        self.entries.push(HistoryEntry {
+            number: self.added,
+            source,
+            result,
+        });
+
+        let excess = self.entries.len().saturating_sub(MAX_HISTORY);
+        self.entries.drain(..excess);
+
+        self.added
+    }
+
+    /// The result of the entry numbered `n`, which must be a whole number that's still remembered
+    pub(crate) fn recall(&self, n: &EvalResult) -> Result<EvalResult, RuntimeError> {
+        let n = usize::try_from(n.int()?).map_err(|_| RuntimeError::Invalid)?;
+        let oldest = self.added + 1 - self.entries.len();
+
+        n.checked_sub(oldest)
+            .and_then(|index| self.entries.get(index))
+            .map(|entry| entry.result.clone())
+            .ok_or(RuntimeError::Invalid)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        exec::{EvalResult, Evaluator, RuntimeError},
+        pipeline::{ChalkError, eval_str_with},
+    };
+
+    use super::MAX_HISTORY;
+
+    #[test]
+    fn recalling_results() {
+        let mut evaluator = Evaluator::default();
+        for statement in ["6 * 7", "2 ^ 10", "1 / 4"] {
+            eval_str_with(statement, &mut evaluator).expect("Evaluate");
+        }
+        // Failures aren't remembered
+        assert!(eval_str_with("(-1)!", &mut evaluator).is_err());
+
+        let sources: Vec<_> = evaluator
+            .history()
+            .iter()
+            .map(|entry| (entry.number, entry.source.as_str()))
+            .collect();
+        assert_eq!(sources, [(1, "6 * 7"), (2, "2 ^ 10"), (3, "1 / 4")]);
+
+        assert_eq!(
+            eval_str_with("hist(1) + 1", &mut evaluator),
+            Ok(EvalResult::Integer(43))
+        );
+        assert_eq!(
+            eval_str_with("hist(4) - hist(2)", &mut evaluator),
+            Ok(EvalResult::Integer(-981))
+        );
+        for out_of_range in ["hist(0)", "hist(-1)", "hist(6)", "hist(1.5)"] {
+            assert!(
+                matches!(
+                    eval_str_with(out_of_range, &mut evaluator),
+                    Err(ChalkError::Runtime(RuntimeError::Invalid, _))
+                ),
+                "{out_of_range}"
+            );
+        }
+    }
+
+    #[test]
+    fn forgetting_old_entries() {
+        let mut evaluator = Evaluator::default();
+        for i in 1..=MAX_HISTORY as i64 + 5 {
+            evaluator.remember(i.to_string(), EvalResult::Integer(i));
+        }
+
+        let history = evaluator.history();
+        assert_eq!(history.len(), MAX_HISTORY);
+        assert_eq!(history[0].number, 6);
+        assert_eq!(history[MAX_HISTORY - 1].number, MAX_HISTORY + 5);
+
+        assert!(matches!(
+            eval_str_with("hist(5)", &mut evaluator),
+            Err(ChalkError::Runtime(RuntimeError::Invalid, _))
+        ));
+        assert_eq!(
+            eval_str_with("hist(6)", &mut evaluator),
+            Ok(EvalResult::Integer(6))
+        );
+    }
+}
@@ -0,0 +1,191 @@
+//! Evaluating just the part of an expression under a cursor, for editors that show values on hover
+
+use std::{error::Error, fmt::Display, ops::Range};
+
+use crate::{
+    ast::{Expr, ParseError, Parser, Spanned},
+    exec::{EvalResult, Evaluator, RuntimeError},
+    tokenizer::{InvalidToken, Tokenizable},
+};
+
+/// Why the expression under a cursor couldn't be evaluated
+#[derive(Clone, Debug, PartialEq)]
+pub enum HoverError {
+    /// The source contained a character that isn't valid Chalk
+    Tokenize(InvalidToken),
+    /// The source didn't form a valid expression
+    Parse(ParseError),
+    /// The expression under the cursor failed, along with where it is in the source
+    Runtime(RuntimeError, Range<usize>),
+}
+
+impl Display for HoverError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Tokenize(err) => write!(f, "{err}"),
+            Self::Parse(err) => write!(f, "{err}"),
+            Self::Runtime(err, _) => write!(f, "{err}"),
+        }
+    }
+}
+
+impl Error for HoverError {}
+
+impl From<InvalidToken> for HoverError {
+    fn from(err: InvalidToken) -> Self {
+        Self::Tokenize(err)
+    }
+}
+
+impl From<ParseError> for HoverError {
+    fn from(err: ParseError) -> Self {
+        Self::Parse(err)
+    }
+}
+
+impl Spanned {
+    /// The smallest node whose range contains the character at `offset`, along with its range.
+    /// Offsets outside every node, like in leading whitespace, give the whole expression
+    pub fn node_at(&self, offset: usize) -> (&Expr, Range<usize>) {
+        // Every node in the order `walk` visits them, to line up with their spans
+        let mut nodes = vec![];
+        let mut stack = vec![&self.expr];
+        while let Some(node) = stack.pop() {
+            nodes.push(node);
+            stack.extend(node.children().into_iter().rev());
+        }
+
+        // Children come after their parents, so searching backwards finds the deepest of any
+        // nodes with the same range
+        nodes
+            .into_iter()
+            .zip(self.spans.iter().cloned())
+            .filter(|(_, span)| span.contains(&offset))
+            .rev()
+            .min_by_key(|(_, span)| span.len())
+            .unwrap_or((&self.expr, self.spans.first().cloned().unwrap_or_default()))
+    }
+}
+
+impl Evaluator {
+    /// Evaluates the smallest subexpression of `source` containing the character at `offset`,
+    /// returning it as written back and its value. Hovering over an assignment evaluates what
+    /// would be assigned without binding it, and assignments nested inside what's hovered over
+    /// are undone, so nothing about the context changes
+    pub fn eval_at(
+        &mut self,
+        source: &str,
+        offset: usize,
+    ) -> Result<(String, EvalResult), HoverError> {
+        let spanned = Parser::with_spans(source.tokenize_spanned()?)
+            .with_max_depth(self.max_depth())
+            .parse_spanned()?;
+        let (node, span) = spanned.node_at(offset);
+        let node = match node {
+            Expr::Assignment(_, value) => value,
+            node => node,
+        };
+
+        let ctx = self.ctx.clone();
+        let result = self.exec(node);
+        let changed: Vec<_> = self
+            .ctx
+            .keys()
+            .chain(ctx.keys())
+            .filter(|var| self.ctx.get(var) != ctx.get(var))
+            .copied()
+            .collect();
+        self.ctx = ctx;
+
+        // Values cached from bindings that were just undone are wrong now
+        for var in changed {
+            self.invalidate(var);
+        }
+
+        let result = result.map_err(|err| HoverError::Runtime(err, span))?;
+        Ok((node.to_string(), result))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        ast::Expr,
+        exec::{EvalResult, Evaluator, RuntimeError},
+    };
+
+    use super::HoverError;
+
+    /// The subexpression at each offset and what it evaluates to
+    fn hover(eval: &mut Evaluator, source: &str, offset: usize) -> (String, EvalResult) {
+        eval.eval_at(source, offset)
+            .unwrap_or_else(|err| panic!("`{source}` at {offset}: {err}"))
+    }
+
+    #[test]
+    fn innermost_subexpression() {
+        let mut eval = Evaluator::default();
+        let source = "1 + 1 - (2 * 4)";
+
+        for (offset, expression, value) in [
+            // On the `*` and the spaces around it
+            (11, "2 * 4", 8),
+            (10, "2 * 4", 8),
+            (9, "2", 2),
+            (8, "(2 * 4)", 8),
+            (6, "1 + 1 - (2 * 4)", -6),
+            (4, "1", 1),
+            (2, "1 + 1", 2),
+            // Outside every node
+            (40, "1 + 1 - (2 * 4)", -6),
+        ] {
+            assert_eq!(
+                hover(&mut eval, source, offset),
+                (expression.to_string(), EvalResult::Integer(value)),
+                "{offset}"
+            );
+        }
+    }
+
+    #[test]
+    fn variables_and_assignments() {
+        let mut eval = Evaluator::default();
+        eval.set('x', Expr::Integer(5));
+
+        let source = "y = 2 * (x + 1)";
+        assert_eq!(
+            hover(&mut eval, source, 9),
+            ("x".to_string(), EvalResult::Integer(5))
+        );
+        assert_eq!(
+            hover(&mut eval, source, 11),
+            ("x + 1".to_string(), EvalResult::Integer(6))
+        );
+        // The assignment itself gives what would be assigned, without assigning it
+        assert_eq!(
+            hover(&mut eval, source, 0),
+            ("2 * (x + 1)".to_string(), EvalResult::Integer(12))
+        );
+        assert!(!eval.ctx.contains_key(&'y'));
+
+        // Nor are assignments nested in it
+        assert_eq!(
+            hover(&mut eval, "y = (z = 3) + x", 0),
+            ("(z = 3) + x".to_string(), EvalResult::Integer(8))
+        );
+        assert_eq!(eval.vars(), [('x', &Expr::Integer(5))]);
+
+        assert_eq!(
+            eval.eval_at("1 + 1 / z", 8),
+            Err(HoverError::Runtime(RuntimeError::Invalid, 8..9))
+        );
+        assert!(matches!(
+            eval.eval_at("1 + $ 2", 0),
+            Err(HoverError::Tokenize(_))
+        ));
+        assert!(matches!(
+            eval.eval_at("(1 + 2", 0),
+            Err(HoverError::Parse(_))
+        ));
+    }
+}
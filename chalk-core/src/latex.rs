@@ -0,0 +1,629 @@
+//! Rendering expressions as LaTeX, and parsing a subset of LaTeX back into expressions
+
+use std::ops::Range;
+
+use crate::{
+    ast::{
+        BinaryOperator, Constant, Expr, ParseError, ParseErrorKind, Parser, Reduction,
+        UnaryOperator,
+    },
+    tokenizer::{Token, Tokenizable},
+};
+
+/// Precedence of assignments, the loosest binding
+const ASSIGNMENT: u8 = 0;
+/// Precedence of `||`
+const OR: u8 = 1;
+/// Precedence of `&&`
+const AND: u8 = 2;
+/// Precedence of comparisons
+const COMPARISON: u8 = 3;
+/// Precedence of unit conversions
+const CONVERSION: u8 = 4;
+/// Precedence of bit shifts
+const SHIFT: u8 = 5;
+/// Precedence of addition and subtraction
+const SUM: u8 = 6;
+/// Precedence of multiplication and fractions
+const PRODUCT: u8 = 7;
+/// Precedence of negation
+const NEGATION: u8 = 8;
+/// Precedence of powers
+const POWER: u8 = 9;
+/// Precedence of postfix operators like factorial
+const POSTFIX: u8 = 10;
+/// Precedence of anything that never needs wrapping, like numbers and function calls
+const ATOM: u8 = 11;
+
+impl Expr {
+    /// Renders the expression as LaTeX, only parenthesizing where precedence requires it
+    pub fn to_latex(&self) -> String {
+        self.latex_at(ASSIGNMENT)
+    }
+
+    /// The expression with any source parentheses around it stripped
+    fn unparenthesized(&self) -> &Expr {
+        match self {
+            Self::Paren(node) => node.unparenthesized(),
+            _ => self,
+        }
+    }
+
+    /// How tightly the expression binds once rendered
+    fn latex_precedence(&self) -> u8 {
+        match self {
+            Self::Assignment(..) => ASSIGNMENT,
+            Self::Paren(node) => node.latex_precedence(),
+            // Big operators take everything up to the next sum as their body
+            Self::Reduce { .. } => PRODUCT,
+            Self::UnaryOp { op, .. } => match op {
+                UnaryOperator::Neg | UnaryOperator::BitNot => NEGATION,
+                UnaryOperator::Factorial | UnaryOperator::Degrees | UnaryOperator::Percent => {
+                    POSTFIX
+                }
+                _ => ATOM,
+            },
+            Self::Index { .. } => POSTFIX,
+            Self::BinaryOp { op, .. } => match op {
+                BinaryOperator::Or => OR,
+                BinaryOperator::And => AND,
+                BinaryOperator::Eq
+                | BinaryOperator::NEq
+                | BinaryOperator::ApproxEq
+                | BinaryOperator::Gt
+                | BinaryOperator::Lt
+                | BinaryOperator::Gte
+                | BinaryOperator::Lte => COMPARISON,
+                BinaryOperator::Convert => CONVERSION,
+                BinaryOperator::Shl | BinaryOperator::Shr => SHIFT,
+                BinaryOperator::Add | BinaryOperator::Subtract => SUM,
+                BinaryOperator::Multiply | BinaryOperator::Divide | BinaryOperator::Mod => PRODUCT,
+                BinaryOperator::Pow => POWER,
+                BinaryOperator::Gcd
+                | BinaryOperator::Lcm
+                | BinaryOperator::Atan2
+                | BinaryOperator::BitAnd
+                | BinaryOperator::BitOr
+                | BinaryOperator::BitXor => ATOM,
+            },
+            Self::Variable(_)
+            | Self::Integer(_)
+            | Self::Real(_)
+            | Self::Bool(_)
+            | Self::AbsVal(_)
+            | Self::Constant(_)
+            | Self::Unit(_)
+            | Self::Solve { .. }
+            | Self::List(_)
+            | Self::FunctionCall { .. } => ATOM,
+        }
+    }
+
+    /// Renders the expression, wrapping it in parentheses if it binds looser than `min`
+    fn latex_at(&self, min: u8) -> String {
+        let latex = match self {
+            // Source parentheses are dropped, precedence decides where they're needed
+            Self::Paren(node) => return node.latex_at(min),
+            Self::Assignment(var, node) => format!("{var} = {}", node.latex_at(ASSIGNMENT)),
+            Self::Variable(var) => var.to_string(),
+            Self::Integer(i) => i.to_string(),
+            Self::Real(r) => r.to_string(),
+            Self::Bool(b) => format!("\\text{{{b}}}"),
+            Self::AbsVal(node) => format!("\\left| {} \\right|", node.to_latex()),
+            Self::Constant(Constant::Pi) => "\\pi".to_string(),
+            Self::Unit(unit) => format!("\\mathrm{{{unit}}}"),
+            Self::UnaryOp { op, node } => unary_latex(op, node),
+            Self::BinaryOp { op, left, right } => binary_latex(op, left, right),
+            Self::Solve { equation, var } => {
+                format!("\\operatorname{{solve}}({}, {var})", equation.to_latex())
+            }
+            Self::Reduce {
+                kind,
+                var,
+                from,
+                to,
+                body,
+            } => {
+                let symbol = match kind {
+                    Reduction::Sum => "\\sum",
+                    Reduction::Product => "\\prod",
+                };
+                format!(
+                    "{symbol}_{{{var} = {}}}^{{{}}} {}",
+                    from.to_latex(),
+                    to.to_latex(),
+                    body.latex_at(PRODUCT)
+                )
+            }
+            Self::FunctionCall { function, args } => {
+                let args: Vec<String> = args.iter().map(Expr::to_latex).collect();
+                format!("\\operatorname{{{function}}}({})", args.join(", "))
+            }
+            Self::List(items) => {
+                let items: Vec<String> = items.iter().map(Expr::to_latex).collect();
+                format!("\\left[ {} \\right]", items.join(", "))
+            }
+            Self::Index { list, index } => {
+                format!("{}_{{{}}}", list.latex_at(ATOM), index.to_latex())
+            }
+        };
+
+        if self.latex_precedence() < min {
+            format!("\\left( {latex} \\right)")
+        } else {
+            latex
+        }
+    }
+}
+
+/// Renders a unary operation
+fn unary_latex(op: &UnaryOperator, node: &Expr) -> String {
+    let function = |name: &str| format!("{name}({})", node.to_latex());
+
+    match op {
+        UnaryOperator::Neg => format!("-{}", node.latex_at(PRODUCT)),
+        UnaryOperator::BitNot => format!("\\sim {}", node.latex_at(PRODUCT)),
+        UnaryOperator::Factorial => format!("{}!", node.latex_at(POSTFIX)),
+        UnaryOperator::Degrees => format!("{}^{{\\circ}}", node.latex_at(POSTFIX)),
+        UnaryOperator::Percent => format!("{}\\%", node.latex_at(POSTFIX)),
+        UnaryOperator::Floor => format!("\\lfloor {} \\rfloor", node.to_latex()),
+        UnaryOperator::Ceil => format!("\\lceil {} \\rceil", node.to_latex()),
+        UnaryOperator::Sqrt => format!("\\sqrt{{{}}}", node.to_latex()),
+        UnaryOperator::Ln => function("\\ln"),
+        UnaryOperator::Sin => function("\\sin"),
+        UnaryOperator::Cos => function("\\cos"),
+        UnaryOperator::Tan => function("\\tan"),
+        UnaryOperator::ASin => function("\\arcsin"),
+        UnaryOperator::ACos => function("\\arccos"),
+        UnaryOperator::ATan => function("\\arctan"),
+        UnaryOperator::Sinh => function("\\sinh"),
+        UnaryOperator::Cosh => function("\\cosh"),
+        UnaryOperator::Tanh => function("\\tanh"),
+        UnaryOperator::ASinh => function("\\operatorname{arsinh}"),
+        UnaryOperator::ACosh => function("\\operatorname{arcosh}"),
+        UnaryOperator::ATanh => function("\\operatorname{artanh}"),
+        UnaryOperator::Factorize => function("\\operatorname{factorize}"),
+        UnaryOperator::Divisors => function("\\operatorname{divisors}"),
+        UnaryOperator::NumDivisors => function("\\operatorname{numdivisors}"),
+        UnaryOperator::SumDivisors => function("\\operatorname{sumdivisors}"),
+        UnaryOperator::NthPrime => format!("p_{{{}}}", node.to_latex()),
+        UnaryOperator::PrimeCount => function("\\pi"),
+        UnaryOperator::Totient => function("\\varphi"),
+        UnaryOperator::Re => function("\\operatorname{Re}"),
+        UnaryOperator::Im => function("\\operatorname{Im}"),
+        UnaryOperator::Conj => format!("\\overline{{{}}}", node.to_latex()),
+        UnaryOperator::Arg => function("\\arg"),
+    }
+}
+
+/// Renders a binary operation. Operators are left associative, so a right operand at the same
+/// precedence keeps its parentheses
+fn binary_latex(op: &BinaryOperator, left: &Expr, right: &Expr) -> String {
+    let infix = |symbol: &str, precedence: u8| {
+        format!(
+            "{} {symbol} {}",
+            left.latex_at(precedence),
+            right.latex_at(precedence + 1)
+        )
+    };
+
+    match op {
+        BinaryOperator::Add => infix("+", SUM),
+        BinaryOperator::Subtract => infix("-", SUM),
+        BinaryOperator::Multiply => match (left.unparenthesized(), right.unparenthesized()) {
+            // Coefficients sit right next to what they multiply, like `3x`
+            (Expr::Integer(_) | Expr::Real(_), Expr::Variable(_)) => {
+                format!("{}{}", left.to_latex(), right.to_latex())
+            }
+            // Units follow what they measure after a thin space, like `3\,\mathrm{km}`
+            (_, Expr::Unit(_)) => format!("{}\\,{}", left.latex_at(PRODUCT), right.to_latex()),
+            _ => infix("\\cdot", PRODUCT),
+        },
+        BinaryOperator::Divide => format!("\\frac{{{}}}{{{}}}", left.to_latex(), right.to_latex()),
+        BinaryOperator::Mod => infix("\\bmod", PRODUCT),
+        BinaryOperator::Pow => format!("{}^{{{}}}", left.latex_at(ATOM), right.to_latex()),
+        BinaryOperator::Gcd => format!("\\gcd({}, {})", left.to_latex(), right.to_latex()),
+        BinaryOperator::Lcm => format!(
+            "\\operatorname{{lcm}}({}, {})",
+            left.to_latex(),
+            right.to_latex()
+        ),
+        BinaryOperator::Atan2 => format!(
+            "\\operatorname{{atan2}}({}, {})",
+            left.to_latex(),
+            right.to_latex()
+        ),
+        BinaryOperator::BitAnd | BinaryOperator::BitOr | BinaryOperator::BitXor => format!(
+            "\\operatorname{{{op}}}({}, {})",
+            left.to_latex(),
+            right.to_latex()
+        ),
+        BinaryOperator::Eq => infix("=", COMPARISON),
+        BinaryOperator::NEq => infix("\\neq", COMPARISON),
+        BinaryOperator::ApproxEq => infix("\\approx", COMPARISON),
+        BinaryOperator::Gt => infix(">", COMPARISON),
+        BinaryOperator::Lt => infix("<", COMPARISON),
+        BinaryOperator::Gte => infix("\\geq", COMPARISON),
+        BinaryOperator::Lte => infix("\\leq", COMPARISON),
+        BinaryOperator::And => infix("\\land", AND),
+        BinaryOperator::Or => infix("\\lor", OR),
+        BinaryOperator::Convert => infix("\\text{ in }", CONVERSION),
+        BinaryOperator::Shl => infix("\\ll", SHIFT),
+        BinaryOperator::Shr => infix("\\gg", SHIFT),
+    }
+}
+
+/// A brace group that's been opened, and what closing it means
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Group {
+    /// Plain grouping, like an exponent or a function argument
+    Plain,
+    /// The first argument of `\frac`, closing it divides by the second
+    Numerator,
+    /// The second argument of `\frac`, closing it closes the whole fraction
+    Denominator,
+}
+
+/// Translates LaTeX into the same tokens the chalk tokenizer produces
+struct LatexLexer<'a> {
+    /// The LaTeX source
+    source: &'a str,
+    /// Byte offset and character of everything in the source
+    chars: Vec<(usize, char)>,
+    /// Character index of the next unread character
+    pos: usize,
+    /// Tokens produced so far, with the range of characters they came from
+    tokens: Vec<(Token<'a>, Range<usize>)>,
+    /// Brace groups that haven't been closed yet
+    groups: Vec<Group>,
+}
+
+impl<'a> LatexLexer<'a> {
+    /// Creates a lexer at the start of some LaTeX
+    fn new(source: &'a str) -> Self {
+        Self {
+            source,
+            chars: source.char_indices().collect(),
+            pos: 0,
+            tokens: vec![],
+            groups: vec![],
+        }
+    }
+
+    /// The next unread character
+    fn peek(&self) -> Option<char> {
+        self.chars.get(self.pos).map(|(_, c)| *c)
+    }
+
+    /// Byte offset of a character index
+    fn byte(&self, pos: usize) -> usize {
+        self.chars
+            .get(pos)
+            .map_or(self.source.len(), |(byte, _)| *byte)
+    }
+
+    /// An error at a range of characters
+    fn error(&self, span: Range<usize>) -> ParseError {
+        ParseError {
+            token: self.tokens.len(),
+            span: Some(span),
+            kind: ParseErrorKind::Unexpected,
+        }
+    }
+
+    /// Skips any whitespace
+    fn skip_whitespace(&mut self) {
+        while self.peek().is_some_and(char::is_whitespace) {
+            self.pos += 1;
+        }
+    }
+
+    /// Opens a brace group, which must be the next non-whitespace character
+    fn open(&mut self, group: Group) -> Result<(), ParseError> {
+        self.skip_whitespace();
+        if self.peek() != Some('{') {
+            return Err(self.error(self.pos..self.pos + 1));
+        }
+
+        self.tokens.push((Token::OpenParen, self.pos..self.pos + 1));
+        self.groups.push(group);
+        self.pos += 1;
+        Ok(())
+    }
+
+    /// Closes the innermost brace group at the current `}`
+    fn close(&mut self) -> Result<(), ParseError> {
+        let span = self.pos..self.pos + 1;
+        let group = self.groups.pop().ok_or_else(|| self.error(span.clone()))?;
+        self.pos += 1;
+
+        self.tokens.push((Token::CloseParen, span.clone()));
+        match group {
+            Group::Plain => {}
+            Group::Numerator => {
+                self.tokens.push((Token::Divide, span));
+                self.open(Group::Denominator)?;
+            }
+            Group::Denominator => self.tokens.push((Token::CloseParen, span)),
+        }
+
+        Ok(())
+    }
+
+    /// Tokenizes plain text between `start` and `end` with the chalk tokenizer
+    fn text(&mut self, start: usize, end: usize) -> Result<(), ParseError> {
+        let text = &self.source[self.byte(start)..self.byte(end)];
+        let tokens = text
+            .tokenize_spanned()
+            .map_err(|err| self.error(start + err.position..start + err.position + 1))?;
+
+        self.tokens.extend(
+            tokens
+                .into_iter()
+                .filter(|(token, _)| *token != Token::EOF)
+                .map(|(token, span)| (token, span.start + start..span.end + start)),
+        );
+        self.pos = end;
+        Ok(())
+    }
+
+    /// Handles a command starting at the current `\`
+    fn command(&mut self) -> Result<(), ParseError> {
+        let start = self.pos;
+        self.pos += 1;
+
+        let name_start = self.byte(self.pos);
+        while self.peek().is_some_and(|c| c.is_ascii_alphabetic()) {
+            self.pos += 1;
+        }
+        // Commands that aren't words are a single symbol, like `\,`
+        if self.pos == start + 1 && self.peek().is_some() {
+            self.pos += 1;
+        }
+
+        let name = &self.source[name_start..self.byte(self.pos)];
+        let span = start..self.pos;
+
+        let token = match name {
+            "frac" | "dfrac" | "tfrac" => {
+                self.tokens.push((Token::OpenParen, span));
+                return self.open(Group::Numerator);
+            }
+            "sqrt" => {
+                self.tokens.push((Token::Ident("sqrt"), span));
+                return self.open(Group::Plain);
+            }
+            "left" | "right" => return self.delimiter(span),
+            // Spacing
+            "," | ";" | ":" | "!" | " " | "quad" | "qquad" => return Ok(()),
+            "cdot" | "times" => Token::Multiply,
+            "div" => Token::Divide,
+            "%" | "bmod" => Token::Percent,
+            "pi" => Token::Ident("pi"),
+            "sin" => Token::Ident("sin"),
+            "cos" => Token::Ident("cos"),
+            "tan" => Token::Ident("tan"),
+            "arcsin" => Token::Ident("asin"),
+            "arccos" => Token::Ident("acos"),
+            "arctan" => Token::Ident("atan"),
+            "ln" => Token::Ident("ln"),
+            "leq" | "le" => Token::Lte,
+            "geq" | "ge" => Token::Gte,
+            "neq" | "ne" => Token::NEq,
+            "approx" => Token::ApproxEq,
+            "lt" => Token::Lt,
+            "gt" => Token::Gt,
+            "land" | "wedge" => Token::And,
+            "lor" | "vee" => Token::Or,
+            _ => return Err(self.error(span)),
+        };
+
+        self.tokens.push((token, span));
+        Ok(())
+    }
+
+    /// Handles the delimiter after `\left` or `\right`
+    fn delimiter(&mut self, command: Range<usize>) -> Result<(), ParseError> {
+        self.skip_whitespace();
+        let span = command.start..self.pos + 1;
+
+        let token = match self.peek() {
+            Some('(' | '[') => Token::OpenParen,
+            Some(')' | ']') => Token::CloseParen,
+            Some('|') => Token::Bar,
+            // `\left.` is an invisible delimiter
+            Some('.') => {
+                self.pos += 1;
+                return Ok(());
+            }
+            _ => return Err(self.error(span)),
+        };
+
+        self.tokens.push((token, span));
+        self.pos += 1;
+        Ok(())
+    }
+
+    /// Skips a subscript after the current `_`
+    fn subscript(&mut self) -> Result<(), ParseError> {
+        let start = self.pos;
+        self.pos += 1;
+
+        match self.peek() {
+            Some('{') => {
+                let mut depth = 0;
+                while let Some(c) = self.peek() {
+                    self.pos += 1;
+                    match c {
+                        '{' => depth += 1,
+                        '}' if depth == 1 => return Ok(()),
+                        '}' => depth -= 1,
+                        _ => {}
+                    }
+                }
+
+                Err(self.error(start..self.pos))
+            }
+            Some(_) => {
+                self.pos += 1;
+                Ok(())
+            }
+            None => Err(self.error(start..self.pos)),
+        }
+    }
+
+    /// Translates the whole source into tokens
+    fn lex(mut self) -> Result<Vec<(Token<'a>, Range<usize>)>, ParseError> {
+        while let Some(c) = self.peek() {
+            match c {
+                '\\' => self.command()?,
+                '{' => {
+                    self.tokens.push((Token::OpenParen, self.pos..self.pos + 1));
+                    self.groups.push(Group::Plain);
+                    self.pos += 1;
+                }
+                '}' => self.close()?,
+                '_' => self.subscript()?,
+                '^' => {
+                    self.tokens.push((Token::Caret, self.pos..self.pos + 1));
+                    self.pos += 1;
+                    self.skip_whitespace();
+
+                    // Without braces only the next character is the exponent, so `x^23` is
+                    // `x^2 * 3`
+                    if !matches!(self.peek(), Some('{' | '\\') | None) {
+                        self.text(self.pos, self.pos + 1)?;
+                    }
+                }
+                _ => {
+                    let start = self.pos;
+                    while self
+                        .peek()
+                        .is_some_and(|c| !matches!(c, '\\' | '{' | '}' | '_' | '^'))
+                    {
+                        self.pos += 1;
+                    }
+                    self.text(start, self.pos)?;
+                }
+            }
+        }
+
+        let end = self.chars.len();
+        if !self.groups.is_empty() {
+            return Err(self.error(end..end));
+        }
+
+        self.tokens.push((Token::EOF, end..end));
+        Ok(self.tokens)
+    }
+}
+
+/// Parses a subset of LaTeX math into an expression: `\frac`, `\sqrt`, `\cdot`, `\times`,
+/// `\left(`/`\right)`, `\pi`, braced powers, trig functions and comparisons. Subscripts are
+/// ignored. Errors are positioned by character index
+pub fn parse_latex(source: &str) -> Result<Expr, ParseError> {
+    let tokens = LatexLexer::new(source).lex()?;
+    Parser::with_spans(tokens).parse()
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        ast::ParseError,
+        exec::{EvalResult, Evaluator},
+        pipeline::testing::parse,
+    };
+
+    use super::parse_latex;
+
+    /// Parses an expression and renders it as LaTeX
+    fn latex(expression: &str) -> String {
+        parse(expression).to_latex()
+    }
+
+    #[test]
+    fn rendering() {
+        let cases = [
+            ("y = 3x + 5", "y = 3x + 5"),
+            ("(1 + 2) / (3 * 4)", "\\frac{1 + 2}{3 \\cdot 4}"),
+            ("(1 / (2 / x)) / 4", "\\frac{\\frac{1}{\\frac{2}{x}}}{4}"),
+            ("(a / b)^2", "\\left( \\frac{a}{b} \\right)^{2}"),
+            ("2^(x + 1)", "2^{x + 1}"),
+            ("(n + 1)! * n!", "\\left( n + 1 \\right)! \\cdot n!"),
+            ("|x - 1| <= 3", "\\left| x - 1 \\right| \\leq 3"),
+            ("gcd(12, 18) != 6", "\\gcd(12, 18) \\neq 6"),
+            ("sin(x) >= -1", "\\sin(x) \\geq -1"),
+            ("(((x)))", "x"),
+            ("a - (b - c)", "a - \\left( b - c \\right)"),
+            ("(a - b) - c", "a - b - c"),
+            ("x > 1 && true", "x > 1 \\land \\text{true}"),
+            (
+                "sum(i, 1, n, i + 1)",
+                "\\sum_{i = 1}^{n} \\left( i + 1 \\right)",
+            ),
+            ("prod(k, 1, 5, k^2)", "\\prod_{k = 1}^{5} k^{2}"),
+            ("powmod(a, 2, 7)", "\\operatorname{powmod}(a, 2, 7)"),
+            ("(1 << x) + ~y", "\\left( 1 \\ll x \\right) + \\sim y"),
+            ("band(a, 3)", "\\operatorname{band}(a, 3)"),
+            ("x[2] + [1, 2][n]!", "x_{2} + \\left[ 1, 2 \\right]_{n}!"),
+        ];
+
+        for (expression, expected) in cases {
+            assert_eq!(latex(expression), expected, "{expression}");
+        }
+    }
+
+    /// Parses LaTeX and evaluates it with `x` bound to 4
+    fn eval_latex(source: &str) -> EvalResult {
+        let ast = parse_latex(source).expect("Parse LaTeX");
+        let mut eval = Evaluator::default();
+        eval.set('x', crate::ast::Expr::Integer(4));
+        eval.exec(&ast).expect("Execute")
+    }
+
+    #[test]
+    fn parsing_latex() {
+        let cases = [
+            ("\\frac{1}{2} + \\sqrt{x}", 2.5),
+            ("\\frac{\\frac{1}{2}}{4}", 0.125),
+            ("3 \\cdot 2 \\times x", 24.0),
+            ("2\\left(x - 1\\right)", 6.0),
+            ("\\left| 1 - x \\right|", 3.0),
+            ("x^{2} + x^2", 32.0),
+            ("2^{x - 2}", 4.0),
+            ("x_{0} + x_1", 8.0),
+            ("\\sin(0) + \\cos(0) + \\tan(0)", 1.0),
+            ("\\frac{2\\pi}{\\pi}", 2.0),
+        ];
+
+        for (source, expected) in cases {
+            assert_eq!(eval_latex(source), EvalResult::Float(expected), "{source}");
+        }
+
+        assert_eq!(
+            eval_latex("x \\geq 4 \\land x \\neq 3"),
+            EvalResult::Bool(true)
+        );
+    }
+
+    #[test]
+    fn rendered_latex_parses_back() {
+        for expression in ["y = 3x + 5", "(a / b)^2 - |x - 1|", "sqrt(x) >= 2 * pi"] {
+            let ast = parse(expression);
+
+            let reparsed = parse_latex(&ast.to_latex()).expect("Parse LaTeX");
+            assert_eq!(reparsed.to_latex(), ast.to_latex(), "{expression}");
+        }
+    }
+
+    #[test]
+    fn rejecting_latex() {
+        let span = |source| parse_latex(source).map_err(|err: ParseError| err.span);
+
+        assert_eq!(span("1 + \\foo{2}"), Err(Some(4..8)));
+        assert_eq!(span("\\frac{1}2"), Err(Some(8..9)));
+        assert_eq!(span("\\sqrt{x"), Err(Some(7..7)));
+        assert_eq!(span("1 + }"), Err(Some(4..5)));
+        assert_eq!(span("1 + $"), Err(Some(4..5)));
+    }
+}
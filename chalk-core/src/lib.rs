@@ -1,5 +1,37 @@
 //! Core language implementation for a simple CLI calculator
+//!
+//! Embedders can start with [`eval_str`], which tokenizes, parses and evaluates source in one
+//! call, or [`eval_str_with`] to keep variables bound across calls with an
+//! [`Evaluator`](exec::Evaluator). Each stage is public too, for finer control
 pub mod ast;
+#[cfg(feature = "bigint")]
+mod bigint;
+pub mod builder;
+pub mod cache;
+pub mod compile;
+pub mod complex;
+pub mod decimal;
+pub mod dot;
 pub mod exec;
+pub mod format;
+pub mod formula;
+pub mod functions;
+pub mod history;
+pub mod hover;
+pub mod latex;
+pub mod list;
 pub mod math;
+pub mod pipeline;
+pub mod simplify;
+pub mod solve;
+#[cfg(feature = "serde")]
+pub mod state;
+pub mod stats;
+pub mod suggest;
 pub mod tokenizer;
+pub mod trace;
+pub mod units;
+pub mod visit;
+pub mod worksheet;
+
+pub use pipeline::{ChalkError, eval_str, eval_str_with};
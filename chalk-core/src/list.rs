@@ -0,0 +1,244 @@
+//! Building list values, picking items out of them and applying functions across them
+
+use std::slice;
+
+use crate::{
+    ast::{Expr, Function},
+    exec::{EvalResult, Evaluator, RuntimeError},
+};
+
+/// The most items `range` builds, so a typo like `range(1, 10^12)` can't exhaust memory
+pub const MAX_RANGE_LEN: usize = 100_000;
+
+/// The item of a list at a position counting from 1
+pub fn index(list: &EvalResult, index: &EvalResult) -> Result<EvalResult, RuntimeError> {
+    let (EvalResult::List(items), Ok(index)) = (list, index.int()) else {
+        return Err(RuntimeError::Invalid);
+    };
+
+    usize::try_from(index)
+        .ok()
+        .and_then(|position| position.checked_sub(1))
+        .and_then(|position| items.get(position))
+        .cloned()
+        .ok_or(RuntimeError::IndexOutOfBounds {
+            index,
+            len: items.len(),
+        })
+}
+
+/// The integers from `start` to `end` inclusive, counting by `step`. Counting down needs a
+/// negative step, and counting away from `end` gives an empty list
+pub fn range(start: i64, end: i64, step: i64) -> Result<EvalResult, RuntimeError> {
+    if step == 0 {
+        return Err(RuntimeError::Invalid);
+    }
+
+    let span = i128::from(end) - i128::from(start);
+    let len = if span.signum() == i128::from(step.signum()) || span == 0 {
+        span / i128::from(step) + 1
+    } else {
+        0
+    };
+    if len > MAX_RANGE_LEN as i128 {
+        return Err(RuntimeError::Invalid);
+    }
+
+    // Every item is between `start` and `end`, but the step taken to reach it may not fit
+    Ok(EvalResult::List(
+        (0..len)
+            .map(|i| EvalResult::Integer((i128::from(start) + i * i128::from(step)) as i64))
+            .collect(),
+    ))
+}
+
+impl Evaluator {
+    /// Calls `map`, `filter` or `reduce`, whose first argument names a function instead of being
+    /// a value. A function is a variable bound to an expression, taking the variables left free
+    /// in it as parameters in alphabetical order, so after `p = a * b`, `p` multiplies two values
+    pub(crate) fn apply(
+        &mut self,
+        function: Function,
+        args: &[Expr],
+    ) -> Result<EvalResult, RuntimeError> {
+        let [Expr::Variable(name), rest @ ..] = args else {
+            return Err(RuntimeError::Invalid);
+        };
+        let body = self.get(*name).cloned().ok_or(RuntimeError::Invalid)?;
+        let params: Vec<char> = self.dependencies(&body).free.into_iter().collect();
+        let values = rest
+            .iter()
+            .map(|arg| self.exec(arg))
+            .collect::<Result<Vec<_>, _>>()?;
+
+        let mut call = |args: &[EvalResult]| {
+            if args.len() != params.len() {
+                return Err(RuntimeError::Invalid);
+            }
+            let bindings: Vec<_> = params.iter().copied().zip(args.iter().cloned()).collect();
+            self.exec_with(&body, &bindings)
+        };
+
+        match (function, values.as_slice()) {
+            (Function::Map, [EvalResult::List(items)]) => items
+                .iter()
+                .map(|item| call(slice::from_ref(item)))
+                .collect::<Result<_, _>>()
+                .map(EvalResult::List),
+            (Function::Filter, [EvalResult::List(items)]) => {
+                let mut kept = vec![];
+                for item in items {
+                    if call(slice::from_ref(item))?.bool()? {
+                        kept.push(item.clone());
+                    }
+                }
+                Ok(EvalResult::List(kept))
+            }
+            (Function::Reduce, [init, EvalResult::List(items)]) => items
+                .iter()
+                .try_fold(init.clone(), |total, item| call(&[total, item.clone()])),
+            _ => Err(RuntimeError::Invalid),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        exec::{EvalResult, Evaluator, RuntimeError},
+        pipeline::testing::run,
+    };
+
+    use super::MAX_RANGE_LEN;
+
+    #[test]
+    fn indexing() {
+        let mut eval = Evaluator::default();
+        run(&mut eval, "x = [10, 20, 30]").expect("Bind x");
+
+        for (expression, expected) in [
+            ("x[1]", "10"),
+            ("x[3]", "30"),
+            ("x[1 + 1] ^ 2", "400"),
+            ("[[1, 2], [3, 4]][2][1]", "3"),
+            ("range(5, 9)[2]", "6"),
+            ("x[2.0]", "20"),
+        ] {
+            assert_eq!(
+                run(&mut eval, expression).map(|result| result.to_string()),
+                Ok(expected.to_string()),
+                "{expression}"
+            );
+        }
+
+        for (expression, index) in [("x[0]", 0), ("x[4]", 4), ("x[-1]", -1)] {
+            let err = run(&mut eval, expression).expect_err("Out of bounds");
+            assert_eq!(err, RuntimeError::IndexOutOfBounds { index, len: 3 });
+        }
+        assert_eq!(
+            RuntimeError::IndexOutOfBounds { index: 4, len: 3 }.to_string(),
+            "Index 4 is out of bounds for a list of 3 items :("
+        );
+
+        for invalid in ["x[1.5]", "x[true]", "5[1]"] {
+            assert_eq!(
+                run(&mut eval, invalid),
+                Err(RuntimeError::Invalid),
+                "{invalid}"
+            );
+        }
+    }
+
+    #[test]
+    fn applying_functions() {
+        let mut eval = Evaluator::default();
+        for definition in ["s = x^2", "e = floor(n / 2) == n / 2", "p = a * b"] {
+            run(&mut eval, definition).expect_err("Free variables");
+        }
+        run(&mut eval, "k = 3").expect("Assign");
+        run(&mut eval, "t = k * x").expect_err("Free variables");
+
+        for (expression, expected) in [
+            ("map(s, range(1, 5))", "[1, 4, 9, 16, 25]"),
+            ("map(t, [1, -2])", "[3, -6]"),
+            ("map(s, [])", "[]"),
+            ("filter(e, range(1, 10))", "[2, 4, 6, 8, 10]"),
+            ("filter(e, [1, 3])", "[]"),
+            ("reduce(p, 1, range(1, 5))", "120"),
+            ("reduce(p, 7, [])", "7"),
+            ("sum(map(s, range(1, 3)))", "14"),
+        ] {
+            assert_eq!(
+                run(&mut eval, expression).map(|result| result.to_string()),
+                Ok(expected.to_string()),
+                "{expression}"
+            );
+        }
+        // The function's parameters don't leak out
+        assert!(eval.get('x').is_none());
+
+        for invalid in [
+            // Not a bool
+            "filter(s, [1, 2])",
+            // Wrong number of parameters
+            "map(p, [1, 2])",
+            "reduce(s, 0, [1, 2])",
+            "map(k, [1])",
+            // Not a function or not a list
+            "map(q, [1])",
+            "map(2, [1])",
+            "map(s, 3)",
+        ] {
+            assert_eq!(
+                run(&mut eval, invalid),
+                Err(RuntimeError::Invalid),
+                "{invalid}"
+            );
+        }
+    }
+
+    #[test]
+    fn ranges() {
+        let mut eval = Evaluator::default();
+
+        for (expression, expected) in [
+            ("range(1, 5)", "[1, 2, 3, 4, 5]"),
+            ("range(0, 10, 4)", "[0, 4, 8]"),
+            ("range(10, 1, -3)", "[10, 7, 4, 1]"),
+            ("range(-2, -4, -1)", "[-2, -3, -4]"),
+            ("range(3, 3)", "[3]"),
+            ("range(5, 1)", "[]"),
+            ("range(1, 5, -1)", "[]"),
+            ("sum(range(1, 100))", "5050"),
+        ] {
+            assert_eq!(
+                run(&mut eval, expression).map(|result| result.to_string()),
+                Ok(expected.to_string()),
+                "{expression}"
+            );
+        }
+
+        let longest = format!("len(range(1, {MAX_RANGE_LEN}))");
+        assert_eq!(
+            run(&mut eval, &longest),
+            Ok(EvalResult::Integer(MAX_RANGE_LEN as i64))
+        );
+
+        for invalid in [
+            "range(1, 5, 0)",
+            "range(1, 10^9)",
+            "range(1.5, 3)",
+            &format!("range(0, {MAX_RANGE_LEN})"),
+        ] {
+            assert_eq!(
+                run(&mut eval, invalid),
+                Err(RuntimeError::Invalid),
+                "{invalid}"
+            );
+        }
+        assert_eq!(
+            run(&mut eval, "len(range(0 - 2^62, 2^62, 2^61))"),
+            Ok(EvalResult::Integer(5))
+        );
+    }
+}
@@ -2,6 +2,10 @@
 //! rn this is just gonna contain a lot of gcd, lcm discrete mathy stuff that I've already written
 //! in other projects
 
+pub mod divisors;
 pub mod gcd;
 pub mod lcm;
+pub mod modular;
 pub mod prime;
+pub mod random;
+pub mod rational;
@@ -0,0 +1,124 @@
+//! Divisor functions
+
+use super::{gcd::Powers, prime::PrimeFactorizable};
+
+/// Every positive divisor of a number in ascending order. 0 has none listed, since every
+/// number divides it
+pub fn divisors(n: u64) -> Vec<u64> {
+    if n == 0 {
+        return vec![];
+    }
+
+    divisors_from(n.prime_factorize())
+}
+
+/// Every positive divisor of the number with these prime factors, in ascending order
+pub fn divisors_from(factors: Vec<u64>) -> Vec<u64> {
+    let mut divisors = vec![1];
+    for (prime, power) in factors.generate_powers() {
+        let smaller = divisors.len();
+        let mut factor = 1;
+        for _ in 0..power {
+            factor *= prime;
+            for i in 0..smaller {
+                divisors.push(divisors[i] * factor);
+            }
+        }
+    }
+
+    divisors.sort_unstable();
+    divisors
+}
+
+/// The number of positive divisors of a number, found from its prime powers
+pub fn num_divisors(n: u64) -> u64 {
+    if n == 0 {
+        return 0;
+    }
+
+    num_divisors_from(n.prime_factorize())
+}
+
+/// The number of positive divisors of the number with these prime factors
+pub fn num_divisors_from(factors: Vec<u64>) -> u64 {
+    factors
+        .generate_powers()
+        .iter()
+        .map(|(_, power)| u64::from(*power) + 1)
+        .product()
+}
+
+/// The sum of every positive divisor of a number, which can exceed the number itself
+pub fn sum_divisors(n: u64) -> u128 {
+    if n == 0 {
+        return 0;
+    }
+
+    sum_divisors_from(n.prime_factorize())
+}
+
+/// The sum of every positive divisor of the number with these prime factors
+pub fn sum_divisors_from(factors: Vec<u64>) -> u128 {
+    factors
+        .generate_powers()
+        .iter()
+        .map(|(prime, power)| {
+            (0..=*power)
+                .map(|k| u128::from(*prime).pow(k))
+                .sum::<u128>()
+        })
+        .product()
+}
+
+/// Euler's totient, the count of numbers from 1 to `n` coprime to `n`
+pub fn totient(n: u64) -> u64 {
+    if n == 0 {
+        return 0;
+    }
+
+    totient_from(n.prime_factorize())
+}
+
+/// Euler's totient of the number with these prime factors
+pub fn totient_from(factors: Vec<u64>) -> u64 {
+    factors
+        .generate_powers()
+        .iter()
+        .map(|(prime, power)| prime.pow(power - 1) * (prime - 1))
+        .product()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{divisors, num_divisors, sum_divisors, totient};
+
+    #[test]
+    fn perfect_numbers() {
+        assert_eq!(divisors(28), [1, 2, 4, 7, 14, 28]);
+        assert_eq!(num_divisors(28), 6);
+        assert_eq!(sum_divisors(28), 56);
+        assert_eq!(sum_divisors(8128), 2 * 8128);
+    }
+
+    #[test]
+    fn totients() {
+        assert_eq!(totient(1), 1);
+        assert_eq!(totient(10), 4);
+        assert_eq!(totient(97), 96);
+        assert_eq!(totient(1 << 40), 1 << 39);
+        assert_eq!(totient(u64::MAX), 9_208_981_628_670_443_520);
+    }
+
+    #[test]
+    fn edge_cases() {
+        assert_eq!(divisors(1), [1]);
+        assert_eq!(num_divisors(1), 1);
+        assert_eq!(sum_divisors(1), 1);
+        assert!(divisors(0).is_empty());
+        assert_eq!(num_divisors(0), 0);
+
+        assert_eq!(divisors(97), [1, 97]);
+        assert_eq!(num_divisors(u64::MAX), 128);
+        assert!(sum_divisors(u64::MAX) > u128::from(u64::MAX));
+    }
+}
@@ -1,45 +1,47 @@
 //! GCD Algorithm
 
-use std::collections::{HashMap, HashSet};
+use std::collections::HashSet;
 
-use super::prime::PrimeFactorizable;
-
-/// Gets the gcd of two integers
-pub fn gcd(a: u32, b: u32) -> i32 {
-    let mut powers_a = a.prime_factorize().generate_powers();
-    let powers_b = b.prime_factorize().generate_powers();
-
-    powers_a.extend(powers_b.iter());
-
-    let mut powers: HashMap<u32, Vec<u32>> = HashMap::new();
-
-    for (val, pow) in powers_a.iter() {
-        powers.entry(*val).or_default().push(*pow);
+/// Gets the greatest common divisor of two integers with Euclid's algorithm. `gcd(0, 0)` is 0
+pub fn gcd(mut a: u64, mut b: u64) -> u64 {
+    while b != 0 {
+        (a, b) = (b, a % b);
     }
 
-    let mut gcd = 1;
+    a
+}
 
-    for (val, power) in powers {
-        let min = if power.len() == 1 {
-            0
-        } else {
-            power.into_iter().min().unwrap_or(0)
-        };
+/// The extended Euclidean algorithm, giving `(g, x, y)` where `g` is the non-negative gcd of `a`
+/// and `b` and `a * x + b * y == g`. The only gcd too large for an `i64` is that of `i64::MIN`
+/// with itself or 0, which wraps
+pub fn ext_gcd(a: i64, b: i64) -> (i64, i64, i64) {
+    // Intermediate steps on i64::MIN can overflow an i64, the results never do
+    let (mut old_r, mut r) = (i128::from(a), i128::from(b));
+    let (mut old_x, mut x) = (1i128, 0i128);
+    let (mut old_y, mut y) = (0i128, 1i128);
+
+    while r != 0 {
+        let quotient = old_r / r;
+        (old_r, r) = (r, old_r - quotient * r);
+        (old_x, x) = (x, old_x - quotient * x);
+        (old_y, y) = (y, old_y - quotient * y);
+    }
 
-        gcd *= val.pow(min);
+    if old_r < 0 {
+        (old_r, old_x, old_y) = (-old_r, -old_x, -old_y);
     }
 
-    gcd as i32
+    (old_r as i64, old_x as i64, old_y as i64)
 }
 
 /// Raise something to a power
 pub trait Powerable {
     /// Raise it to a power
-    fn power(&self) -> u32;
+    fn power(&self) -> u64;
 }
 
-impl Powerable for (u32, u32) {
-    fn power(&self) -> u32 {
+impl Powerable for (u64, u32) {
+    fn power(&self) -> u64 {
         self.0.pow(self.1)
     }
 }
@@ -47,11 +49,11 @@ impl Powerable for (u32, u32) {
 /// Generate powers
 pub trait Powers {
     /// Generates some powers
-    fn generate_powers(&self) -> Vec<(u32, u32)>;
+    fn generate_powers(&self) -> Vec<(u64, u32)>;
 }
 
-impl Powers for Vec<u32> {
-    fn generate_powers(&self) -> Vec<(u32, u32)> {
+impl Powers for Vec<u64> {
+    fn generate_powers(&self) -> Vec<(u64, u32)> {
         let mut seen = HashSet::new();
         let mut powers = vec![];
         for val in self {
@@ -72,7 +74,7 @@ mod tests {
 
     use crate::math::{gcd::Powers, prime::PrimeFactorizable};
 
-    use super::gcd;
+    use super::{ext_gcd, gcd};
 
     #[test]
     fn powers_power_properly() {
@@ -86,4 +88,51 @@ mod tests {
 
         assert_eq!(gcd, 4)
     }
+
+    #[test]
+    fn zeroes() {
+        assert_eq!(gcd(0, 0), 0);
+        assert_eq!(gcd(0, 7), 7);
+        assert_eq!(gcd(7, 0), 7);
+    }
+
+    #[test]
+    fn bezout_identities() {
+        let mut state: u64 = 0x2545_f491_4f6c_dd1d;
+        let mut next = || {
+            state ^= state << 13;
+            state ^= state >> 7;
+            state ^= state << 17;
+            state as i64 >> (state % 40)
+        };
+
+        for _ in 0..1000 {
+            let (a, b) = (next(), next());
+            let (g, x, y) = ext_gcd(a, b);
+
+            assert_eq!(
+                g as u64,
+                gcd(a.unsigned_abs(), b.unsigned_abs()),
+                "{a}, {b}"
+            );
+            assert_eq!(
+                i128::from(a) * i128::from(x) + i128::from(b) * i128::from(y),
+                i128::from(g),
+                "{a}, {b}"
+            );
+        }
+
+        assert_eq!(ext_gcd(240, 46), (2, -9, 47));
+        assert_eq!(ext_gcd(0, 0), (0, 1, 0));
+        assert_eq!(ext_gcd(-4, 0), (4, -1, 0));
+        assert_eq!(ext_gcd(i64::MIN, i64::MAX).0, 1);
+    }
+
+    #[test]
+    fn large_inputs() {
+        assert_eq!(gcd(1 << 40, 6 << 20), 2 << 20);
+        assert_eq!(gcd(600_851_475_143, 600_851_475_149), 1);
+        assert_eq!(gcd(u64::MAX, u64::MAX - 1), 1);
+        assert_eq!(gcd(u64::MAX, 65_537 * 641), 65_537 * 641);
+    }
 }
@@ -1,36 +1,14 @@
 //! LCD Algorithm
 
-use std::collections::HashMap;
+use super::gcd::gcd;
 
-use super::gcd::Powers;
-use super::prime::PrimeFactorizable;
-
-/// Gets the least common multiple of two integers
-pub fn lcm(a: u32, b: u32) -> i32 {
-    let mut powers_a = a.prime_factorize().generate_powers();
-    let powers_b = b.prime_factorize().generate_powers();
-
-    powers_a.extend(powers_b.iter());
-
-    let mut powers: HashMap<u32, Vec<u32>> = HashMap::new();
-
-    for (val, pow) in powers_a.iter() {
-        powers.entry(*val).or_default().push(*pow);
-    }
-
-    let mut lcm = 1;
-
-    for (val, power) in powers {
-        let max = if power.len() == 1 {
-            power[0]
-        } else {
-            power.into_iter().max().unwrap_or(0)
-        };
-
-        lcm *= val.pow(max);
+/// Gets the least common multiple of two integers, or `None` if it overflows. `lcm(0, n)` is 0
+pub fn lcm(a: u64, b: u64) -> Option<u64> {
+    if a == 0 || b == 0 {
+        return Some(0);
     }
 
-    lcm as i32
+    (a / gcd(a, b)).checked_mul(b)
 }
 
 #[cfg(test)]
@@ -40,6 +18,23 @@ mod tests {
     #[test]
     fn simple_lcm() {
         let lcm = lcm(12, 15);
-        assert_eq!(lcm, 60)
+        assert_eq!(lcm, Some(60))
+    }
+
+    #[test]
+    fn zeroes() {
+        assert_eq!(lcm(0, 5), Some(0));
+        assert_eq!(lcm(5, 0), Some(0));
+        assert_eq!(lcm(0, 0), Some(0));
+    }
+
+    #[test]
+    fn large_inputs() {
+        assert_eq!(
+            lcm(600_851_475_143, 1_000_003),
+            Some(600_851_475_143 * 1_000_003)
+        );
+        assert_eq!(lcm(1 << 62, 1 << 40), Some(1 << 62));
+        assert_eq!(lcm(u64::MAX, u64::MAX - 1), None);
     }
 }
@@ -0,0 +1,58 @@
+//! Modular arithmetic
+
+use super::gcd::ext_gcd;
+
+/// `base ^ exp` modulo `m` by repeated squaring, in `0..m`. `None` unless `m` is positive
+pub fn pow_mod(base: i64, mut exp: u64, m: i64) -> Option<i64> {
+    if m <= 0 {
+        return None;
+    }
+
+    // Both factors are below an i64, so every product fits in an i128
+    let m = i128::from(m);
+    let mut base = i128::from(base).rem_euclid(m);
+    let mut result = 1 % m;
+    while exp > 0 {
+        if exp & 1 == 1 {
+            result = result * base % m;
+        }
+        base = base * base % m;
+        exp >>= 1;
+    }
+
+    i64::try_from(result).ok()
+}
+
+/// The inverse of `a` modulo `m`, in `0..m`. `None` unless `m` is positive and coprime to `a`
+pub fn inv_mod(a: i64, m: i64) -> Option<i64> {
+    if m <= 0 {
+        return None;
+    }
+
+    let (g, x, _) = ext_gcd(a.rem_euclid(m), m);
+    (g == 1).then(|| x.rem_euclid(m))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{inv_mod, pow_mod};
+
+    #[test]
+    fn powers() {
+        assert_eq!(pow_mod(2, 10, 1000), Some(24));
+        assert_eq!(pow_mod(2, 64, 1_000_000_007), Some(582_344_008));
+        assert_eq!(pow_mod(-2, 3, 5), Some(2));
+        assert_eq!(pow_mod(5, 0, 1), Some(0));
+        assert_eq!(pow_mod(i64::MAX, u64::MAX, i64::MAX - 1), Some(1));
+        assert_eq!(pow_mod(2, 3, 0), None);
+    }
+
+    #[test]
+    fn inverses() {
+        assert_eq!(inv_mod(3, 7), Some(5));
+        assert_eq!(inv_mod(-3, 7), Some(2));
+        assert_eq!(inv_mod(10, 1_000_000_007), Some(700_000_005));
+        assert_eq!(inv_mod(2, 4), None);
+        assert_eq!(inv_mod(3, 0), None);
+    }
+}
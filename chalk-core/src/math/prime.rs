@@ -1,30 +1,116 @@
 //! Prime number formula
 
-/// A prime generation iterator
+use std::convert::Infallible;
+
+/// How many numbers a [`PrimeMachine`] sieves at a time
+const SEGMENT: u64 = 1 << 15;
+/// Largest `n` [`nth_prime`] will find the nth prime for
+pub const MAX_NTH_PRIME: u64 = 10_000_000;
+/// Largest number [`prime_count`] will count primes up to, a little past the
+/// [`MAX_NTH_PRIME`]th prime
+pub const MAX_PRIME_COUNT: u64 = 200_000_000;
+/// How many trial divisions [`factorize_within`] makes between checks that it may carry on
+pub const TRIAL_DIVISIONS_PER_CHECK: u64 = 64;
+
+/// A prime generation iterator, backed by a segmented sieve of Eratosthenes
 #[derive(Default)]
 pub struct PrimeMachine {
-    /// The cache of previous primes for deriving new primes
-    cache: Vec<u32>,
+    /// Every prime up to `base_limit`, used to sieve segments
+    base: Vec<u64>,
+    /// The largest number `base` covers
+    base_limit: u64,
+    /// Primes from the last sieved segment that haven't been yielded yet, largest first
+    pending: Vec<u64>,
+    /// The start of the next segment to sieve
+    low: u64,
+}
+
+impl PrimeMachine {
+    /// Sieves the next segment, queueing up its primes. `None` once every `u64` is sieved
+    fn sieve_segment(&mut self) -> Option<()> {
+        let low = self.low.max(2);
+        if low == u64::MAX {
+            return None;
+        }
+        let high = low.saturating_add(SEGMENT);
+
+        // Sieving a segment needs every prime up to the square root of its end, which are
+        // regenerated with room to spare whenever segments outgrow them
+        let limit = high.isqrt();
+        if limit > self.base_limit {
+            self.base_limit = limit.max(self.base_limit.saturating_mul(2));
+            self.base = sieve(self.base_limit);
+        }
+
+        let mut composite = vec![false; (high - low) as usize];
+        for &prime in self.base.iter().take_while(|prime| **prime <= limit) {
+            let Some(first) = low.div_ceil(prime).checked_mul(prime) else {
+                continue;
+            };
+            let start = first.max(prime * prime);
+            for multiple in (start..high).step_by(prime as usize) {
+                composite[(multiple - low) as usize] = true;
+            }
+        }
+
+        self.pending = (low..high)
+            .rev()
+            .filter(|n| !composite[(n - low) as usize])
+            .collect();
+        self.low = high;
+        Some(())
+    }
 }
 
 impl Iterator for PrimeMachine {
-    type Item = u32;
+    type Item = u64;
     fn next(&mut self) -> Option<Self::Item> {
-        let next = if self.cache.is_empty() {
-            2
-        } else {
-            let mut curr = self.cache[self.cache.len() - 1] + 1;
+        while self.pending.is_empty() {
+            self.sieve_segment()?;
+        }
 
-            while self.cache.iter().any(|val| curr % *val == 0) {
-                curr += 1;
-            }
+        self.pending.pop()
+    }
+}
+
+/// The nth prime, counting 2 as the first. `None` for 0 or anything above [`MAX_NTH_PRIME`]
+pub fn nth_prime(n: u64) -> Option<u64> {
+    if n > MAX_NTH_PRIME {
+        return None;
+    }
+
+    PrimeMachine::default().nth(usize::try_from(n.checked_sub(1)?).ok()?)
+}
+
+/// The number of primes up to and including `n`. `None` for anything above [`MAX_PRIME_COUNT`]
+pub fn prime_count(n: u64) -> Option<u64> {
+    if n > MAX_PRIME_COUNT {
+        return None;
+    }
+
+    Some(
+        PrimeMachine::default()
+            .take_while(|prime| *prime <= n)
+            .count() as u64,
+    )
+}
 
-            curr
-        };
+/// Every prime up to and including `limit`, by a plain sieve of Eratosthenes
+fn sieve(limit: u64) -> Vec<u64> {
+    let mut composite = vec![false; limit as usize + 1];
+    let mut primes = vec![];
 
-        self.cache.push(next);
-        Some(next)
+    for n in 2..=limit {
+        if composite[n as usize] {
+            continue;
+        }
+        primes.push(n);
+        for multiple in (n.saturating_mul(n)..=limit).step_by(n as usize) {
+            composite[multiple as usize] = true;
+        }
     }
+
+    primes
 }
 
 /// Checking if a number is prime
@@ -38,14 +124,14 @@ pub trait PrimeCheck {
     }
 }
 
-impl PrimeCheck for u32 {
+impl PrimeCheck for u64 {
     fn is_prime_with_machine(&self, primes: &mut PrimeMachine) -> bool {
-        let num_sqrt = (*self as f32).sqrt() as u32;
+        let num_sqrt = (*self as f64).sqrt() as u64;
 
         for prime in primes.by_ref() {
             if prime > num_sqrt {
                 break;
-            } else if self % prime == 0 {
+            } else if self.is_multiple_of(prime) {
                 return false;
             }
         }
@@ -57,39 +143,60 @@ impl PrimeCheck for u32 {
 /// Any type that can be prime factorized
 pub trait PrimeFactorizable {
     /// Generates the prime factors of a number
-    fn prime_factorize(&self) -> Vec<u32>;
+    fn prime_factorize(&self) -> Vec<u64>;
 }
 
-impl PrimeFactorizable for u32 {
-    fn prime_factorize(&self) -> Vec<u32> {
-        let mut curr = *self;
-        let mut factors = vec![];
-
-        while curr != 1 {
-            let curr_sqrt = f32::sqrt(curr as f32).ceil() as u32;
-            let primes = PrimeMachine::default();
-
-            for prime in primes {
-                if prime > curr_sqrt {
-                    factors.push(curr);
-                    curr /= curr;
-                    break;
-                } else if curr % prime == 0 {
-                    factors.push(prime);
-                    curr /= prime;
-                    break;
-                }
-            }
+impl PrimeFactorizable for u64 {
+    /// Trial divides by 2 and then every odd number up to the square root of what's left. 0 and
+    /// 1 have no prime factors
+    fn prime_factorize(&self) -> Vec<u64> {
+        factorize_within(*self, || Ok::<_, Infallible>(())).unwrap_or_else(|never| match never {})
+    }
+}
+
+/// Prime factors of `n` like [`PrimeFactorizable::prime_factorize`], calling `check` every
+/// [`TRIAL_DIVISIONS_PER_CHECK`] trial divisions and giving up with its error if it fails, since
+/// a large prime takes billions of divisions
+pub fn factorize_within<E>(
+    n: u64,
+    mut check: impl FnMut() -> Result<(), E>,
+) -> Result<Vec<u64>, E> {
+    let mut curr = n;
+    let mut factors = vec![];
+
+    let mut divisor: u64 = 2;
+    let mut divisions = 0u64;
+    while divisor
+        .checked_mul(divisor)
+        .is_some_and(|square| square <= curr)
+    {
+        divisions += 1;
+        if divisions.is_multiple_of(TRIAL_DIVISIONS_PER_CHECK) {
+            check()?;
         }
 
-        factors.sort();
-        factors
+        while curr.is_multiple_of(divisor) {
+            factors.push(divisor);
+            curr /= divisor;
+        }
+        divisor += if divisor == 2 { 1 } else { 2 };
+    }
+
+    if curr > 1 {
+        factors.push(curr);
     }
+
+    Ok(factors)
 }
 
 #[cfg(test)]
 mod tests {
-    use crate::math::prime::PrimeFactorizable;
+    use std::time::{Duration, Instant};
+
+    use crate::math::prime::{
+        MAX_NTH_PRIME, MAX_PRIME_COUNT, PrimeCheck, PrimeFactorizable, PrimeMachine, nth_prime,
+        prime_count,
+    };
 
     #[test]
     fn prime_factorization() {
@@ -105,7 +212,65 @@ mod tests {
 
     #[test]
     fn prime_factorize_max_usize() {
-        let factors = u32::MAX.prime_factorize();
-        assert_eq!(factors, &[3, 5, 17, 257, 65537]);
+        let factors = u64::MAX.prime_factorize();
+        assert_eq!(factors, &[3, 5, 17, 257, 641, 65537, 6700417]);
+    }
+
+    #[test]
+    fn zero_and_one_have_no_factors() {
+        assert!(0.prime_factorize().is_empty());
+        assert!(1.prime_factorize().is_empty());
+    }
+
+    #[test]
+    fn prime_machine_generates_primes() {
+        let primes: Vec<u64> = PrimeMachine::default().take(10).collect();
+        assert_eq!(primes, [2, 3, 5, 7, 11, 13, 17, 19, 23, 29]);
+
+        // Well past the first segment
+        assert_eq!(PrimeMachine::default().nth(9_999), Some(104_729));
+        assert_eq!(PrimeMachine::default().nth(99_999), Some(1_299_709));
+    }
+
+    #[test]
+    fn nth_primes_and_counts() {
+        assert_eq!(nth_prime(1), Some(2));
+        assert_eq!(nth_prime(100), Some(541));
+        assert_eq!(nth_prime(0), None);
+        assert_eq!(nth_prime(MAX_NTH_PRIME + 1), None);
+
+        assert_eq!(prime_count(1), Some(0));
+        assert_eq!(prime_count(2), Some(1));
+        assert_eq!(prime_count(1000), Some(168));
+        assert_eq!(prime_count(1_000_000), Some(78_498));
+        assert_eq!(prime_count(MAX_PRIME_COUNT + 1), None);
+    }
+
+    #[test]
+    fn prime_checks() {
+        assert!(7919.is_prime());
+        assert!(!7917.is_prime());
+        assert!(4_294_967_291.is_prime());
+    }
+
+    #[test]
+    fn factorizing_is_fast() {
+        let start = Instant::now();
+
+        assert_eq!(
+            u64::from(u32::MAX).prime_factorize(),
+            &[3, 5, 17, 257, 65537]
+        );
+        assert_eq!(4_294_967_291.prime_factorize(), &[4_294_967_291]);
+        assert_eq!(
+            (1_000_003 * 999_983).prime_factorize(),
+            &[999_983, 1_000_003]
+        );
+        assert_eq!(
+            (4_294_967_291 * 65_521).prime_factorize(),
+            &[65_521, 4_294_967_291]
+        );
+
+        assert!(start.elapsed() < Duration::from_millis(250));
     }
 }
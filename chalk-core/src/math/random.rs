@@ -0,0 +1,109 @@
+//! A small seedable random number generator, so results can be reproduced and nothing depends on
+//! the platform providing randomness
+
+use std::hash::{BuildHasher, RandomState};
+
+/// An xorshift64* generator. Not suitable for cryptography, but fast and good enough for
+/// simulations
+#[derive(Clone, Debug, PartialEq)]
+pub struct Rng {
+    /// The current state, never 0
+    state: u64,
+}
+
+impl Default for Rng {
+    /// A generator seeded differently each run, falling back to a fixed seed on platforms without
+    /// any randomness
+    fn default() -> Self {
+        Self::seeded(RandomState::new().hash_one(0u8))
+    }
+}
+
+impl Rng {
+    /// A generator that always produces the same sequence for the same seed
+    pub fn seeded(seed: u64) -> Self {
+        // Scrambled with splitmix64 so similar seeds give unrelated sequences, and a seed of 0
+        // doesn't leave xorshift stuck at 0
+        let mut z = seed.wrapping_add(0x9E37_79B9_7F4A_7C15);
+        z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+        z ^= z >> 31;
+
+        Self { state: z.max(1) }
+    }
+
+    /// The next 64 random bits
+    pub fn next_u64(&mut self) -> u64 {
+        self.state ^= self.state >> 12;
+        self.state ^= self.state << 25;
+        self.state ^= self.state >> 27;
+        self.state.wrapping_mul(0x2545_F491_4F6C_DD1D)
+    }
+
+    /// A float uniformly distributed in `[0, 1)`
+    pub fn float(&mut self) -> f64 {
+        // The top 53 bits fill a float's mantissa exactly
+        (self.next_u64() >> 11) as f64 / (1u64 << 53) as f64
+    }
+
+    /// An integer uniformly distributed in `low..=high`. `None` if the bounds are reversed
+    pub fn int(&mut self, low: i64, high: i64) -> Option<i64> {
+        if low > high {
+            return None;
+        }
+
+        // Both bounds inclusive, so the whole i64 range has 2^64 values and wraps to 0
+        let span = high.abs_diff(low).wrapping_add(1);
+        let offset = if span == 0 {
+            self.next_u64()
+        } else {
+            // Lemire's method: multiply into 128 bits, rejecting the few draws that would bias
+            // the result towards low values
+            let threshold = span.wrapping_neg() % span;
+            loop {
+                let product = u128::from(self.next_u64()) * u128::from(span);
+                if product as u64 >= threshold {
+                    break (product >> 64) as u64;
+                }
+            }
+        };
+
+        Some(low.wrapping_add_unsigned(offset))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Rng;
+
+    #[test]
+    fn seeds_are_reproducible() {
+        let draws = |seed| {
+            let mut rng = Rng::seeded(seed);
+            [rng.next_u64(), rng.next_u64(), rng.next_u64()]
+        };
+
+        assert_eq!(draws(42), draws(42));
+        assert_ne!(draws(42), draws(43));
+        assert_ne!(draws(0), [0; 3]);
+    }
+
+    #[test]
+    fn bounds() {
+        let mut rng = Rng::seeded(7);
+        for _ in 0..10_000 {
+            assert!((0.0..1.0).contains(&rng.float()));
+            assert!((-3..=3).contains(&rng.int(-3, 3).unwrap()));
+        }
+
+        let mut seen = [false; 7];
+        for _ in 0..1000 {
+            seen[(rng.int(-3, 3).unwrap() + 3) as usize] = true;
+        }
+        assert!(seen.iter().all(|&seen| seen), "every value is drawn");
+
+        assert_eq!(rng.int(5, 5), Some(5));
+        assert!(rng.int(i64::MIN, i64::MAX).is_some());
+        assert_eq!(rng.int(2, 1), None);
+    }
+}
@@ -0,0 +1,200 @@
+//! Exact fraction arithmetic on numerator, denominator pairs. Every operation returns `None` if
+//! the result would overflow or divide by zero, leaving callers to fall back to floats
+
+use std::cmp::Ordering;
+
+use super::gcd::gcd;
+
+/// A fraction as `(numerator, denominator)`
+pub type Fraction = (i64, i64);
+
+/// Reduces a fraction to lowest terms with a positive denominator
+pub fn reduce(numerator: i64, denominator: i64) -> Option<Fraction> {
+    if denominator == 0 {
+        return None;
+    }
+
+    let divisor = gcd(numerator.unsigned_abs(), denominator.unsigned_abs()) as i64;
+    let (numerator, denominator) = (numerator / divisor, denominator / divisor);
+
+    if denominator < 0 {
+        Some((numerator.checked_neg()?, denominator.checked_neg()?))
+    } else {
+        Some((numerator, denominator))
+    }
+}
+
+/// Adds two fractions
+pub fn add((an, ad): Fraction, (bn, bd): Fraction) -> Option<Fraction> {
+    let numerator = an.checked_mul(bd)?.checked_add(bn.checked_mul(ad)?)?;
+    reduce(numerator, ad.checked_mul(bd)?)
+}
+
+/// Subtracts one fraction from another
+pub fn sub(a: Fraction, (bn, bd): Fraction) -> Option<Fraction> {
+    add(a, (bn.checked_neg()?, bd))
+}
+
+/// Multiplies two fractions
+pub fn mul((an, ad): Fraction, (bn, bd): Fraction) -> Option<Fraction> {
+    reduce(an.checked_mul(bn)?, ad.checked_mul(bd)?)
+}
+
+/// Divides one fraction by another
+pub fn div((an, ad): Fraction, (bn, bd): Fraction) -> Option<Fraction> {
+    reduce(an.checked_mul(bd)?, ad.checked_mul(bn)?)
+}
+
+/// The remainder of dividing one fraction by another, taking the sign of the divisor like
+/// `a - b * floor(a / b)`
+pub fn rem(a: Fraction, b: Fraction) -> Option<Fraction> {
+    let (numerator, denominator) = div(a, b)?;
+    sub(a, mul(b, (numerator.div_euclid(denominator), 1))?)
+}
+
+/// Raises a fraction to an integer power
+pub fn pow((numerator, denominator): Fraction, exponent: i64) -> Option<Fraction> {
+    let power = u32::try_from(exponent.unsigned_abs()).ok()?;
+    let (numerator, denominator) = (
+        numerator.checked_pow(power)?,
+        denominator.checked_pow(power)?,
+    );
+
+    if exponent < 0 {
+        reduce(denominator, numerator)
+    } else {
+        reduce(numerator, denominator)
+    }
+}
+
+/// Orders two fractions with positive denominators, without any chance of overflow
+pub fn cmp((an, ad): Fraction, (bn, bd): Fraction) -> Ordering {
+    (i128::from(an) * i128::from(bd)).cmp(&(i128::from(bn) * i128::from(ad)))
+}
+
+/// Splits a finite float into `(mantissa, exponent)` with `float == mantissa * 2^exponent` exactly
+pub fn decompose(float: f64) -> (i64, i32) {
+    let bits = float.to_bits();
+    let exponent = ((bits >> 52) & 0x7ff) as i32;
+    let fraction = (bits & ((1 << 52) - 1)) as i64;
+
+    // Subnormals have no implicit leading bit
+    let (mantissa, exponent) = if exponent == 0 {
+        (fraction, -1074)
+    } else {
+        (fraction | (1 << 52), exponent - 1075)
+    };
+
+    if float.is_sign_negative() {
+        (-mantissa, exponent)
+    } else {
+        (mantissa, exponent)
+    }
+}
+
+/// Orders a fraction with a positive denominator against a float exactly, rather than rounding
+/// the fraction to the nearest float first. `None` if the float is NaN
+pub fn cmp_float((numerator, denominator): Fraction, float: f64) -> Option<Ordering> {
+    if float.is_nan() {
+        return None;
+    } else if float.abs() >= 2f64.powi(63) {
+        // Bigger than any fraction an `i64` can hold
+        return Some(if float > 0.0 {
+            Ordering::Less
+        } else {
+            Ordering::Greater
+        });
+    }
+
+    let (mantissa, exponent) = decompose(float);
+    if exponent >= 0 {
+        return Some(cmp((numerator, denominator), (mantissa << exponent, 1)));
+    }
+
+    // n / d against m / 2^k is n against m * d / 2^k, split into its floor and whether anything
+    // was left over. Shifting by more than 127 is the same as shifting by 127 for these sizes
+    let scaled = i128::from(mantissa) * i128::from(denominator);
+    let shift = exponent.unsigned_abs().min(127);
+    let floor = scaled >> shift;
+    let exact = floor << shift == scaled;
+
+    Some(i128::from(numerator).cmp(&floor).then(if exact {
+        Ordering::Equal
+    } else {
+        Ordering::Less
+    }))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::cmp::Ordering;
+
+    use super::{add, cmp, cmp_float, decompose, div, mul, pow, reduce, sub};
+
+    #[test]
+    fn reducing() {
+        assert_eq!(reduce(2, 4), Some((1, 2)));
+        assert_eq!(reduce(3, -6), Some((-1, 2)));
+        assert_eq!(reduce(0, -5), Some((0, 1)));
+        assert_eq!(reduce(1, 0), None);
+        assert_eq!(reduce(1, i64::MIN), None);
+    }
+
+    #[test]
+    fn arithmetic() {
+        assert_eq!(add((1, 3), (1, 6)), Some((1, 2)));
+        assert_eq!(sub((1, 3), (1, 3)), Some((0, 1)));
+        assert_eq!(mul((1, 3), (3, 1)), Some((1, 1)));
+        assert_eq!(div((2, 3), (4, 9)), Some((3, 2)));
+        assert_eq!(div((2, 3), (0, 1)), None);
+        assert_eq!(pow((2, 3), 3), Some((8, 27)));
+        assert_eq!(pow((2, 3), -2), Some((9, 4)));
+        assert_eq!(pow((0, 1), -1), None);
+        assert_eq!(pow((2, 1), 64), None);
+        assert_eq!(mul((i64::MAX, 1), (2, 1)), None);
+    }
+
+    #[test]
+    fn ordering() {
+        assert_eq!(cmp((1, 3), (1, 2)), Ordering::Less);
+        assert_eq!(cmp((-1, 2), (-2, 3)), Ordering::Greater);
+        assert_eq!(cmp((2, 4), (1, 2)), Ordering::Equal);
+        assert_eq!(cmp((i64::MAX, 1), (i64::MAX - 1, 1)), Ordering::Greater);
+    }
+
+    #[test]
+    fn decomposing() {
+        for float in [0.0, 1.0, -0.375, 1e300, -5e-324, f64::MAX, 0.1] {
+            // Scaled in two halves so subnormals don't underflow along the way
+            let (mantissa, exponent) = decompose(float);
+            let half = exponent / 2;
+            let rebuilt = mantissa as f64 * 2f64.powi(half) * 2f64.powi(exponent - half);
+            assert_eq!(rebuilt, float, "{float}");
+        }
+    }
+
+    #[test]
+    fn ordering_against_floats() {
+        // Both round to the same float, but only one of them equals it
+        assert_eq!(
+            cmp_float((9_007_199_254_740_993, 1), 9_007_199_254_740_992.0),
+            Some(Ordering::Greater)
+        );
+        assert_eq!(
+            cmp_float((9_007_199_254_740_992, 1), 9_007_199_254_740_992.0),
+            Some(Ordering::Equal)
+        );
+        assert_eq!(cmp_float((1, 2), 0.5), Some(Ordering::Equal));
+        assert_eq!(cmp_float((1, 3), 1.0 / 3.0), Some(Ordering::Greater));
+        assert_eq!(cmp_float((-1, 3), -1.0 / 3.0), Some(Ordering::Less));
+        assert_eq!(cmp_float((1, 10), 0.1), Some(Ordering::Less));
+        assert_eq!(cmp_float((0, 1), -0.0), Some(Ordering::Equal));
+        assert_eq!(cmp_float((1, i64::MAX), 5e-324), Some(Ordering::Greater));
+        assert_eq!(cmp_float((i64::MAX, 1), 1e19), Some(Ordering::Less));
+        assert_eq!(
+            cmp_float((i64::MIN, 1), f64::NEG_INFINITY),
+            Some(Ordering::Greater)
+        );
+        assert_eq!(cmp_float((1, 1), f64::NAN), None);
+    }
+}
@@ -0,0 +1,343 @@
+//! Tokenizing, parsing and evaluating source in one call, for checking whether it's a valid
+//! expression or working out its value without every caller collapsing each stage's errors its
+//! own way. Each stage can be run on its own too, for callers that time or inspect them
+
+use std::{error::Error, fmt::Display, ops::Range};
+
+use crate::{
+    ast::{Expr, MAX_DEPTH, ParseError, ParseErrorKind, Parser},
+    exec::{EvalResult, Evaluator, ExecOutcome, RuntimeError},
+    tokenizer::{InvalidToken, Token, Tokenizable, TokenizerOptions},
+};
+
+/// Tokens paired with the range of characters each came from
+pub type SpannedTokens<'a> = Vec<(Token<'a>, Range<usize>)>;
+
+/// How source fared being tokenized and parsed
+#[derive(Clone, Debug, PartialEq)]
+pub enum CheckResult {
+    /// The source is a valid expression
+    Ok(Expr),
+    /// The source is blank or only a comment, so there's nothing to parse
+    Empty,
+    /// A character isn't valid Chalk
+    TokenizeError(InvalidToken),
+    /// The tokens don't form a valid expression, with the error positioned in the source
+    ParseError(ParseError),
+}
+
+impl CheckResult {
+    /// Whether the source could stand on its own, which is everything except source ending while
+    /// more was expected, like `(1 +`. A REPL can keep reading lines until this holds
+    pub fn is_complete(&self) -> bool {
+        !matches!(self, Self::ParseError(err) if err.kind == ParseErrorKind::Incomplete)
+    }
+
+    /// Character index in the source of the first error, if there is one
+    pub fn position(&self) -> Option<usize> {
+        match self {
+            Self::Ok(_) | Self::Empty => None,
+            Self::TokenizeError(err) => Some(err.position),
+            Self::ParseError(err) => err.span.as_ref().map(|span| span.start),
+        }
+    }
+}
+
+/// Why source couldn't be evaluated, from whichever stage failed
+#[derive(Clone, Debug, PartialEq)]
+pub enum ChalkError {
+    /// The source is blank or only a comment, so there's nothing to evaluate
+    Empty,
+    /// A character isn't valid Chalk
+    Tokenize(InvalidToken),
+    /// The tokens don't form a valid expression
+    Parse(ParseError),
+    /// The expression parsed but failed to evaluate, along with the expression
+    Runtime(RuntimeError, Expr),
+}
+
+impl Display for ChalkError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Empty => write!(f, "Expression is empty :("),
+            Self::Tokenize(err) => write!(f, "{err}"),
+            Self::Parse(err) => write!(f, "{err}"),
+            Self::Runtime(err, _) => write!(f, "{err}"),
+        }
+    }
+}
+
+impl Error for ChalkError {}
+
+impl From<InvalidToken> for ChalkError {
+    fn from(err: InvalidToken) -> Self {
+        Self::Tokenize(err)
+    }
+}
+
+impl From<ParseError> for ChalkError {
+    fn from(err: ParseError) -> Self {
+        Self::Parse(err)
+    }
+}
+
+/// Tokenizes and parses source with the default options and depth limit
+pub fn check(source: &str) -> CheckResult {
+    check_with(source, TokenizerOptions::default(), MAX_DEPTH)
+}
+
+/// Tokenizes source with `options` and parses it, nested at most `max_depth` deep
+pub fn check_with(source: &str, options: TokenizerOptions, max_depth: usize) -> CheckResult {
+    let tokens = match tokenize(source, options) {
+        Ok(Some(tokens)) => tokens,
+        Ok(None) => return CheckResult::Empty,
+        Err(err) => return CheckResult::TokenizeError(err),
+    };
+
+    match parse(tokens, max_depth) {
+        Ok(expr) => CheckResult::Ok(expr),
+        Err(err) => CheckResult::ParseError(err),
+    }
+}
+
+/// The first stage, tokenizing source with `options` and pairing each token with the range of
+/// characters it came from. Gives `None` for source that's blank or only a comment
+pub fn tokenize(
+    source: &str,
+    options: TokenizerOptions,
+) -> Result<Option<SpannedTokens<'_>>, InvalidToken> {
+    let tokens = source.tokenize_with(options)?;
+    if matches!(tokens[..], [(Token::EOF, _)]) {
+        return Ok(None);
+    }
+
+    Ok(Some(tokens))
+}
+
+/// The second stage, parsing tokens nested at most `max_depth` deep, with errors positioned by
+/// the ranges the tokens came from
+pub fn parse(tokens: SpannedTokens<'_>, max_depth: usize) -> Result<Expr, ParseError> {
+    Parser::with_spans(tokens).with_max_depth(max_depth).parse()
+}
+
+/// The last stage, executing a parsed statement. Statements that succeed are added to the
+/// evaluator's history for `hist`, under the source they were parsed from
+pub fn execute(
+    source: &str,
+    expr: Expr,
+    eval: &mut Evaluator,
+) -> Result<(Expr, ExecOutcome), ChalkError> {
+    match eval.exec_statement(&expr) {
+        Ok(outcome) => {
+            eval.remember(source.trim(), outcome.value().clone());
+            Ok((expr, outcome))
+        }
+        Err(err) => Err(ChalkError::Runtime(err, expr)),
+    }
+}
+
+/// Evaluates source with a fresh evaluator, so nothing is bound beforehand and nothing bound is
+/// kept
+pub fn eval_str(source: &str) -> Result<EvalResult, ChalkError> {
+    eval_str_with(source, &mut Evaluator::default())
+}
+
+/// Evaluates source with an evaluator, which keeps whatever the source binds for evaluations
+/// after it
+pub fn eval_str_with(source: &str, eval: &mut Evaluator) -> Result<EvalResult, ChalkError> {
+    eval_statement(source, TokenizerOptions::default(), eval)
+        .map(|(_, outcome)| outcome.into_value())
+}
+
+/// Tokenizes source with `options`, parses it nested at most as deep as the evaluator allows and
+/// executes it as a statement, giving the expression parsed and what executing it did. Statements
+/// that succeed are added to the evaluator's history for `hist`
+pub fn eval_statement(
+    source: &str,
+    options: TokenizerOptions,
+    eval: &mut Evaluator,
+) -> Result<(Expr, ExecOutcome), ChalkError> {
+    let expr = match check_with(source, options, eval.max_depth()) {
+        CheckResult::Ok(expr) => expr,
+        CheckResult::Empty => return Err(ChalkError::Empty),
+        CheckResult::TokenizeError(err) => return Err(err.into()),
+        CheckResult::ParseError(err) => return Err(err.into()),
+    };
+
+    execute(source, expr, eval)
+}
+
+/// Shorthands for tests elsewhere in the crate working with expressions known to be valid
+#[cfg(test)]
+pub(crate) mod testing {
+    use crate::{
+        ast::Expr,
+        exec::{EvalResult, Evaluator, RuntimeError},
+    };
+
+    use super::{CheckResult, check};
+
+    /// Parses an expression, panicking if it isn't one
+    pub(crate) fn parse(source: &str) -> Expr {
+        match check(source) {
+            CheckResult::Ok(expr) => expr,
+            other => panic!("`{source}` isn't an expression: {other:?}"),
+        }
+    }
+
+    /// Parses an expression and evaluates it, panicking if it isn't one
+    pub(crate) fn run(eval: &mut Evaluator, source: &str) -> Result<EvalResult, RuntimeError> {
+        eval.exec(&parse(source))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        ast::{Expr, ParseErrorKind},
+        exec::{EvalResult, Evaluator, ExecOutcome, RuntimeError},
+        tokenizer::{InvalidToken, TokenizerOptions},
+    };
+
+    use super::{
+        ChalkError, CheckResult, check, check_with, eval_statement, eval_str, eval_str_with,
+    };
+
+    #[test]
+    fn outcomes() {
+        assert_eq!(
+            check("x + 1"),
+            CheckResult::Ok(Expr::var('x') + Expr::Integer(1))
+        );
+        assert!(check("x + 1").is_complete());
+
+        for blank in ["", "   ", "# just a note"] {
+            assert_eq!(check(blank), CheckResult::Empty, "{blank:?}");
+            assert!(check(blank).is_complete());
+        }
+
+        let invalid = check("1 $ 2");
+        assert_eq!(
+            invalid,
+            CheckResult::TokenizeError(InvalidToken {
+                position: 2,
+                found: '$'
+            })
+        );
+        assert_eq!(invalid.position(), Some(2));
+        assert!(invalid.is_complete());
+
+        let unexpected = check("1 + )");
+        assert!(
+            matches!(&unexpected, CheckResult::ParseError(err) if err.kind == ParseErrorKind::Unexpected)
+        );
+        assert_eq!(unexpected.position(), Some(4));
+        assert!(unexpected.is_complete());
+    }
+
+    #[test]
+    fn incomplete_input() {
+        let incomplete = check("(1 +");
+        assert!(
+            matches!(&incomplete, CheckResult::ParseError(err) if err.kind == ParseErrorKind::Incomplete)
+        );
+        assert_eq!(incomplete.position(), Some(4));
+        assert!(!incomplete.is_complete());
+        assert!(!check("(1 + 2").is_complete());
+        assert!(check("(1 + 2)").is_complete());
+    }
+
+    #[test]
+    fn options() {
+        let commas = TokenizerOptions {
+            decimal_comma: true,
+            ..Default::default()
+        };
+        assert_eq!(
+            check_with("2,5", commas, 8),
+            CheckResult::Ok(Expr::Real(2.5))
+        );
+
+        let too_deep = check_with("((((1))))", TokenizerOptions::default(), 2);
+        assert!(
+            matches!(too_deep, CheckResult::ParseError(err) if err.kind == ParseErrorKind::TooDeep)
+        );
+    }
+
+    #[test]
+    fn evaluating() {
+        assert_eq!(eval_str("2^10 - 24"), Ok(EvalResult::Integer(1000)));
+
+        // Bindings carry over between evaluations with the same evaluator
+        let mut eval = Evaluator::default();
+        assert_eq!(
+            eval_str_with("r = 3", &mut eval),
+            Ok(EvalResult::Integer(3))
+        );
+        assert_eq!(
+            eval_str_with("s = r * 2", &mut eval),
+            Ok(EvalResult::Integer(6))
+        );
+        assert_eq!(
+            eval_str_with("r + s", &mut eval),
+            Ok(EvalResult::Integer(9))
+        );
+        assert_eq!(
+            eval_str_with("hist(1)", &mut eval),
+            Ok(EvalResult::Integer(3))
+        );
+
+        let commas = TokenizerOptions {
+            decimal_comma: true,
+            ..Default::default()
+        };
+        assert_eq!(
+            eval_statement("t = 2,5", commas, &mut eval),
+            Ok((
+                Expr::Assignment('t', Box::new(Expr::Real(2.5))),
+                ExecOutcome::Assigned {
+                    name: 't',
+                    value: EvalResult::Float(2.5)
+                }
+            ))
+        );
+    }
+
+    #[test]
+    fn evaluation_errors() {
+        assert_eq!(eval_str("  # nothing"), Err(ChalkError::Empty));
+        assert_eq!(
+            eval_str("1 $ 2"),
+            Err(ChalkError::Tokenize(InvalidToken {
+                position: 2,
+                found: '$'
+            }))
+        );
+        assert!(matches!(
+            eval_str("(1 +"),
+            Err(ChalkError::Parse(err)) if err.kind == ParseErrorKind::Incomplete
+        ));
+        assert_eq!(
+            eval_str("q + 1"),
+            Err(ChalkError::Runtime(
+                RuntimeError::Invalid,
+                Expr::var('q') + Expr::Integer(1)
+            ))
+        );
+
+        // Messages are the failing stage's own
+        assert_eq!(
+            eval_str("(-2)!").map_err(|err| err.to_string()),
+            Err(RuntimeError::OutOfDomain("factorial").to_string())
+        );
+        assert_eq!(ChalkError::Empty.to_string(), "Expression is empty :(");
+
+        // The evaluator's depth limit applies to parsing too
+        let mut eval = Evaluator::default();
+        eval.set_max_depth(3);
+        assert!(matches!(
+            eval_str_with("((((1))))", &mut eval),
+            Err(ChalkError::Parse(err)) if err.kind == ParseErrorKind::TooDeep
+        ));
+    }
+}
@@ -0,0 +1,275 @@
+//! Algebraic simplification of expressions that may contain unbound variables
+
+use crate::{
+    ast::{BinaryOperator, Expr, UnaryOperator},
+    exec::{EvalResult, Evaluator},
+};
+
+/// Most rewrite passes made before giving up on reaching a fixed point
+const MAX_PASSES: usize = 32;
+
+/// Simplifies an expression without needing its variables bound. Constant subexpressions are
+/// folded, identities like `x * 1`, `x + 0` and `x ^ 1` are removed, `x * 0` and `x ^ 0` collapse
+/// to constants, double negations cancel and parentheses are dropped, since displaying the
+/// result adds back any that precedence needs. Rewrites assume variables hold finite numbers
+pub fn simplify(ast: &Expr) -> Expr {
+    simplify_with(ast, &Evaluator::default())
+}
+
+/// Simplifies an expression like [`simplify`], folding constants the way `eval` would evaluate
+/// them, so `sin(90)` folds to 1 when it's in degrees mode
+pub fn simplify_with(ast: &Expr, eval: &Evaluator) -> Expr {
+    // Folding binds nothing, it only needs the settings that change what constants evaluate to
+    let mut folder = Evaluator::default();
+    folder.set_angle_mode(eval.angle_mode());
+    folder.set_epsilon(eval.epsilon());
+    folder.allow_non_finite(eval.allows_non_finite());
+
+    let mut current = ast.clone();
+    for _ in 0..MAX_PASSES {
+        let next = pass(&current, &mut folder);
+        if next == current {
+            break;
+        }
+        current = next;
+    }
+
+    current
+}
+
+/// Simplifies every node once, children first
+fn pass(ast: &Expr, eval: &mut Evaluator) -> Expr {
+    let expr = match ast {
+        Expr::Paren(node) => return pass(node, eval),
+        Expr::Assignment(var, node) => Expr::Assignment(*var, Box::new(pass(node, eval))),
+        Expr::AbsVal(node) => Expr::AbsVal(Box::new(pass(node, eval))),
+        Expr::UnaryOp { op, node } => Expr::UnaryOp {
+            op: op.clone(),
+            node: Box::new(pass(node, eval)),
+        },
+        Expr::BinaryOp { op, left, right } => Expr::BinaryOp {
+            op: op.clone(),
+            left: Box::new(pass(left, eval)),
+            right: Box::new(pass(right, eval)),
+        },
+        Expr::Solve { equation, var } => Expr::Solve {
+            equation: Box::new(pass(equation, eval)),
+            var: *var,
+        },
+        Expr::Reduce {
+            kind,
+            var,
+            from,
+            to,
+            body,
+        } => Expr::Reduce {
+            kind: *kind,
+            var: *var,
+            from: Box::new(pass(from, eval)),
+            to: Box::new(pass(to, eval)),
+            body: Box::new(pass(body, eval)),
+        },
+        Expr::List(items) => Expr::List(items.iter().map(|item| pass(item, eval)).collect()),
+        Expr::Index { list, index } => Expr::Index {
+            list: Box::new(pass(list, eval)),
+            index: Box::new(pass(index, eval)),
+        },
+        Expr::FunctionCall { function, args } => Expr::FunctionCall {
+            function: *function,
+            args: args.iter().map(|item| pass(item, eval)).collect(),
+        },
+        Expr::Variable(_)
+        | Expr::Integer(_)
+        | Expr::Real(_)
+        | Expr::Bool(_)
+        | Expr::Constant(_)
+        | Expr::Unit(_) => return ast.clone(),
+    };
+
+    fold(&expr, eval).or_else(|| rewrite(&expr)).unwrap_or(expr)
+}
+
+/// Whether an expression can be evaluated without any variables, always to the same result. Named
+/// constants and units are left alone so `2 * pi` and `3 km` stay readable
+fn is_constant(ast: &Expr) -> bool {
+    match ast {
+        Expr::Integer(_) | Expr::Real(_) | Expr::Bool(_) => true,
+        Expr::Variable(_)
+        | Expr::Constant(_)
+        | Expr::Unit(_)
+        | Expr::Assignment(..)
+        | Expr::Solve { .. }
+        | Expr::Reduce { .. } => false,
+        Expr::Paren(node) | Expr::AbsVal(node) | Expr::UnaryOp { node, .. } => is_constant(node),
+        Expr::BinaryOp { left, right, .. }
+        | Expr::Index {
+            list: left,
+            index: right,
+        } => is_constant(left) && is_constant(right),
+        Expr::FunctionCall { function, args } => {
+            !function.is_volatile() && args.iter().all(is_constant)
+        }
+        Expr::List(items) => items.iter().all(is_constant),
+    }
+}
+
+/// Evaluates a constant expression down to a literal
+fn fold(ast: &Expr, eval: &mut Evaluator) -> Option<Expr> {
+    if !is_constant(ast) {
+        return None;
+    }
+
+    match eval.exec(ast).ok()? {
+        EvalResult::Integer(i) => Some(Expr::Integer(i)),
+        EvalResult::Bool(b) => Some(Expr::Bool(b)),
+        // Whole floats become integers so `2 * 3` folds to `6`, integer operations accept either
+        EvalResult::Float(f) if f.fract() == 0.0 && f.abs() < i64::MAX as f64 => {
+            Some(Expr::Integer(f as i64))
+        }
+        EvalResult::Float(f) if f.is_finite() => Some(Expr::Real(f)),
+        EvalResult::Float(_) => None,
+        rational @ EvalResult::Rational(..) => Some(Expr::from(rational)),
+        // Literals can't hold a big integer exactly, so its expression is kept instead
+        #[cfg(feature = "bigint")]
+        EvalResult::BigInt(_) => None,
+        // Folding is done in float mode, so there are never decimals to fold
+        EvalResult::List(_)
+        | EvalResult::Quantity { .. }
+        | EvalResult::Complex(..)
+        | EvalResult::Decimal(..) => None,
+    }
+}
+
+/// Whether an expression is a literal zero
+fn is_zero(ast: &Expr) -> bool {
+    matches!(ast, Expr::Integer(0)) || matches!(ast, Expr::Real(r) if *r == 0.0)
+}
+
+/// Whether an expression is a literal one
+fn is_one(ast: &Expr) -> bool {
+    matches!(ast, Expr::Integer(1)) || matches!(ast, Expr::Real(r) if *r == 1.0)
+}
+
+/// Applies an identity to the top of an expression, if one matches
+fn rewrite(ast: &Expr) -> Option<Expr> {
+    match ast {
+        Expr::UnaryOp {
+            op: UnaryOperator::Neg,
+            node,
+        } => match node.as_ref() {
+            Expr::UnaryOp {
+                op: UnaryOperator::Neg,
+                node,
+            } => Some(node.as_ref().clone()),
+            _ => None,
+        },
+        Expr::BinaryOp { op, left, right } => {
+            let (left, right) = (left.as_ref(), right.as_ref());
+            match op {
+                BinaryOperator::Add if is_zero(left) => Some(right.clone()),
+                BinaryOperator::Add | BinaryOperator::Subtract if is_zero(right) => {
+                    Some(left.clone())
+                }
+                BinaryOperator::Subtract if is_zero(left) => Some(Expr::UnaryOp {
+                    op: UnaryOperator::Neg,
+                    node: Box::new(right.clone()),
+                }),
+                BinaryOperator::Multiply if is_zero(left) || is_zero(right) => {
+                    Some(Expr::Integer(0))
+                }
+                BinaryOperator::Multiply if is_one(left) => Some(right.clone()),
+                BinaryOperator::Multiply | BinaryOperator::Divide | BinaryOperator::Pow
+                    if is_one(right) =>
+                {
+                    Some(left.clone())
+                }
+                BinaryOperator::Pow if is_zero(right) => Some(Expr::Integer(1)),
+                _ => None,
+            }
+        }
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        ast::Expr,
+        exec::{AngleMode, Evaluator},
+        pipeline::testing::parse,
+    };
+
+    use super::{simplify, simplify_with};
+
+    #[test]
+    fn simplifying() {
+        let cases = [
+            ("2 * 3 + x", "6 + x"),
+            ("x * 1 + 0", "x"),
+            ("1 * (x + 0) ^ 1", "x"),
+            ("x ^ 0 + y * 0", "1"),
+            ("--x", "x"),
+            ("0 - x", "-x"),
+            ("((((x + y))))", "x + y"),
+            ("(x + y) * (2 - 1)", "x + y"),
+            ("(1 + 1) * pi", "2 * pi"),
+            ("sin(0) + cos(x * 0)", "1"),
+            ("y = 3 * 4 * x", "y = 12 * x"),
+            ("x / 2 / 1", "x / 2"),
+            ("2 / 8 + x", "1 / 4 + x"),
+            ("0.5 / 2 + x", "0.25 + x"),
+            ("randint(1, 2 * 3) + 0", "randint(1, 6)"),
+        ];
+
+        for (expression, expected) in cases {
+            assert_eq!(
+                simplify(&parse(expression)).to_string(),
+                expected,
+                "{expression}"
+            );
+        }
+    }
+
+    #[test]
+    fn folding_in_degrees() {
+        let mut eval = Evaluator::default();
+        eval.set_angle_mode(AngleMode::Degrees);
+
+        let ast = parse("sin(90) * x + acos(0)");
+        assert_eq!(simplify_with(&ast, &eval).to_string(), "x + 90");
+        assert_eq!(
+            simplify(&parse("sin(90) * x")).to_string(),
+            format!("{} * x", 90f64.sin())
+        );
+    }
+
+    #[test]
+    fn simplifying_preserves_values() {
+        let expressions = [
+            "2 * 3 + x",
+            "(x * 1 + 0) ^ (y - y + 1)",
+            "--(x - 0) * (1 + 1) / 1",
+            "|x * 0 - y| + 3!",
+            "x ^ 0 * sin(y * 1) + (2 + 3) * x",
+            "x > 2 * 1 && y <= 0 + 3",
+        ];
+        let values = [(-2.5, 3.0), (0.0, 0.0), (1.0, -1.0), (4.0, 2.5)];
+
+        for expression in expressions {
+            let ast = parse(expression);
+            let simplified = simplify(&ast);
+
+            for (x, y) in values {
+                let mut eval = Evaluator::default();
+                eval.set('x', Expr::Real(x));
+                eval.set('y', Expr::Real(y));
+
+                assert_eq!(
+                    eval.exec(&simplified).expect("Simplified"),
+                    eval.exec(&ast).expect("Original"),
+                    "{expression} at x = {x}, y = {y}"
+                );
+            }
+        }
+    }
+}
@@ -0,0 +1,283 @@
+//! Numeric root finding for `solve(equation, var)`
+
+use crate::{
+    ast::{BinaryOperator, Expr},
+    exec::{EvalResult, Evaluator, RuntimeError},
+};
+
+/// Furthest from zero the scan looks for a sign change
+const LIMIT: f64 = 1e6;
+/// Number of intervals scanned on each side of zero, spaced more finely near zero
+const SCAN_STEPS: u32 = 2000;
+/// Most steps taken refining a single root before giving up
+const MAX_ITERATIONS: usize = 200;
+/// Starting points for Newton's method, used for roots the function touches without crossing
+const SEEDS: [f64; 6] = [0.0, 1.0, -1.0, 10.0, -10.0, 100.0];
+/// Largest residual accepted for a root found without a sign change
+const TOLERANCE: f64 = 1e-4;
+
+impl Evaluator {
+    /// Numerically finds a value of `var` satisfying `equation`, which is either an `==`
+    /// comparison or an expression taken to equal zero. Intervals out to ±1e6 are scanned for a
+    /// sign change starting from zero, so roots near zero are found first, and each candidate is
+    /// refined with bisection and Newton steps. Any binding of `var` is shadowed while solving
+    /// and restored afterwards. Errors if no root is found
+    pub fn solve(&mut self, equation: &Expr, var: char) -> Result<EvalResult, RuntimeError> {
+        let mut equation = equation;
+        while let Expr::Paren(inner) = equation {
+            equation = inner;
+        }
+
+        let mut solver = Solver {
+            eval: self,
+            equation,
+            var,
+        };
+        solver.root().map(EvalResult::Float)
+    }
+}
+
+/// An equation being solved for one variable
+struct Solver<'a> {
+    /// The evaluator providing every other variable
+    eval: &'a mut Evaluator,
+    /// The equation being solved
+    equation: &'a Expr,
+    /// The variable being solved for
+    var: char,
+}
+
+impl Solver<'_> {
+    /// How far the equation is from holding with the variable set to `x`, NaN where it has no
+    /// value
+    fn residual(&mut self, x: f64) -> Result<f64, RuntimeError> {
+        let equation = self.equation;
+        let residual =
+            self.eval
+                .with_bindings([(self.var, Expr::Real(x))], |eval| match equation {
+                    Expr::BinaryOp {
+                        op: BinaryOperator::Eq,
+                        left,
+                        right,
+                    } => Ok(eval.exec(left)?.float()? - eval.exec(right)?.float()?),
+                    _ => eval.exec(equation)?.float(),
+                });
+        match residual {
+            Err(err) if err.is_undefined() => Ok(f64::NAN),
+            residual => residual,
+        }
+    }
+
+    /// Scans outwards from zero for a root, falling back to Newton's method from a few seeds
+    fn root(&mut self) -> Result<f64, RuntimeError> {
+        let point = |n: u32| LIMIT * (n as f64 / SCAN_STEPS as f64).powi(3);
+
+        let at_zero = self.residual(0.0)?;
+        if at_zero == 0.0 {
+            return Ok(0.0);
+        }
+
+        let (mut above, mut below) = (at_zero, at_zero);
+        for n in 1..=SCAN_STEPS {
+            let (near, far) = (point(n - 1), point(n));
+
+            let next = self.residual(far)?;
+            if let Some(root) = self.bracketed(near, above, far, next)? {
+                return Ok(root);
+            }
+            above = next;
+
+            let next = self.residual(-far)?;
+            if let Some(root) = self.bracketed(-near, below, -far, next)? {
+                return Ok(root);
+            }
+            below = next;
+        }
+
+        for seed in SEEDS {
+            if let Some(root) = self.newton(seed)? {
+                return Ok(root);
+            }
+        }
+
+        Err(RuntimeError::Invalid)
+    }
+
+    /// Looks for a root between `a` and `b` given the residuals at each end
+    fn bracketed(&mut self, a: f64, fa: f64, b: f64, fb: f64) -> Result<Option<f64>, RuntimeError> {
+        if fb == 0.0 {
+            Ok(Some(b))
+        } else if fa.is_finite() && fb.is_finite() && fa.signum() != fb.signum() {
+            self.refine(a.min(b), a.max(b))
+        } else {
+            Ok(None)
+        }
+    }
+
+    /// Narrows a bracket with a sign change down to a root. A sign change across a pole is
+    /// rejected, since the residual grows instead of shrinking towards it
+    fn refine(&mut self, mut a: f64, mut b: f64) -> Result<Option<f64>, RuntimeError> {
+        let mut fa = self.residual(a)?;
+        let bound = fa.abs().min(self.residual(b)?.abs());
+
+        for _ in 0..MAX_ITERATIONS {
+            let mid = (a + b) / 2.0;
+            let fm = self.residual(mid)?;
+
+            if fm == 0.0 {
+                return Ok(Some(mid));
+            } else if !fm.is_finite() {
+                return Ok(None);
+            } else if b - a <= f64::EPSILON * mid.abs().max(1.0) {
+                return Ok((fm.abs() <= bound).then_some(mid));
+            }
+
+            if fm.signum() == fa.signum() {
+                (a, fa) = (mid, fm);
+            } else {
+                b = mid;
+            }
+
+            // A Newton step from the midpoint is only taken if it stays inside the bracket
+            let guess = mid - fm / self.slope(mid)?;
+            if guess > a && guess < b {
+                let fg = self.residual(guess)?;
+                if fg == 0.0 {
+                    return Ok(Some(guess));
+                } else if fg.signum() == fa.signum() {
+                    (a, fa) = (guess, fg);
+                } else if fg.is_finite() {
+                    b = guess;
+                }
+            }
+        }
+
+        Err(RuntimeError::Invalid)
+    }
+
+    /// Runs Newton's method from `x`, accepting where it settles if the residual is small
+    fn newton(&mut self, mut x: f64) -> Result<Option<f64>, RuntimeError> {
+        for _ in 0..MAX_ITERATIONS {
+            let fx = self.residual(x)?;
+            let step = fx / self.slope(x)?;
+            if !step.is_finite() {
+                return Ok(None);
+            }
+
+            x -= step;
+            if step.abs() <= f64::EPSILON * x.abs().max(1.0) {
+                return Ok((self.residual(x)?.abs() <= TOLERANCE).then_some(x));
+            }
+        }
+
+        Ok(None)
+    }
+
+    /// The residual's slope at `x`, by central difference
+    fn slope(&mut self, x: f64) -> Result<f64, RuntimeError> {
+        let h = 1e-3 * x.abs().max(1.0);
+        Ok((self.residual(x + h)? - self.residual(x - h)?) / (2.0 * h))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        ast::Expr,
+        exec::{EvalResult, Evaluator, RuntimeError},
+        pipeline::{CheckResult, check, testing::parse},
+    };
+
+    /// Parses and evaluates an expression, returning the result as a float
+    fn solve(eval: &mut Evaluator, expression: &str) -> Result<f64, RuntimeError> {
+        eval.exec(&parse(expression))?.float()
+    }
+
+    /// Asserts a solved value is within a small distance of the expected root
+    fn assert_root(found: f64, expected: f64) {
+        assert!(
+            (found - expected).abs() < 1e-4,
+            "Expected {expected}, found {found}"
+        );
+    }
+
+    #[test]
+    fn linear() {
+        let mut eval = Evaluator::default();
+        assert_root(solve(&mut eval, "solve(3x + 5 == 11, x)").unwrap(), 2.0);
+        assert_root(solve(&mut eval, "solve(2y - 7, y)").unwrap(), 3.5);
+        assert_root(
+            solve(&mut eval, "solve(x / 4 == -1000, x)").unwrap(),
+            -4000.0,
+        );
+    }
+
+    #[test]
+    fn quadratic() {
+        let mut eval = Evaluator::default();
+        let root = solve(&mut eval, "solve(x^2 - 4 == 0, x)").unwrap();
+        assert_root(root.abs(), 2.0);
+
+        assert_root(solve(&mut eval, "solve((x - 1)^2 == 0, x)").unwrap(), 1.0);
+    }
+
+    #[test]
+    fn transcendental() {
+        let mut eval = Evaluator::default();
+        assert_root(
+            solve(&mut eval, "solve(cos(x) == x, x)").unwrap(),
+            0.739_085,
+        );
+        assert_root(
+            solve(&mut eval, "solve(ln(x) == 1, x)").unwrap(),
+            std::f64::consts::E,
+        );
+    }
+
+    #[test]
+    fn uses_outer_variables_without_changing_them() {
+        let mut eval = Evaluator::default();
+        eval.set('a', Expr::Integer(9));
+        eval.set('x', Expr::Integer(5));
+
+        assert_root(solve(&mut eval, "solve(x^2 == a, x)").unwrap().abs(), 3.0);
+        assert_eq!(eval.get('x'), Some(&Expr::Integer(5)));
+        assert_eq!(
+            solve(&mut eval, "solve(x - a, x) + x").map(EvalResult::Float),
+            Ok(EvalResult::Float(14.0))
+        );
+    }
+
+    #[test]
+    fn unsolvable() {
+        let mut eval = Evaluator::default();
+        assert_eq!(
+            solve(&mut eval, "solve(x^2 + 1 == 0, x)"),
+            Err(RuntimeError::Invalid)
+        );
+        assert_eq!(
+            solve(&mut eval, "solve(1 / (x - 1) == 0, x)"),
+            Err(RuntimeError::Invalid)
+        );
+        assert_eq!(
+            solve(&mut eval, "solve(x > 2, x)"),
+            Err(RuntimeError::Invalid)
+        );
+        assert_eq!(
+            solve(&mut eval, "solve(x == b, x)"),
+            Err(RuntimeError::Invalid)
+        );
+    }
+
+    #[test]
+    fn parsing_and_dependencies() {
+        let ast = parse("solve((x^2 == a), x)");
+        assert_eq!(ast.to_string(), "solve((x ^ 2 == a), x)");
+
+        let deps = Evaluator::default().dependencies(&ast);
+        assert_eq!(deps.free.into_iter().collect::<Vec<_>>(), ['a']);
+
+        assert!(matches!(check("solve(x^2, 2)"), CheckResult::ParseError(_)));
+        assert!(matches!(check("solve(x^2)"), CheckResult::ParseError(_)));
+    }
+}
@@ -0,0 +1,128 @@
+//! Saving and restoring an evaluator's variables as JSON
+
+use std::{collections::HashMap, error::Error, fmt::Display};
+
+use serde::{Deserialize, Serialize};
+
+use crate::{ast::Expr, exec::Evaluator};
+
+/// The current version of the saved state format
+pub const STATE_VERSION: u32 = 2;
+
+/// The saved form of an evaluator
+#[derive(Serialize, Deserialize)]
+struct State {
+    /// Format version, bumped whenever the layout changes
+    version: u32,
+    /// Every bound variable and its (unevaluated) expression
+    vars: HashMap<char, Expr>,
+}
+
+/// Saved state that couldn't be restored
+#[derive(Debug)]
+pub enum StateError {
+    /// The JSON was malformed or didn't describe an evaluator
+    Json(serde_json::Error),
+    /// The state was saved in a format version this build doesn't understand
+    UnsupportedVersion(u32),
+}
+
+impl Display for StateError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Json(err) => write!(f, "Saved state is invalid: {err}"),
+            Self::UnsupportedVersion(version) => write!(
+                f,
+                "Saved state has version {version}, only version {STATE_VERSION} is supported"
+            ),
+        }
+    }
+}
+
+impl Error for StateError {}
+
+impl From<serde_json::Error> for StateError {
+    fn from(err: serde_json::Error) -> Self {
+        Self::Json(err)
+    }
+}
+
+impl Evaluator {
+    /// Saves every bound variable as JSON, keeping expressions unevaluated so dependencies
+    /// between variables survive a restore
+    pub fn to_json(&self) -> String {
+        let state = State {
+            version: STATE_VERSION,
+            vars: self
+                .ctx
+                .iter()
+                .map(|(var, expr)| (*var, expr.as_ref().clone()))
+                .collect(),
+        };
+
+        serde_json::to_string(&state).expect("Expressions always serialize")
+    }
+
+    /// Restores an evaluator saved with [`Evaluator::to_json`]
+    pub fn from_json(json: &str) -> Result<Evaluator, StateError> {
+        let state: State = serde_json::from_str(json)?;
+        if state.version != STATE_VERSION {
+            return Err(StateError::UnsupportedVersion(state.version));
+        }
+
+        let mut eval = Evaluator::default();
+        for (var, expr) in state.vars {
+            eval.set(var, expr);
+        }
+        Ok(eval)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        exec::{EvalResult, Evaluator},
+        pipeline::testing::run,
+    };
+
+    use super::StateError;
+
+    #[test]
+    fn round_trip() {
+        let mut eval = Evaluator::default();
+        run(&mut eval, "x = 2").expect("Execute");
+        run(&mut eval, "y = 3x + 5").expect("Execute");
+        run(&mut eval, "r = 2.5 * |0 - 2|").expect("Execute");
+        run(&mut eval, "b = x >= 2 && y != 1").expect("Execute");
+
+        let mut restored = Evaluator::from_json(&eval.to_json()).expect("Restore");
+        assert_eq!(restored.vars(), eval.vars());
+
+        assert_eq!(
+            run(&mut restored, "r").expect("Execute"),
+            EvalResult::Float(5.0)
+        );
+        assert_eq!(
+            run(&mut restored, "b").expect("Execute"),
+            EvalResult::Bool(true)
+        );
+
+        run(&mut restored, "x = 4").expect("Execute");
+        assert_eq!(
+            run(&mut restored, "y").expect("Execute"),
+            EvalResult::Integer(17)
+        );
+    }
+
+    #[test]
+    fn rejects_bad_state() {
+        assert!(matches!(
+            Evaluator::from_json("not json"),
+            Err(StateError::Json(_))
+        ));
+        assert!(matches!(
+            Evaluator::from_json(r#"{"version":99,"vars":{}}"#),
+            Err(StateError::UnsupportedVersion(99))
+        ));
+    }
+}
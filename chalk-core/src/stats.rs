@@ -0,0 +1,197 @@
+//! Statistics over a list of numbers, like `mean([1, 2, 3])`, or over every argument given, like
+//! `median(4, 1, 3)`
+
+use std::cmp::Ordering;
+
+use crate::{
+    ast::BinaryOperator,
+    exec::{EvalResult, RuntimeError},
+};
+
+/// The numbers a statistic is taken over, the items of a lone list argument or else every
+/// argument. Lists nested inside and bools are rejected
+pub fn values(args: &[EvalResult]) -> Result<&[EvalResult], RuntimeError> {
+    let values = match args {
+        [EvalResult::List(items)] => items.as_slice(),
+        _ => args,
+    };
+
+    if values
+        .iter()
+        .any(|value| matches!(value, EvalResult::List(_) | EvalResult::Bool(_)))
+    {
+        return Err(RuntimeError::Invalid);
+    }
+    Ok(values)
+}
+
+/// The total of the values, 0 if there are none. Stays exact like `+` does
+pub fn sum(values: &[EvalResult]) -> Result<EvalResult, RuntimeError> {
+    values
+        .iter()
+        .try_fold(EvalResult::Integer(0), |total, value| {
+            BinaryOperator::Add.eval(total, value.clone())
+        })
+}
+
+/// The average of the values
+pub fn mean(values: &[EvalResult]) -> Result<EvalResult, RuntimeError> {
+    if values.is_empty() {
+        return Err(RuntimeError::Invalid);
+    }
+    BinaryOperator::Divide.eval(sum(values)?, len(values))
+}
+
+/// The middle value once sorted, or the mean of the middle two if there's an even number
+pub fn median(values: &[EvalResult]) -> Result<EvalResult, RuntimeError> {
+    let mut sorted = values.to_vec();
+    let mut incomparable = false;
+    sorted.sort_by(|a, b| {
+        a.compare(b).ok().flatten().unwrap_or_else(|| {
+            incomparable = true;
+            Ordering::Equal
+        })
+    });
+    if incomparable {
+        return Err(RuntimeError::Invalid);
+    }
+
+    let middle = sorted.len() / 2;
+    match sorted.len() {
+        0 => Err(RuntimeError::Invalid),
+        len if len % 2 == 1 => Ok(sorted.swap_remove(middle)),
+        _ => mean(&sorted[middle - 1..=middle]),
+    }
+}
+
+/// The smallest value
+pub fn min(values: &[EvalResult]) -> Result<EvalResult, RuntimeError> {
+    extreme(values, Ordering::Less)
+}
+
+/// The largest value
+pub fn max(values: &[EvalResult]) -> Result<EvalResult, RuntimeError> {
+    extreme(values, Ordering::Greater)
+}
+
+/// The first value ordered `wanted` against every other value it isn't equal to
+fn extreme(values: &[EvalResult], wanted: Ordering) -> Result<EvalResult, RuntimeError> {
+    let (first, rest) = values.split_first().ok_or(RuntimeError::Invalid)?;
+    rest.iter()
+        .try_fold(first.clone(), |best, value| match value.compare(&best)? {
+            Some(ordering) if ordering == wanted => Ok(value.clone()),
+            Some(_) => Ok(best),
+            None => Err(RuntimeError::Invalid),
+        })
+}
+
+/// The sample standard deviation, which needs at least two values
+pub fn stdev(values: &[EvalResult]) -> Result<EvalResult, RuntimeError> {
+    if values.len() < 2 {
+        return Err(RuntimeError::Invalid);
+    }
+
+    let values = values
+        .iter()
+        .map(EvalResult::float)
+        .collect::<Result<Vec<_>, _>>()?;
+    let n = values.len() as f64;
+    let mean = values.iter().sum::<f64>() / n;
+    let squares: f64 = values.iter().map(|value| (value - mean).powi(2)).sum();
+
+    Ok(EvalResult::Float((squares / (n - 1.0)).sqrt()))
+}
+
+/// How many values there are
+pub fn len(values: &[EvalResult]) -> EvalResult {
+    EvalResult::Integer(values.len() as i64)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        exec::{EvalResult, Evaluator, RuntimeError},
+        pipeline::testing::run,
+    };
+
+    #[test]
+    fn statistics() {
+        let mut eval = Evaluator::default();
+
+        for (expression, expected) in [
+            ("sum(1, 2, 3, 4)", EvalResult::Integer(10)),
+            ("sum([1, 2, 3, 4])", EvalResult::Integer(10)),
+            ("sum([1/2, 1/3])", EvalResult::Rational(5, 6)),
+            ("sum([])", EvalResult::Integer(0)),
+            ("sum(i, 1, 4, i)", EvalResult::Integer(10)),
+            ("mean(1, 2, 3, 4)", EvalResult::Rational(5, 2)),
+            ("mean([-3, 3, 6])", EvalResult::Integer(2)),
+            ("mean(7)", EvalResult::Integer(7)),
+            ("median(5, 1, 3)", EvalResult::Integer(3)),
+            ("median([4, 1, 3, 2])", EvalResult::Rational(5, 2)),
+            ("median(-1, -5)", EvalResult::Integer(-3)),
+            ("min(3, -2, 2.5)", EvalResult::Integer(-2)),
+            ("max([3, -2, 2.5])", EvalResult::Integer(3)),
+            ("max(2, 2.5)", EvalResult::Float(2.5)),
+            ("max(1/3, 0.3)", EvalResult::Rational(1, 3)),
+            (
+                "stdev(2, 4, 4, 4, 5, 5, 7, 9)",
+                EvalResult::Float((32f64 / 7.0).sqrt()),
+            ),
+            ("stdev([-1, 1])", EvalResult::Float(2f64.sqrt())),
+            ("len([1, 2, 3])", EvalResult::Integer(3)),
+            ("len(1, 2)", EvalResult::Integer(2)),
+            ("len([])", EvalResult::Integer(0)),
+        ] {
+            assert_eq!(run(&mut eval, expression), Ok(expected), "{expression}");
+        }
+    }
+
+    #[test]
+    fn rejected_inputs() {
+        let mut eval = Evaluator::default();
+
+        for expression in [
+            "mean([])",
+            "median([])",
+            "min([])",
+            "max([])",
+            "stdev([])",
+            "stdev([1])",
+            "sum([1, [2, 3]])",
+            "mean([1, 2], 3)",
+            "len([[1]])",
+            "max(1, true)",
+        ] {
+            assert_eq!(
+                run(&mut eval, expression),
+                Err(RuntimeError::Invalid),
+                "{expression}"
+            );
+        }
+
+        // NaN only gets this far when it's allowed
+        eval.allow_non_finite(true);
+        assert_eq!(
+            run(&mut eval, "median(0/0 * 1.0, 1)"),
+            Err(RuntimeError::Invalid)
+        );
+    }
+
+    #[test]
+    fn lists_in_variables() {
+        let mut eval = Evaluator::default();
+
+        assert_eq!(
+            run(&mut eval, "x = [3, 1, 2]").map(|list| list.to_string()),
+            Ok("[3, 1, 2]".to_string())
+        );
+        assert_eq!(run(&mut eval, "median(x)"), Ok(EvalResult::Integer(2)));
+        assert_eq!(
+            run(&mut eval, "sum(x) + len(x)"),
+            Ok(EvalResult::Integer(9))
+        );
+        // A list variable among other arguments is nested, not spread
+        assert_eq!(run(&mut eval, "max(x, 4)"), Err(RuntimeError::Invalid));
+    }
+}
@@ -0,0 +1,177 @@
+//! Autocompletion of the names an expression can use, for editors and the REPL
+
+use std::fmt::Display;
+
+use crate::{ast::Constant, exec::Evaluator, functions};
+
+/// What a suggested name refers to
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize),
+    serde(rename_all = "lowercase")
+)]
+pub enum SuggestionKind {
+    /// A currently bound variable
+    Variable,
+    /// A named constant like `pi`
+    Constant,
+    /// A function called with arguments
+    Function,
+}
+
+/// A name that could complete what's been typed so far
+#[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub struct Suggestion {
+    /// The complete name
+    pub name: String,
+    /// What the name refers to
+    pub kind: SuggestionKind,
+    /// How many arguments it takes, 0 for anything that isn't a function
+    pub arity: usize,
+    /// Names for its arguments, only used for display
+    pub params: &'static [&'static str],
+}
+
+impl Display for Suggestion {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self.kind {
+            SuggestionKind::Function => write!(f, "{}({})", self.name, self.params.join(", ")),
+            SuggestionKind::Variable | SuggestionKind::Constant => write!(f, "{}", self.name),
+        }
+    }
+}
+
+/// Every function, constant and bound variable whose name starts with `prefix`, ignoring case.
+/// Names matching the prefix's case exactly come first, then variables before constants before
+/// functions, then alphabetically. An empty prefix suggests everything
+pub fn suggest(prefix: &str, evaluator: &Evaluator) -> Vec<Suggestion> {
+    let named = |name: String, kind| Suggestion {
+        name,
+        kind,
+        arity: 0,
+        params: &[],
+    };
+
+    let variables = evaluator
+        .vars()
+        .into_iter()
+        .map(|(var, _)| named(var.to_string(), SuggestionKind::Variable));
+    let constants = Constant::ALL
+        .iter()
+        .map(|constant| named(constant.to_string(), SuggestionKind::Constant));
+    let functions = functions::all().flat_map(|signature| {
+        std::iter::once(&signature.name)
+            .chain(signature.aliases)
+            .map(|name| Suggestion {
+                name: name.to_string(),
+                kind: SuggestionKind::Function,
+                arity: signature.arity(),
+                params: signature.params,
+            })
+    });
+
+    let mut suggestions: Vec<_> = variables
+        .chain(constants)
+        .chain(functions)
+        .filter(|suggestion| starts_with_ignoring_case(&suggestion.name, prefix))
+        .collect();
+    suggestions.sort_by_cached_key(|suggestion| {
+        let exact = suggestion.name.starts_with(prefix);
+        (!exact, suggestion.kind, suggestion.name.clone())
+    });
+
+    suggestions
+}
+
+/// Whether `name` starts with `prefix`, ignoring case
+fn starts_with_ignoring_case(name: &str, prefix: &str) -> bool {
+    let mut name = name.chars().flat_map(char::to_lowercase);
+    prefix
+        .chars()
+        .flat_map(char::to_lowercase)
+        .all(|c| name.next() == Some(c))
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{ast::Expr, exec::Evaluator, functions};
+
+    use super::{SuggestionKind, suggest};
+
+    /// An evaluator with a few variables bound
+    fn evaluator() -> Evaluator {
+        let mut eval = Evaluator::default();
+        for var in ['s', 'S', 'x', 'p'] {
+            eval.set(var, Expr::Integer(1));
+        }
+        eval
+    }
+
+    /// The displayed suggestions for a prefix
+    fn shown(prefix: &str) -> Vec<String> {
+        suggest(prefix, &evaluator())
+            .iter()
+            .map(ToString::to_string)
+            .collect()
+    }
+
+    #[test]
+    fn prefix_matching() {
+        assert_eq!(shown("gc"), ["gcd(a, b)"]);
+        assert_eq!(shown("GC"), ["gcd(a, b)"]);
+        assert_eq!(
+            shown("p"),
+            [
+                "p",
+                "pi",
+                "pctchange(old, new)",
+                "pctof(part, whole)",
+                "piecewise(cond, value, more...)",
+                "pow(a, b)",
+                "powmod(b, e, m)",
+                "primecount(n)"
+            ]
+        );
+        assert_eq!(
+            shown("su"),
+            [
+                "sub(a, b)",
+                "subtract(a, b)",
+                "sum(xs...)",
+                "sumdivisors(n)"
+            ]
+        );
+        assert_eq!(
+            shown("s")[..3],
+            ["s", "shl(a, n)", "shr(a, n)"],
+            "exact case first"
+        );
+        assert_eq!(shown("S")[..2], ["S", "s"]);
+        assert!(shown("frob").is_empty());
+
+        let atan2 = suggest("atan2", &evaluator());
+        assert_eq!(atan2.len(), 1);
+        assert_eq!(atan2[0].kind, SuggestionKind::Function);
+        assert_eq!(atan2[0].arity, 2);
+    }
+
+    #[test]
+    fn empty_prefix_suggests_everything() {
+        let names = functions::all()
+            .map(|signature| 1 + signature.aliases.len())
+            .sum::<usize>();
+        let everything = suggest("", &evaluator());
+
+        assert_eq!(everything.len(), 4 + 1 + names);
+        let variables: Vec<_> = everything
+            .iter()
+            .take_while(|suggestion| suggestion.kind == SuggestionKind::Variable)
+            .map(|suggestion| suggestion.name.as_str())
+            .collect();
+        assert_eq!(variables, ["S", "p", "s", "x"]);
+        assert_eq!(everything[4].name, "pi");
+        assert_eq!(suggest("", &Evaluator::default()).len(), 1 + names);
+    }
+}
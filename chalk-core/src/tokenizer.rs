@@ -1,20 +1,31 @@
 //! Raw tokenizer
 
-use std::{error::Error, fmt::Display};
+use std::{
+    borrow::Cow,
+    error::Error,
+    fmt::Display,
+    iter::{Enumerate, Peekable},
+    ops::Range,
+    str::CharIndices,
+};
+
+use crate::units::Unit;
 
 /// A token
 #[derive(Clone, Copy, PartialEq, Debug)]
 pub enum Token<'a> {
     /// An integer
-    Integer(i32),
+    Integer(i64),
     /// A single character variable
     Variable(char),
     /// An arbitrary identifier
     Ident(&'a str),
     /// A floating point number
-    Real(f32),
+    Real(f64),
     /// A boolean
     Bool(bool),
+    /// A unit written after a number, like `km` in `3km`
+    Unit(&'a str),
     /// Multiplication sign
     Multiply,
     /// Division sign
@@ -29,19 +40,37 @@ pub enum Token<'a> {
     OpenParen,
     /// Closing parenthesis
     CloseParen,
+    /// Open square bracket, starting a list
+    OpenBracket,
+    /// Closing square bracket
+    CloseBracket,
     /// Exclamation mark !
     Exclamation,
+    /// Degree sign °
+    Degree,
+    /// Percent sign %
+    Percent,
     /// Bar |
     Bar,
     /// Comma
     Comma,
     /// Assignment operator "="
     Assign,
+    /// Adding assignment "+="
+    PlusAssign,
+    /// Subtracting assignment "-="
+    MinusAssign,
+    /// Multiplying assignment "*="
+    MultiplyAssign,
+    /// Dividing assignment "/="
+    DivideAssign,
 
     /// Double equals "=="
     Eq,
     /// Not equals "!="
     NEq,
+    /// Approximately equals "~="
+    ApproxEq,
     /// Greater than ">"
     Gt,
     /// Greater than or equal to ">="
@@ -50,6 +79,12 @@ pub enum Token<'a> {
     Lt,
     /// Less than or equal to "<="
     Lte,
+    /// Shift left "<<"
+    ShiftLeft,
+    /// Shift right ">>"
+    ShiftRight,
+    /// Bitwise not "~"
+    Tilde,
 
     /// Logical AND &&
     And,
@@ -60,151 +95,601 @@ pub enum Token<'a> {
     EOF,
 }
 
+/// An owned copy of a [`Token`], for keeping tokens around without their source string
+#[derive(Clone, PartialEq, Debug)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize, serde::Deserialize),
+    serde(tag = "type", content = "value", rename_all = "snake_case")
+)]
+pub enum OwnedToken {
+    /// An integer
+    Integer(i64),
+    /// A single character variable
+    Variable(char),
+    /// An arbitrary identifier
+    Ident(String),
+    /// A floating point number
+    Real(f64),
+    /// A boolean
+    Bool(bool),
+    /// A unit written after a number, like `km` in `3km`
+    Unit(String),
+    /// Multiplication sign
+    Multiply,
+    /// Division sign
+    Divide,
+    /// Addition sign
+    Plus,
+    /// Subtraction sign
+    Minus,
+    /// Power caret
+    Caret,
+    /// Open parenthesis
+    OpenParen,
+    /// Closing parenthesis
+    CloseParen,
+    /// Open square bracket, starting a list
+    OpenBracket,
+    /// Closing square bracket
+    CloseBracket,
+    /// Exclamation mark !
+    Exclamation,
+    /// Degree sign °
+    Degree,
+    /// Percent sign %
+    Percent,
+    /// Bar |
+    Bar,
+    /// Comma
+    Comma,
+    /// Assignment operator "="
+    Assign,
+    /// Adding assignment "+="
+    PlusAssign,
+    /// Subtracting assignment "-="
+    MinusAssign,
+    /// Multiplying assignment "*="
+    MultiplyAssign,
+    /// Dividing assignment "/="
+    DivideAssign,
+    /// Double equals "=="
+    Eq,
+    /// Not equals "!="
+    NEq,
+    /// Approximately equals "~="
+    ApproxEq,
+    /// Greater than ">"
+    Gt,
+    /// Greater than or equal to ">="
+    Gte,
+    /// Less than "<"
+    Lt,
+    /// Less than or equal to "<="
+    Lte,
+    /// Shift left "<<"
+    ShiftLeft,
+    /// Shift right ">>"
+    ShiftRight,
+    /// Bitwise not "~"
+    Tilde,
+    /// Logical AND &&
+    And,
+    /// Logical OR ||
+    Or,
+    /// End Token
+    EOF,
+}
+
+impl Token<'_> {
+    /// Copies the token so it no longer borrows from its source
+    pub fn into_owned(self) -> OwnedToken {
+        match self {
+            Token::Integer(value) => OwnedToken::Integer(value),
+            Token::Variable(value) => OwnedToken::Variable(value),
+            Token::Ident(ident) => OwnedToken::Ident(ident.to_string()),
+            Token::Real(value) => OwnedToken::Real(value),
+            Token::Bool(value) => OwnedToken::Bool(value),
+            Token::Unit(unit) => OwnedToken::Unit(unit.to_string()),
+            Token::Multiply => OwnedToken::Multiply,
+            Token::Divide => OwnedToken::Divide,
+            Token::Plus => OwnedToken::Plus,
+            Token::Minus => OwnedToken::Minus,
+            Token::Caret => OwnedToken::Caret,
+            Token::OpenParen => OwnedToken::OpenParen,
+            Token::CloseParen => OwnedToken::CloseParen,
+            Token::OpenBracket => OwnedToken::OpenBracket,
+            Token::CloseBracket => OwnedToken::CloseBracket,
+            Token::Exclamation => OwnedToken::Exclamation,
+            Token::Degree => OwnedToken::Degree,
+            Token::Percent => OwnedToken::Percent,
+            Token::Bar => OwnedToken::Bar,
+            Token::Comma => OwnedToken::Comma,
+            Token::Assign => OwnedToken::Assign,
+            Token::PlusAssign => OwnedToken::PlusAssign,
+            Token::MinusAssign => OwnedToken::MinusAssign,
+            Token::MultiplyAssign => OwnedToken::MultiplyAssign,
+            Token::DivideAssign => OwnedToken::DivideAssign,
+            Token::Eq => OwnedToken::Eq,
+            Token::NEq => OwnedToken::NEq,
+            Token::ApproxEq => OwnedToken::ApproxEq,
+            Token::Gt => OwnedToken::Gt,
+            Token::Gte => OwnedToken::Gte,
+            Token::Lt => OwnedToken::Lt,
+            Token::Lte => OwnedToken::Lte,
+            Token::ShiftLeft => OwnedToken::ShiftLeft,
+            Token::ShiftRight => OwnedToken::ShiftRight,
+            Token::Tilde => OwnedToken::Tilde,
+            Token::And => OwnedToken::And,
+            Token::Or => OwnedToken::Or,
+            Token::EOF => OwnedToken::EOF,
+        }
+    }
+}
+
+impl OwnedToken {
+    /// Borrows the token back into the form the parser reads
+    pub fn as_token(&self) -> Token<'_> {
+        match self {
+            OwnedToken::Integer(value) => Token::Integer(*value),
+            OwnedToken::Variable(value) => Token::Variable(*value),
+            OwnedToken::Ident(ident) => Token::Ident(ident),
+            OwnedToken::Real(value) => Token::Real(*value),
+            OwnedToken::Bool(value) => Token::Bool(*value),
+            OwnedToken::Unit(unit) => Token::Unit(unit),
+            OwnedToken::Multiply => Token::Multiply,
+            OwnedToken::Divide => Token::Divide,
+            OwnedToken::Plus => Token::Plus,
+            OwnedToken::Minus => Token::Minus,
+            OwnedToken::Caret => Token::Caret,
+            OwnedToken::OpenParen => Token::OpenParen,
+            OwnedToken::CloseParen => Token::CloseParen,
+            OwnedToken::OpenBracket => Token::OpenBracket,
+            OwnedToken::CloseBracket => Token::CloseBracket,
+            OwnedToken::Exclamation => Token::Exclamation,
+            OwnedToken::Degree => Token::Degree,
+            OwnedToken::Percent => Token::Percent,
+            OwnedToken::Bar => Token::Bar,
+            OwnedToken::Comma => Token::Comma,
+            OwnedToken::Assign => Token::Assign,
+            OwnedToken::PlusAssign => Token::PlusAssign,
+            OwnedToken::MinusAssign => Token::MinusAssign,
+            OwnedToken::MultiplyAssign => Token::MultiplyAssign,
+            OwnedToken::DivideAssign => Token::DivideAssign,
+            OwnedToken::Eq => Token::Eq,
+            OwnedToken::NEq => Token::NEq,
+            OwnedToken::ApproxEq => Token::ApproxEq,
+            OwnedToken::Gt => Token::Gt,
+            OwnedToken::Gte => Token::Gte,
+            OwnedToken::Lt => Token::Lt,
+            OwnedToken::Lte => Token::Lte,
+            OwnedToken::ShiftLeft => Token::ShiftLeft,
+            OwnedToken::ShiftRight => Token::ShiftRight,
+            OwnedToken::Tilde => Token::Tilde,
+            OwnedToken::And => Token::And,
+            OwnedToken::Or => Token::Or,
+            OwnedToken::EOF => Token::EOF,
+        }
+    }
+}
+
+/// Options changing what the tokenizer accepts, all off by default
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct TokenizerOptions {
+    /// A character allowed between groups of digits in numbers, like `,` in `1,234,567`. Groups
+    /// after the first must be three digits long, and only the whole part of a number is grouped.
+    /// It's only taken as grouping when a digit follows, so `f(1, 2)` still has two arguments
+    /// while `f(1,2)` is malformed grouping
+    pub digit_group_char: Option<char>,
+    /// Reads `,` between digits as the decimal point, like `3,14`, for locales that write numbers
+    /// that way. Arguments and list items are separated by `;` instead, like `gcd(12; 18)`, and a
+    /// `,` anywhere else is invalid. It takes precedence over grouping digits with `,`
+    pub decimal_comma: bool,
+    /// Reads characters that sneak in when pasting from word processors as the ASCII they stand
+    /// for, like `–` and `−` as `-`, fullwidth digits and operators as their ASCII forms, and
+    /// non-breaking or thin spaces as spaces, while curly quotes are skipped. Every replacement
+    /// is one character for one, so positions still line up with the source. [`normalizations`]
+    /// reports what was replaced
+    pub lenient: bool,
+}
+
+/// A character the lenient tokenizer reads as something else
+#[derive(Clone, Copy, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub struct Normalization {
+    /// Character index in the source
+    pub position: usize,
+    /// The character as written
+    pub found: char,
+    /// What it's read as, a space for characters that are skipped
+    pub read_as: char,
+}
+
+/// What a pasted character is read as by the lenient tokenizer, a space for those it skips, or
+/// `None` if it's read as it is
+fn normalize(c: char) -> Option<char> {
+    match c {
+        // No-break, figure, thin, hair and other fixed width spaces, and zero width ones
+        '\u{00A0}' | '\u{2000}'..='\u{200B}' | '\u{202F}' | '\u{205F}' | '\u{3000}' => Some(' '),
+        // Curly quotes
+        '\u{2018}' | '\u{2019}' | '\u{201C}' | '\u{201D}' => Some(' '),
+        // Figure, en and em dashes, and the minus sign
+        '\u{2012}' | '\u{2013}' | '\u{2014}' | '\u{2212}' | '\u{FE63}' => Some('-'),
+        '×' => Some('*'),
+        // Fullwidth forms of ASCII digits and symbols, leaving letters for identifiers as written
+        '\u{FF01}'..='\u{FF5E}' => {
+            char::from_u32(c as u32 - 0xFEE0).filter(|ascii| !ascii.is_ascii_alphabetic())
+        }
+        _ => None,
+    }
+}
+
+/// Every character in `source` the lenient tokenizer reads as something else, in order, so
+/// callers can mention that pasted text was cleaned up
+pub fn normalizations(source: &str) -> Vec<Normalization> {
+    source
+        .chars()
+        .enumerate()
+        .filter_map(|(position, found)| {
+            normalize(found).map(|read_as| Normalization {
+                position,
+                found,
+                read_as,
+            })
+        })
+        .collect()
+}
+
 /// Trait for providing tokenization functionality for a struct
 pub trait Tokenizable {
     /// The error type on tokenization failure
     type Error;
+    /// Tokenize the current struct with options, pairing each token with the range of characters
+    /// it came from
+    fn tokenize_with(
+        &self,
+        options: TokenizerOptions,
+    ) -> Result<Vec<(Token<'_>, Range<usize>)>, Self::Error>;
+    /// Tokenize the current struct, pairing each token with the range of characters it came from.
+    /// Ranges are character indices rather than byte offsets, the same as error positions, so
+    /// `×` in `2 × x` spans `2..3` even though it's two bytes long
+    fn tokenize_spanned(&self) -> Result<Vec<(Token<'_>, Range<usize>)>, Self::Error> {
+        self.tokenize_with(TokenizerOptions::default())
+    }
     /// Tokenize the current struct
-    fn tokenize(&self) -> Result<Vec<Token<'_>>, Self::Error>;
+    fn tokenize(&self) -> Result<Vec<Token<'_>>, Self::Error> {
+        Ok(self
+            .tokenize_spanned()?
+            .into_iter()
+            .map(|(token, _)| token)
+            .collect())
+    }
 }
 
 /// Invalid token read while tokenizing
-#[derive(Debug)]
-pub struct InvalidToken;
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct InvalidToken {
+    /// Character index the invalid token starts at
+    pub position: usize,
+    /// The character that couldn't be tokenized
+    pub found: char,
+}
 
 impl Display for InvalidToken {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        write!(f, "Token was invalid :(")
+        write!(
+            f,
+            "Invalid character `{}` at position {} :(",
+            self.found, self.position
+        )
     }
 }
 
 impl Error for InvalidToken {}
 
-impl<STR> Tokenizable for STR
-where
-    STR: AsRef<str>,
-{
-    type Error = InvalidToken;
-    fn tokenize(&self) -> Result<Vec<Token<'_>>, Self::Error> {
-        let mut peek = self.as_ref().chars().enumerate().peekable();
-        let mut tokens = vec![];
+/// A lazy tokenizer, scanning only as far into the source as the tokens asked for. Each token is
+/// paired with the range of characters it came from, and the stream ends with `Token::EOF`. An
+/// invalid character ends the stream with an error, after every token before it
+pub struct TokenStream<'a> {
+    /// The source being tokenized
+    source: &'a str,
+    /// Characters that haven't been scanned yet, with their character and byte offsets
+    chars: Peekable<Enumerate<CharIndices<'a>>>,
+    /// Character offset just past the last character scanned
+    position: usize,
+    /// Byte offset just past the last character scanned
+    byte: usize,
+    /// Whether the end of the stream or an error has been yielded
+    finished: bool,
+    /// The last token yielded, deciding whether a word is a unit
+    last: Option<Token<'a>>,
+    /// Whether the last token was a `/` right after a unit, so `h` in `3km/h` is one too
+    divides_unit: bool,
+    /// What's accepted beyond the defaults
+    options: TokenizerOptions,
+}
 
-        while let Some((idx, c)) = peek.next() {
-            let token = match c {
-                '(' => Token::OpenParen,
-                ')' => Token::CloseParen,
-                '*' => Token::Multiply,
-                '/' | '÷' => Token::Divide,
-                '+' => Token::Plus,
-                '^' => Token::Caret,
-                ',' => Token::Comma,
-                '|' => match peek.peek() {
-                    Some((_, '|')) => {
-                        peek.next();
-                        Token::Or
-                    }
-                    _ => Token::Bar,
-                },
-                '!' => match peek.peek() {
-                    Some((_, '=')) => {
-                        peek.next();
-                        Token::NEq
-                    }
-                    _ => Token::Exclamation,
-                },
-                '&' => {
-                    if let Some((_, '&')) = peek.next() {
-                        Token::And
-                    } else {
-                        return Err(InvalidToken);
-                    }
-                }
-                '=' => match peek.peek() {
-                    Some((_, '=')) => {
-                        peek.next();
-                        Token::Eq
-                    }
-                    _ => Token::Assign,
-                },
+impl<'a> TokenStream<'a> {
+    /// Creates a stream over the tokens of `source`
+    pub fn new(source: &'a str) -> Self {
+        Self::with_options(source, TokenizerOptions::default())
+    }
 
-                '>' => match peek.peek() {
-                    Some((_, '=')) => {
-                        peek.next();
-                        Token::Gte
-                    }
-                    _ => Token::Gt,
-                },
+    /// Creates a stream over the tokens of `source`, tokenized with options
+    pub fn with_options(source: &'a str, options: TokenizerOptions) -> Self {
+        TokenStream {
+            source,
+            chars: source.char_indices().enumerate().peekable(),
+            position: 0,
+            byte: 0,
+            finished: false,
+            last: None,
+            divides_unit: false,
+            options,
+        }
+    }
 
-                '<' => match peek.peek() {
-                    Some((_, '=')) => {
-                        peek.next();
-                        Token::Lte
-                    }
-                    _ => Token::Lt,
-                },
+    /// A character as it's read, normalized if the tokenizer is lenient
+    fn read(&self, c: char) -> char {
+        match self.options.lenient {
+            true => normalize(c).unwrap_or(c),
+            false => c,
+        }
+    }
 
-                '-' => Token::Minus,
-                ws if ws.is_whitespace() => continue,
-                numeric if numeric.is_numeric() => {
-                    let mut curr = String::new();
-                    curr.push(numeric);
-
-                    let mut dot = false;
-                    while let Some((_, next)) = peek.peek() {
-                        if next.is_numeric() {
-                            curr.push(peek.next().unwrap().1);
-                        } else if *next == '.' && !dot {
-                            curr.push(peek.next().unwrap().1);
-                            dot = true;
-                        } else {
-                            break;
-                        }
-                    }
+    /// Peeks at the next character
+    fn peek(&mut self) -> Option<char> {
+        let c = self.chars.peek().map(|(_, (_, c))| *c)?;
+        Some(self.read(c))
+    }
 
-                    if curr.contains(".") {
-                        // Unwrap safety, as we build the number we are ensuring that only numeric
-                        // characters are added to it, this cannot fail
-                        Token::Real(curr.parse().unwrap())
-                    } else {
-                        // Unwrap safety, as we build the number we are ensuring that only numeric
-                        // characters are added to it, this cannot fail
-                        Token::Integer(curr.parse().unwrap())
-                    }
+    /// Scans the next character, returning it with its character offset
+    fn bump(&mut self) -> Option<(usize, char)> {
+        let (idx, (byte, c)) = self.chars.next()?;
+        self.position = idx + 1;
+        self.byte = byte + c.len_utf8();
+        Some((idx, self.read(c)))
+    }
+
+    /// Scans the next character if it's `expected`
+    fn bump_if(&mut self, expected: char) -> bool {
+        let matches = self.peek() == Some(expected);
+        if matches {
+            self.bump();
+        }
+        matches
+    }
+
+    /// Scans characters up to (but not including) the next newline
+    fn skip_comment(&mut self) {
+        while self.peek().is_some_and(|next| next != '\n') {
+            self.bump();
+        }
+    }
+
+    /// Whether the character after the next one is a digit
+    fn digit_after_next(&self) -> bool {
+        let mut ahead = self.chars.clone();
+        ahead.next();
+        ahead
+            .peek()
+            .is_some_and(|(_, (_, c))| self.read(*c).is_numeric())
+    }
+
+    /// Scans the rest of a number starting with `first`, which began at byte `start`. Digit
+    /// grouping that isn't well formed fails at the separator starting the bad group
+    fn number(&mut self, start: usize) -> Result<Token<'a>, InvalidToken> {
+        let mut dot = false;
+        // Digits since the last group separator, and where that separator was
+        let mut group_len = 1;
+        let mut separator: Option<(usize, char)> = None;
+        let malformed = |separator: Option<(usize, char)>, group_len: usize| match separator {
+            Some((position, found)) if group_len != 3 => Err(InvalidToken { position, found }),
+            _ => Ok(()),
+        };
+
+        while let Some(next) = self.peek() {
+            if next.is_numeric() {
+                group_len += 1;
+            } else if !dot
+                && (next == '.'
+                    || (next == ',' && self.options.decimal_comma && self.digit_after_next()))
+            {
+                malformed(separator, group_len)?;
+                dot = true;
+            } else if !dot && Some(next) == self.options.digit_group_char && self.digit_after_next()
+            {
+                if separator.is_none() && group_len > 3 {
+                    return Err(InvalidToken {
+                        position: self.position,
+                        found: next,
+                    });
                 }
+                malformed(separator, group_len)?;
+                separator = Some((self.position, next));
+                group_len = 0;
+            } else {
+                break;
+            }
+            self.bump();
+        }
+        if !dot {
+            malformed(separator, group_len)?;
+        }
 
-                character if character.is_alphabetic() => {
-                    let mut end = idx;
+        // Most numbers are written just as they're read, so they're parsed straight from the
+        // source. Others drop their group separators and have their digits normalized and any
+        // decimal comma made a point first
+        let written = &self.source[start..self.byte];
+        let digits = match written.bytes().all(|b| b.is_ascii_digit() || b == b'.') {
+            true => Cow::Borrowed(written),
+            false => Cow::Owned(
+                written
+                    .chars()
+                    .map(|c| self.read(c))
+                    .filter_map(|c| match c {
+                        '.' => Some('.'),
+                        ',' if self.options.decimal_comma => Some('.'),
+                        c if c.is_numeric() => Some(c),
+                        _ => None,
+                    })
+                    .collect(),
+            ),
+        };
 
-                    while let Some((idx2, next)) = peek.peek() {
-                        if !next.is_alphabetic() {
-                            break;
-                        }
+        if dot {
+            // Unwrap safety, as we scan the number we are ensuring that only numeric
+            // characters and one point are in it, this cannot fail
+            Ok(Token::Real(digits.parse().unwrap()))
+        } else {
+            // Integers too large to fit are promoted to reals. Unwrap safety, as we
+            // scan the number we are ensuring that only numeric characters are in it,
+            // parsing as a real cannot fail
+            Ok(digits
+                .parse()
+                .map(Token::Integer)
+                .unwrap_or_else(|_| Token::Real(digits.parse().unwrap())))
+        }
+    }
 
-                        end = *idx2;
-                        peek.next();
-                    }
+    /// Whether a word here is a unit rather than a variable or function, which it is right after
+    /// a number (with or without a space) and after a `/` following a unit. Only with the `units`
+    /// feature, otherwise `2m` is always `2 * m`
+    fn unit_follows(&self) -> bool {
+        cfg!(feature = "units")
+            && match self.last {
+                Some(Token::Integer(_) | Token::Real(_)) => true,
+                Some(Token::Divide) => self.divides_unit,
+                _ => false,
+            }
+    }
+
+    /// Scans the rest of a word starting with `first`, which began at byte `start`
+    fn word(&mut self, first: char, start: usize) -> Token<'a> {
+        let mut letters = 1;
+
+        // Digits can end a word of two or more letters, like `atan2`, but a single
+        // letter followed by digits is still a variable times a number
+        while let Some(next) = self.peek() {
+            let continues = next.is_alphabetic() || (letters > 1 && next.is_ascii_digit());
+            if !continues {
+                break;
+            }
+
+            letters += 1;
+            self.bump();
+        }
+
+        let word = &self.source[start..self.byte];
+        if word == "true" {
+            Token::Bool(true)
+        } else if word == "false" {
+            Token::Bool(false)
+        } else if self.unit_follows() && Unit::parse(word).is_some() {
+            Token::Unit(word)
+        } else if letters == 1 {
+            Token::Variable(first)
+        } else {
+            Token::Ident(word)
+        }
+    }
+}
+
+impl<'a> Iterator for TokenStream<'a> {
+    type Item = Result<(Token<'a>, Range<usize>), InvalidToken>;
 
-                    let word = &self.as_ref()[idx..=end];
-                    if word == "true" {
-                        Token::Bool(true)
-                    } else if word == "false" {
-                        Token::Bool(false)
-                    } else {
-                        if word.len() == 1 {
-                            Token::Variable(word.chars().nth(0).unwrap())
-                        } else {
-                            Token::Ident(word)
-                        }
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.finished {
+            return None;
+        }
+
+        loop {
+            let start = self.byte;
+            let Some((idx, c)) = self.bump() else {
+                self.finished = true;
+                return Some(Ok((Token::EOF, self.position..self.position)));
+            };
+
+            let token = match c {
+                '(' => Token::OpenParen,
+                ')' => Token::CloseParen,
+                '[' => Token::OpenBracket,
+                ']' => Token::CloseBracket,
+                '*' if self.bump_if('=') => Token::MultiplyAssign,
+                '*' => Token::Multiply,
+                // Comments run until the end of the current line
+                '#' => {
+                    self.skip_comment();
+                    continue;
+                }
+                '/' if self.peek() == Some('/') => {
+                    self.skip_comment();
+                    continue;
+                }
+                '/' if self.bump_if('=') => Token::DivideAssign,
+                '/' | '÷' => Token::Divide,
+                '+' if self.bump_if('=') => Token::PlusAssign,
+                '+' => Token::Plus,
+                '^' => Token::Caret,
+                ',' if !self.options.decimal_comma => Token::Comma,
+                // With decimal commas, `;` separates arguments in place of a comma
+                ';' if self.options.decimal_comma => Token::Comma,
+                '°' => Token::Degree,
+                '%' => Token::Percent,
+                '|' if self.bump_if('|') => Token::Or,
+                '|' => Token::Bar,
+                '!' if self.bump_if('=') => Token::NEq,
+                '!' => Token::Exclamation,
+                // A lone `&` isn't an operator, and neither is whatever follows it
+                '&' if self.bump().is_some_and(|(_, next)| next == '&') => Token::And,
+                '=' if self.bump_if('=') => Token::Eq,
+                '=' => Token::Assign,
+                '>' if self.bump_if('=') => Token::Gte,
+                '>' if self.bump_if('>') => Token::ShiftRight,
+                '>' => Token::Gt,
+                '<' if self.bump_if('=') => Token::Lte,
+                '<' if self.bump_if('<') => Token::ShiftLeft,
+                '<' => Token::Lt,
+                '~' if self.bump_if('=') => Token::ApproxEq,
+                '~' => Token::Tilde,
+                '-' if self.bump_if('=') => Token::MinusAssign,
+                '-' => Token::Minus,
+                ws if ws.is_whitespace() => continue,
+                numeric if numeric.is_numeric() => match self.number(start) {
+                    Ok(token) => token,
+                    Err(err) => {
+                        self.finished = true;
+                        return Some(Err(err));
                     }
+                },
+                character if character.is_alphabetic() => self.word(character, start),
+                _ => {
+                    self.finished = true;
+                    return Some(Err(InvalidToken {
+                        position: idx,
+                        found: c,
+                    }));
                 }
-                _ => return Err(InvalidToken),
             };
 
-            tokens.push(token);
+            self.divides_unit = token == Token::Divide && matches!(self.last, Some(Token::Unit(_)));
+            self.last = Some(token);
+
+            return Some(Ok((token, idx..self.position)));
         }
+    }
+}
 
-        tokens.push(Token::EOF);
-        Ok(tokens)
+impl<STR> Tokenizable for STR
+where
+    STR: AsRef<str> + ?Sized,
+{
+    type Error = InvalidToken;
+    fn tokenize_with(
+        &self,
+        options: TokenizerOptions,
+    ) -> Result<Vec<(Token<'_>, Range<usize>)>, Self::Error> {
+        TokenStream::with_options(self.as_ref(), options).collect()
     }
 }
 
@@ -212,7 +697,10 @@ where
 mod tests {
     use crate::tokenizer::Token;
 
-    use super::Tokenizable;
+    use super::{
+        InvalidToken, Normalization, OwnedToken, TokenStream, Tokenizable, TokenizerOptions,
+        normalizations,
+    };
 
     #[test]
     fn tokenization() {
@@ -232,6 +720,7 @@ mod tests {
     }
 
     #[test]
+    #[allow(clippy::approx_constant)]
     fn tokenize_real_numbers() {
         let tokens = "3.1415".tokenize().expect("Tokenize statement");
 
@@ -252,6 +741,29 @@ mod tests {
         assert_eq!(tokens, [Token::NEq, Token::EOF])
     }
 
+    #[test]
+    fn tokenize_compound_assignment() {
+        let tokens = "x += 1 -= 2 *= 3 /= 4 // note"
+            .tokenize()
+            .expect("Tokenize statement");
+
+        assert_eq!(
+            tokens,
+            [
+                Token::Variable('x'),
+                Token::PlusAssign,
+                Token::Integer(1),
+                Token::MinusAssign,
+                Token::Integer(2),
+                Token::MultiplyAssign,
+                Token::Integer(3),
+                Token::DivideAssign,
+                Token::Integer(4),
+                Token::EOF
+            ]
+        )
+    }
+
     #[test]
     fn tokenize_with_whitespace() {
         let tokens = " 1024              /           1.23 "
@@ -316,6 +828,22 @@ mod tests {
         assert_eq!(tokens, expected)
     }
 
+    #[test]
+    fn identifiers_with_digits() {
+        let tokens = "atan2(x2)".tokenize().expect("Tokenize");
+        assert_eq!(
+            tokens,
+            [
+                Token::Ident("atan2"),
+                Token::OpenParen,
+                Token::Variable('x'),
+                Token::Integer(2),
+                Token::CloseParen,
+                Token::EOF
+            ]
+        );
+    }
+
     #[test]
     fn tokenize_larger_numbers() {
         let tokens = "1024".tokenize().expect("Tokenize statement");
@@ -342,6 +870,57 @@ mod tests {
         )
     }
 
+    #[test]
+    fn tokenize_approx_eq() {
+        let tokens = "1~=~2".tokenize().expect("Tokenize statement");
+
+        assert_eq!(
+            tokens,
+            [
+                Token::Integer(1),
+                Token::ApproxEq,
+                Token::Tilde,
+                Token::Integer(2),
+                Token::EOF
+            ]
+        )
+    }
+
+    #[test]
+    fn tokenize_shifts() {
+        let tokens = "~1<<2>>x".tokenize().expect("Tokenize statement");
+
+        assert_eq!(
+            tokens,
+            [
+                Token::Tilde,
+                Token::Integer(1),
+                Token::ShiftLeft,
+                Token::Integer(2),
+                Token::ShiftRight,
+                Token::Variable('x'),
+                Token::EOF
+            ]
+        )
+    }
+
+    #[test]
+    fn tokenize_list() {
+        let tokens = "[1, x]".tokenize().expect("Tokenize statement");
+
+        assert_eq!(
+            tokens,
+            [
+                Token::OpenBracket,
+                Token::Integer(1),
+                Token::Comma,
+                Token::Variable('x'),
+                Token::CloseBracket,
+                Token::EOF
+            ]
+        )
+    }
+
     #[test]
     fn tokenize_lt() {
         let tokens = "1<2".tokenize().expect("Tokenize statement");
@@ -368,6 +947,83 @@ mod tests {
         assert!(tokens.is_err())
     }
 
+    #[test]
+    fn invalid_token_position() {
+        let err = "3 $ 4".tokenize().expect_err("$ is invalid");
+
+        assert_eq!(
+            err,
+            InvalidToken {
+                position: 2,
+                found: '$'
+            }
+        )
+    }
+
+    #[test]
+    fn owned_tokens() {
+        let tokens = "gcd(x, 2.5)".tokenize().expect("Tokenize");
+        let owned: Vec<_> = tokens.iter().map(|token| token.into_owned()).collect();
+
+        assert_eq!(owned[0], OwnedToken::Ident("gcd".to_string()));
+        assert_eq!(
+            owned.iter().map(OwnedToken::as_token).collect::<Vec<_>>(),
+            tokens
+        );
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn serde_round_trip() {
+        let tokens: Vec<_> = "x = gcd(12, 3.5) >= 2 && true"
+            .tokenize()
+            .expect("Tokenize")
+            .into_iter()
+            .map(Token::into_owned)
+            .collect();
+
+        let json = serde_json::to_string(&tokens).expect("Serialize");
+        let restored: Vec<OwnedToken> = serde_json::from_str(&json).expect("Deserialize");
+        assert_eq!(restored, tokens);
+
+        assert_eq!(
+            serde_json::to_string(&tokens[..2]).expect("Serialize"),
+            r#"[{"type":"variable","value":"x"},{"type":"assign"}]"#
+        );
+    }
+
+    #[test]
+    fn multibyte_characters() {
+        let tokens = "90°+sin(1)".tokenize().expect("Tokenize");
+        assert_eq!(tokens[3], Token::Ident("sin"));
+
+        let tokens = "aé ü".tokenize().expect("Tokenize");
+        assert_eq!(
+            tokens,
+            [Token::Ident("aé"), Token::Variable('ü'), Token::EOF]
+        );
+    }
+
+    #[test]
+    fn oversized_integers_become_reals() {
+        let tokens = "99999999999".tokenize().expect("Tokenize");
+        assert_eq!(tokens[0], Token::Integer(99_999_999_999));
+
+        let tokens = "99999999999999999999".tokenize().expect("Tokenize");
+        assert_eq!(tokens[0], Token::Real(1e20));
+    }
+
+    #[test]
+    fn spans() {
+        let tokens = "gcd(12, x) >= 3.5".tokenize_spanned().expect("Tokenize");
+        let spans: Vec<_> = tokens.into_iter().map(|(_, span)| span).collect();
+
+        assert_eq!(
+            spans,
+            [0..3, 3..4, 4..6, 6..7, 8..9, 9..10, 11..13, 14..17, 17..17]
+        )
+    }
+
     #[test]
     fn variables() {
         let tokens = "x".tokenize().expect("Tokenize");
@@ -375,6 +1031,112 @@ mod tests {
         assert_eq!(tokens, [Token::Variable('x'), Token::EOF])
     }
 
+    #[test]
+    fn trailing_comment() {
+        let tokens = "x = 9.81 # gravity".tokenize().expect("Tokenize");
+
+        assert_eq!(
+            tokens,
+            [
+                Token::Variable('x'),
+                Token::Assign,
+                Token::Real(9.81),
+                Token::EOF
+            ]
+        )
+    }
+
+    #[test]
+    fn slash_comment() {
+        let tokens = "4 / 2 // halve it".tokenize().expect("Tokenize");
+
+        assert_eq!(
+            tokens,
+            [
+                Token::Integer(4),
+                Token::Divide,
+                Token::Integer(2),
+                Token::EOF
+            ]
+        )
+    }
+
+    #[test]
+    fn comment_only() {
+        let tokens = "# just a note, with 1 + 1 and $ inside"
+            .tokenize()
+            .expect("Tokenize");
+
+        assert_eq!(tokens, [Token::EOF])
+    }
+
+    #[test]
+    fn comment_ends_at_newline() {
+        let tokens = "1 # one\n+ 2".tokenize().expect("Tokenize");
+
+        assert_eq!(
+            tokens,
+            [
+                Token::Integer(1),
+                Token::Plus,
+                Token::Integer(2),
+                Token::EOF
+            ]
+        )
+    }
+
+    #[test]
+    fn postfix_units() {
+        let tokens = "30° + 20%".tokenize().expect("Tokenize");
+
+        assert_eq!(
+            tokens,
+            [
+                Token::Integer(30),
+                Token::Degree,
+                Token::Plus,
+                Token::Integer(20),
+                Token::Percent,
+                Token::EOF
+            ]
+        )
+    }
+
+    #[cfg(feature = "units")]
+    #[test]
+    fn unit_suffixes() {
+        let tokens = "3km/h + 2 s - x m".tokenize().expect("Tokenize");
+
+        assert_eq!(
+            tokens,
+            [
+                Token::Integer(3),
+                Token::Unit("km"),
+                Token::Divide,
+                Token::Unit("h"),
+                Token::Plus,
+                Token::Integer(2),
+                Token::Unit("s"),
+                Token::Minus,
+                Token::Variable('x'),
+                Token::Variable('m'),
+                Token::EOF
+            ]
+        );
+        assert_eq!(
+            "2sin(x) in km".tokenize().expect("Tokenize")[1..],
+            [
+                Token::Ident("sin"),
+                Token::OpenParen,
+                Token::Variable('x'),
+                Token::CloseParen,
+                Token::Ident("in"),
+                Token::Ident("km"),
+                Token::EOF
+            ]
+        );
+    }
+
     #[test]
     fn go_crazy() {
         let tokens = "((360 * 9.2) / 0.25) - (5 + 5.0)"
@@ -403,4 +1165,272 @@ mod tests {
             ]
         )
     }
+
+    #[test]
+    fn streaming_matches_collecting() {
+        let inputs = [
+            "(1+1)",
+            "3.125",
+            "==",
+            "!=",
+            " 1024              /           1.23 ",
+            "hello these are many identifiers and 1 2 3 numbers",
+            "atan2(x2)",
+            "1<=2 1>=2 1<2 1>2",
+            "gcd(12, x) >= 3.5",
+            "x = 9.81 # gravity",
+            "4 / 2 // halve it",
+            "90°+sin(1) aé ü",
+            "99999999999999999999",
+            "|x| || y && true != false",
+            "",
+        ];
+
+        for input in inputs {
+            let streamed: Vec<_> = TokenStream::new(input)
+                .map(|token| token.expect("Tokenize").0)
+                .collect();
+            assert_eq!(streamed, input.tokenize().expect("Tokenize"), "{input}");
+        }
+    }
+
+    #[test]
+    fn streaming_is_lazy() {
+        let mut stream = TokenStream::new("1 + x $ 2");
+        assert_eq!(stream.next(), Some(Ok((Token::Integer(1), 0..1))));
+        assert_eq!(stream.next(), Some(Ok((Token::Plus, 2..3))));
+        assert_eq!(stream.next(), Some(Ok((Token::Variable('x'), 4..5))));
+        assert_eq!(
+            stream.next(),
+            Some(Err(InvalidToken {
+                position: 6,
+                found: '$'
+            }))
+        );
+        assert_eq!(stream.next(), None);
+
+        let mut stream = TokenStream::new("x");
+        assert_eq!(stream.nth(1), Some(Ok((Token::EOF, 1..1))));
+        assert_eq!(stream.next(), None);
+    }
+
+    #[test]
+    fn grouped_digits() {
+        fn tokens(input: &str, separator: char) -> Result<Vec<Token<'_>>, InvalidToken> {
+            let options = TokenizerOptions {
+                digit_group_char: Some(separator),
+                ..Default::default()
+            };
+            let tokens = input.tokenize_with(options)?;
+            Ok(tokens.into_iter().map(|(token, _)| token).collect())
+        }
+
+        for (input, separator, expected) in [
+            ("1,234,567", ',', Token::Integer(1_234_567)),
+            ("12,345.678", ',', Token::Real(12345.678)),
+            ("999,999", ',', Token::Integer(999_999)),
+            ("1_000", '_', Token::Integer(1000)),
+            ("1 000 000", ' ', Token::Integer(1_000_000)),
+            ("1234", ',', Token::Integer(1234)),
+            ("0.123,4", ',', Token::Real(0.123)),
+        ] {
+            assert_eq!(
+                tokens(input, separator).map(|tokens| tokens[0]),
+                Ok(expected),
+                "{input}"
+            );
+        }
+
+        // A separator not followed by a digit is left alone
+        assert_eq!(
+            tokens("gcd(1, 2)", ','),
+            Ok(vec![
+                Token::Ident("gcd"),
+                Token::OpenParen,
+                Token::Integer(1),
+                Token::Comma,
+                Token::Integer(2),
+                Token::CloseParen,
+                Token::EOF
+            ])
+        );
+
+        for (input, position) in [
+            ("1,23,4", 1),
+            ("1,2345", 1),
+            ("1234,567", 4),
+            ("1,234,56", 5),
+            ("x + 1,23.5", 5),
+        ] {
+            assert_eq!(
+                tokens(input, ','),
+                Err(InvalidToken {
+                    position,
+                    found: ','
+                }),
+                "{input}"
+            );
+        }
+    }
+
+    #[test]
+    fn decimal_commas() {
+        let options = TokenizerOptions {
+            decimal_comma: true,
+            ..Default::default()
+        };
+        let tokens = |input| {
+            TokenStream::with_options(input, options)
+                .map(|token| token.map(|(token, _)| token))
+                .collect::<Result<Vec<_>, _>>()
+        };
+
+        assert_eq!(
+            tokens("3,14 * 2"),
+            Ok(vec![
+                Token::Real(314.0 / 100.0),
+                Token::Multiply,
+                Token::Integer(2),
+                Token::EOF
+            ])
+        );
+        assert_eq!(
+            tokens("gcd(12; 18)"),
+            Ok(vec![
+                Token::Ident("gcd"),
+                Token::OpenParen,
+                Token::Integer(12),
+                Token::Comma,
+                Token::Integer(18),
+                Token::CloseParen,
+                Token::EOF
+            ])
+        );
+        // A decimal point still works, and wins over grouping with commas
+        assert_eq!(tokens("0.5"), Ok(vec![Token::Real(0.5), Token::EOF]));
+        let grouped = TokenizerOptions {
+            digit_group_char: Some(','),
+            ..options
+        };
+        assert_eq!(
+            "1,234".tokenize_with(grouped).map(|tokens| tokens[0].0),
+            Ok(Token::Real(1.234))
+        );
+
+        // Commas that aren't decimal points are invalid
+        for (input, position) in [("gcd(12, 18)", 6), ("1,5,2", 3), ("2,", 1)] {
+            assert_eq!(
+                tokens(input),
+                Err(InvalidToken {
+                    position,
+                    found: ','
+                }),
+                "{input}"
+            );
+        }
+    }
+
+    #[test]
+    fn decimal_commas_are_off_by_default() {
+        assert_eq!(
+            "3,14".tokenize(),
+            Ok(vec![
+                Token::Integer(3),
+                Token::Comma,
+                Token::Integer(14),
+                Token::EOF
+            ])
+        );
+        assert_eq!(
+            "gcd(12; 18)".tokenize(),
+            Err(InvalidToken {
+                position: 6,
+                found: ';'
+            })
+        );
+    }
+
+    #[test]
+    fn grouping_is_off_by_default() {
+        assert_eq!(
+            "gcd(1,234)".tokenize(),
+            Ok(vec![
+                Token::Ident("gcd"),
+                Token::OpenParen,
+                Token::Integer(1),
+                Token::Comma,
+                Token::Integer(234),
+                Token::CloseParen,
+                Token::EOF
+            ])
+        );
+        assert_eq!(
+            "1_000".tokenize(),
+            Err(InvalidToken {
+                position: 1,
+                found: '_'
+            })
+        );
+    }
+
+    #[test]
+    fn pasted_characters() {
+        let pasted = "“12 – ３” × 2\u{A0}+ −4";
+        let lenient = TokenizerOptions {
+            lenient: true,
+            ..Default::default()
+        };
+
+        let tokens = pasted.tokenize_with(lenient).expect("Tokenize leniently");
+        assert_eq!(
+            tokens.iter().map(|(token, _)| *token).collect::<Vec<_>>(),
+            [
+                Token::Integer(12),
+                Token::Minus,
+                Token::Integer(3),
+                Token::Multiply,
+                Token::Integer(2),
+                Token::Plus,
+                Token::Minus,
+                Token::Integer(4),
+                Token::EOF
+            ]
+        );
+        // Positions are still those of the source
+        assert_eq!(tokens[2].1, 6..7);
+
+        assert_eq!(
+            normalizations(pasted)
+                .into_iter()
+                .map(
+                    |Normalization {
+                         position, read_as, ..
+                     }| (position, read_as)
+                )
+                .collect::<Vec<_>>(),
+            [
+                (0, ' '),
+                (4, '-'),
+                (6, '3'),
+                (7, ' '),
+                (9, '*'),
+                (12, ' '),
+                (15, '-')
+            ]
+        );
+        assert_eq!(normalizations("1 - 2"), []);
+
+        // Fullwidth letters are left alone, and strict tokenizing is the default
+        assert_eq!(
+            "ｘ".tokenize_with(lenient).map(|tokens| tokens[0].0),
+            Ok(Token::Variable('ｘ'))
+        );
+        assert_eq!(
+            pasted.tokenize(),
+            Err(InvalidToken {
+                position: 0,
+                found: '“'
+            })
+        );
+    }
 }
@@ -0,0 +1,152 @@
+//! Step by step traces of evaluation, for showing the work behind a result
+
+use std::fmt::Display;
+
+use crate::{
+    ast::Expr,
+    exec::{EvalResult, Evaluator, RuntimeError},
+};
+
+/// What happened in a step of evaluation
+#[derive(Clone, Copy, Debug, PartialEq)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize),
+    serde(rename_all = "lowercase")
+)]
+pub enum StepKind {
+    /// An operation was applied to values that were already evaluated, like `1 + 2`
+    Reduction,
+    /// A variable was replaced with the value of its expression
+    Substitution,
+}
+
+/// A single step of evaluation
+#[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub struct TraceStep {
+    /// What was evaluated, with any operands already replaced by their values
+    pub expr: String,
+    /// What it evaluated to
+    pub result: EvalResult,
+    /// Whether this was an operation or a variable
+    pub kind: StepKind,
+}
+
+impl Display for TraceStep {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self.kind {
+            StepKind::Reduction => write!(f, "{} = {}", self.expr, self.result),
+            StepKind::Substitution => write!(f, "{} → {}", self.expr, self.result),
+        }
+    }
+}
+
+impl Evaluator {
+    /// Executes an AST, also returning every operation and variable substitution in the order
+    /// they were evaluated. Sums, products and `solve` are a single step each rather than every
+    /// evaluation of their body
+    pub fn exec_traced(
+        &mut self,
+        ast: &Expr,
+    ) -> Result<(EvalResult, Vec<TraceStep>), RuntimeError> {
+        let outer = self.trace.replace(vec![]);
+        let result = self.exec(ast);
+        let steps = std::mem::replace(&mut self.trace, outer).unwrap_or_default();
+
+        Ok((result?, steps))
+    }
+
+    /// Whether a trace is being taken, so steps are worth building
+    pub(crate) fn tracing(&self) -> bool {
+        self.trace.is_some()
+    }
+
+    /// Records a successful step if a trace is being taken. Steps that don't change how anything
+    /// looks, like negating a literal, are skipped
+    pub(crate) fn record(
+        &mut self,
+        kind: StepKind,
+        expr: Option<Expr>,
+        result: &Result<EvalResult, RuntimeError>,
+    ) {
+        let (Some(trace), Some(expr), Ok(result)) = (&mut self.trace, expr, result) else {
+            return;
+        };
+
+        let expr = expr.to_string();
+        if expr != result.to_string() {
+            trace.push(TraceStep {
+                expr,
+                result: result.clone(),
+                kind,
+            });
+        }
+    }
+
+    /// Runs `f` without recording any steps, for evaluations whose every step would be noise
+    pub(crate) fn untraced<T>(&mut self, f: impl FnOnce(&mut Self) -> T) -> T {
+        let trace = self.trace.take();
+        let result = f(self);
+        self.trace = trace;
+        result
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        ast::Expr,
+        exec::{EvalResult, Evaluator},
+        pipeline::testing::parse,
+    };
+
+    /// The displayed steps of evaluating an expression
+    fn steps(eval: &mut Evaluator, expression: &str) -> Vec<String> {
+        let (_, steps) = eval.exec_traced(&parse(expression)).expect("Evaluate");
+        steps.iter().map(ToString::to_string).collect()
+    }
+
+    #[test]
+    fn nested_arithmetic() {
+        let mut eval = Evaluator::default();
+        assert_eq!(steps(&mut eval, "(1 + 2) * 3"), ["1 + 2 = 3", "3 * 3 = 9"]);
+        assert_eq!(
+            steps(&mut eval, "2 ^ (1 + 1) - |-6| / 4"),
+            [
+                "1 + 1 = 2",
+                "2 ^ 2 = 4",
+                "|-6| = 6",
+                "6 / 4 = 3/2",
+                "4 - 3 / 2 = 5/2"
+            ]
+        );
+        assert_eq!(
+            steps(&mut eval, "gcd(4, 6)! + sum(i, 1, 3, i)"),
+            [
+                "gcd(4, 6) = 2",
+                "2! = 2",
+                "sum(i, 1, 3, i) = 6",
+                "2 + 6 = 8"
+            ]
+        );
+        assert!(steps(&mut eval, "7").is_empty());
+    }
+
+    #[test]
+    fn bound_variables() {
+        let mut eval = Evaluator::default();
+        eval.set('x', Expr::Integer(4));
+        eval.set('y', parse("x * 2"));
+
+        assert_eq!(
+            steps(&mut eval, "y + x"),
+            ["x → 4", "4 * 2 = 8", "y → 8", "x → 4", "8 + 4 = 12"]
+        );
+
+        let (result, _) = eval.exec_traced(&parse("x - 1")).expect("Evaluate");
+        assert_eq!(result, EvalResult::Integer(3));
+        assert!(eval.trace.is_none(), "tracing stops afterwards");
+        assert!(eval.exec_traced(&parse("z + 1")).is_err());
+    }
+}
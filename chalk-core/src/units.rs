@@ -0,0 +1,585 @@
+//! Physical units and arithmetic on quantities carrying them, like `3km + 200m` or
+//! `60 mph * 2 h`. Units come from a fixed table of lengths, times, masses, areas and speeds, and
+//! any combination of them can be built by multiplying, dividing and raising to powers
+
+use std::{cmp::Ordering, fmt::Display};
+
+use crate::{
+    ast::{BinaryOperator, UnaryOperator},
+    exec::{EvalResult, RuntimeError},
+};
+
+/// Powers of the base dimensions a unit measures, so speed is length / time
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct Dimension {
+    /// Power of length
+    pub length: i32,
+    /// Power of time
+    pub time: i32,
+    /// Power of mass
+    pub mass: i32,
+}
+
+impl Dimension {
+    /// Length, like metres
+    pub const LENGTH: Self = Self::new(1, 0, 0);
+    /// Time, like seconds
+    pub const TIME: Self = Self::new(0, 1, 0);
+    /// Mass, like grams
+    pub const MASS: Self = Self::new(0, 0, 1);
+    /// Area, length squared
+    pub const AREA: Self = Self::new(2, 0, 0);
+    /// Speed, length over time
+    pub const SPEED: Self = Self::new(1, -1, 0);
+
+    /// A dimension from its powers of length, time and mass
+    const fn new(length: i32, time: i32, mass: i32) -> Self {
+        Self { length, time, mass }
+    }
+
+    /// Whether this is a plain number, with every power cancelled out
+    pub fn is_dimensionless(&self) -> bool {
+        *self == Self::default()
+    }
+
+    /// The dimension of a product, adding `power` of `other` to this one
+    fn times(self, other: Self, power: i32) -> Self {
+        Self::new(
+            self.length + other.length * power,
+            self.time + other.time * power,
+            self.mass + other.mass * power,
+        )
+    }
+}
+
+/// A unit from the table, which every other unit is a product of powers of
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize, serde::Deserialize),
+    serde(rename_all = "snake_case")
+)]
+pub enum NamedUnit {
+    /// Millimetre
+    Millimetre,
+    /// Centimetre
+    Centimetre,
+    /// Metre
+    Metre,
+    /// Kilometre
+    Kilometre,
+    /// International foot
+    Foot,
+    /// International yard
+    Yard,
+    /// International mile
+    Mile,
+    /// Millisecond
+    Millisecond,
+    /// Second
+    Second,
+    /// Minute
+    Minute,
+    /// Hour
+    Hour,
+    /// Day of 24 hours
+    Day,
+    /// Milligram
+    Milligram,
+    /// Gram
+    Gram,
+    /// Kilogram
+    Kilogram,
+    /// Avoirdupois pound
+    Pound,
+    /// Avoirdupois ounce
+    Ounce,
+    /// Hectare
+    Hectare,
+    /// International acre
+    Acre,
+}
+
+impl NamedUnit {
+    /// Every unit in the table
+    pub const ALL: &[Self] = &[
+        Self::Millimetre,
+        Self::Centimetre,
+        Self::Metre,
+        Self::Kilometre,
+        Self::Foot,
+        Self::Yard,
+        Self::Mile,
+        Self::Millisecond,
+        Self::Second,
+        Self::Minute,
+        Self::Hour,
+        Self::Day,
+        Self::Milligram,
+        Self::Gram,
+        Self::Kilogram,
+        Self::Pound,
+        Self::Ounce,
+        Self::Hectare,
+        Self::Acre,
+    ];
+
+    /// The symbol it's written with
+    pub fn symbol(&self) -> &'static str {
+        match self {
+            Self::Millimetre => "mm",
+            Self::Centimetre => "cm",
+            Self::Metre => "m",
+            Self::Kilometre => "km",
+            Self::Foot => "ft",
+            Self::Yard => "yd",
+            Self::Mile => "mi",
+            Self::Millisecond => "ms",
+            Self::Second => "s",
+            Self::Minute => "min",
+            Self::Hour => "h",
+            Self::Day => "day",
+            Self::Milligram => "mg",
+            Self::Gram => "g",
+            Self::Kilogram => "kg",
+            Self::Pound => "lb",
+            Self::Ounce => "oz",
+            Self::Hectare => "ha",
+            Self::Acre => "acre",
+        }
+    }
+
+    /// What it measures
+    pub fn dimension(&self) -> Dimension {
+        match self {
+            Self::Millimetre
+            | Self::Centimetre
+            | Self::Metre
+            | Self::Kilometre
+            | Self::Foot
+            | Self::Yard
+            | Self::Mile => Dimension::LENGTH,
+            Self::Millisecond | Self::Second | Self::Minute | Self::Hour | Self::Day => {
+                Dimension::TIME
+            }
+            Self::Milligram | Self::Gram | Self::Kilogram | Self::Pound | Self::Ounce => {
+                Dimension::MASS
+            }
+            Self::Hectare | Self::Acre => Dimension::AREA,
+        }
+    }
+
+    /// How many metres, seconds, kilograms or square metres it is
+    pub fn scale(&self) -> f64 {
+        match self {
+            Self::Millimetre => 1e-3,
+            Self::Centimetre => 1e-2,
+            Self::Metre => 1.0,
+            Self::Kilometre => 1e3,
+            Self::Foot => 0.3048,
+            Self::Yard => 0.9144,
+            Self::Mile => 1609.344,
+            Self::Millisecond => 1e-3,
+            Self::Second => 1.0,
+            Self::Minute => 60.0,
+            Self::Hour => 3600.0,
+            Self::Day => 86400.0,
+            Self::Milligram => 1e-6,
+            Self::Gram => 1e-3,
+            Self::Kilogram => 1.0,
+            Self::Pound => 0.453_592_37,
+            Self::Ounce => 0.028_349_523_125,
+            Self::Hectare => 1e4,
+            Self::Acre => 4_046.856_422_4,
+        }
+    }
+}
+
+/// A product of powers of named units, like `km/h`. Units with no powers are plain numbers
+#[derive(Clone, Debug, Default, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Unit(Vec<(NamedUnit, i32)>);
+
+impl Unit {
+    /// Looks up a unit by its symbol, including the speeds `mph` and `kph`. Symbols are case
+    /// sensitive, since `m` and `M` would mean different things
+    pub fn parse(symbol: &str) -> Option<Self> {
+        match symbol {
+            "mph" => Some(Self(vec![(NamedUnit::Mile, 1), (NamedUnit::Hour, -1)])),
+            "kph" => Some(Self(vec![(NamedUnit::Kilometre, 1), (NamedUnit::Hour, -1)])),
+            _ => NamedUnit::ALL
+                .iter()
+                .find(|unit| unit.symbol() == symbol)
+                .map(|unit| Self::from(*unit)),
+        }
+    }
+
+    /// Whether this is a plain number
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+
+    /// What the unit measures
+    pub fn dimension(&self) -> Dimension {
+        self.0
+            .iter()
+            .fold(Dimension::default(), |dimension, (unit, power)| {
+                dimension.times(unit.dimension(), *power)
+            })
+    }
+
+    /// How many of the base units of its dimension one of it is, like 1000 for `km`
+    pub fn scale(&self) -> f64 {
+        self.0
+            .iter()
+            .map(|(unit, power)| unit.scale().powi(*power))
+            .product()
+    }
+
+    /// The unit raised to a power
+    pub fn powi(&self, power: i32) -> Self {
+        Self(
+            self.0
+                .iter()
+                .filter(|_| power != 0)
+                .map(|(unit, p)| (*unit, p * power))
+                .collect(),
+        )
+    }
+
+    /// The product of two units, along with what to multiply the value by. Units of `other`
+    /// measuring the same thing as one already here are converted into it, so `mph * h` is `mi`
+    /// and `km * m` is `km^2`
+    pub fn times(&self, other: &Self) -> (Self, f64) {
+        let mut units = self.0.clone();
+        let mut factor = 1.0;
+
+        for (unit, power) in &other.0 {
+            match units
+                .iter_mut()
+                .find(|(existing, _)| existing.dimension() == unit.dimension())
+            {
+                Some((existing, existing_power)) => {
+                    factor *= (unit.scale() / existing.scale()).powi(*power);
+                    *existing_power += power;
+                }
+                None => units.push((*unit, *power)),
+            }
+        }
+        units.retain(|(_, power)| *power != 0);
+
+        (Self(units), factor)
+    }
+}
+
+impl From<NamedUnit> for Unit {
+    fn from(unit: NamedUnit) -> Self {
+        Self(vec![(unit, 1)])
+    }
+}
+
+/// Units are written like `kg*m/s^2`, with every unit of a negative power after a `/`
+impl Display for Unit {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let write_power =
+            |f: &mut std::fmt::Formatter<'_>, unit: &NamedUnit, power: i32| match power {
+                1 => write!(f, "{}", unit.symbol()),
+                power => write!(f, "{}^{power}", unit.symbol()),
+            };
+
+        let mut numerator = self.0.iter().filter(|(_, power)| *power > 0).peekable();
+        if numerator.peek().is_none() {
+            write!(f, "1")?;
+        }
+        for (i, (unit, power)) in numerator.enumerate() {
+            if i > 0 {
+                write!(f, "*")?;
+            }
+            write_power(f, unit, *power)?;
+        }
+
+        for (unit, power) in self.0.iter().filter(|(_, power)| *power < 0) {
+            write!(f, "/")?;
+            write_power(f, unit, -power)?;
+        }
+
+        Ok(())
+    }
+}
+
+/// A value in a unit, a plain float once every dimension cancels out
+pub fn quantity(value: f64, unit: Unit) -> EvalResult {
+    if unit.is_empty() {
+        EvalResult::Float(value)
+    } else if unit.dimension().is_dimensionless() {
+        EvalResult::Float(value * unit.scale())
+    } else {
+        EvalResult::Quantity { value, unit }
+    }
+}
+
+/// Converts a value from one unit to another measuring the same thing
+pub fn convert(value: f64, from: &Unit, to: &Unit) -> Result<f64, RuntimeError> {
+    if from.dimension() != to.dimension() {
+        return Err(RuntimeError::DimensionMismatch);
+    }
+
+    Ok(value * from.scale() / to.scale())
+}
+
+/// A number as a value and its unit, plain numbers having no unit
+fn parts(value: &EvalResult) -> Result<(f64, Unit), RuntimeError> {
+    match value {
+        EvalResult::Quantity { value, unit } => Ok((*value, unit.clone())),
+        value => Ok((value.float()?, Unit::default())),
+    }
+}
+
+/// Whether either value carries a unit
+fn either_quantity(left: &EvalResult, right: &EvalResult) -> bool {
+    matches!(left, EvalResult::Quantity { .. }) || matches!(right, EvalResult::Quantity { .. })
+}
+
+/// Applies arithmetic or a conversion if either side is a quantity, `None` otherwise.
+/// Comparisons aren't handled here, they go through [`cmp`]
+pub(crate) fn binary(
+    op: &BinaryOperator,
+    left: &EvalResult,
+    right: &EvalResult,
+) -> Option<Result<EvalResult, RuntimeError>> {
+    if !either_quantity(left, right) {
+        return None;
+    }
+
+    let result = match op {
+        BinaryOperator::Add
+        | BinaryOperator::Subtract
+        | BinaryOperator::Multiply
+        | BinaryOperator::Divide
+        | BinaryOperator::Pow
+        | BinaryOperator::Convert => arithmetic(op, left, right),
+        BinaryOperator::Eq
+        | BinaryOperator::NEq
+        | BinaryOperator::ApproxEq
+        | BinaryOperator::Gt
+        | BinaryOperator::Lt
+        | BinaryOperator::Gte
+        | BinaryOperator::Lte => return None,
+        BinaryOperator::Gcd
+        | BinaryOperator::Lcm
+        | BinaryOperator::Atan2
+        | BinaryOperator::BitAnd
+        | BinaryOperator::BitOr
+        | BinaryOperator::BitXor
+        | BinaryOperator::Shl
+        | BinaryOperator::Shr
+        | BinaryOperator::Mod
+        | BinaryOperator::And
+        | BinaryOperator::Or => Err(RuntimeError::Invalid),
+    };
+
+    Some(result)
+}
+
+/// Arithmetic where at least one side is a quantity
+fn arithmetic(
+    op: &BinaryOperator,
+    left: &EvalResult,
+    right: &EvalResult,
+) -> Result<EvalResult, RuntimeError> {
+    let (l, left_unit) = parts(left)?;
+    let (r, right_unit) = parts(right)?;
+
+    match op {
+        BinaryOperator::Add => Ok(quantity(
+            l + convert(r, &right_unit, &left_unit)?,
+            left_unit,
+        )),
+        BinaryOperator::Subtract => Ok(quantity(
+            l - convert(r, &right_unit, &left_unit)?,
+            left_unit,
+        )),
+        BinaryOperator::Multiply => {
+            let (unit, factor) = left_unit.times(&right_unit);
+            Ok(quantity(l * r * factor, unit))
+        }
+        BinaryOperator::Divide => {
+            let (unit, factor) = left_unit.times(&right_unit.powi(-1));
+            Ok(quantity(l / r * factor, unit))
+        }
+        BinaryOperator::Pow => {
+            if !right_unit.is_empty() {
+                return Err(RuntimeError::DimensionMismatch);
+            }
+            let power = i32::try_from(right.int()?).map_err(|_| RuntimeError::Invalid)?;
+            Ok(quantity(l.powi(power), left_unit.powi(power)))
+        }
+        // The right side is the unit to convert to, as a quantity of one
+        BinaryOperator::Convert => {
+            if right_unit.is_empty() {
+                return Err(RuntimeError::Invalid);
+            }
+            Ok(quantity(
+                convert(l, &left_unit, &right_unit)? / r,
+                right_unit,
+            ))
+        }
+        _ => Err(RuntimeError::Invalid),
+    }
+}
+
+/// Orders two values if either is a quantity, `None` otherwise. Quantities only compare with
+/// quantities measuring the same thing
+pub(crate) fn cmp(
+    left: &EvalResult,
+    right: &EvalResult,
+) -> Option<Result<Option<Ordering>, RuntimeError>> {
+    Some(same_unit(left, right)?.map(|(l, r)| l.partial_cmp(&r)))
+}
+
+/// The values of two quantities in the left one's unit if either is a quantity, `None` otherwise
+pub(crate) fn same_unit(
+    left: &EvalResult,
+    right: &EvalResult,
+) -> Option<Result<(f64, f64), RuntimeError>> {
+    if !either_quantity(left, right) {
+        return None;
+    }
+
+    Some(parts(left).and_then(|(l, left_unit)| {
+        let (r, right_unit) = parts(right)?;
+        Ok((l, convert(r, &right_unit, &left_unit)?))
+    }))
+}
+
+/// Applies a unary operation to a quantity. Only operations that keep the unit meaningful, like
+/// negation or rounding, are allowed
+pub(crate) fn unary(
+    op: &UnaryOperator,
+    value: f64,
+    unit: &Unit,
+) -> Result<EvalResult, RuntimeError> {
+    let value = match op {
+        UnaryOperator::Neg => -value,
+        UnaryOperator::Floor => value.floor(),
+        UnaryOperator::Ceil => value.ceil(),
+        UnaryOperator::Percent => value / 100.0,
+        _ => return Err(RuntimeError::Invalid),
+    };
+
+    Ok(quantity(value, unit.clone()))
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::exec::{EvalResult, RuntimeError};
+
+    use super::{Dimension, NamedUnit, Unit, quantity};
+
+    /// Parses a unit known to be in the table
+    fn unit(symbol: &str) -> Unit {
+        Unit::parse(symbol).expect("Known unit")
+    }
+
+    /// Evaluates an expression with a fresh evaluator
+    #[cfg(feature = "units")]
+    fn run(expression: &str) -> Result<EvalResult, RuntimeError> {
+        crate::pipeline::testing::run(&mut crate::exec::Evaluator::default(), expression)
+    }
+
+    /// The displayed result of evaluating an expression
+    #[cfg(feature = "units")]
+    fn shown(expression: &str) -> String {
+        run(expression).expect("Evaluate").to_string()
+    }
+
+    #[test]
+    fn table() {
+        for named in NamedUnit::ALL {
+            assert_eq!(Unit::parse(named.symbol()), Some(Unit::from(*named)));
+        }
+        assert_eq!(NamedUnit::ALL.len() + 2, 21, "the table plus mph and kph");
+        assert_eq!(unit("mph").dimension(), Dimension::SPEED);
+        assert_eq!(unit("kph").to_string(), "km/h");
+        assert!(Unit::parse("M").is_none());
+        assert!(Unit::parse("in").is_none(), "`in` converts");
+    }
+
+    #[test]
+    fn combining_units() {
+        let (mi, factor) = unit("mph").times(&unit("h"));
+        assert_eq!((mi.to_string(), factor), ("mi".to_string(), 1.0));
+
+        let (area, factor) = unit("km").times(&unit("m"));
+        assert_eq!(area.to_string(), "km^2");
+        assert_eq!(factor, 1e-3);
+
+        let (unit, _) = unit("kg").times(&unit("m").times(&unit("s").powi(-2)).0);
+        assert_eq!(unit.to_string(), "kg*m/s^2");
+        assert_eq!(Unit::from(NamedUnit::Second).powi(-1).to_string(), "1/s");
+    }
+
+    #[test]
+    #[cfg(feature = "units")]
+    fn adding_with_conversion() {
+        assert_eq!(shown("3km + 200m"), "3.2 km");
+        assert_eq!(shown("200m + 3km"), "3200 m");
+        assert_eq!(shown("1 h - 30 min"), "0.5 h");
+        assert_eq!(shown("3km + 200m in m"), "3200 m");
+        assert_eq!(shown("1 mi in ft"), "5280 ft");
+        assert_eq!(shown("-|2kg - 5kg|"), "-3 kg");
+        assert_eq!(run("1km == 1000m"), Ok(EvalResult::Bool(true)));
+        assert_eq!(run("1 lb > 1 kg"), Ok(EvalResult::Bool(false)));
+        assert_eq!(run("0.1 km + 0.2 km ~= 300 m"), Ok(EvalResult::Bool(true)));
+    }
+
+    #[test]
+    #[cfg(feature = "units")]
+    fn dimension_mismatches() {
+        for expression in [
+            "1km + 1h",
+            "2 kg - 3",
+            "5 + 1 s",
+            "2 kg in m",
+            "3 in km",
+            "1 km < 1 s",
+            "1 km ~= 1 s",
+        ] {
+            assert_eq!(
+                run(expression),
+                Err(RuntimeError::DimensionMismatch),
+                "{expression}"
+            );
+        }
+        assert_eq!(run("1 km == 1 s"), Ok(EvalResult::Bool(false)));
+        assert_eq!(run("sin(1 km)"), Err(RuntimeError::Invalid));
+        assert_eq!(run("2 ^ 3 m"), Err(RuntimeError::DimensionMismatch));
+        assert_eq!(run("(3 m) ^ 1.5"), Err(RuntimeError::Invalid));
+    }
+
+    #[test]
+    #[cfg(feature = "units")]
+    fn deriving_units() {
+        assert_eq!(shown("60 mph * 2 h"), "120 mi");
+        assert_eq!(shown("10 m * 20 m"), "200 m^2");
+        assert_eq!(shown("(3 km)^2"), "9 km^2");
+        assert_eq!(shown("1 ha in m^2"), "10000 m^2");
+        assert_eq!(shown("100 km / 2 h"), "50 km/h");
+        assert_eq!(shown("90 km/h in m/s"), "25 m/s");
+        assert_eq!(shown("2 kg * 3 m / 2 s^2"), "3 kg*m/s^2");
+        assert_eq!(shown("1 / 4 s"), "0.25 1/s");
+        assert_eq!(run("6 m / 2 m"), Ok(EvalResult::Float(3.0)));
+        assert_eq!(run("1 acre / 1 m^2"), Ok(EvalResult::Float(4046.8564224)));
+    }
+
+    #[test]
+    fn cancelling_units() {
+        assert_eq!(quantity(2.0, Unit::default()), EvalResult::Float(2.0));
+        let (ratio, factor) = unit("ha").times(&unit("m").powi(-2));
+        assert_eq!(quantity(factor, ratio), EvalResult::Float(1e4));
+        assert_eq!(
+            super::convert(1.0, &unit("km"), &unit("h")),
+            Err(RuntimeError::DimensionMismatch)
+        );
+    }
+}
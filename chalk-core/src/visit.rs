@@ -0,0 +1,171 @@
+//! Generic traversals over expression trees, so analyses and rewrites don't need to match on
+//! every variant themselves
+
+use std::convert::Infallible;
+
+use crate::ast::Expr;
+
+impl Expr {
+    /// The expressions directly inside this one, in the order they're written
+    pub fn children(&self) -> Vec<&Expr> {
+        match self {
+            Expr::Variable(_)
+            | Expr::Integer(_)
+            | Expr::Real(_)
+            | Expr::Bool(_)
+            | Expr::Constant(_)
+            | Expr::Unit(_) => vec![],
+            Expr::Assignment(_, node)
+            | Expr::UnaryOp { node, .. }
+            | Expr::Paren(node)
+            | Expr::AbsVal(node)
+            | Expr::Solve { equation: node, .. } => vec![node],
+            Expr::BinaryOp { left, right, .. }
+            | Expr::Index {
+                list: left,
+                index: right,
+            } => {
+                vec![left, right]
+            }
+            Expr::Reduce { from, to, body, .. } => vec![from, to, body],
+            Expr::List(items) | Expr::FunctionCall { args: items, .. } => items.iter().collect(),
+        }
+    }
+
+    /// Calls `f` on this expression and then every expression inside it, parents before children
+    pub fn walk(&self, f: &mut impl FnMut(&Expr)) {
+        f(self);
+        for child in self.children() {
+            child.walk(f);
+        }
+    }
+
+    /// Rebuilds the expression by passing every node through `f`, children before parents, so `f`
+    /// sees each node with its children already rewritten. Stops at the first error
+    pub fn try_map<E>(self, f: &mut impl FnMut(Expr) -> Result<Expr, E>) -> Result<Expr, E> {
+        let mapped = self.try_map_children(&mut |child| child.try_map(f))?;
+        f(mapped)
+    }
+
+    /// Rebuilds the expression with every expression directly inside it passed through `f`,
+    /// leaving anything deeper for `f` to handle. Stops at the first error
+    pub fn try_map_children<E>(
+        self,
+        f: &mut impl FnMut(Expr) -> Result<Expr, E>,
+    ) -> Result<Expr, E> {
+        let mut inner = |node: Box<Expr>| f(*node).map(Box::new);
+
+        Ok(match self {
+            Expr::Variable(_)
+            | Expr::Integer(_)
+            | Expr::Real(_)
+            | Expr::Bool(_)
+            | Expr::Constant(_)
+            | Expr::Unit(_) => self,
+            Expr::Assignment(var, node) => Expr::Assignment(var, inner(node)?),
+            Expr::UnaryOp { op, node } => Expr::UnaryOp {
+                op,
+                node: inner(node)?,
+            },
+            Expr::Paren(node) => Expr::Paren(inner(node)?),
+            Expr::AbsVal(node) => Expr::AbsVal(inner(node)?),
+            Expr::Solve { equation, var } => Expr::Solve {
+                equation: inner(equation)?,
+                var,
+            },
+            Expr::BinaryOp { op, left, right } => Expr::BinaryOp {
+                op,
+                left: inner(left)?,
+                right: inner(right)?,
+            },
+            Expr::Reduce {
+                kind,
+                var,
+                from,
+                to,
+                body,
+            } => Expr::Reduce {
+                kind,
+                var,
+                from: inner(from)?,
+                to: inner(to)?,
+                body: inner(body)?,
+            },
+            Expr::List(items) => Expr::List(items.into_iter().map(f).collect::<Result<_, _>>()?),
+            Expr::Index { list, index } => Expr::Index {
+                list: inner(list)?,
+                index: inner(index)?,
+            },
+            Expr::FunctionCall { function, args } => Expr::FunctionCall {
+                function,
+                args: args.into_iter().map(f).collect::<Result<_, _>>()?,
+            },
+        })
+    }
+
+    /// Rebuilds the expression with every expression directly inside it passed through `f`
+    pub fn map_children(self, mut f: impl FnMut(Expr) -> Expr) -> Expr {
+        match self.try_map_children(&mut |child| Ok::<_, Infallible>(f(child))) {
+            Ok(mapped) => mapped,
+            Err(never) => match never {},
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{ast::Expr, pipeline::testing::parse};
+
+    #[test]
+    fn counting_nodes() {
+        // y = (BinaryOp (Paren (BinaryOp 1 x)) (AbsVal (UnaryOp x)))
+        let ast = parse("y = (1 + x) * |-x|");
+
+        let mut nodes = 0;
+        let mut vars = vec![];
+        ast.walk(&mut |node| {
+            nodes += 1;
+            if let Expr::Variable(var) = node {
+                vars.push(*var);
+            }
+        });
+
+        assert_eq!(nodes, 9);
+        assert_eq!(vars, ['x', 'x']);
+        assert_eq!(ast.children().len(), 1);
+        assert_eq!(
+            parse("sum(i, 1, n, i^2)").children().len(),
+            3,
+            "from, to and body"
+        );
+    }
+
+    #[test]
+    fn rewriting_integers() {
+        let ast = parse("sum(i, 1, 10, 2 * i) + |3 - x| + powmod(2, 10, 7)");
+        let doubled = ast.try_map(&mut |node| match node {
+            Expr::Integer(i) => Ok::<_, ()>(Expr::Integer(i * 2)),
+            node => Ok(node),
+        });
+
+        assert_eq!(
+            doubled.map(|expr| expr.to_string()),
+            Ok("sum(i, 2, 20, 4 * i) + |6 - x| + powmod(4, 20, 14)".to_string())
+        );
+    }
+
+    #[test]
+    fn rewriting_stops_at_errors() {
+        let mut seen = 0;
+        let result = parse("1 + x * 2").try_map(&mut |node| {
+            seen += 1;
+            match node {
+                Expr::Variable(var) => Err(var),
+                node => Ok(node),
+            }
+        });
+
+        assert_eq!(result, Err('x'));
+        assert_eq!(seen, 2, "only `1` and `x` are visited");
+    }
+}
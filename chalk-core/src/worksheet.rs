@@ -0,0 +1,192 @@
+//! Whole worksheets of statements evaluated at once, keeping track of which variables they change
+//! so only what depends on those needs showing again, and checked beforehand for variables that
+//! are never read or read too early
+
+use std::collections::{BTreeMap, BTreeSet};
+
+use crate::{
+    ast::Expr,
+    exec::{EvalResult, Evaluator, RuntimeError},
+};
+
+impl Evaluator {
+    /// Executes statements in order, carrying on past any that fail, and gives each one's result
+    /// along with every variable whose value is different afterwards. Variables are compared by
+    /// evaluating them before and after, so rebinding `x` changes `y = 2x` too, while binding a
+    /// variable to something equal to what it was, or to an expression it already has, changes
+    /// nothing
+    pub fn exec_all_tracked(
+        &mut self,
+        statements: &[Expr],
+    ) -> (Vec<Result<EvalResult, RuntimeError>>, BTreeSet<char>) {
+        let before: BTreeMap<_, _> = self.values().into_iter().collect();
+        let results = statements
+            .iter()
+            .map(|statement| self.exec(statement))
+            .collect();
+        let after: BTreeMap<_, _> = self.values().into_iter().collect();
+
+        let changed = before
+            .keys()
+            .chain(after.keys())
+            .filter(|var| !unchanged(before.get(var), after.get(var)))
+            .copied()
+            .collect();
+        (results, changed)
+    }
+}
+
+/// What checking a worksheet without evaluating it found
+#[derive(Clone, Debug, Default, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub struct WorksheetReport {
+    /// Variables the worksheet assigns that nothing reads afterwards, in order
+    pub unused: Vec<char>,
+    /// Variables read before anything binds them, as the index of the statement reading them and
+    /// the variable, in statement order
+    pub undefined_uses: Vec<(usize, char)>,
+}
+
+/// Checks a worksheet starting from no variables at all, like [`Evaluator::analyze`]
+pub fn analyze(statements: &[Expr]) -> WorksheetReport {
+    Evaluator::default().analyze(statements)
+}
+
+impl Evaluator {
+    /// Checks statements for variables they assign but never read and variables they read before
+    /// anything binds them, following bindings like [`Evaluator::dependencies`] without evaluating
+    /// anything. Variables already bound count as assigned, so only the worksheet's own
+    /// assignments can be unused, and in complex mode an unbound `i` is the imaginary unit
+    pub fn analyze(&self, statements: &[Expr]) -> WorksheetReport {
+        let mut scope = Evaluator::default();
+        scope.ctx = self.ctx.clone();
+
+        let mut report = WorksheetReport::default();
+        let (mut assigned, mut read) = (BTreeSet::new(), BTreeSet::new());
+        for (index, statement) in statements.iter().enumerate() {
+            let deps = scope.dependencies(statement);
+            read.extend(deps.bound);
+            report.undefined_uses.extend(
+                deps.free
+                    .into_iter()
+                    .filter(|var| !(self.complex && *var == 'i'))
+                    .map(|var| (index, var)),
+            );
+
+            // Bound only after the statement is read, so `x = x + 1` reads the `x` from before it
+            statement.walk(&mut |node| {
+                if let Expr::Assignment(var, value) = node {
+                    assigned.insert(*var);
+                    scope.set(*var, (**value).clone());
+                }
+            });
+        }
+
+        report.unused = assigned.difference(&read).copied().collect();
+        report
+    }
+}
+
+/// Whether a variable's value is the same before and after. Values that show the same count too,
+/// so one that's NaN both times is unchanged despite not equalling itself
+fn unchanged(
+    before: Option<&Result<EvalResult, RuntimeError>>,
+    after: Option<&Result<EvalResult, RuntimeError>>,
+) -> bool {
+    match (before, after) {
+        (Some(Ok(before)), Some(Ok(after))) => {
+            before == after || before.to_string() == after.to_string()
+        }
+        (before, after) => before == after,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::BTreeSet;
+
+    use crate::{
+        ast::Expr,
+        exec::{EvalResult, Evaluator, RuntimeError},
+        pipeline::testing::parse,
+    };
+
+    use super::{WorksheetReport, analyze};
+
+    #[test]
+    fn reassigning_one_variable() {
+        let mut eval = Evaluator::default();
+        eval.exec_all_tracked(&["x = 1", "y = 2", "z = 3"].map(parse));
+
+        let (results, changed) = eval.exec_all_tracked(&["x = 1", "y = 5", "z = 3"].map(parse));
+        assert_eq!(
+            results,
+            [
+                Ok(EvalResult::Integer(1)),
+                Ok(EvalResult::Integer(5)),
+                Ok(EvalResult::Integer(3))
+            ]
+        );
+        assert_eq!(changed, BTreeSet::from(['y']));
+    }
+
+    #[test]
+    fn no_assignments() {
+        let mut eval = Evaluator::default();
+        eval.exec_all_tracked(&["x = 1", "y = 2x"].map(parse));
+
+        let (results, changed) = eval.exec_all_tracked(&["x + y", "q", "y * 2"].map(parse));
+        assert_eq!(
+            results,
+            [
+                Ok(EvalResult::Integer(3)),
+                Err(RuntimeError::Invalid),
+                Ok(EvalResult::Integer(4))
+            ]
+        );
+        assert!(changed.is_empty());
+    }
+
+    #[test]
+    fn unused_and_undefined_variables() {
+        let sheet = [
+            "a = 2",
+            "b = a * q",
+            "c = sum(k, 1, 3, k) + pi",
+            "d = b + e",
+            "q = 1",
+            "b",
+            "n = n + 1",
+        ]
+        .map(parse);
+
+        assert_eq!(
+            analyze(&sheet),
+            WorksheetReport {
+                unused: vec!['c', 'd', 'n'],
+                undefined_uses: vec![(1, 'q'), (3, 'e'), (3, 'q'), (6, 'n')],
+            }
+        );
+
+        // Variables bound beforehand are defined, and the imaginary unit is in complex mode
+        let mut eval = Evaluator::default();
+        eval.set('e', Expr::Integer(1));
+        eval.set_complex(true);
+        let report = eval.analyze(&["e + i", "x = 2i"].map(parse));
+        assert_eq!(report.unused, ['x']);
+        assert!(report.undefined_uses.is_empty());
+    }
+
+    #[test]
+    fn changes_follow_dependencies() {
+        let mut eval = Evaluator::default();
+        eval.allow_non_finite(true);
+
+        let (_, changed) = eval.exec_all_tracked(&["x = 1", "y = 2x", "n = 0/0.0"].map(parse));
+        assert_eq!(changed, BTreeSet::from(['n', 'x', 'y']));
+
+        // Equal values and NaN count as unchanged, even bound through another expression
+        let (_, changed) = eval.exec_all_tracked(&["x = 2 - 1", "n = 0/0.0", "x = 3"].map(parse));
+        assert_eq!(changed, BTreeSet::from(['x', 'y']));
+    }
+}
@@ -0,0 +1,186 @@
+//! Structured errors handed back to JS instead of an opaque placeholder string
+
+use chalk_core::{
+    ast::{Expr, ParseError, ParseErrorKind},
+    exec::RuntimeError,
+    hover::HoverError,
+    pipeline::ChalkError,
+    tokenizer::InvalidToken,
+};
+use serde::Serialize;
+
+/// Which stage of evaluation failed
+#[derive(Clone, Copy, Debug, PartialEq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ErrorKind {
+    /// The expression contained a character that isn't valid Chalk
+    Tokenize,
+    /// The tokens didn't form a valid expression
+    Parse,
+    /// The expression was valid but couldn't be evaluated
+    Runtime,
+}
+
+/// A limit on how much work evaluating may do
+#[derive(Clone, Copy, Debug, PartialEq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Limit {
+    /// How deeply an expression may nest, parsed or executed
+    Depth,
+    /// How many steps an evaluation may take
+    Fuel,
+}
+
+/// An evaluation failure, serialized as `{ kind, message, position, limit?, limit_value? }`
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct EvalError {
+    /// Which stage failed
+    pub kind: ErrorKind,
+    /// Human readable description of the failure
+    pub message: String,
+    /// Character index in the expression the failure happened at, if known
+    pub position: Option<usize>,
+    /// The limit the failure ran into, if that's why it failed
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub limit: Option<Limit>,
+    /// What that limit was set to, filled in by whichever parser knows it
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub limit_value: Option<u64>,
+}
+
+impl EvalError {
+    /// An expression with nothing to evaluate, because it's blank or only a comment
+    pub fn empty() -> Self {
+        Self {
+            kind: ErrorKind::Parse,
+            message: "Expression is empty :(".to_string(),
+            position: None,
+            limit: None,
+            limit_value: None,
+        }
+    }
+}
+
+impl From<ChalkError> for EvalError {
+    fn from(err: ChalkError) -> Self {
+        match err {
+            ChalkError::Empty => Self::empty(),
+            ChalkError::Tokenize(err) => err.into(),
+            ChalkError::Parse(err) => err.into(),
+            ChalkError::Runtime(err, _) => err.into(),
+        }
+    }
+}
+
+impl From<InvalidToken> for EvalError {
+    fn from(err: InvalidToken) -> Self {
+        Self {
+            kind: ErrorKind::Tokenize,
+            message: err.to_string(),
+            position: Some(err.position),
+            limit: None,
+            limit_value: None,
+        }
+    }
+}
+
+impl From<ParseError> for EvalError {
+    fn from(err: ParseError) -> Self {
+        Self {
+            kind: ErrorKind::Parse,
+            message: err.to_string(),
+            position: err.span.map(|span| span.start),
+            limit: (err.kind == ParseErrorKind::TooDeep).then_some(Limit::Depth),
+            limit_value: None,
+        }
+    }
+}
+
+impl From<HoverError> for EvalError {
+    fn from(err: HoverError) -> Self {
+        match err {
+            HoverError::Tokenize(err) => err.into(),
+            HoverError::Parse(err) => err.into(),
+            HoverError::Runtime(err, span) => Self {
+                position: Some(span.start),
+                ..err.into()
+            },
+        }
+    }
+}
+
+impl From<RuntimeError> for EvalError {
+    fn from(err: RuntimeError) -> Self {
+        Self {
+            kind: ErrorKind::Runtime,
+            message: err.to_string(),
+            position: None,
+            limit: match err {
+                RuntimeError::TooDeep => Some(Limit::Depth),
+                RuntimeError::FuelExhausted => Some(Limit::Fuel),
+                _ => None,
+            },
+            limit_value: None,
+        }
+    }
+}
+
+/// The outcome of a checked evaluation, serialized as `{ ok, value?, error? }`
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct Checked {
+    /// Whether evaluation succeeded
+    pub ok: bool,
+    /// The displayed result on success
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub value: Option<String>,
+    /// What went wrong on failure
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<EvalError>,
+}
+
+impl<T: ToString> From<Result<T, EvalError>> for Checked {
+    fn from(result: Result<T, EvalError>) -> Self {
+        match result {
+            Ok(value) => Self {
+                ok: true,
+                value: Some(value.to_string()),
+                error: None,
+            },
+            Err(error) => Self {
+                ok: false,
+                value: None,
+                error: Some(error),
+            },
+        }
+    }
+}
+
+/// Whether an expression parses, serialized as `{ valid, position?, message? }`
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct Validation {
+    /// Whether the expression tokenized and parsed
+    pub valid: bool,
+    /// Character index of the first error
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub position: Option<usize>,
+    /// Description of the first error
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub message: Option<String>,
+}
+
+impl From<Result<Expr, EvalError>> for Validation {
+    fn from(result: Result<Expr, EvalError>) -> Self {
+        match result {
+            Ok(_) => Self {
+                valid: true,
+                position: None,
+                message: None,
+            },
+            Err(err) => Self {
+                valid: false,
+                position: err.position,
+                message: Some(err.message),
+            },
+        }
+    }
+}
@@ -0,0 +1,185 @@
+//! Token spans for syntax highlighting, tolerant of invalid input
+
+use std::ops::Range;
+
+use chalk_core::tokenizer::{Token, Tokenizable};
+use serde::Serialize;
+
+/// What a highlighted span of source is
+#[derive(Clone, Copy, Debug, PartialEq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Kind {
+    /// An integer or real literal
+    Number,
+    /// Arithmetic, comparison, logical and assignment operators, plus commas
+    Operator,
+    /// A single letter variable
+    Variable,
+    /// A named function like `gcd`
+    Function,
+    /// A unit after a number, like `km` in `3km`
+    Unit,
+    /// Parentheses, list brackets and absolute value bars
+    Paren,
+    /// `true` or `false`
+    Bool,
+    /// A character that isn't valid Chalk
+    Invalid,
+}
+
+impl Kind {
+    /// The highlighting kind of a token, or `None` for the end of input
+    fn of(token: &Token<'_>) -> Option<Self> {
+        let kind = match token {
+            Token::Integer(_) | Token::Real(_) => Self::Number,
+            Token::Variable(_) => Self::Variable,
+            Token::Ident(_) => Self::Function,
+            Token::Unit(_) => Self::Unit,
+            Token::Bool(_) => Self::Bool,
+            Token::OpenParen
+            | Token::CloseParen
+            | Token::OpenBracket
+            | Token::CloseBracket
+            | Token::Bar => Self::Paren,
+            Token::EOF => return None,
+            _ => Self::Operator,
+        };
+
+        Some(kind)
+    }
+}
+
+/// A highlighted range of characters, serialized as `{ start, end, kind }`. Positions count
+/// Unicode code points, not bytes or UTF-16 units
+#[derive(Clone, Copy, Debug, PartialEq, Serialize)]
+pub struct Span {
+    /// Character index the span starts at
+    pub start: usize,
+    /// Character index just past the end of the span
+    pub end: usize,
+    /// What the span is
+    pub kind: Kind,
+}
+
+/// Byte offset of a character index, or the end of the string if it's past the end
+fn byte_offset(s: &str, chars: usize) -> usize {
+    s.char_indices()
+        .nth(chars)
+        .map_or(s.len(), |(byte, _)| byte)
+}
+
+/// Appends a span for every token, shifted along by the character offset they were read at
+fn push_tokens(spans: &mut Vec<Span>, tokens: &[(Token<'_>, Range<usize>)], offset: usize) {
+    spans.extend(tokens.iter().filter_map(|(token, range)| {
+        Some(Span {
+            start: range.start + offset,
+            end: range.end + offset,
+            kind: Kind::of(token)?,
+        })
+    }));
+}
+
+/// Splits an expression into highlighted spans. Invalid characters get an `invalid` span and
+/// scanning carries on after them
+pub fn highlight(expression: &str) -> Vec<Span> {
+    let mut spans = vec![];
+    let mut rest = expression;
+    // Character index `rest` starts at within the whole expression
+    let mut offset = 0;
+
+    loop {
+        let position = match rest.tokenize_spanned() {
+            Ok(tokens) => {
+                push_tokens(&mut spans, &tokens, offset);
+                break;
+            }
+            Err(err) => err.position,
+        };
+
+        // Everything before the invalid character is made of whole tokens, so it tokenizes on
+        // its own
+        let before = &rest[..byte_offset(rest, position)];
+        push_tokens(
+            &mut spans,
+            &before.tokenize_spanned().unwrap_or_default(),
+            offset,
+        );
+        spans.push(Span {
+            start: position + offset,
+            end: position + offset + 1,
+            kind: Kind::Invalid,
+        });
+
+        rest = &rest[byte_offset(rest, position + 1)..];
+        offset += position + 1;
+    }
+
+    spans
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Kind, Span, highlight};
+
+    /// Shorthand for building an expected span
+    fn span(start: usize, end: usize, kind: Kind) -> Span {
+        Span { start, end, kind }
+    }
+
+    #[test]
+    fn highlighting() {
+        assert_eq!(
+            highlight("gcd(12, x) + 3.5"),
+            [
+                span(0, 3, Kind::Function),
+                span(3, 4, Kind::Paren),
+                span(4, 6, Kind::Number),
+                span(6, 7, Kind::Operator),
+                span(8, 9, Kind::Variable),
+                span(9, 10, Kind::Paren),
+                span(11, 12, Kind::Operator),
+                span(13, 16, Kind::Number),
+            ]
+        );
+        assert_eq!(
+            highlight("2 km"),
+            [span(0, 1, Kind::Number), span(2, 4, Kind::Unit)]
+        );
+    }
+
+    #[test]
+    fn invalid_characters_dont_stop_scanning() {
+        assert_eq!(
+            highlight("1 $ true$$x"),
+            [
+                span(0, 1, Kind::Number),
+                span(2, 3, Kind::Invalid),
+                span(4, 8, Kind::Bool),
+                span(8, 9, Kind::Invalid),
+                span(9, 10, Kind::Invalid),
+                span(10, 11, Kind::Variable),
+            ]
+        );
+        assert!(highlight("").is_empty());
+    }
+
+    #[test]
+    fn spans_are_character_indices() {
+        assert_eq!(
+            highlight("2 ÷ x"),
+            [
+                span(0, 1, Kind::Number),
+                span(2, 3, Kind::Operator),
+                span(4, 5, Kind::Variable),
+            ]
+        );
+        assert_eq!(
+            highlight("🙂 + x"),
+            [
+                span(0, 1, Kind::Invalid),
+                span(2, 3, Kind::Operator),
+                span(4, 5, Kind::Variable),
+            ]
+        );
+    }
+}
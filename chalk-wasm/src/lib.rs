@@ -1,16 +1,128 @@
 //! WASM Runtime for a web-based chalk runtime
 
+use std::collections::{BTreeSet, HashMap};
+
 use chalk_core::{
-    ast::{Expr, Parser},
-    exec::Evaluator,
-    tokenizer::Tokenizable,
+    ast::Expr,
+    compile::CompiledExpr,
+    exec::{
+        AngleMode, Dependencies, EvalResult, Evaluator, ExecOutcome, NumericMode, RuntimeError,
+    },
+    format::{FormatOptions, Notation},
+    functions,
+    latex::parse_latex,
+    pipeline::{self, CheckResult},
+    simplify::simplify_with,
+    suggest::{Suggestion, suggest},
+    tokenizer::{Normalization, TokenizerOptions, normalizations},
+    trace::TraceStep,
 };
-use wasm_bindgen::prelude::wasm_bindgen;
+use error::{Checked, EvalError, Limit, Validation};
+use serde::Serialize;
+use serde_json::{Map, Value};
+use typed::{TypedEntry, TypedOutcome};
+use wasm_bindgen::{JsError, JsValue, prelude::wasm_bindgen};
+
+pub mod error;
+pub mod highlight;
+pub mod typed;
+
+/// How many steps an evaluation may take unless `set_fuel` says otherwise, enough for a million
+/// terms of a sum while still stopping runaway input from freezing the page
+pub const DEFAULT_FUEL: u64 = 1_000_000;
+
+/// How deeply expressions may nest unless `set_max_depth` says otherwise, half the native default
+/// since browsers give WASM a smaller stack
+pub const DEFAULT_MAX_DEPTH: usize = 128;
+
+/// A part of an expression and what it evaluates to, serialized as `{ expression, value }`
+#[derive(Serialize)]
+struct Hover {
+    /// The part of the expression, written back out
+    expression: String,
+    /// What it evaluates to, shown in the chosen notation
+    value: String,
+}
+
+/// The results of a batch of expressions, the variables it changed and any that look misused,
+/// serialized as `{ results, changed, diagnostics }`
+#[derive(Serialize)]
+struct Diff {
+    /// Each expression's outcome, in order
+    results: Vec<Checked>,
+    /// Every variable whose value is different after the batch, in order
+    changed: Vec<String>,
+    /// Variables read too early or never read, found before the batch ran
+    diagnostics: Vec<Diagnostic>,
+}
+
+/// What looks wrong with a variable in a batch
+#[derive(Clone, Copy, Debug, PartialEq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum DiagnosticKind {
+    /// Assigned but never read afterwards
+    Unused,
+    /// Read before anything assigned it
+    Undefined,
+}
+
+/// A variable in a batch that looks misused, serialized as `{ kind, variable, index }`
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct Diagnostic {
+    /// What looks wrong
+    pub kind: DiagnosticKind,
+    /// The variable
+    pub variable: String,
+    /// The expression reading an undefined variable or first assigning an unused one, null for a
+    /// variable only assigned inside another expression
+    pub index: Option<usize>,
+}
+
+/// The limits on evaluation currently set, serialized as `{ max_depth, fuel }` with `fuel` null
+/// when unlimited
+#[derive(Serialize)]
+struct Limits {
+    /// How deeply expressions may nest
+    max_depth: usize,
+    /// How many steps an evaluation may take, if limited
+    fuel: Option<u64>,
+}
 
 /// WASM accessible execution engine for Chalk
 #[wasm_bindgen]
 pub struct MathParser {
+    /// The evaluator holding the variable context between calls
     executor: Evaluator,
+    /// Expressions parsed ahead of time by `compile` and lowered to instructions where they can
+    /// be, by handle
+    compiled: HashMap<u32, (Expr, Option<CompiledExpr>)>,
+    /// The handle given to the next compiled expression
+    next_handle: u32,
+    /// Why the most recent evaluation, validation or compilation failed, if it did
+    last_error: Option<EvalError>,
+    /// How results are shown
+    format: FormatOptions,
+    /// What expressions are allowed to contain beyond the defaults
+    input: TokenizerOptions,
+}
+
+impl Default for MathParser {
+    fn default() -> Self {
+        let mut executor = Evaluator::default();
+        executor.set_fuel(Some(DEFAULT_FUEL));
+        executor.set_max_depth(DEFAULT_MAX_DEPTH);
+        Self {
+            executor,
+            compiled: HashMap::new(),
+            next_handle: 0,
+            last_error: None,
+            format: FormatOptions::default(),
+            input: TokenizerOptions {
+                lenient: true,
+                ..Default::default()
+            },
+        }
+    }
 }
 
 #[wasm_bindgen]
@@ -18,54 +130,1702 @@ impl MathParser {
     /// Creates a new Math Parser
     #[wasm_bindgen(constructor)]
     pub fn new() -> Self {
-        Self {
-            executor: Evaluator::default(),
-        }
+        Self::default()
     }
 
-    /// Checks if an expression depends on a specific variable
-    pub fn depends_on(&mut self, expression: String, dep: char) -> bool {
-        let ast = expression
-            .tokenize()
-            .ok()
-            .and_then(|tokens| Parser::new(tokens).parse().ok())
-            .unwrap_or(Expr::Bool(false));
+    /// Checks if an expression depends on a specific variable. Unparseable expressions don't
+    /// depend on anything, use `depends_on_checked` to tell them apart
+    pub fn depends_on(&self, expression: String, dep: char) -> bool {
+        self.expression_depends_on(&expression, dep)
+            .unwrap_or(false)
+    }
+
+    /// Checks if an expression depends on a specific variable, throwing if it doesn't parse
+    pub fn depends_on_checked(&self, expression: String, dep: char) -> Result<bool, JsError> {
+        self.expression_depends_on(&expression, dep)
+            .map_err(|err| JsError::new(&err.message))
+    }
+
+    /// Every variable an expression depends on as `{ free: [...], bound: [...] }`, following bound
+    /// variables transitively. Unparseable expressions have no dependencies
+    pub fn dependencies(&self, expression: String) -> JsValue {
+        let deps = self.dependencies_of(&expression);
+        serde_wasm_bindgen::to_value(&deps).unwrap_or(JsValue::NULL)
+    }
 
-        self.executor.depends_on(&ast, dep)
+    /// Evaluates an expression at `steps` evenly spaced values of `var` from `start` to `end`
+    /// inclusive, parsing it only once. Points with non-finite results are NaN
+    pub fn sample(
+        &mut self,
+        expression: String,
+        var: char,
+        start: f64,
+        end: f64,
+        steps: usize,
+    ) -> Result<Vec<f64>, JsError> {
+        self.sample_expression(&expression, var, start, end, steps)
+            .map_err(|err| JsError::new(&err.message))
     }
 
     /// Evaluates an expression, returning a string of it's evaluation
+    #[deprecated(note = "failures are all reported as `???`, use `eval_checked` instead")]
+    // The generated JS glue still calls through to this
+    #[allow(deprecated)]
     pub fn eval(&mut self, expression: String) -> String {
-        expression
-            .tokenize()
-            .ok()
-            .and_then(|tokens| Parser::new(tokens).parse().ok())
-            .and_then(|expr| self.executor.exec(&expr).ok())
-            .and_then(|res| Some(format!("{res}")))
+        self.evaluate(&expression)
+            .map(|res| self.display(&res))
             .unwrap_or("???".to_string())
     }
+
+    /// Evaluates an expression, returning `{ ok, value?, error?: { kind, message, position } }`
+    pub fn eval_checked(&mut self, expression: String) -> JsValue {
+        let result = self.evaluate(&expression);
+        let checked = Checked::from(result.map(|result| self.display(&result)));
+        serde_wasm_bindgen::to_value(&checked).unwrap_or(JsValue::NULL)
+    }
+
+    /// Evaluates expressions in order, like re-running a whole worksheet, returning
+    /// `{ results, changed, diagnostics }` with each expression's `{ ok, value?, error? }`, every
+    /// variable whose value is different afterwards, including ones bound through a changed
+    /// variable, and `{ kind, variable, index }` for each variable read before it's assigned
+    /// (`undefined`) or assigned but never read (`unused`)
+    pub fn eval_all_diff(&mut self, expressions: Vec<String>) -> JsValue {
+        let diagnostics = self.diagnose(&expressions);
+        let (results, changed) = self.evaluate_all(&expressions);
+        let diff = Diff {
+            results: results
+                .into_iter()
+                .map(|result| Checked::from(result.map(|result| self.display(&result))))
+                .collect(),
+            changed: changed.into_iter().map(String::from).collect(),
+            diagnostics,
+        };
+        serde_wasm_bindgen::to_value(&diff).unwrap_or(JsValue::NULL)
+    }
+
+    /// Evaluates an expression, returning `{ type, value }` where type is `integer`, `float`,
+    /// `bool` or `error`. Integers outside JS's safe range are given as strings, and fractions carry
+    /// both their exact form and an approximation, like `{ exact: "1/2", approx: 0.5 }`. An
+    /// assignment also names the variable it bound, like `{ type, value, assigned: "x" }`
+    pub fn eval_typed(&mut self, expression: String) -> JsValue {
+        let typed = TypedOutcome::from(self.evaluate_statement(&expression));
+        typed
+            .serialize(&serde_wasm_bindgen::Serializer::new().serialize_maps_as_objects(true))
+            .unwrap_or(JsValue::NULL)
+    }
+
+    /// Evaluates an expression, showing a float result as a fraction like `3/4` when one with a
+    /// denominator up to 10000 is close enough, or as a decimal otherwise. Returns an empty
+    /// string on failure, with the details in `last_error`
+    pub fn eval_fraction(&mut self, expression: String) -> String {
+        let fraction = FormatOptions {
+            fraction: true,
+            ..self.format
+        };
+        self.evaluate(&expression)
+            .map(|result| result.format_with(&fraction))
+            .unwrap_or_default()
+    }
+
+    /// Evaluates the smallest part of an expression containing the character at `offset`,
+    /// returning `{ expression, value }` with both written out, or null on failure with the
+    /// details in `last_error`. Assignments aren't bound, so hovering never changes variables
+    pub fn eval_at(&mut self, expression: String, offset: usize) -> JsValue {
+        let Ok((expression, value)) = self.evaluate_at(&expression, offset) else {
+            return JsValue::NULL;
+        };
+        let hover = Hover {
+            expression,
+            value: self.display(&value),
+        };
+        serde_wasm_bindgen::to_value(&hover).unwrap_or(JsValue::NULL)
+    }
+
+    /// Parses an expression once, returning a handle for `eval_compiled` so evaluating it again
+    /// skips tokenizing, parsing and walking the tree. Throws if the expression doesn't parse
+    pub fn compile(&mut self, expression: String) -> Result<u32, JsError> {
+        self.compile_expression(&expression)
+            .map_err(|err| JsError::new(&err.message))
+    }
+
+    /// Evaluates a compiled expression with the current variables, returning the same
+    /// `{ ok, value?, error? }` object as `eval_checked`. Throws if the handle is unknown
+    pub fn eval_compiled(&mut self, handle: u32) -> Result<JsValue, JsError> {
+        let result = self
+            .evaluate_compiled(handle)
+            .ok_or_else(|| JsError::new(&format!("No compiled expression has handle {handle}")))?;
+        let checked = Checked::from(result.map(|result| self.display(&result)));
+        Ok(serde_wasm_bindgen::to_value(&checked).unwrap_or(JsValue::NULL))
+    }
+
+    /// Forgets a compiled expression, returning whether the handle was in use
+    pub fn free_compiled(&mut self, handle: u32) -> bool {
+        self.compiled.remove(&handle).is_some()
+    }
+
+    /// Checks whether an expression parses without evaluating it, returning `{ valid: true }` or
+    /// `{ valid: false, position, message }`. Assignments are never applied
+    pub fn validate(&mut self, expression: String) -> JsValue {
+        let validation = Validation::from(self.validate_expression(&expression));
+        serde_wasm_bindgen::to_value(&validation).unwrap_or(JsValue::NULL)
+    }
+
+    /// Why the most recent evaluation, validation or compilation failed as
+    /// `{ kind, message, position }` where kind is `tokenize`, `parse` or `runtime`, or null if it
+    /// succeeded
+    pub fn last_error(&self) -> JsValue {
+        self.last_error
+            .as_ref()
+            .and_then(|err| serde_wasm_bindgen::to_value(err).ok())
+            .unwrap_or(JsValue::NULL)
+    }
+
+    /// Forgets the most recent failure, so `last_error` is null until the next one
+    pub fn clear_error(&mut self) {
+        self.last_error = None;
+    }
+
+    /// Splits an expression into `{ start, end, kind }` spans for syntax highlighting, where
+    /// kind is `number`, `operator`, `variable`, `function`, `paren`, `bool` or `invalid`.
+    /// Positions are character indices, counting Unicode code points rather than the UTF-16
+    /// units JS strings index by, so slice `Array.from(expression)` with them instead of the
+    /// string itself. They only differ after a character outside the Basic Multilingual Plane,
+    /// like an emoji, and invalid input never throws
+    pub fn highlight(&self, expression: String) -> JsValue {
+        let spans = highlight::highlight(&expression);
+        serde_wasm_bindgen::to_value(&spans).unwrap_or(JsValue::NULL)
+    }
+
+    /// Evaluates an expression step by step, returning an array of `{ expr, result, kind }`
+    /// steps in evaluation order where kind is `reduction` or `substitution`. Throws if the
+    /// expression doesn't parse or evaluate
+    pub fn eval_steps(&mut self, expression: String) -> Result<JsValue, JsError> {
+        let steps = self
+            .evaluate_steps(&expression)
+            .map_err(|err| JsError::new(&err.message))?;
+        Ok(serde_wasm_bindgen::to_value(&steps).unwrap_or(JsValue::NULL))
+    }
+
+    /// Functions, constants and bound variables starting with `prefix` ignoring case, each as
+    /// `{ name, kind, arity, params }` where kind is `variable`, `constant` or `function`.
+    /// Exact case matches and variables come first
+    pub fn suggest(&self, prefix: String) -> Vec<JsValue> {
+        self.suggestions(&prefix)
+            .iter()
+            .map(|suggestion| serde_wasm_bindgen::to_value(suggestion).unwrap_or(JsValue::NULL))
+            .collect()
+    }
+
+    /// Every function Chalk understands as `{ name, aliases, arity, params, description }`, for
+    /// autocompletion and help
+    pub fn functions(&self) -> JsValue {
+        let functions: Vec<_> = functions::all().collect();
+        serde_wasm_bindgen::to_value(&functions).unwrap_or(JsValue::NULL)
+    }
+
+    /// Evaluates a LaTeX expression like `\\frac{1}{2} + \\sqrt{x}`, returning the same
+    /// `{ ok, value?, error? }` object as `eval_checked`
+    pub fn eval_latex(&mut self, expression: String) -> JsValue {
+        let result = self.evaluate_latex(&expression);
+        let checked = Checked::from(result.map(|result| self.display(&result)));
+        serde_wasm_bindgen::to_value(&checked).unwrap_or(JsValue::NULL)
+    }
+
+    /// Renders an expression as LaTeX, or an empty string if it doesn't parse
+    pub fn to_latex(&self, expression: String) -> String {
+        self.parse(&expression)
+            .map(|expr| expr.to_latex())
+            .unwrap_or_default()
+    }
+
+    /// Renders the syntax tree of an expression as a Graphviz digraph, or an empty string if it
+    /// doesn't parse
+    pub fn ast_dot(&self, expression: String) -> String {
+        self.parse(&expression)
+            .map(|expr| expr.to_dot())
+            .unwrap_or_default()
+    }
+
+    /// Simplifies an expression algebraically, folding constants in the angle mode set, or
+    /// returns an empty string if it doesn't parse
+    pub fn simplify(&self, expression: String) -> String {
+        self.parse(&expression)
+            .map(|expr| simplify_with(&expr, &self.executor).to_string())
+            .unwrap_or_default()
+    }
+
+    /// Replaces every use of a variable in an expression with another expression, following bound
+    /// variables, without evaluating anything. Returns an empty string if either doesn't parse,
+    /// the name isn't a single letter, or bound variables refer to themselves
+    pub fn substitute(&self, expression: String, var: String, replacement: String) -> String {
+        let (Ok(expression), Some(var), Ok(replacement)) = (
+            self.parse(&expression),
+            variable_name(&var),
+            self.parse(&replacement),
+        ) else {
+            return String::new();
+        };
+
+        self.executor
+            .substitute(&expression, var, &replacement)
+            .map(|substituted| substituted.to_string())
+            .unwrap_or_default()
+    }
+
+    /// Stores a formula under a name, apart from the variables so nothing else can refer to it.
+    /// Throws if the expression doesn't parse
+    pub fn store_formula(&mut self, name: String, expression: String) -> Result<(), JsError> {
+        let formula = self
+            .parse(&expression)
+            .map_err(|err| JsError::new(&err.message))?;
+        self.executor.store_formula(name, formula);
+        Ok(())
+    }
+
+    /// The formula stored under a name, as it would be displayed
+    pub fn recall_formula(&self, name: String) -> Option<String> {
+        self.executor.recall_formula(&name).map(ToString::to_string)
+    }
+
+    /// Evaluates a stored formula with the variables in a JSON object like `{ "x": 2 }` bound
+    /// just for this evaluation, returning the same `{ ok, value?, error? }` object as
+    /// `eval_checked`. Throws if no formula has that name or the bindings aren't single letters
+    /// mapped to numbers
+    pub fn eval_formula(
+        &mut self,
+        name: String,
+        bindings_json: String,
+    ) -> Result<JsValue, JsError> {
+        let bindings = formula_bindings(&bindings_json)
+            .ok_or_else(|| JsError::new("Bindings must map single letters to numbers"))?;
+        let result = self
+            .evaluate_formula(&name, &bindings)
+            .ok_or_else(|| JsError::new(&format!("No formula is stored as `{name}`")))?;
+        let checked = Checked::from(result.map(|result| self.display(&result)));
+        Ok(serde_wasm_bindgen::to_value(&checked).unwrap_or(JsValue::NULL))
+    }
+
+    /// Binds a variable to a number, returning false if the name isn't a single letter
+    pub fn set_variable(&mut self, name: String, value: f64) -> bool {
+        let Some(var) = variable_name(&name) else {
+            return false;
+        };
+
+        let expr = if value.fract() == 0.0 && value.abs() < i64::MAX as f64 {
+            Expr::Integer(value as i64)
+        } else {
+            Expr::Real(value)
+        };
+        self.executor.set(var, expr);
+        true
+    }
+
+    /// Gets the expression bound to a variable, as it would be displayed
+    pub fn get_variable(&self, name: String) -> Option<String> {
+        let var = variable_name(&name)?;
+        self.executor.get(var).map(|expr| expr.to_string())
+    }
+
+    /// Unbinds a variable, returning whether it was bound
+    pub fn remove_variable(&mut self, name: String) -> bool {
+        variable_name(&name).is_some_and(|var| self.executor.remove(var).is_some())
+    }
+
+    /// A JSON object mapping every bound variable to its current value. Variables that fail to
+    /// evaluate are mapped to `null`
+    pub fn list_variables(&mut self) -> String {
+        let values: Map<String, Value> = self
+            .executor
+            .values()
+            .into_iter()
+            .map(|(var, result)| (var.to_string(), result.map_or(Value::Null, json_value)))
+            .collect();
+
+        Value::Object(values).to_string()
+    }
+
+    /// Sets whether trig functions work in `"deg"` or `"rad"`, returning false for any other mode
+    pub fn set_angle_mode(&mut self, mode: String) -> bool {
+        let Ok(mode) = AngleMode::try_from(mode.trim()) else {
+            return false;
+        };
+
+        self.executor.set_angle_mode(mode);
+        true
+    }
+
+    /// Every expression evaluated successfully so far that `hist` can recall, oldest first, as
+    /// `[{ number, source, result: { type, value } }]`
+    pub fn get_history(&self) -> JsValue {
+        let entries: Vec<_> = self
+            .executor
+            .history()
+            .iter()
+            .map(TypedEntry::from)
+            .collect();
+        serde_wasm_bindgen::to_value(&entries).unwrap_or(JsValue::NULL)
+    }
+
+    /// Sets whether results are shown in `"sci"`, `"eng"` or `"plain"` notation, returning false
+    /// for any other style. Integers below 10000 are always shown in full
+    pub fn set_notation(&mut self, style: String) -> bool {
+        let Ok(notation) = Notation::try_from(style.trim()) else {
+            return false;
+        };
+
+        self.format.notation = notation;
+        true
+    }
+
+    /// Sets a character accepted between groups of three digits in numbers, like `,` in
+    /// `1,234,567`, or `undefined` to accept none. Only a separator followed by a digit groups, so
+    /// `gcd(1, 2)` still works with `,` but `gcd(1,2)` is malformed grouping
+    pub fn set_input_grouping(&mut self, separator: Option<char>) {
+        self.input.digit_group_char = separator;
+    }
+
+    /// Sets whether `,` between digits is the decimal point, like `3,14`, with `;` separating
+    /// arguments and list items instead, like `gcd(12; 18)`
+    pub fn set_decimal_comma(&mut self, decimal_comma: bool) {
+        self.input.decimal_comma = decimal_comma;
+    }
+
+    /// Sets whether characters pasted from word processors, like `–`, `×`, fullwidth digits and
+    /// non-breaking spaces, are read as the ASCII they stand for, with curly quotes skipped. On
+    /// unless turned off
+    pub fn set_lenient_input(&mut self, lenient: bool) {
+        self.input.lenient = lenient;
+    }
+
+    /// The characters in an expression read as something else, each as
+    /// `{ position, found, read_as }`, so a page can mention that pasted text was cleaned up.
+    /// Always empty when lenient input is off
+    pub fn normalized(&self, expression: String) -> JsValue {
+        serde_wasm_bindgen::to_value(&self.normalizations(&expression)).unwrap_or(JsValue::NULL)
+    }
+
+    /// Sets whether `i` is the imaginary unit, so operations like `sqrt(-4)` give complex results
+    /// rather than failing
+    pub fn set_complex(&mut self, complex: bool) {
+        self.executor.set_complex(complex);
+    }
+
+    /// Sets decimal mode, where numbers with a decimal point are fixed-point with `scale` places,
+    /// up to 4, and results are rounded to them, so `0.1 + 0.2` is exactly `0.30`. Operations that
+    /// can't stay exact, like `sqrt(2)`, give floats, or fail when `strict`. An undefined scale
+    /// goes back to floats
+    pub fn set_decimal_mode(&mut self, scale: Option<u8>, strict: bool) {
+        self.executor.set_numeric_mode(match scale {
+            Some(scale) => NumericMode::Decimal { scale, strict },
+            None => NumericMode::Float,
+        });
+    }
+
+    /// Sets whether operations give NaN where they have no defined result, like `acos(2)` and
+    /// `0/0`, and variables can be bound to infinities, rather than both failing
+    pub fn allow_non_finite(&mut self, allow: bool) {
+        self.executor.allow_non_finite(allow);
+    }
+
+    /// Sets the relative tolerance `~=` compares within, 1e-9 unless set
+    pub fn set_epsilon(&mut self, epsilon: f64) {
+        self.executor.set_epsilon(epsilon);
+    }
+
+    /// Sets how many steps an evaluation may take before failing, or `undefined` for no limit.
+    /// Every node executed, term of a sum or product and point sampled is a step
+    pub fn set_fuel(&mut self, fuel: Option<u64>) {
+        self.executor.set_fuel(fuel);
+    }
+
+    /// Sets how deeply expressions may nest, both when parsing and when executing through bound
+    /// variables, before failing
+    pub fn set_max_depth(&mut self, depth: usize) {
+        self.executor.set_max_depth(depth);
+    }
+
+    /// The limits currently set as `{ max_depth, fuel }`, where `fuel` is null when unlimited.
+    /// Failures from reaching one name it in their `limit` and give its value in `limit_value`
+    pub fn get_limits(&self) -> JsValue {
+        let limits = Limits {
+            max_depth: self.executor.max_depth(),
+            fuel: self.executor.fuel(),
+        };
+        serde_wasm_bindgen::to_value(&limits).unwrap_or(JsValue::NULL)
+    }
+
+    /// Seeds `rand` and `randint`, so the same seed always draws the same sequence
+    pub fn seed(&mut self, seed: u64) {
+        self.executor.seed(seed);
+    }
+
+    /// Unbinds every variable
+    pub fn reset(&mut self) {
+        self.executor.clear()
+    }
+
+    /// Saves every bound variable as JSON, suitable for `load_state`
+    pub fn save_state(&self) -> String {
+        self.executor.to_json()
+    }
+
+    /// Replaces every bound variable with ones saved by `save_state`, throwing if the state is
+    /// invalid. The current variables are kept on failure, and the angle mode and random seed
+    /// are kept either way
+    pub fn load_state(&mut self, json: String) -> Result<(), JsError> {
+        let loaded = Evaluator::from_json(&json)?;
+        self.executor.clear();
+        for (var, expr) in loaded.vars() {
+            self.executor.set(var, expr.clone());
+        }
+        Ok(())
+    }
+}
+
+/// Reads bindings for `eval_formula` from a JSON object mapping single letters to numbers
+fn formula_bindings(json: &str) -> Option<Vec<(char, EvalResult)>> {
+    let bindings: Map<String, Value> = serde_json::from_str(json).ok()?;
+    bindings
+        .iter()
+        .map(|(name, value)| {
+            let value = match value.as_i64() {
+                Some(i) => EvalResult::Integer(i),
+                None => EvalResult::Float(value.as_f64()?),
+            };
+            Some((variable_name(name)?, value))
+        })
+        .collect()
+}
+
+/// Reads a variable name, which must be a single letter
+fn variable_name(name: &str) -> Option<char> {
+    let mut chars = name.trim().chars();
+    match (chars.next(), chars.next()) {
+        (Some(var), None) if var.is_alphabetic() => Some(var),
+        _ => None,
+    }
+}
+
+impl MathParser {
+    /// Tokenizes, parses and executes an expression, keeping track of which stage failed
+    pub fn evaluate(&mut self, expression: &str) -> Result<EvalResult, EvalError> {
+        self.evaluate_statement(expression)
+            .map(ExecOutcome::into_value)
+    }
+
+    /// Parses and executes an expression like [`MathParser::evaluate`], telling assignments apart
+    /// from expressions that only give a value
+    pub fn evaluate_statement(&mut self, expression: &str) -> Result<ExecOutcome, EvalError> {
+        let outcome = pipeline::eval_statement(expression, self.input, &mut self.executor)
+            .map(|(_, outcome)| outcome)
+            .map_err(EvalError::from);
+        self.track(outcome)
+    }
+
+    /// Evaluates expressions in order, carrying on past any that fail, giving each one's result
+    /// and the variables whose values the batch changed
+    pub fn evaluate_all(
+        &mut self,
+        expressions: &[String],
+    ) -> (Vec<Result<EvalResult, EvalError>>, BTreeSet<char>) {
+        let parsed: Vec<_> = expressions
+            .iter()
+            .map(|expression| self.parse(expression))
+            .collect();
+        let statements: Vec<_> = parsed.iter().flatten().cloned().collect();
+        let (executed, changed) = self.executor.exec_all_tracked(&statements);
+
+        // Expressions that didn't parse were never executed, so they're skipped over
+        let mut executed = executed.into_iter();
+        let results = parsed
+            .into_iter()
+            .zip(expressions)
+            .map(|(parsed, expression)| {
+                let result = match parsed {
+                    Ok(_) => executed
+                        .next()
+                        .unwrap_or(Err(RuntimeError::Invalid))
+                        .map_err(EvalError::from),
+                    Err(err) => Err(err),
+                }
+                .map_err(|err| self.limited(err));
+                if let Ok(result) = &result {
+                    self.executor.remember(expression.trim(), result.clone());
+                }
+                result
+            })
+            .collect();
+        (results, changed)
+    }
+
+    /// Checks a batch of expressions for variables read before they're assigned and variables
+    /// assigned but never read, without evaluating them. Expressions that don't parse are skipped,
+    /// and variables already bound count as assigned
+    pub fn diagnose(&self, expressions: &[String]) -> Vec<Diagnostic> {
+        let (indices, statements): (Vec<_>, Vec<_>) = expressions
+            .iter()
+            .enumerate()
+            .filter_map(|(index, expression)| Some((index, self.parse(expression).ok()?)))
+            .unzip();
+        let report = self.executor.analyze(&statements);
+
+        let undefined = report
+            .undefined_uses
+            .into_iter()
+            .map(|(index, var)| Diagnostic {
+                kind: DiagnosticKind::Undefined,
+                variable: var.to_string(),
+                index: Some(indices[index]),
+            });
+        let unused = report.unused.into_iter().map(|var| Diagnostic {
+            kind: DiagnosticKind::Unused,
+            variable: var.to_string(),
+            index: statements
+                .iter()
+                .position(|statement| matches!(statement, Expr::Assignment(v, _) if *v == var))
+                .map(|index| indices[index]),
+        });
+        undefined.chain(unused).collect()
+    }
+
+    /// Tokenizes and parses an expression with the chosen input options and depth limit
+    fn parse(&self, expression: &str) -> Result<Expr, EvalError> {
+        parse(expression, self.input, self.executor.max_depth())
+    }
+
+    /// Shows a result in the chosen notation
+    pub fn display(&self, result: &EvalResult) -> String {
+        result.format_with(&self.format)
+    }
+
+    /// Tokenizes and parses an expression without evaluating it
+    pub fn validate_expression(&mut self, expression: &str) -> Result<Expr, EvalError> {
+        let result = self.parse(expression);
+        self.track(result)
+    }
+
+    /// Why the most recent evaluation, validation or compilation failed, if it did
+    pub fn last_eval_error(&self) -> Option<&EvalError> {
+        self.last_error.as_ref()
+    }
+
+    /// Remembers why a call failed for `last_error`, or forgets the previous failure if it didn't
+    fn track<T>(&mut self, result: Result<T, EvalError>) -> Result<T, EvalError> {
+        let result = result.map_err(|err| self.limited(err));
+        self.last_error = result.as_ref().err().cloned();
+        result
+    }
+
+    /// Fills in what the limit a failure ran into is set to, so the page can offer to raise it
+    fn limited(&self, err: EvalError) -> EvalError {
+        let limit_value = match err.limit {
+            Some(Limit::Depth) => Some(self.executor.max_depth() as u64),
+            Some(Limit::Fuel) => self.executor.fuel(),
+            None => None,
+        };
+        EvalError { limit_value, ..err }
+    }
+
+    /// Parses an expression and stores it under a new handle, compiled with a slot for every
+    /// variable it uses so later changes to them are still seen
+    pub fn compile_expression(&mut self, expression: &str) -> Result<u32, EvalError> {
+        let result = self.parse(expression).map(|expr| self.store_compiled(expr));
+        self.track(result)
+    }
+
+    /// Compiles a parsed expression and stores it under a new handle
+    fn store_compiled(&mut self, expr: Expr) -> u32 {
+        let mut slots = Vec::new();
+        expr.walk(&mut |node| {
+            if let Expr::Variable(var) = node
+                && !slots.contains(var)
+            {
+                slots.push(*var);
+            }
+        });
+        let compiled = self.executor.compile(&expr, &slots);
+
+        let handle = self.next_handle;
+        self.next_handle = self.next_handle.wrapping_add(1);
+        self.compiled.insert(handle, (expr, compiled));
+        handle
+    }
+
+    /// Evaluates a compiled expression, or `None` if the handle is unknown. Runs its instructions
+    /// when the angle mode, tolerance and NaN handling haven't changed since compiling and decimal
+    /// mode is off, executing the tree
+    /// otherwise or if running fails, so errors are always the ones executing gives
+    pub fn evaluate_compiled(&mut self, handle: u32) -> Option<Result<EvalResult, EvalError>> {
+        let result = self.run_compiled(handle)?;
+        Some(self.track(result))
+    }
+
+    /// Evaluates a compiled expression without keeping track of whether it failed
+    fn run_compiled(&mut self, handle: u32) -> Option<Result<EvalResult, EvalError>> {
+        let (expr, compiled) = self.compiled.get(&handle)?;
+
+        if let Some(compiled) = compiled.as_ref().filter(|compiled| {
+            compiled.angle_mode() == self.executor.angle_mode()
+                && compiled.epsilon().to_bits() == self.executor.epsilon().to_bits()
+                && compiled.allows_non_finite() == self.executor.allows_non_finite()
+                && self.executor.numeric_mode() == NumericMode::Float
+        }) {
+            let slots: Option<Vec<_>> = compiled
+                .slots()
+                .iter()
+                .map(|var| self.executor.value(*var)?.ok())
+                .collect();
+            if let Some(Ok(result)) = slots.map(|slots| compiled.run(&slots)) {
+                return Some(Ok(result));
+            }
+        }
+
+        Some(self.executor.exec(expr).map_err(EvalError::from))
+    }
+
+    /// Evaluates the smallest part of an expression containing the character at `offset`,
+    /// returning that part written out and its value
+    pub fn evaluate_at(
+        &mut self,
+        expression: &str,
+        offset: usize,
+    ) -> Result<(String, EvalResult), EvalError> {
+        let result = self
+            .executor
+            .eval_at(expression, offset)
+            .map_err(EvalError::from);
+        self.track(result)
+    }
+
+    /// Evaluates the formula stored under a name with some variables bound just for it, or
+    /// `None` if no formula has that name
+    pub fn evaluate_formula(
+        &mut self,
+        name: &str,
+        bindings: &[(char, EvalResult)],
+    ) -> Option<Result<EvalResult, EvalError>> {
+        let result = self
+            .executor
+            .eval_formula_with(name, bindings)?
+            .map_err(EvalError::from);
+        Some(self.track(result))
+    }
+
+    /// Parses and executes an expression, recording every step
+    pub fn evaluate_steps(&mut self, expression: &str) -> Result<Vec<TraceStep>, EvalError> {
+        let result = self
+            .parse(expression)
+            .and_then(|expr| Ok(self.executor.exec_traced(&expr)?.1));
+        self.track(result)
+    }
+
+    /// The characters in an expression the tokenizer reads as something else, if it's lenient
+    pub fn normalizations(&self, expression: &str) -> Vec<Normalization> {
+        match self.input.lenient {
+            true => normalizations(expression),
+            false => vec![],
+        }
+    }
+
+    /// Names that could complete a prefix, given the variables currently bound
+    pub fn suggestions(&self, prefix: &str) -> Vec<Suggestion> {
+        suggest(prefix, &self.executor)
+    }
+
+    /// Parses and executes a LaTeX expression
+    pub fn evaluate_latex(&mut self, expression: &str) -> Result<EvalResult, EvalError> {
+        let result = parse_latex(expression)
+            .map_err(EvalError::from)
+            .and_then(|expr| Ok(self.executor.exec(&expr)?));
+        self.track(result)
+    }
+
+    /// Checks if an expression depends on a specific variable
+    pub fn expression_depends_on(&self, expression: &str, dep: char) -> Result<bool, EvalError> {
+        let expr = self.parse(expression)?;
+        Ok(self.executor.depends_on(&expr, dep))
+    }
+
+    /// Parses an expression once and evaluates it across a range of values of `var`
+    pub fn sample_expression(
+        &mut self,
+        expression: &str,
+        var: char,
+        start: f64,
+        end: f64,
+        steps: usize,
+    ) -> Result<Vec<f64>, EvalError> {
+        let result = self
+            .parse(expression)
+            .and_then(|expr| Ok(self.executor.sample(&expr, var, start, end, steps)?));
+        self.track(result)
+    }
+
+    /// Every variable an expression depends on, or none if it doesn't parse
+    pub fn dependencies_of(&self, expression: &str) -> Dependencies {
+        self.parse(expression)
+            .map(|expr| self.executor.dependencies(&expr))
+            .unwrap_or_default()
+    }
+}
+
+/// Tokenizes with options and parses an expression nested at most `max_depth` deep, with errors
+/// positioned in the source
+fn parse(expression: &str, input: TokenizerOptions, max_depth: usize) -> Result<Expr, EvalError> {
+    match pipeline::check_with(expression, input, max_depth) {
+        CheckResult::Ok(expr) => Ok(expr),
+        CheckResult::Empty => Err(EvalError::empty()),
+        CheckResult::TokenizeError(err) => Err(err.into()),
+        CheckResult::ParseError(err) => Err(err.into()),
+    }
+}
+
+/// A result as plain JSON
+fn json_value(result: EvalResult) -> Value {
+    match result {
+        EvalResult::Integer(i) => Value::from(i),
+        EvalResult::Float(f) => Value::from(f),
+        EvalResult::Bool(b) => Value::from(b),
+        // JSON has no fractions or decimals, so exact values are given as their nearest number
+        exact @ (EvalResult::Rational(..) | EvalResult::Decimal(..)) => {
+            Value::from(exact.float().unwrap_or(f64::NAN))
+        }
+        // Big integers are stringified, like in typed results
+        EvalResult::BigInt(big) => Value::from(big.to_string()),
+        EvalResult::List(items) => Value::Array(items.into_iter().map(json_value).collect()),
+        EvalResult::Quantity { value, unit } => {
+            serde_json::json!({ "value": value, "unit": unit.to_string() })
+        }
+        EvalResult::Complex(re, im) => serde_json::json!({ "re": re, "im": im }),
+    }
 }
 
 #[cfg(test)]
 mod tests {
-    use crate::MathParser;
+    use std::collections::BTreeSet;
+
+    use chalk_core::{
+        exec::{Dependencies, EvalResult, ExecOutcome},
+        format::Notation,
+        functions,
+        tokenizer::TokenizerOptions,
+    };
+
+    use crate::{
+        DEFAULT_FUEL, DEFAULT_MAX_DEPTH, DiagnosticKind, MathParser,
+        error::{Checked, ErrorKind, EvalError, Limit, Validation},
+        formula_bindings, json_value, parse,
+        typed::TypedEntry,
+    };
 
     #[test]
+    #[allow(deprecated)]
     fn unsuccessful() {
         let mut parser = MathParser::new();
         assert_eq!(parser.eval("1 + 1 !== 2".to_string()), "???".to_string())
     }
 
     #[test]
+    #[allow(deprecated)]
     fn successful() {
         let mut parser = MathParser::new();
         assert_eq!(parser.eval("1 + 1 == 2".to_string()), "true".to_string())
     }
 
     #[test]
+    #[allow(deprecated)]
     fn chained() {
         let mut parser = MathParser::new();
         assert_eq!(parser.eval("x = 4".to_string()), "4".to_string());
         assert_eq!(parser.eval("x + 4".to_string()), "8".to_string());
     }
+
+    #[test]
+    fn tokenize_error() {
+        let mut parser = MathParser::new();
+        let err = parser.evaluate("1 + $").expect_err("$ is invalid");
+
+        assert_eq!(err.kind, ErrorKind::Tokenize);
+        assert_eq!(err.position, Some(4));
+    }
+
+    #[test]
+    fn parse_error() {
+        let mut parser = MathParser::new();
+        let err = parser.evaluate("1 + 1 !== 2").expect_err("!== is invalid");
+
+        assert_eq!(err.kind, ErrorKind::Parse);
+        assert_eq!(err.position, Some(8));
+    }
+
+    #[test]
+    fn runtime_error() {
+        let mut parser = MathParser::new();
+        let err = parser.evaluate("y + 1").expect_err("y is unbound");
+
+        assert_eq!(err.kind, ErrorKind::Runtime);
+        assert_eq!(err.position, None);
+    }
+
+    #[test]
+    fn checked_outcome() {
+        let mut parser = MathParser::new();
+        assert_eq!(parser.evaluate("x = 4"), Ok(EvalResult::Integer(4)));
+        assert_eq!(
+            parser.evaluate_statement("y = x - 1"),
+            Ok(ExecOutcome::Assigned {
+                name: 'y',
+                value: EvalResult::Integer(3)
+            })
+        );
+        assert_eq!(
+            parser.evaluate_statement("y"),
+            Ok(ExecOutcome::Value(EvalResult::Integer(3)))
+        );
+
+        assert_eq!(
+            Checked::from(parser.evaluate("x + 4")),
+            Checked {
+                ok: true,
+                value: Some("8".to_string()),
+                error: None,
+            }
+        );
+
+        let failed = Checked::from(parser.evaluate("(x"));
+        assert!(!failed.ok);
+        assert_eq!(failed.value, None);
+        assert!(matches!(
+            failed.error,
+            Some(EvalError {
+                kind: ErrorKind::Parse,
+                position: Some(2),
+                ..
+            })
+        ));
+    }
+
+    #[test]
+    fn setting_and_getting_variables() {
+        let mut parser = MathParser::new();
+        assert!(parser.set_variable("x".to_string(), 3.0));
+        assert!(parser.set_variable("y".to_string(), -0.5));
+        assert!(!parser.set_variable("xy".to_string(), 1.0));
+
+        assert_eq!(parser.get_variable("x".to_string()), Some("3".to_string()));
+        assert_eq!(
+            parser.get_variable("y".to_string()),
+            Some("-0.5".to_string())
+        );
+        assert_eq!(parser.get_variable("z".to_string()), None);
+        assert_eq!(parser.evaluate("x + 1"), Ok(EvalResult::Integer(4)));
+    }
+
+    #[test]
+    fn removing_variables() {
+        let mut parser = MathParser::new();
+        parser.set_variable("x".to_string(), 3.0);
+
+        assert!(parser.remove_variable("x".to_string()));
+        assert!(!parser.remove_variable("x".to_string()));
+        assert_eq!(parser.get_variable("x".to_string()), None);
+    }
+
+    #[test]
+    fn listing_variables() {
+        let mut parser = MathParser::new();
+        assert_eq!(parser.list_variables(), "{}");
+
+        // y is still bound even though x isn't yet
+        let _ = parser.evaluate("y = 3x + 5");
+        parser.evaluate("b = 1 == 1").expect("Assign b");
+        assert_eq!(parser.list_variables(), r#"{"b":true,"y":null}"#);
+
+        parser.set_variable("x".to_string(), 2.0);
+        assert_eq!(parser.list_variables(), r#"{"b":true,"x":2,"y":11}"#);
+
+        parser.evaluate("h = 1 / 2").expect("Assign h");
+        assert!(parser.list_variables().contains(r#""h":0.5"#));
+
+        parser.evaluate("g = 2^64").expect("Assign g");
+        assert!(
+            parser
+                .list_variables()
+                .contains(r#""g":"18446744073709551616""#)
+        );
+    }
+
+    #[test]
+    fn resetting() {
+        let mut parser = MathParser::new();
+        parser.evaluate("x = 4").expect("Assign x");
+        parser.reset();
+
+        assert_eq!(parser.list_variables(), "{}");
+        assert!(parser.evaluate("x").is_err());
+    }
+
+    #[test]
+    fn saving_and_loading_state() {
+        let mut parser = MathParser::new();
+        parser.evaluate("x = 2").expect("Assign x");
+        parser.evaluate("y = 3x + 5").expect("Assign y");
+        let saved = parser.save_state();
+
+        let mut restored = MathParser::new();
+        restored.load_state(saved).expect("Load state");
+        restored.evaluate("x = 1").expect("Assign x");
+
+        assert_eq!(restored.evaluate("y"), Ok(EvalResult::Float(8.0)));
+    }
+
+    #[test]
+    fn angle_modes() {
+        let mut parser = MathParser::new();
+        assert!(parser.set_angle_mode("deg".to_string()));
+        assert_eq!(parser.evaluate("sin(90)"), Ok(EvalResult::Float(1.0)));
+
+        // Loading variables keeps the mode
+        parser
+            .load_state(MathParser::new().save_state())
+            .expect("Load state");
+        assert_eq!(parser.evaluate("acos(0)"), Ok(EvalResult::Float(90.0)));
+
+        assert!(!parser.set_angle_mode("turns".to_string()));
+        assert!(parser.set_angle_mode("rad".to_string()));
+        assert_eq!(parser.evaluate("sin(0)"), Ok(EvalResult::Float(0.0)));
+    }
+
+    #[test]
+    fn seeding() {
+        let draws = |parser: &mut MathParser| {
+            parser.seed(7);
+            [
+                parser.evaluate("randint(1, 100)"),
+                parser.evaluate("rand()"),
+            ]
+        };
+
+        let mut parser = MathParser::new();
+        let first = draws(&mut parser);
+        assert_eq!(draws(&mut parser), first);
+        assert_eq!(draws(&mut MathParser::new()), first);
+
+        // Loading variables keeps the generator
+        parser.seed(7);
+        parser
+            .load_state(MathParser::new().save_state())
+            .expect("Load state");
+        assert_eq!(parser.evaluate("randint(1, 100)"), first[0]);
+        assert!(parser.evaluate("randint(2, 1)").is_err());
+    }
+
+    #[test]
+    fn history() {
+        let mut parser = MathParser::new();
+        parser.evaluate("6 * 7").expect("Evaluate");
+        assert!(parser.evaluate("y + 1").is_err());
+        assert_eq!(parser.evaluate("hist(1) / 2"), Ok(EvalResult::Integer(21)));
+
+        let entries: Vec<_> = parser
+            .executor
+            .history()
+            .iter()
+            .map(TypedEntry::from)
+            .collect();
+        assert_eq!(
+            serde_json::to_value(entries).expect("Serialize"),
+            serde_json::json!([
+                { "number": 1, "source": "6 * 7", "result": { "type": "integer", "value": 42 } },
+                {
+                    "number": 2,
+                    "source": "hist(1) / 2",
+                    "result": { "type": "integer", "value": 21 }
+                }
+            ])
+        );
+    }
+
+    #[test]
+    fn compiled_expressions() {
+        // Skipping the parse is most of the cost of a short formula. Natively in a release build,
+        // `sin(x)^2 + 3x / (x^2 + 1) - sqrt(|x|)` took about 3.4µs to tokenize, parse and
+        // evaluate but only 0.5µs to evaluate from its compiled handle
+        let mut parser = MathParser::new();
+        let handle = parser.compile_expression("x^2 + 1").expect("Compile");
+
+        parser.set_variable("x".to_string(), 2.0);
+        assert_eq!(
+            parser.evaluate_compiled(handle),
+            Some(Ok(EvalResult::Integer(5)))
+        );
+        parser.set_variable("x".to_string(), 3.0);
+        assert_eq!(
+            parser.evaluate_compiled(handle),
+            Some(Ok(EvalResult::Integer(10)))
+        );
+
+        let other = parser.compile_expression("x / 2").expect("Compile");
+        assert_ne!(handle, other);
+        assert!(parser.free_compiled(handle));
+        assert!(!parser.free_compiled(handle));
+        assert_eq!(parser.evaluate_compiled(handle), None);
+        assert_eq!(parser.evaluate_compiled(99), None);
+        assert_eq!(
+            parser.evaluate_compiled(other),
+            Some(Ok(EvalResult::Rational(3, 2)))
+        );
+
+        let err = parser.compile_expression("1 +").expect_err("Doesn't parse");
+        assert_eq!(err.kind, ErrorKind::Parse);
+    }
+
+    #[test]
+    fn compiled_expressions_follow_changes() {
+        let mut parser = MathParser::new();
+        let handle = parser.compile_expression("y + sin(x)").expect("Compile");
+        assert!(parser.evaluate_compiled(handle).expect("Handle").is_err());
+
+        parser.set_variable("x".to_string(), 90.0);
+        parser.evaluate("y = x + 1").expect("Bind y");
+        let radians = 91.0 + 90f64.sin();
+        assert_eq!(
+            parser.evaluate_compiled(handle),
+            Some(Ok(EvalResult::Float(radians)))
+        );
+
+        parser.set_angle_mode("deg".to_string());
+        assert_eq!(
+            parser.evaluate_compiled(handle),
+            Some(Ok(EvalResult::Float(92.0)))
+        );
+
+        let close = parser.compile_expression("x ~= 91").expect("Compile");
+        assert_eq!(
+            parser.evaluate_compiled(close),
+            Some(Ok(EvalResult::Bool(false)))
+        );
+        parser.set_epsilon(0.1);
+        assert_eq!(
+            parser.evaluate_compiled(close),
+            Some(Ok(EvalResult::Bool(true)))
+        );
+
+        // Assignments can't be compiled, so they're still executed
+        let assign = parser.compile_expression("z = 2x").expect("Compile");
+        assert_eq!(
+            parser.evaluate_compiled(assign),
+            Some(Ok(EvalResult::Float(180.0)))
+        );
+        assert_eq!(parser.evaluate("z"), Ok(EvalResult::Float(180.0)));
+    }
+
+    #[test]
+    fn deep_nesting() {
+        let mut parser = MathParser::new();
+        let source = format!("{}1{}", "(".repeat(50_000), ")".repeat(50_000));
+        let err = parser.evaluate(&source).expect_err("Too deep");
+        assert_eq!(err.kind, ErrorKind::Parse);
+        assert_eq!(err.position, Some(DEFAULT_MAX_DEPTH));
+        assert!(err.message.contains("too deeply nested"));
+
+        parser.evaluate("x = y").expect_err("y isn't bound");
+        let err = parser.evaluate("y = x").expect_err("Cyclic");
+        assert_eq!(err.kind, ErrorKind::Runtime);
+    }
+
+    #[test]
+    fn validating() {
+        let parser = MathParser::new();
+        assert!(
+            Validation::from(parse(
+                "y = 3x + 5",
+                TokenizerOptions::default(),
+                DEFAULT_MAX_DEPTH
+            ))
+            .valid
+        );
+        assert_eq!(parser.get_variable("y".to_string()), None);
+
+        let invalid_char = Validation::from(parse(
+            "2 $ 3",
+            TokenizerOptions::default(),
+            DEFAULT_MAX_DEPTH,
+        ));
+        assert!(!invalid_char.valid);
+        assert_eq!(invalid_char.position, Some(2));
+
+        let incomplete =
+            Validation::from(parse("1 +", TokenizerOptions::default(), DEFAULT_MAX_DEPTH));
+        assert!(!incomplete.valid);
+        assert_eq!(incomplete.position, Some(3));
+        assert!(incomplete.message.is_some());
+
+        assert!(!Validation::from(parse("", TokenizerOptions::default(), DEFAULT_MAX_DEPTH)).valid);
+    }
+
+    #[test]
+    fn dependencies() {
+        let mut parser = MathParser::new();
+        parser.evaluate("x = 2").expect("Assign x");
+        parser.evaluate("y = 3x + 5").expect("Assign y");
+
+        let deps = parser.dependencies_of("y * t");
+        assert_eq!(deps.free, BTreeSet::from(['t']));
+        assert_eq!(deps.bound, BTreeSet::from(['x', 'y']));
+
+        assert_eq!(parser.dependencies_of("y +"), Dependencies::default());
+    }
+
+    #[test]
+    fn sampling() {
+        let mut parser = MathParser::new();
+        parser.evaluate("y = x^2").expect_err("x is unbound");
+
+        assert_eq!(
+            parser.sample_expression("y", 'x', 0.0, 4.0, 5),
+            Ok(vec![0.0, 1.0, 4.0, 9.0, 16.0])
+        );
+        assert_eq!(
+            parser
+                .sample_expression("y +", 'x', 0.0, 4.0, 5)
+                .map_err(|err| err.kind),
+            Err(ErrorKind::Parse)
+        );
+    }
+
+    #[test]
+    fn depends_on() {
+        let mut parser = MathParser::default();
+        parser.evaluate("y = 3x + 5").expect_err("x is unbound");
+
+        assert!(parser.depends_on("y^2".to_string(), 'x'));
+        assert!(!parser.depends_on("y^2".to_string(), 'z'));
+        assert_eq!(parser.expression_depends_on("y^2", 'x'), Ok(true));
+    }
+
+    #[test]
+    fn depends_on_unparseable() {
+        let parser = MathParser::default();
+        assert!(!parser.depends_on("x +".to_string(), 'x'));
+
+        let err = parser
+            .expression_depends_on("x +", 'x')
+            .expect_err("x + doesn't parse");
+        assert_eq!(err.kind, ErrorKind::Parse);
+        assert_eq!(err.position, Some(3));
+    }
+
+    #[test]
+    fn listing_functions() {
+        let functions: Vec<_> = functions::all().collect();
+        let json = serde_json::to_value(&functions).expect("Serialize");
+        let gcd = json
+            .as_array()
+            .and_then(|functions| functions.iter().find(|f| f["name"] == "gcd"))
+            .expect("gcd is listed");
+
+        assert_eq!(
+            gcd,
+            &serde_json::json!({
+                "name": "gcd",
+                "aliases": [],
+                "arity": 2,
+                "params": ["a", "b"],
+                "description": "greatest common divisor",
+            })
+        );
+    }
+
+    #[test]
+    fn evaluating_steps() {
+        let mut parser = MathParser::new();
+        parser.evaluate("x = 4").expect("Assign");
+
+        let steps = parser.evaluate_steps("(1 + 2) * x").expect("Evaluate");
+        let json = serde_json::to_value(&steps).expect("Serialize");
+        assert_eq!(
+            json,
+            serde_json::json!([
+                {
+                    "expr": "1 + 2",
+                    "result": { "type": "integer", "value": 3 },
+                    "kind": "reduction",
+                },
+                {
+                    "expr": "x",
+                    "result": { "type": "integer", "value": 4 },
+                    "kind": "substitution",
+                },
+                {
+                    "expr": "3 * 4",
+                    "result": { "type": "integer", "value": 12 },
+                    "kind": "reduction",
+                },
+            ])
+        );
+
+        let err = parser.evaluate_steps("y + 1").expect_err("y isn't bound");
+        assert_eq!(err.kind, ErrorKind::Runtime);
+    }
+
+    #[test]
+    fn suggesting() {
+        let mut parser = MathParser::new();
+        parser.evaluate("t = 2").expect("Assign");
+
+        let names = |prefix: &str| -> Vec<String> {
+            parser
+                .suggestions(prefix)
+                .into_iter()
+                .map(|suggestion| suggestion.name)
+                .collect()
+        };
+        assert_eq!(names("t"), ["t", "tan", "tanh", "totient"]);
+        assert_eq!(names("Pi"), ["pi", "piecewise"]);
+
+        let json = serde_json::to_value(parser.suggestions("invm")).expect("Serialize");
+        assert_eq!(
+            json,
+            serde_json::json!([{
+                "name": "invmod",
+                "kind": "function",
+                "arity": 2,
+                "params": ["a", "m"],
+            }])
+        );
+    }
+
+    #[test]
+    fn latex() {
+        let parser = MathParser::default();
+        assert_eq!(
+            parser.to_latex("y = x^2 / 2".to_string()),
+            "y = \\frac{x^{2}}{2}"
+        );
+        assert_eq!(parser.to_latex("x +".to_string()), "");
+    }
+
+    #[test]
+    fn dot() {
+        let parser = MathParser::default();
+        let dot = parser.ast_dot("(1+2)*3".to_string());
+        assert!(dot.starts_with("digraph {"));
+        assert_eq!(dot.matches(" -> ").count(), 5);
+        assert_eq!(parser.ast_dot("1 +".to_string()), "");
+    }
+
+    #[test]
+    fn simplifying() {
+        let mut parser = MathParser::default();
+        assert_eq!(
+            parser.simplify("y = (2 + 3) * x ^ 1 + 0".to_string()),
+            "y = 5 * x"
+        );
+        assert_eq!(parser.simplify("x *".to_string()), "");
+
+        // Constants fold in the angle mode set
+        assert!(parser.set_angle_mode("deg".to_string()));
+        assert_eq!(parser.simplify("sin(90) * x".to_string()), "x");
+    }
+
+    #[test]
+    fn substituting() {
+        let mut parser = MathParser::default();
+        let substitute = |parser: &MathParser, expression: &str, var: &str, replacement: &str| {
+            parser.substitute(
+                expression.to_string(),
+                var.to_string(),
+                replacement.to_string(),
+            )
+        };
+
+        assert_eq!(
+            substitute(&parser, "x^2 + x", "x", "(y+1)"),
+            "(y + 1) ^ 2 + (y + 1)"
+        );
+        parser.set_variable("x".to_string(), 1.0);
+        parser.evaluate("f = 3x").expect("Bind f");
+        assert_eq!(substitute(&parser, "f - y", "x", "2"), "3 * 2 - y");
+
+        assert_eq!(substitute(&parser, "x +", "x", "2"), "");
+        assert_eq!(substitute(&parser, "x", "xy", "2"), "");
+    }
+
+    #[test]
+    fn evaluating_latex() {
+        let mut parser = MathParser::default();
+        parser.evaluate("x = 16").expect("Assign x");
+
+        assert_eq!(
+            parser.evaluate_latex("\\frac{1}{2} + \\sqrt{x}"),
+            Ok(EvalResult::Float(4.5))
+        );
+
+        let err = parser
+            .evaluate_latex("\\frac{1}")
+            .expect_err("Missing denominator");
+        assert_eq!(err.kind, ErrorKind::Parse);
+        assert_eq!(err.position, Some(8));
+    }
+
+    #[test]
+    fn units() {
+        let mut parser = MathParser::default();
+        let distance = parser.evaluate("3km + 200m").expect("Add lengths");
+        assert_eq!(distance.to_string(), "3.2 km");
+        assert_eq!(
+            json_value(distance),
+            serde_json::json!({ "value": 3.2, "unit": "km" })
+        );
+        assert_eq!(
+            parser
+                .evaluate("60 mph * 2 h in km")
+                .map(|result| result.to_string()),
+            Ok("193.12128 km".to_string())
+        );
+
+        let err = parser.evaluate("1 km + 1 h").expect_err("Length plus time");
+        assert_eq!(err.kind, ErrorKind::Runtime);
+        assert_eq!(err.message, "Those units don't measure the same thing :(");
+    }
+
+    #[test]
+    fn fuel() {
+        let mut parser = MathParser::new();
+        assert_eq!(parser.executor.fuel(), Some(DEFAULT_FUEL));
+        assert!(parser.evaluate("sum(i, 1, 1000, i)").is_ok());
+
+        parser.set_fuel(Some(1000));
+        let err = parser
+            .evaluate("sum(i, 1, 1000, i)")
+            .expect_err("Out of fuel");
+        assert_eq!(err.kind, ErrorKind::Runtime);
+        assert_eq!(err.message, "Expression took too long to evaluate :(");
+
+        parser.set_fuel(None);
+        assert_eq!(
+            parser.evaluate("sum(i, 1, 1000, i)"),
+            Ok(EvalResult::Integer(500500))
+        );
+    }
+
+    #[test]
+    fn limits() {
+        let mut parser = MathParser::new();
+        assert_eq!(parser.executor.max_depth(), DEFAULT_MAX_DEPTH);
+        assert_eq!(parser.executor.fuel(), Some(DEFAULT_FUEL));
+
+        // Too deep to parse, naming the limit and what it's set to
+        let nested = format!("{}1{}", "(".repeat(150), ")".repeat(150));
+        let err = parser.evaluate(&nested).expect_err("Too deep");
+        assert_eq!(err.kind, ErrorKind::Parse);
+        assert_eq!(
+            (err.limit, err.limit_value),
+            (Some(Limit::Depth), Some(DEFAULT_MAX_DEPTH as u64))
+        );
+        assert_eq!(parser.last_eval_error(), Some(&err));
+        parser.set_max_depth(200);
+        assert_eq!(parser.evaluate(&nested), Ok(EvalResult::Integer(1)));
+
+        // Too deep to execute through bound variables
+        for statement in ["a = 1", "b = a + 1", "c = b + 1"] {
+            assert!(parser.evaluate(statement).is_ok(), "{statement}");
+        }
+        parser.set_max_depth(4);
+        let err = parser.evaluate("c").expect_err("Too deep");
+        assert_eq!(err.kind, ErrorKind::Runtime);
+        assert_eq!((err.limit, err.limit_value), (Some(Limit::Depth), Some(4)));
+        parser.set_max_depth(DEFAULT_MAX_DEPTH);
+        assert_eq!(parser.evaluate("c"), Ok(EvalResult::Integer(3)));
+
+        // Too many steps, even through a batch
+        let sum = "sum(i, 1, 500000, i)".to_string();
+        let (results, _) = parser.evaluate_all(std::slice::from_ref(&sum));
+        let err = results[0].clone().expect_err("Out of fuel");
+        assert_eq!(
+            (err.limit, err.limit_value),
+            (Some(Limit::Fuel), Some(DEFAULT_FUEL))
+        );
+        parser.set_fuel(Some(10 * DEFAULT_FUEL));
+        assert_eq!(parser.evaluate(&sum), Ok(EvalResult::Integer(125000250000)));
+
+        // Trial dividing a large prime counts too, rather than freezing the page
+        parser.set_fuel(Some(DEFAULT_FUEL));
+        for expression in [
+            "factorize(9223372036854775783)",
+            "numdivisors(9223372036854775783)",
+        ] {
+            let err = parser.evaluate(expression).expect_err("Out of fuel");
+            assert_eq!(err.limit, Some(Limit::Fuel), "{expression}");
+        }
+        assert_eq!(
+            parser.evaluate("numdivisors(2^62)"),
+            Ok(EvalResult::Integer(63))
+        );
+
+        // Other failures aren't about limits
+        let err = parser.evaluate("q + 1").expect_err("Unbound");
+        assert_eq!((err.limit, err.limit_value), (None, None));
+    }
+
+    #[test]
+    fn formulas() {
+        let mut parser = MathParser::new();
+        parser
+            .store_formula("quad".to_string(), "x^2 + 3x + 1".to_string())
+            .expect("Store");
+        assert_eq!(
+            parser.recall_formula("quad".to_string()),
+            Some("x ^ 2 + 3 * x + 1".to_string())
+        );
+        assert_eq!(parser.recall_formula("cube".to_string()), None);
+
+        let bindings = formula_bindings(r#"{ "x": 2 }"#).expect("Bindings");
+        assert_eq!(
+            parser.evaluate_formula("quad", &bindings),
+            Some(Ok(EvalResult::Integer(11)))
+        );
+        let bindings = formula_bindings(r#"{ "x": 0.5 }"#).expect("Bindings");
+        assert_eq!(
+            parser.evaluate_formula("quad", &bindings),
+            Some(Ok(EvalResult::Float(2.75)))
+        );
+        assert!(
+            parser
+                .evaluate_formula("quad", &[])
+                .is_some_and(|r| r.is_err())
+        );
+        assert_eq!(parser.evaluate_formula("cube", &[]), None);
+
+        for invalid in [r#"{ "xy": 1 }"#, r#"{ "x": "1" }"#, "[1]", "{"] {
+            assert_eq!(formula_bindings(invalid), None, "{invalid}");
+        }
+    }
+
+    #[test]
+    fn hover() {
+        let mut parser = MathParser::new();
+        parser.evaluate("x = 3").expect("Assign");
+
+        assert_eq!(
+            parser.evaluate_at("1 + 1 - (2 * x)", 11),
+            Ok(("2 * x".to_string(), EvalResult::Integer(6)))
+        );
+        assert_eq!(
+            parser.evaluate_at("1 + 1 - (2 * x)", 13),
+            Ok(("x".to_string(), EvalResult::Integer(3)))
+        );
+
+        assert!(parser.evaluate_at("2 + 5 / z", 8).is_err());
+        let err = parser.last_eval_error().expect("Failed");
+        assert_eq!(err.kind, ErrorKind::Runtime);
+        assert_eq!(err.position, Some(8));
+    }
+
+    #[test]
+    fn last_error() {
+        let mut parser = MathParser::new();
+        assert!(parser.evaluate("1 + 2").is_ok());
+        assert_eq!(parser.last_eval_error(), None);
+
+        assert!(parser.evaluate("3 $ 4").is_err());
+        let err = parser.last_eval_error().expect("Failed");
+        assert_eq!(err.kind, ErrorKind::Tokenize);
+        assert_eq!(err.position, Some(2));
+
+        // Every call replaces the last error rather than leaving a stale one behind
+        assert!(parser.validate_expression("(1 +").is_err());
+        assert_eq!(
+            parser.last_eval_error().map(|err| err.kind),
+            Some(ErrorKind::Parse)
+        );
+        assert!(parser.evaluate("q + 1").is_err());
+        assert_eq!(
+            parser.last_eval_error().map(|err| err.kind),
+            Some(ErrorKind::Runtime)
+        );
+        assert!(parser.compile_expression("2 * 3").is_ok());
+        assert_eq!(parser.last_eval_error(), None);
+
+        assert!(parser.evaluate_latex("\\frac{1}{").is_err());
+        assert!(parser.last_eval_error().is_some());
+        parser.clear_error();
+        assert_eq!(parser.last_eval_error(), None);
+    }
+
+    #[test]
+    fn evaluating_worksheets() {
+        let mut parser = MathParser::new();
+        let sheet = |statements: &[&str]| -> Vec<String> {
+            statements
+                .iter()
+                .map(|statement| statement.to_string())
+                .collect()
+        };
+        parser.evaluate_all(&sheet(&["a = 1", "b = 2", "c = 3"]));
+
+        let (results, changed) = parser.evaluate_all(&sheet(&["a = 1", "b = 4", "c = 3"]));
+        assert_eq!(
+            results,
+            [
+                Ok(EvalResult::Integer(1)),
+                Ok(EvalResult::Integer(4)),
+                Ok(EvalResult::Integer(3))
+            ]
+        );
+        assert_eq!(changed, BTreeSet::from(['b']));
+
+        // Expressions that fail don't stop the rest, and nothing changes without assignments
+        let (results, changed) = parser.evaluate_all(&sheet(&["a + b", "(1 +", "q", "c * 2"]));
+        let kinds: Vec<_> = results
+            .iter()
+            .map(|result| result.as_ref().map_err(|err| err.kind))
+            .collect();
+        assert_eq!(
+            kinds,
+            [
+                Ok(&EvalResult::Integer(5)),
+                Err(ErrorKind::Parse),
+                Err(ErrorKind::Runtime),
+                Ok(&EvalResult::Integer(6))
+            ]
+        );
+        assert!(changed.is_empty());
+        let recalled: Vec<_> = parser
+            .executor
+            .history()
+            .iter()
+            .map(|entry| entry.source.as_str())
+            .collect();
+        assert_eq!(recalled.last(), Some(&"c * 2"));
+
+        // Diagnostics point at expressions by their place in the batch, counting ones that
+        // don't parse
+        let diagnostics = parser.diagnose(&sheet(&["(1 +", "d = 2q", "q = 1", "f = d", "g = 3"]));
+        let found: Vec<_> = diagnostics
+            .iter()
+            .map(|diagnostic| {
+                (
+                    diagnostic.kind,
+                    diagnostic.variable.as_str(),
+                    diagnostic.index,
+                )
+            })
+            .collect();
+        assert_eq!(
+            found,
+            [
+                (DiagnosticKind::Undefined, "q", Some(1)),
+                (DiagnosticKind::Unused, "f", Some(3)),
+                (DiagnosticKind::Unused, "g", Some(4)),
+            ]
+        );
+    }
+
+    #[test]
+    fn fractions() {
+        let mut parser = MathParser::new();
+        assert_eq!(parser.eval_fraction("0.5 + 0.25".to_string()), "3/4");
+        assert_eq!(parser.eval_fraction("-1 / 8.0".to_string()), "-1/8");
+        assert_eq!(parser.eval_fraction("pi".to_string()), "3.14159265358979");
+        assert_eq!(parser.eval_fraction("1 +".to_string()), "");
+        assert!(parser.last_eval_error().is_some());
+    }
+
+    #[test]
+    #[allow(deprecated)]
+    fn notation() {
+        let mut parser = MathParser::new();
+        assert_eq!(parser.eval("2^40".to_string()), "1099511627776");
+
+        assert!(parser.set_notation("eng".to_string()));
+        assert_eq!(parser.eval("2^40".to_string()), "1.099511627776e12");
+        assert_eq!(parser.eval("-12345".to_string()), "-12.345e3");
+        assert_eq!(parser.eval("1 / 4.0".to_string()), "250e-3");
+        assert_eq!(parser.eval("12".to_string()), "12");
+
+        assert!(parser.set_notation("sci".to_string()));
+        assert_eq!(parser.eval("0.001 * 3".to_string()), "3e-3");
+        assert!(!parser.set_notation("roman".to_string()));
+        assert_eq!(parser.format.notation, Notation::Scientific);
+    }
+
+    #[test]
+    fn input_grouping() {
+        let mut parser = MathParser::new();
+        assert!(parser.evaluate("1,234").is_err());
+
+        parser.set_input_grouping(Some(','));
+        assert_eq!(
+            parser.evaluate("1,234,567 - 1"),
+            Ok(EvalResult::Integer(1_234_566))
+        );
+        assert_eq!(parser.evaluate("gcd(4, 6)"), Ok(EvalResult::Integer(2)));
+
+        let err = parser.evaluate("1,23,4").expect_err("Malformed");
+        assert_eq!(err.kind, ErrorKind::Tokenize);
+        assert_eq!(err.position, Some(1));
+        assert!(parser.validate_expression("12,34").is_err());
+    }
+
+    #[test]
+    fn decimal_comma() {
+        let mut parser = MathParser::new();
+        parser.set_decimal_comma(true);
+        assert_eq!(parser.evaluate("2,5 * 2"), Ok(EvalResult::Float(5.0)));
+        assert_eq!(parser.evaluate("gcd(12; 18)"), Ok(EvalResult::Integer(6)));
+        assert_eq!(parser.evaluate("sum([1,5; 2])"), Ok(EvalResult::Float(3.5)));
+
+        let err = parser.evaluate("gcd(12, 18)").expect_err("Comma separator");
+        assert_eq!((err.kind, err.position), (ErrorKind::Tokenize, Some(6)));
+
+        parser.set_decimal_comma(false);
+        assert_eq!(parser.evaluate("gcd(12, 18)"), Ok(EvalResult::Integer(6)));
+        assert!(parser.evaluate("2,5").is_err());
+    }
+
+    #[test]
+    fn decimal_mode() {
+        let mut parser = MathParser::new();
+        parser.set_decimal_mode(Some(2), false);
+        assert_eq!(parser.evaluate("0.1 + 0.2"), Ok(EvalResult::Decimal(30, 2)));
+        assert_eq!(parser.evaluate("10 / 3"), Ok(EvalResult::Decimal(333, 2)));
+        assert_eq!(parser.evaluate("sin(0)"), Ok(EvalResult::Float(0.0)));
+
+        // Compiled expressions are evaluated in decimal mode too
+        let handle = parser.compile_expression("x * 1.5").expect("Compile");
+        parser.set_variable("x".to_string(), 3.0);
+        assert_eq!(
+            parser.evaluate_compiled(handle),
+            Some(Ok(EvalResult::Decimal(450, 2)))
+        );
+
+        parser.set_decimal_mode(Some(2), true);
+        let err = parser.evaluate("sqrt(2)").expect_err("Inexact");
+        assert_eq!(err.kind, ErrorKind::Runtime);
+
+        parser.set_decimal_mode(None, true);
+        assert_eq!(parser.evaluate("0.5"), Ok(EvalResult::Float(0.5)));
+    }
+
+    #[test]
+    fn pasted_input() {
+        let mut parser = MathParser::new();
+        let pasted = "“12 – ３” × 2";
+        assert_eq!(parser.evaluate(pasted), Ok(EvalResult::Integer(6)));
+        assert_eq!(
+            parser
+                .normalizations(pasted)
+                .iter()
+                .map(|normalized| (normalized.found, normalized.read_as))
+                .collect::<Vec<_>>(),
+            [('“', ' '), ('–', '-'), ('３', '3'), ('”', ' '), ('×', '*')]
+        );
+
+        parser.set_lenient_input(false);
+        let err = parser.evaluate(pasted).expect_err("Strict");
+        assert_eq!((err.kind, err.position), (ErrorKind::Tokenize, Some(0)));
+        assert!(parser.normalizations(pasted).is_empty());
+    }
 }
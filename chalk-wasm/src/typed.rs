@@ -0,0 +1,281 @@
+//! Results handed to JS with their type attached, so numbers don't need re-parsing
+
+use chalk_core::{
+    exec::{EvalResult, ExecOutcome},
+    history::HistoryEntry,
+};
+use serde::Serialize;
+
+use crate::error::EvalError;
+
+/// Largest integer a JS number can represent exactly
+const MAX_SAFE_INTEGER: i64 = (1 << 53) - 1;
+
+/// An integer's value, stringified if a JS number would lose precision
+#[derive(Clone, Debug, PartialEq, Serialize)]
+#[serde(untagged)]
+pub enum Integer {
+    /// Fits within JS's safe integer range
+    Exact(i64),
+    /// Too large for a JS number, given in decimal instead
+    Text(String),
+}
+
+impl From<i64> for Integer {
+    fn from(i: i64) -> Self {
+        if i.unsigned_abs() <= MAX_SAFE_INTEGER as u64 {
+            Self::Exact(i)
+        } else {
+            Self::Text(i.to_string())
+        }
+    }
+}
+
+/// A result serialized as `{ type, value }`
+#[derive(Clone, Debug, PartialEq, Serialize)]
+#[serde(tag = "type", content = "value", rename_all = "lowercase")]
+pub enum Typed {
+    /// An integer result
+    Integer(Integer),
+    /// A floating point result
+    Float(f64),
+    /// A boolean result
+    Bool(bool),
+    /// An exact fraction
+    Rational {
+        /// The numerator, carrying the sign
+        numerator: Integer,
+        /// The denominator, always above 1
+        denominator: Integer,
+        /// The fraction written out, like `1/2`
+        exact: String,
+        /// The nearest float to the fraction
+        approx: f64,
+    },
+    /// A list of results
+    List(Vec<Typed>),
+    /// A measurement in some unit
+    Quantity {
+        /// How many of the unit
+        value: f64,
+        /// The unit, written like `km/h`
+        unit: String,
+    },
+    /// A fixed-point decimal, from decimal mode
+    Decimal {
+        /// The decimal counted in its last place, so 0.30 is 30
+        mantissa: Integer,
+        /// How many decimal places it has
+        scale: u8,
+        /// The decimal written out with every place, like `0.30`
+        exact: String,
+        /// The nearest float to the decimal
+        approx: f64,
+    },
+    /// A complex number
+    Complex {
+        /// The real part
+        re: f64,
+        /// The imaginary part
+        im: f64,
+    },
+    /// Evaluation failed
+    Error(EvalError),
+}
+
+impl From<EvalResult> for Typed {
+    fn from(result: EvalResult) -> Self {
+        match result {
+            EvalResult::Integer(i) => Self::Integer(i.into()),
+            EvalResult::Float(f) => Self::Float(f),
+            EvalResult::Bool(b) => Self::Bool(b),
+            rational @ EvalResult::Rational(numerator, denominator) => {
+                let (exact, approx) = rational.exact_and_approx().unwrap_or_default();
+                Self::Rational {
+                    numerator: numerator.into(),
+                    denominator: denominator.into(),
+                    exact,
+                    approx,
+                }
+            }
+            EvalResult::BigInt(big) => Self::Integer(Integer::Text(big.to_string())),
+            EvalResult::List(items) => Self::List(items.into_iter().map(Self::from).collect()),
+            EvalResult::Quantity { value, unit } => Self::Quantity {
+                value,
+                unit: unit.to_string(),
+            },
+            EvalResult::Complex(re, im) => Self::Complex { re, im },
+            decimal @ EvalResult::Decimal(mantissa, scale) => Self::Decimal {
+                mantissa: mantissa.into(),
+                scale,
+                exact: decimal.to_string(),
+                approx: decimal.float().unwrap_or(f64::NAN),
+            },
+        }
+    }
+}
+
+impl From<Result<EvalResult, EvalError>> for Typed {
+    fn from(result: Result<EvalResult, EvalError>) -> Self {
+        result.map_or_else(Self::Error, Self::from)
+    }
+}
+
+/// A statement's typed result, along with the variable it bound if it was an assignment. Flattened
+/// into `{ type, value, assigned? }`, so maps need serializing as objects
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct TypedOutcome {
+    /// What the statement gave
+    #[serde(flatten)]
+    pub result: Typed,
+    /// The variable bound, left out for anything but an assignment
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub assigned: Option<String>,
+}
+
+impl From<Result<ExecOutcome, EvalError>> for TypedOutcome {
+    fn from(outcome: Result<ExecOutcome, EvalError>) -> Self {
+        let assigned = outcome
+            .as_ref()
+            .ok()
+            .and_then(ExecOutcome::assigned)
+            .map(String::from);
+        Self {
+            result: Typed::from(outcome.map(ExecOutcome::into_value)),
+            assigned,
+        }
+    }
+}
+
+/// A history entry serialized as `{ number, source, result }`, with the result typed
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct TypedEntry {
+    /// The number `hist` recalls the entry with
+    pub number: usize,
+    /// The expression as it was written
+    pub source: String,
+    /// What the expression evaluated to
+    pub result: Typed,
+}
+
+impl From<&HistoryEntry> for TypedEntry {
+    fn from(entry: &HistoryEntry) -> Self {
+        Self {
+            number: entry.number,
+            source: entry.source.clone(),
+            result: Typed::from(entry.result.clone()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use chalk_core::{
+        exec::{EvalResult, ExecOutcome},
+        units::Unit,
+    };
+    use serde_json::json;
+
+    use super::{Integer, MAX_SAFE_INTEGER, Typed, TypedOutcome};
+
+    #[test]
+    fn discriminants() {
+        let cases = [
+            (
+                EvalResult::Integer(42),
+                json!({ "type": "integer", "value": 42 }),
+            ),
+            (
+                EvalResult::Float(3.5),
+                json!({ "type": "float", "value": 3.5 }),
+            ),
+            (
+                EvalResult::Bool(true),
+                json!({ "type": "bool", "value": true }),
+            ),
+            (
+                EvalResult::Rational(-1, 3),
+                json!({ "type": "rational", "value": {
+                    "numerator": -1,
+                    "denominator": 3,
+                    "exact": "-1/3",
+                    "approx": -1.0 / 3.0,
+                } }),
+            ),
+            (
+                EvalResult::Decimal(30, 2),
+                json!({ "type": "decimal", "value": {
+                    "mantissa": 30,
+                    "scale": 2,
+                    "exact": "0.30",
+                    "approx": 0.3,
+                } }),
+            ),
+            (
+                EvalResult::List(vec![EvalResult::Integer(2), EvalResult::Bool(false)]),
+                json!({ "type": "list", "value": [
+                    { "type": "integer", "value": 2 },
+                    { "type": "bool", "value": false },
+                ] }),
+            ),
+        ];
+
+        let speed = Unit::parse("mph").expect("Known unit");
+        let typed = serde_json::to_value(Typed::from(EvalResult::Quantity {
+            value: 30.0,
+            unit: speed,
+        }))
+        .expect("Serialize");
+        assert_eq!(
+            typed,
+            json!({ "type": "quantity", "value": { "value": 30.0, "unit": "mi/h" } })
+        );
+
+        let big = EvalResult::BigInt("18446744073709551616".parse().expect("Digits"));
+        let typed = serde_json::to_value(Typed::from(big)).expect("Serialize");
+        assert_eq!(
+            typed,
+            json!({ "type": "integer", "value": "18446744073709551616" })
+        );
+
+        for (result, expected) in cases {
+            let typed = serde_json::to_value(Typed::from(result)).expect("Serialize");
+            assert_eq!(typed, expected);
+        }
+    }
+
+    #[test]
+    fn assignments_name_their_variable() {
+        let assigned = TypedOutcome::from(Ok(ExecOutcome::Assigned {
+            name: 'x',
+            value: EvalResult::Integer(4),
+        }));
+        assert_eq!(
+            serde_json::to_value(assigned).expect("Serialize"),
+            json!({ "type": "integer", "value": 4, "assigned": "x" })
+        );
+
+        let value = TypedOutcome::from(Ok(ExecOutcome::Value(EvalResult::Bool(true))));
+        assert_eq!(
+            serde_json::to_value(value).expect("Serialize"),
+            json!({ "type": "bool", "value": true })
+        );
+    }
+
+    #[test]
+    fn unsafe_integers_are_stringified() {
+        assert_eq!(
+            Integer::from(MAX_SAFE_INTEGER),
+            Integer::Exact(MAX_SAFE_INTEGER)
+        );
+        assert_eq!(
+            Integer::from(-MAX_SAFE_INTEGER - 1),
+            Integer::Text("-9007199254740992".to_string())
+        );
+        // The one integer without a positive counterpart
+        assert_eq!(
+            Integer::from(i64::MIN),
+            Integer::Text("-9223372036854775808".to_string())
+        );
+    }
+}
@@ -0,0 +1,537 @@
+//! Command line argument parsing
+
+use std::{error::Error, fmt::Display, path::PathBuf};
+
+use chalk_core::{
+    decimal::MAX_SCALE,
+    exec::NumericMode,
+    format::{DigitSeparator, FormatOptions, Notation},
+};
+
+/// Decimal places used by `--fixed` when no `--precision` is given
+const DEFAULT_FIXED_PRECISION: usize = 6;
+
+/// Decimal places used by `--decimal-strict` when no `--decimal-scale` is given
+const DEFAULT_DECIMAL_SCALE: u8 = 2;
+
+/// Extension used to recognize script files passed without `--file`
+const SCRIPT_EXTENSION: &str = ".chalk";
+
+/// Printed for `--help`
+pub const USAGE: &str = "\
+Usage: chalk [OPTIONS] [EXPRESSION | FILE.chalk]
+
+With an expression, evaluates it and exits. With a script file, evaluates
+it line by line. With neither, starts the REPL, or reads one expression per
+line from stdin if it isn't a terminal.
+
+Options:
+  -f, --file <PATH>    evaluate a script file line by line
+  -D, --define <x=expr>  bind a variable before evaluating anything, repeatable
+  -p, --precision <N>  round floats to N decimal places, trimming trailing zeros
+      --fixed          always show exactly N decimal places (default N is 6)
+      --input-grouping <C>
+                       accept C between groups of three digits in input, like 1,234,567
+      --decimal-comma  read 3,14 as 3.14, separating arguments with ; like gcd(12; 18)
+      --strict-input   reject pasted characters like – and × instead of reading them as - and *
+      --fraction       show floats as fractions like 3/4 when one is close enough
+      --decimal-only   show exact fractions like 1/3 only as decimals, without 1/3 ≈
+      --notation <sci|eng|plain>
+                       show numbers in scientific, engineering or plain notation
+      --group-digits   separate every three digits with commas, like 1,234,567
+      --group-separator <,|_|space>
+                       separate every three digits with the given separator
+      --degrees        evaluate trig functions in degrees instead of radians
+      --seed <N>       seed rand() and randint() so results can be reproduced
+      --decimal-scale <N>
+                       keep decimals exact to N places (at most 4), like 0.1 + 0.2 = 0.30
+      --decimal-strict fail on results that can't be exact decimals, like sqrt(2),
+                       keeping 2 places unless --decimal-scale is given
+  -t, --time           print tokenize, parse and eval timings to stderr
+      --no-color       don't color output (also disabled by NO_COLOR or when piped)
+  -q, --quiet          only print the last result of a script
+  -i, --interactive    start the REPL after running a script
+  -w, --watch          run a script again whenever it changes, until Ctrl-C
+      --stdin          read expressions from stdin even if it's a terminal
+      --serve          answer JSON requests from stdin, one per line, like
+                       {\"id\": 1, \"op\": \"eval\", \"expr\": \"x^2\"}, with ops eval,
+                       vars, reset and validate
+      --tokens         print the tokens of the expression instead of evaluating it
+      --ast            print the syntax tree of the expression instead of evaluating it
+      --dot            print the syntax tree as a Graphviz digraph, e.g. for `dot -Tpng`
+      --list-functions print every function chalk understands
+  -h, --help           print this message
+
+Exit codes:
+  0  success
+  1  bad arguments, unreadable file, or a failing line when reading stdin
+  2  a statement failed to tokenize or parse
+  3  a statement failed to evaluate";
+
+/// Debug views that show how an expression was understood instead of evaluating it
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum DebugView {
+    /// The token stream, one token per line
+    Tokens,
+    /// The indented syntax tree
+    Ast,
+    /// The syntax tree as a Graphviz digraph
+    Dot,
+}
+
+/// Everything the CLI was asked to do
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Args {
+    /// A script file to evaluate line by line
+    pub file: Option<PathBuf>,
+    /// Only print the result of the final line of a script
+    pub quiet: bool,
+    /// Drop into the REPL after running the script
+    pub interactive: bool,
+    /// Run the script again every time it changes
+    pub watch: bool,
+    /// Read expressions from stdin even if it's a terminal
+    pub stdin: bool,
+    /// Answer JSON requests from stdin instead of reading expressions
+    pub serve: bool,
+    /// Show a debug view of the expression instead of evaluating it
+    pub debug: Option<DebugView>,
+    /// Variables to bind before anything is evaluated, as `(name, expression source)` pairs
+    pub defines: Vec<(char, String)>,
+    /// Decimal places floats are rounded to
+    pub precision: Option<usize>,
+    /// Show exactly `precision` decimal places instead of trimming trailing zeros
+    pub fixed: bool,
+    /// A character accepted between groups of digits in numbers
+    pub input_grouping: Option<char>,
+    /// Read `,` between digits as the decimal point, separating arguments with `;`
+    pub decimal_comma: bool,
+    /// Reject characters pasted from word processors instead of reading them as ASCII
+    pub strict_input: bool,
+    /// Show floats as fractions when one is close enough
+    pub fraction: bool,
+    /// Show exact fractions only as decimals, rather than both exactly and approximately
+    pub decimal_only: bool,
+    /// How numbers are written out
+    pub notation: Notation,
+    /// What separates groups of three digits, if they're grouped
+    pub group: Option<DigitSeparator>,
+    /// Evaluate trig functions in degrees
+    pub degrees: bool,
+    /// Seed for the random number generator
+    pub seed: Option<u64>,
+    /// Decimal places kept exactly in decimal mode, or `None` for floats
+    pub decimal_scale: Option<u8>,
+    /// Fail on results that can't be exact in decimal mode instead of giving floats
+    pub decimal_strict: bool,
+    /// Report how long each evaluation phase took
+    pub time: bool,
+    /// Never color output
+    pub no_color: bool,
+    /// Print usage and exit
+    pub help: bool,
+    /// Print every function and exit
+    pub list_functions: bool,
+    /// An immediate expression to evaluate, empty if none was given
+    pub expression: String,
+}
+
+/// An argument was malformed
+#[derive(Debug, PartialEq)]
+pub struct ArgsError(String);
+
+impl Display for ArgsError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.0)
+    }
+}
+
+impl Error for ArgsError {}
+
+/// Splits a `-D name=value` definition into the variable and its (unparsed) expression
+fn parse_define(definition: &str) -> Result<(char, String), ArgsError> {
+    let invalid = |why: &str| ArgsError(format!("Invalid definition `-D {definition}`: {why}"));
+
+    let (name, value) = definition
+        .split_once('=')
+        .ok_or_else(|| invalid("expected `name=value`"))?;
+
+    let mut chars = name.trim().chars();
+    let var = match (chars.next(), chars.next()) {
+        (Some(var), None) if var.is_alphabetic() => var,
+        _ => return Err(invalid("variable names are a single letter")),
+    };
+
+    if value.trim().is_empty() {
+        return Err(invalid("missing value"));
+    }
+
+    Ok((var, value.to_string()))
+}
+
+impl Args {
+    /// The formatting requested by `--precision`, `--fixed`, `--fraction`, `--decimal-only`,
+    /// `--notation` and digit grouping. Exact fractions are approximated too unless only decimals
+    /// are asked for
+    pub fn format(&self) -> FormatOptions {
+        let format = match (self.precision, self.fixed) {
+            (Some(precision), false) => FormatOptions::precision(precision),
+            (precision, true) => FormatOptions::fixed(precision.unwrap_or(DEFAULT_FIXED_PRECISION)),
+            (None, false) => FormatOptions::default(),
+        };
+        FormatOptions {
+            fraction: self.fraction,
+            decimal: self.decimal_only,
+            approximate: !self.decimal_only,
+            notation: self.notation,
+            group: self.group,
+            ..format
+        }
+    }
+
+    /// The numeric mode requested by `--decimal-scale` and `--decimal-strict`, floats if neither
+    /// was given
+    pub fn numeric_mode(&self) -> NumericMode {
+        match (self.decimal_scale, self.decimal_strict) {
+            (None, false) => NumericMode::Float,
+            (scale, strict) => NumericMode::Decimal {
+                scale: scale.unwrap_or(DEFAULT_DECIMAL_SCALE),
+                strict,
+            },
+        }
+    }
+}
+
+/// Parses the arguments (without the program name). Anything that isn't a recognized flag is part
+/// of the immediate expression, so `chalk -1 + 2` still works
+pub fn parse(args: impl IntoIterator<Item = String>) -> Result<Args, ArgsError> {
+    let mut parsed = Args::default();
+    let mut positional = vec![];
+    let mut args = args.into_iter();
+
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "-f" | "--file" => {
+                let path = args
+                    .next()
+                    .ok_or_else(|| ArgsError(format!("`{arg}` expects a file path")))?;
+                parsed.file = Some(path.into());
+            }
+            "-D" | "--define" => {
+                let definition = args.next().ok_or_else(|| {
+                    ArgsError(format!("`{arg}` expects a `name=value` definition"))
+                })?;
+                parsed.defines.push(parse_define(&definition)?);
+            }
+            define if define.starts_with("-D") => parsed.defines.push(parse_define(&define[2..])?),
+            "-p" | "--precision" => {
+                let precision = args
+                    .next()
+                    .and_then(|precision| precision.parse().ok())
+                    .ok_or_else(|| {
+                        ArgsError(format!("`{arg}` expects a number of decimal places"))
+                    })?;
+                parsed.precision = Some(precision);
+            }
+            "--notation" => {
+                parsed.notation = args
+                    .next()
+                    .and_then(|notation| Notation::try_from(notation.as_str()).ok())
+                    .ok_or_else(|| ArgsError(format!("`{arg}` expects sci, eng or plain")))?;
+            }
+            "--input-grouping" => {
+                let separator = args.next().unwrap_or_default();
+                let mut chars = separator.chars();
+                parsed.input_grouping = match (chars.next(), chars.next()) {
+                    (Some(c), None) if !c.is_alphanumeric() && c != '.' => Some(c),
+                    _ => {
+                        return Err(ArgsError(format!(
+                            "`{arg}` expects a single character that isn't a letter, digit or `.`"
+                        )));
+                    }
+                };
+            }
+            "--group-digits" => parsed.group = Some(DigitSeparator::Comma),
+            "--group-separator" => {
+                let separator = args
+                    .next()
+                    .and_then(|separator| DigitSeparator::try_from(separator.as_str()).ok())
+                    .ok_or_else(|| ArgsError(format!("`{arg}` expects `,`, `_` or `space`")))?;
+                parsed.group = Some(separator);
+            }
+            "--seed" => {
+                let seed = args
+                    .next()
+                    .and_then(|seed| seed.parse().ok())
+                    .ok_or_else(|| ArgsError(format!("`{arg}` expects a whole number")))?;
+                parsed.seed = Some(seed);
+            }
+            "--decimal-scale" => {
+                let scale = args
+                    .next()
+                    .and_then(|scale| scale.parse().ok())
+                    .filter(|scale| *scale <= MAX_SCALE)
+                    .ok_or_else(|| {
+                        ArgsError(format!(
+                            "`{arg}` expects a number of decimal places up to {MAX_SCALE}"
+                        ))
+                    })?;
+                parsed.decimal_scale = Some(scale);
+            }
+            "--decimal-strict" => parsed.decimal_strict = true,
+            "--fixed" => parsed.fixed = true,
+            "--decimal-comma" => parsed.decimal_comma = true,
+            "--strict-input" => parsed.strict_input = true,
+            "--fraction" => parsed.fraction = true,
+            "--decimal-only" => parsed.decimal_only = true,
+            "--degrees" => parsed.degrees = true,
+            "-t" | "--time" => parsed.time = true,
+            "--no-color" => parsed.no_color = true,
+            "-q" | "--quiet" => parsed.quiet = true,
+            "-i" | "--interactive" => parsed.interactive = true,
+            "-w" | "--watch" => parsed.watch = true,
+            "--stdin" => parsed.stdin = true,
+            "--serve" => parsed.serve = true,
+            "-h" | "--help" => parsed.help = true,
+            "--list-functions" => parsed.list_functions = true,
+            "--tokens" => parsed.debug = Some(DebugView::Tokens),
+            "--ast" => parsed.debug = Some(DebugView::Ast),
+            "--dot" => parsed.debug = Some(DebugView::Dot),
+            _ => positional.push(arg),
+        }
+    }
+
+    if parsed.file.is_none() && positional.len() == 1 && positional[0].ends_with(SCRIPT_EXTENSION) {
+        parsed.file = positional.pop().map(PathBuf::from);
+    }
+
+    if parsed.watch && parsed.file.is_none() {
+        return Err(ArgsError("`--watch` expects a script file".to_string()));
+    }
+
+    parsed.expression = positional.concat();
+    Ok(parsed)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::PathBuf;
+
+    use chalk_core::{
+        exec::NumericMode,
+        format::{DigitSeparator, FormatOptions, Notation},
+    };
+
+    use super::{Args, DebugView, parse};
+
+    fn args(raw: &[&str]) -> Args {
+        parse(raw.iter().map(|arg| arg.to_string())).expect("Parse args")
+    }
+
+    #[test]
+    fn expression_only() {
+        let parsed = args(&["1", "+", "1"]);
+        assert_eq!(parsed.expression, "1+1");
+        assert_eq!(parsed.file, None);
+    }
+
+    #[test]
+    fn negative_numbers_are_not_flags() {
+        assert_eq!(args(&["-1", "+", "2"]).expression, "-1+2");
+    }
+
+    #[test]
+    fn script_files() {
+        let parsed = args(&["-i", "defs.chalk"]);
+        assert_eq!(parsed.file, Some(PathBuf::from("defs.chalk")));
+        assert!(parsed.interactive);
+
+        let parsed = args(&["--file", "defs.txt", "--quiet"]);
+        assert_eq!(parsed.file, Some(PathBuf::from("defs.txt")));
+        assert!(parsed.quiet);
+    }
+
+    #[test]
+    fn watching() {
+        let parsed = args(&["--watch", "formulas.chalk"]);
+        assert!(parsed.watch);
+        assert_eq!(parsed.file, Some(PathBuf::from("formulas.chalk")));
+        assert!(args(&["-w", "-f", "defs.txt"]).watch);
+        assert!(!args(&["defs.chalk"]).watch);
+
+        let err = parse(["--watch".to_string(), "1 + 1".to_string()]).expect_err("No file");
+        assert_eq!(err.to_string(), "`--watch` expects a script file");
+    }
+
+    #[test]
+    fn debug_views() {
+        let parsed = args(&["--ast", "2", "^", "3"]);
+        assert_eq!(parsed.debug, Some(DebugView::Ast));
+        assert_eq!(parsed.expression, "2^3");
+
+        assert_eq!(args(&["--dot", "(1+2)*3"]).debug, Some(DebugView::Dot));
+    }
+
+    #[test]
+    fn definitions() {
+        let parsed = args(&["-D", "x=3", "-Dy = x + 1", "x", "*", "y"]);
+        assert_eq!(
+            parsed.defines,
+            [('x', "3".to_string()), ('y', " x + 1".to_string())]
+        );
+        assert_eq!(parsed.expression, "x*y");
+    }
+
+    #[test]
+    fn invalid_definitions() {
+        for definition in ["x", "xy=3", "1=2", "x="] {
+            let err = parse(["-D".to_string(), definition.to_string()])
+                .expect_err("Definition is invalid");
+            assert!(err.to_string().contains(definition));
+        }
+    }
+
+    #[test]
+    fn formatting() {
+        let approximate = |format| FormatOptions {
+            approximate: true,
+            ..format
+        };
+        assert_eq!(
+            args(&["1/3"]).format(),
+            approximate(FormatOptions::default())
+        );
+        assert_eq!(
+            args(&["--precision", "3", "1/3"]).format(),
+            approximate(FormatOptions::precision(3))
+        );
+        assert_eq!(
+            args(&["-p", "2", "--fixed", "1/3"]).format(),
+            approximate(FormatOptions::fixed(2))
+        );
+        assert_eq!(
+            args(&["--fixed", "1/3"]).format(),
+            approximate(FormatOptions::fixed(6))
+        );
+        assert_eq!(
+            args(&["--decimal-only", "1/3"]).format(),
+            FormatOptions {
+                decimal: true,
+                ..FormatOptions::default()
+            }
+        );
+        assert!(parse(["--precision".to_string(), "lots".to_string()]).is_err());
+        assert!(args(&["--fraction", "0.75"]).format().fraction);
+        assert_eq!(
+            args(&["--notation", "eng", "-p", "2", "2^40"]).format(),
+            FormatOptions {
+                notation: Notation::Engineering,
+                ..approximate(FormatOptions::precision(2))
+            }
+        );
+        assert!(parse(["--notation".to_string(), "roman".to_string()]).is_err());
+
+        assert_eq!(args(&["1e3"]).format().group, None);
+        assert_eq!(
+            args(&["--group-digits", "2^20"]).format().group,
+            Some(DigitSeparator::Comma)
+        );
+        assert_eq!(
+            args(&["--group-separator", "space", "2^20"]).format().group,
+            Some(DigitSeparator::Space)
+        );
+        assert!(parse(["--group-separator".to_string(), ".".to_string()]).is_err());
+    }
+
+    #[test]
+    fn input_grouping() {
+        assert_eq!(args(&["1,234"]).input_grouping, None);
+        let parsed = args(&["--input-grouping", ",", "1,234", "*", "2"]);
+        assert_eq!(parsed.input_grouping, Some(','));
+        assert_eq!(parsed.expression, "1,234*2");
+
+        for invalid in [".", "ab", "7"] {
+            assert!(
+                parse(["--input-grouping".to_string(), invalid.to_string()]).is_err(),
+                "{invalid}"
+            );
+        }
+        assert!(parse(["--input-grouping".to_string()]).is_err());
+    }
+
+    #[test]
+    fn decimal_comma() {
+        assert!(!args(&["3,14"]).decimal_comma);
+        let parsed = args(&["--decimal-comma", "3,14", "*", "2"]);
+        assert!(parsed.decimal_comma);
+        assert_eq!(parsed.expression, "3,14*2");
+    }
+
+    #[test]
+    fn strict_input() {
+        assert!(!args(&["2 × 3"]).strict_input);
+        let parsed = args(&["--strict-input", "2 × 3"]);
+        assert!(parsed.strict_input);
+        assert_eq!(parsed.expression, "2 × 3");
+    }
+
+    #[test]
+    fn degrees() {
+        assert!(!args(&["sin(90)"]).degrees);
+        let parsed = args(&["--degrees", "sin(90)"]);
+        assert!(parsed.degrees);
+        assert_eq!(parsed.expression, "sin(90)");
+    }
+
+    #[test]
+    fn seeds() {
+        assert_eq!(args(&["rand()"]).seed, None);
+        let parsed = args(&["--seed", "42", "rand()"]);
+        assert_eq!(parsed.seed, Some(42));
+        assert_eq!(parsed.expression, "rand()");
+        assert!(parse(["--seed".to_string(), "-1".to_string()]).is_err());
+    }
+
+    #[test]
+    fn decimal_mode() {
+        let parsed = args(&["0.1 + 0.2"]);
+        assert_eq!((parsed.decimal_scale, parsed.decimal_strict), (None, false));
+        let parsed = args(&["--decimal-scale", "2", "--decimal-strict", "0.1"]);
+        assert_eq!(
+            (parsed.decimal_scale, parsed.decimal_strict),
+            (Some(2), true)
+        );
+        assert_eq!(parsed.expression, "0.1");
+        assert_eq!(
+            parsed.numeric_mode(),
+            NumericMode::Decimal {
+                scale: 2,
+                strict: true
+            }
+        );
+        assert_eq!(
+            args(&["--decimal-strict"]).numeric_mode(),
+            parsed.numeric_mode()
+        );
+        assert_eq!(args(&["1"]).numeric_mode(), NumericMode::Float);
+        assert!(parse(["--decimal-scale".to_string(), "5".to_string()]).is_err());
+        assert!(parse(["--decimal-scale".to_string(), "two".to_string()]).is_err());
+    }
+
+    #[test]
+    fn serving() {
+        assert!(args(&["--serve"]).serve);
+        assert!(!args(&["--stdin"]).serve);
+    }
+
+    #[test]
+    fn list_functions() {
+        assert!(args(&["--list-functions"]).list_functions);
+        assert!(!args(&["1 + 2"]).list_functions);
+    }
+
+    #[test]
+    fn missing_file_path() {
+        assert!(parse(["--file".to_string()]).is_err());
+    }
+}
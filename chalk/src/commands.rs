@@ -0,0 +1,782 @@
+//! REPL meta-commands, intercepted before a line ever reaches the tokenizer
+
+use std::{fmt::Write, fs};
+
+use chalk_core::{
+    ast::Expr,
+    exec::AngleMode,
+    format::{DigitSeparator, FloatStyle, FormatOptions, Notation},
+    functions,
+};
+
+use crate::{
+    script,
+    session::Session,
+    statement::{self, StatementError, split_args},
+};
+
+/// Operators listed by `:help`, before the functions
+const OPERATORS: &str = "\
+Operators:
+  + - * / ^        arithmetic, with the usual precedence
+  ! ° %            factorial, degrees and percent (postfix)
+  |x|              absolute value
+  [a, b, c]        a list, e.g. mean([1, 2, 3])
+  x[i]             the ith item of a list, counting from 1
+  << >> ~          bit shifts and bitwise not, on integers
+  == != > < >= <=  comparisons
+  ~=               approximately equal, within a relative 1e-9
+  && ||            logical and/or
+  x = expr         bind a single letter variable (lazily evaluated)";
+
+/// Forms listed by `:help` after the functions, which take a variable rather than a value
+const SPECIAL_FORMS: &str = "\
+  solve(eq, x)     numerically find an x where eq holds, e.g. solve(x^2 == 2, x)
+  sum(i, a, b, expr) prod(i, a, b, expr)
+                   add or multiply expr for every integer i from a to b
+
+Constants:
+  pi
+
+Units, written after a number like 3km or 60 mph:
+  mm cm m km ft yd mi  ms s min h day  mg g kg lb oz  ha acre  mph kph
+  x in unit        convert to another unit, e.g. 3km + 200m in m";
+
+/// Commands listed by `:help`
+const COMMANDS: &str = "\
+Commands:
+  :vars            list bound variables
+  :clear           unbind every variable
+  :store name expr store a formula by name, apart from the variables
+  :recall [name]   show a stored formula, or every one
+  :eval name [with x=2, y=3]
+                   evaluate a stored formula, with variables bound just for it
+  :save file       write every variable to a file as chalk source
+  :load [--replace] file
+                   bind the variables defined in a file, first unbinding the rest
+                   if --replace is given
+  :tokens expr     show the tokens of an expression
+  :ast expr        show the syntax tree of an expression
+  :simplify expr   show an expression after basic algebraic simplification
+  :sub expr, x, replacement
+                   show an expression with every x replaced, without evaluating it
+  :steps expr      evaluate an expression, showing every step of the work
+  :precision [N] [fixed]
+                   round floats to N decimal places, no N shows them in full
+  :time on|off     show how long tokenizing, parsing and evaluating took
+  :decimal on|off  show exact fractions like 1/3 as decimals
+  :exact-only on|off
+                   show exact fractions without their decimal approximation
+  :frac on|off     show floats as fractions like 3/4 when one is close enough
+  :notation sci|eng|plain
+                   show numbers in scientific, engineering or plain notation
+  :group on|off|,|_|space
+                   group digits in threes, with commas unless given _ or space
+  :mode deg|rad    evaluate trig functions in degrees or radians
+  :complex on|off  treat i as the imaginary unit, giving complex results like sqrt(-4) = 2i
+  :help            show this message
+  :quit            exit the REPL (also `exit` or `quit`)";
+
+/// Every function chalk understands, one per line with its description, built from the registry
+/// so new functions are listed without touching this
+pub fn list_functions() -> String {
+    let mut out = String::new();
+    for signature in functions::all() {
+        let _ = write!(
+            out,
+            "  {:<16} {}",
+            signature.to_string(),
+            signature.description
+        );
+        for alias in signature.aliases {
+            let _ = write!(out, ", also `{alias}`");
+        }
+        out.push('\n');
+    }
+    out.pop();
+    out
+}
+
+/// Everything listed by `:help`
+fn help() -> String {
+    format!(
+        "{OPERATORS}\n\nFunctions:\n{}\n{SPECIAL_FORMS}\n\n{COMMANDS}",
+        list_functions()
+    )
+}
+
+/// What the REPL should do after running a meta-command
+#[derive(Clone, Debug, PartialEq)]
+pub enum Action {
+    /// Print the output and keep reading lines
+    Print(String),
+    /// Leave the REPL
+    Quit,
+}
+
+/// Shows either a command's output or why it failed
+fn show(shown: Result<String, StatementError>) -> String {
+    shown.unwrap_or_else(|err| err.to_string())
+}
+
+/// Handles `:precision [N] [fixed]`, with no arguments resetting to the shortest representation
+fn set_precision(args: &str, session: &mut Session) -> String {
+    let mut args = args.split_whitespace();
+
+    match (args.next(), args.next()) {
+        (None, _) => {
+            session.format = FormatOptions {
+                precision: None,
+                style: FloatStyle::Trimmed,
+                ..session.format
+            };
+            "Floats are shown in full".to_string()
+        }
+        (Some(precision), style) => match (precision.parse(), style) {
+            (Ok(precision), None) => {
+                session.format = FormatOptions {
+                    precision: Some(precision),
+                    style: FloatStyle::Trimmed,
+                    ..session.format
+                };
+                format!("Floats are rounded to {precision} decimal places")
+            }
+            (Ok(precision), Some("fixed")) => {
+                session.format = FormatOptions {
+                    precision: Some(precision),
+                    style: FloatStyle::Fixed,
+                    ..session.format
+                };
+                format!("Floats are shown with exactly {precision} decimal places")
+            }
+            _ => "Usage: `:precision [N] [fixed]`".to_string(),
+        },
+    }
+}
+
+/// Handles `:store name expression`
+fn store_formula(args: &str, session: &mut Session) -> String {
+    let usage = "Usage: `:store name expression`";
+    let Some((name, expression)) = args.split_once(char::is_whitespace) else {
+        return usage.to_string();
+    };
+    if !name.chars().all(|c| c.is_alphanumeric() || c == '_') {
+        return usage.to_string();
+    }
+
+    match statement::parse(expression) {
+        Ok(Some(formula)) => {
+            let shown = format!("Stored {name} = {formula}");
+            session.executor.store_formula(name, formula);
+            shown
+        }
+        Ok(None) => usage.to_string(),
+        Err(err) => err.to_string(),
+    }
+}
+
+/// Handles `:recall [name]`, with no name listing every formula
+fn recall_formula(name: &str, session: &Session) -> String {
+    if !name.is_empty() {
+        return match session.executor.recall_formula(name) {
+            Some(formula) => formula.to_string(),
+            None => format!("No formula is stored as `{name}`"),
+        };
+    }
+
+    let formulas = session.executor.formulas();
+    if formulas.is_empty() {
+        return "No formulas are stored".to_string();
+    }
+    let mut out = String::new();
+    for (name, formula) in formulas {
+        let _ = writeln!(out, "{name} = {formula}");
+    }
+    out.trim_end().to_string()
+}
+
+/// Handles `:eval name [with x=2, y=3]`, evaluating each binding before the formula
+fn eval_formula(args: &str, session: &mut Session) -> String {
+    let (name, bindings) = match args.split_once(" with ") {
+        Some((name, bindings)) => (name.trim(), split_args(bindings)),
+        None => (args, vec![]),
+    };
+    let mut values = vec![];
+    for binding in bindings {
+        let Ok(Some(Expr::Assignment(var, value))) = statement::parse(binding) else {
+            return format!("`{}` isn't a binding like `x = 2`", binding.trim());
+        };
+        match session.executor.exec(&value) {
+            Ok(value) => values.push((var, value)),
+            Err(err) => return StatementError::Runtime(err, *value).to_string(),
+        }
+    }
+
+    match session.executor.eval_formula_with(name, &values) {
+        Some(Ok(result)) => session.display(&result),
+        Some(Err(err)) => err.to_string(),
+        None => format!("No formula is stored as `{name}`"),
+    }
+}
+
+/// Handles `:save file`, writing every variable out as assignments
+fn save(path: &str, session: &Session) -> String {
+    if path.is_empty() {
+        return "Usage: `:save file`".to_string();
+    }
+
+    match fs::write(path, script::save(&session.executor)) {
+        Ok(()) => format!(
+            "Saved {} variables to {path}",
+            session.executor.vars().len()
+        ),
+        Err(err) => format!("Couldn't write {path}: {err}"),
+    }
+}
+
+/// Handles `:load [--replace] file`, binding the assignments in a file saved by `:save`
+fn load(args: &str, session: &mut Session) -> String {
+    let (replace, path) = match args.strip_prefix("--replace") {
+        Some(path) => (true, path.trim()),
+        None => (false, args),
+    };
+    if path.is_empty() {
+        return "Usage: `:load [--replace] file`".to_string();
+    }
+
+    let source = match fs::read_to_string(path) {
+        Ok(source) => source,
+        Err(err) => return format!("Couldn't read {path}: {err}"),
+    };
+    match script::load(&source, &mut session.executor, replace) {
+        Ok(loaded) => format!("Loaded {loaded} variables from {path}"),
+        Err(err) => format!("Nothing loaded from {path}, {err}"),
+    }
+}
+
+/// Runs a line as a meta-command if it is one, returning `None` if it should be evaluated instead
+pub fn dispatch(line: &str, session: &mut Session) -> Option<Action> {
+    let line = line.trim();
+
+    let command = match line {
+        "exit" | "quit" => return Some(Action::Quit),
+        _ => line.strip_prefix(':')?,
+    };
+
+    let (name, rest) = command
+        .trim()
+        .split_once(char::is_whitespace)
+        .unwrap_or((command.trim(), ""));
+
+    let action = match name {
+        "quit" | "q" | "exit" => Action::Quit,
+        "vars" => {
+            let vars = session.executor.vars();
+            if vars.is_empty() {
+                Action::Print("No variables are bound".to_string())
+            } else {
+                let mut out = String::new();
+                for (var, expr) in vars {
+                    let _ = writeln!(out, "{var} = {expr}");
+                }
+                Action::Print(out.trim_end().to_string())
+            }
+        }
+        "clear" => {
+            session.executor.clear();
+            Action::Print("Cleared all variables".to_string())
+        }
+        "store" => Action::Print(store_formula(rest.trim(), session)),
+        "recall" => Action::Print(recall_formula(rest.trim(), session)),
+        "eval" => Action::Print(eval_formula(rest.trim(), session)),
+        "save" => Action::Print(save(rest.trim(), session)),
+        "load" => Action::Print(load(rest.trim(), session)),
+        "help" | "h" => Action::Print(help()),
+        "tokens" => Action::Print(show(statement::show_tokens(rest))),
+        "ast" => Action::Print(show(statement::show_ast(rest))),
+        "simplify" => Action::Print(show(statement::simplify(rest, &session.executor))),
+        "steps" => Action::Print(show(statement::show_steps(rest, &mut session.executor))),
+        "sub" => Action::Print(show(statement::substitute(rest, &session.executor))),
+        "precision" => Action::Print(set_precision(rest, session)),
+        "time" => Action::Print(match rest.trim() {
+            "on" => {
+                session.time = true;
+                "Timings will be shown".to_string()
+            }
+            "off" => {
+                session.time = false;
+                "Timings will be hidden".to_string()
+            }
+            _ => "Usage: `:time on|off`".to_string(),
+        }),
+        "decimal" => Action::Print(match rest.trim() {
+            "on" => {
+                session.format.decimal = true;
+                "Fractions will be shown as decimals".to_string()
+            }
+            "off" => {
+                session.format.decimal = false;
+                "Fractions will be shown exactly".to_string()
+            }
+            _ => "Usage: `:decimal on|off`".to_string(),
+        }),
+        "exact-only" => Action::Print(match rest.trim() {
+            "on" => {
+                session.format.approximate = false;
+                "Fractions will be shown exactly, without a decimal approximation".to_string()
+            }
+            "off" => {
+                session.format.approximate = true;
+                "Fractions will be shown exactly and as decimals".to_string()
+            }
+            _ => "Usage: `:exact-only on|off`".to_string(),
+        }),
+        "frac" => Action::Print(match rest.trim() {
+            "on" => {
+                session.format.fraction = true;
+                "Floats will be shown as fractions where they can be".to_string()
+            }
+            "off" => {
+                session.format.fraction = false;
+                "Floats will be shown as decimals".to_string()
+            }
+            _ => "Usage: `:frac on|off`".to_string(),
+        }),
+        "notation" => Action::Print(match Notation::try_from(rest.trim()) {
+            Ok(notation) => {
+                session.format.notation = notation;
+                match notation {
+                    Notation::Plain => "Numbers are shown in full",
+                    Notation::Scientific => "Numbers are shown in scientific notation",
+                    Notation::Engineering => "Numbers are shown in engineering notation",
+                }
+                .to_string()
+            }
+            Err(()) => "Usage: `:notation sci|eng|plain`".to_string(),
+        }),
+        "group" => Action::Print(match rest.trim() {
+            "off" => {
+                session.format.group = None;
+                "Digits will be shown ungrouped".to_string()
+            }
+            separator => {
+                let separator = match separator {
+                    "on" => Ok(DigitSeparator::Comma),
+                    separator => DigitSeparator::try_from(separator),
+                };
+                match separator {
+                    Ok(separator) => {
+                        session.format.group = Some(separator);
+                        format!("Digits will be grouped with `{}`", separator.char())
+                    }
+                    Err(()) => "Usage: `:group on|off|,|_|space`".to_string(),
+                }
+            }
+        }),
+        "mode" => Action::Print(match AngleMode::try_from(rest.trim()) {
+            Ok(mode) => {
+                session.executor.set_angle_mode(mode);
+                match mode {
+                    AngleMode::Radians => "Angles are in radians".to_string(),
+                    AngleMode::Degrees => "Angles are in degrees".to_string(),
+                }
+            }
+            Err(_) => "Usage: `:mode deg|rad`".to_string(),
+        }),
+        "complex" => Action::Print(match rest.trim() {
+            "on" => {
+                session.executor.set_complex(true);
+                "`i` is the imaginary unit unless it's bound".to_string()
+            }
+            "off" => {
+                session.executor.set_complex(false);
+                "Results will stay real".to_string()
+            }
+            _ => "Usage: `:complex on|off`".to_string(),
+        }),
+        unknown => Action::Print(format!(
+            "Unknown command `:{unknown}`, try `:help` for a list of commands"
+        )),
+    };
+
+    Some(action)
+}
+
+#[cfg(test)]
+mod tests {
+    use chalk_core::{
+        ast::Expr,
+        exec::{AngleMode, EvalResult},
+        format::FormatOptions,
+    };
+
+    use crate::session::Session;
+
+    use super::{Action, dispatch};
+
+    #[test]
+    fn expressions_are_not_commands() {
+        let mut session = Session::default();
+        assert_eq!(dispatch("1 + 1", &mut session), None);
+        assert_eq!(dispatch("x = 5", &mut session), None);
+    }
+
+    #[test]
+    fn quitting() {
+        let mut session = Session::default();
+        assert_eq!(dispatch(":quit", &mut session), Some(Action::Quit));
+        assert_eq!(dispatch("exit", &mut session), Some(Action::Quit));
+        assert_eq!(dispatch(" quit ", &mut session), Some(Action::Quit));
+    }
+
+    #[test]
+    fn listing_and_clearing_vars() {
+        let mut session = Session::default();
+        session.executor.set('y', Expr::Integer(2));
+        session.executor.set('x', Expr::Integer(1));
+
+        assert_eq!(
+            dispatch(":vars", &mut session),
+            Some(Action::Print("x = 1\ny = 2".to_string()))
+        );
+
+        dispatch(":clear", &mut session);
+        assert!(session.executor.vars().is_empty());
+        assert_eq!(
+            dispatch(":vars", &mut session),
+            Some(Action::Print("No variables are bound".to_string()))
+        );
+    }
+
+    #[test]
+    fn help_lists_every_function() {
+        let mut session = Session::default();
+        let Some(Action::Print(out)) = dispatch(":help", &mut session) else {
+            panic!("Help should print");
+        };
+
+        for signature in chalk_core::functions::all() {
+            assert!(out.contains(&signature.to_string()), "{signature}");
+        }
+        assert!(out.contains("  gcd(a, b)        greatest common divisor\n"));
+        assert!(out.contains("subtract(a, b)   a - b, also `sub`"));
+        assert!(out.contains(":quit"));
+    }
+
+    #[test]
+    fn showing_steps() {
+        let mut session = Session::default();
+        session.executor.set('x', Expr::Integer(4));
+
+        assert_eq!(
+            dispatch(":steps (1 + 2) * x", &mut session),
+            Some(Action::Print("1 + 2 = 3\nx → 4\n3 * 4 = 12".to_string()))
+        );
+        assert_eq!(
+            dispatch(":steps 7", &mut session),
+            Some(Action::Print("7".to_string()))
+        );
+        let Some(Action::Print(failed)) = dispatch(":steps y + 1", &mut session) else {
+            panic!("Failures should print");
+        };
+        assert!(failed.contains("Runtime error"));
+    }
+
+    #[test]
+    fn substituting() {
+        let mut session = Session::default();
+        session.executor.set('y', Expr::Integer(2));
+        session.executor.set('f', Expr::Variable('x'));
+
+        assert_eq!(
+            dispatch(":sub x^2 + x, x, (y+1)", &mut session),
+            Some(Action::Print("(y + 1) ^ 2 + (y + 1)".to_string()))
+        );
+        assert_eq!(
+            dispatch(":sub gcd(f, 4) * z, x, a - b", &mut session),
+            Some(Action::Print("gcd(a - b, 4) * z".to_string()))
+        );
+        for invalid in [":sub x + 1", ":sub x + 1, 2, 3", ":sub x, x, 1, 2"] {
+            let Some(Action::Print(out)) = dispatch(invalid, &mut session) else {
+                panic!("Failures should print");
+            };
+            assert!(out.contains("invalid"), "{invalid}");
+        }
+    }
+
+    #[test]
+    fn unknown_command_hints() {
+        let mut session = Session::default();
+        let Some(Action::Print(out)) = dispatch(":frobnicate", &mut session) else {
+            panic!("Unknown commands should print a hint");
+        };
+
+        assert!(out.contains(":help"));
+    }
+
+    #[test]
+    fn debug_views() {
+        let mut session = Session::default();
+        assert_eq!(
+            dispatch(":ast 1 + 2", &mut session),
+            Some(Action::Print(
+                "BinaryOp Add\n  Integer 1\n  Integer 2".to_string()
+            ))
+        );
+        assert_eq!(
+            dispatch(":tokens 1", &mut session),
+            Some(Action::Print("Integer(1)\nEOF".to_string()))
+        );
+        assert_eq!(
+            dispatch(":simplify 2 * 3 + x * 1", &mut session),
+            Some(Action::Print("6 + x".to_string()))
+        );
+        session.executor.set_angle_mode(AngleMode::Degrees);
+        assert_eq!(
+            dispatch(":simplify sin(90) * x", &mut session),
+            Some(Action::Print("x".to_string()))
+        );
+        session.executor.set_angle_mode(AngleMode::Radians);
+    }
+
+    #[test]
+    fn setting_precision() {
+        let mut session = Session::default();
+
+        dispatch(":precision 3", &mut session);
+        assert_eq!(session.format, FormatOptions::precision(3));
+
+        dispatch(":precision 2 fixed", &mut session);
+        assert_eq!(session.format, FormatOptions::fixed(2));
+
+        let Some(Action::Print(out)) = dispatch(":precision two", &mut session) else {
+            panic!("Bad precision should print usage");
+        };
+        assert!(out.contains("Usage"));
+        assert_eq!(session.format, FormatOptions::fixed(2));
+
+        dispatch(":precision", &mut session);
+        assert_eq!(session.format, FormatOptions::default());
+    }
+
+    #[test]
+    fn toggling_decimals() {
+        let mut session = Session::default();
+        let half = EvalResult::Rational(1, 2);
+        assert_eq!(session.display(&half), "1/2");
+
+        dispatch(":decimal on", &mut session);
+        assert_eq!(session.display(&half), "0.5");
+
+        // Changing the precision keeps the toggle
+        dispatch(":precision", &mut session);
+        assert!(session.format.decimal);
+
+        dispatch(":decimal off", &mut session);
+        assert_eq!(session.display(&half), "1/2");
+    }
+
+    #[test]
+    fn toggling_approximations() {
+        let mut session = Session {
+            format: FormatOptions {
+                approximate: true,
+                ..FormatOptions::default()
+            },
+            ..Session::default()
+        };
+        let half = EvalResult::Rational(1, 2);
+        assert_eq!(session.display(&half), "1/2 ≈ 0.5");
+        assert_eq!(session.display(&EvalResult::Float(0.25)), "0.25");
+
+        dispatch(":precision 2", &mut session);
+        assert_eq!(session.display(&EvalResult::Rational(2, 3)), "2/3 ≈ 0.67");
+
+        dispatch(":precision", &mut session);
+        dispatch(":exact-only on", &mut session);
+        assert_eq!(session.display(&half), "1/2");
+
+        let Some(Action::Print(out)) = dispatch(":exact-only maybe", &mut session) else {
+            panic!("Bad settings should print usage");
+        };
+        assert!(out.contains("Usage"));
+
+        dispatch(":exact-only off", &mut session);
+        assert_eq!(session.display(&half), "1/2 ≈ 0.5");
+        dispatch(":decimal on", &mut session);
+        assert_eq!(session.display(&half), "0.5");
+    }
+
+    #[test]
+    fn setting_notation() {
+        let mut session = Session::default();
+        let big = EvalResult::Integer(1 << 40);
+
+        dispatch(":notation eng", &mut session);
+        assert_eq!(session.display(&big), "1.099511627776e12");
+        dispatch(":precision 2", &mut session);
+        assert_eq!(session.display(&big), "1.1e12");
+        assert_eq!(session.display(&EvalResult::Float(0.012345)), "12.3e-3");
+
+        dispatch(":notation sci", &mut session);
+        assert_eq!(session.display(&EvalResult::Integer(-12345)), "-1.23e4");
+        assert_eq!(session.display(&EvalResult::Integer(123)), "123");
+
+        dispatch(":notation plain", &mut session);
+        assert_eq!(session.display(&big), "1099511627776");
+        let Some(Action::Print(out)) = dispatch(":notation hex", &mut session) else {
+            panic!("Expected usage");
+        };
+        assert!(out.starts_with("Usage"));
+    }
+
+    #[test]
+    fn grouping_digits() {
+        let mut session = Session::default();
+        let big = EvalResult::Float(1234567.891);
+
+        dispatch(":group on", &mut session);
+        assert_eq!(session.display(&big), "1,234,567.891");
+        dispatch(":precision 1 fixed", &mut session);
+        assert_eq!(session.display(&big), "1,234,567.9");
+        dispatch(":group _", &mut session);
+        assert_eq!(session.display(&EvalResult::Integer(-65536)), "-65_536");
+        dispatch(":group space", &mut session);
+        assert_eq!(session.display(&EvalResult::Integer(65536)), "65 536");
+
+        dispatch(":group off", &mut session);
+        assert_eq!(session.display(&big), "1234567.9");
+        let Some(Action::Print(out)) = dispatch(":group .", &mut session) else {
+            panic!("Expected usage");
+        };
+        assert!(out.starts_with("Usage"));
+    }
+
+    #[test]
+    fn toggling_fractions() {
+        let mut session = Session::default();
+        let three_quarters = EvalResult::Float(0.75);
+        assert_eq!(session.display(&three_quarters), "0.75");
+
+        dispatch(":frac on", &mut session);
+        assert_eq!(session.display(&three_quarters), "3/4");
+        assert_eq!(
+            session.display(&EvalResult::Float(2f64.sqrt())),
+            "1.4142135623731"
+        );
+
+        // Changing the precision keeps the toggle
+        dispatch(":precision 2", &mut session);
+        assert_eq!(session.display(&three_quarters), "3/4");
+
+        dispatch(":frac off", &mut session);
+        assert_eq!(session.display(&three_quarters), "0.75");
+    }
+
+    #[test]
+    fn switching_angle_modes() {
+        let mut session = Session::default();
+
+        dispatch(":mode deg", &mut session);
+        assert_eq!(session.executor.angle_mode(), AngleMode::Degrees);
+
+        let Some(Action::Print(out)) = dispatch(":mode gradians", &mut session) else {
+            panic!("Bad modes should print usage");
+        };
+        assert!(out.contains("Usage"));
+        assert_eq!(session.executor.angle_mode(), AngleMode::Degrees);
+
+        dispatch(":mode rad", &mut session);
+        assert_eq!(session.executor.angle_mode(), AngleMode::Radians);
+    }
+
+    #[test]
+    fn switching_complex_mode() {
+        let mut session = Session::default();
+
+        dispatch(":complex on", &mut session);
+        assert!(session.executor.complex());
+
+        let Some(Action::Print(out)) = dispatch(":complex maybe", &mut session) else {
+            panic!("Bad settings should print usage");
+        };
+        assert!(out.contains("Usage"));
+        assert!(session.executor.complex());
+
+        dispatch(":complex off", &mut session);
+        assert!(!session.executor.complex());
+    }
+
+    #[test]
+    fn saving_and_loading_sessions() {
+        let path = std::env::temp_dir().join(format!("chalk-session-{}.chalk", std::process::id()));
+        let path = path.to_str().expect("UTF-8 path");
+
+        let mut session = Session::default();
+        session.executor.set('x', Expr::Integer(2));
+        session.executor.set('y', Expr::mul(3, Expr::var('x')));
+        assert_eq!(
+            dispatch(&format!(":save {path}"), &mut session),
+            Some(Action::Print(format!("Saved 2 variables to {path}")))
+        );
+
+        let mut loaded = Session::default();
+        loaded.executor.set('z', Expr::Integer(1));
+        assert_eq!(
+            dispatch(&format!(":load {path}"), &mut loaded),
+            Some(Action::Print(format!("Loaded 2 variables from {path}")))
+        );
+        assert_eq!(loaded.executor.vars().len(), 3);
+        assert_eq!(loaded.executor.value('y'), Some(Ok(EvalResult::Integer(6))));
+
+        dispatch(&format!(":load --replace {path}"), &mut loaded);
+        assert_eq!(loaded.executor.vars(), session.executor.vars());
+
+        std::fs::remove_file(path).expect("Remove saved session");
+        let Some(Action::Print(missing)) = dispatch(&format!(":load {path}"), &mut loaded) else {
+            panic!("Expected output");
+        };
+        assert!(missing.starts_with("Couldn't read"));
+    }
+
+    #[test]
+    fn storing_formulas() {
+        let mut session = Session::default();
+        assert_eq!(
+            dispatch(":store quad x^2 + 3x + 1", &mut session),
+            Some(Action::Print("Stored quad = x ^ 2 + 3 * x + 1".to_string()))
+        );
+        dispatch(":store hyp sqrt(a^2 + b^2)", &mut session);
+        assert!(session.executor.vars().is_empty());
+
+        assert_eq!(
+            dispatch(":recall quad", &mut session),
+            Some(Action::Print("x ^ 2 + 3 * x + 1".to_string()))
+        );
+        assert_eq!(
+            dispatch(":recall", &mut session),
+            Some(Action::Print(
+                "hyp = sqrt(a ^ 2 + b ^ 2)\nquad = x ^ 2 + 3 * x + 1".to_string()
+            ))
+        );
+
+        session.executor.set('b', Expr::Integer(4));
+        for (command, expected) in [
+            (":eval quad with x=2", "11"),
+            (":eval quad with x = -1", "-1"),
+            (":eval hyp with a=3", "5"),
+            (":eval hyp with a = gcd(6, 9), b = 2 * 2", "5"),
+            (":eval cube", "No formula is stored as `cube`"),
+            (":recall cube", "No formula is stored as `cube`"),
+            (":eval quad with 2", "`2` isn't a binding like `x = 2`"),
+        ] {
+            assert_eq!(
+                dispatch(command, &mut session),
+                Some(Action::Print(expected.to_string())),
+                "{command}"
+            );
+        }
+        // Bindings only last for the evaluation
+        assert_eq!(session.executor.vars().len(), 1);
+    }
+}
@@ -1,74 +1,211 @@
 //! Chalk Command Line Tool
 
-use std::{
-    env,
-    io::{Write, stdin},
-};
+mod args;
+mod commands;
+mod repl;
+mod script;
+mod serve;
+mod session;
+mod statement;
+mod style;
+mod watch;
 
-use chalk_core::{
-    ast::{Expr, Parser},
-    exec::Evaluator,
-    tokenizer::Tokenizable,
+use std::{
+    env, fs,
+    io::{self, IsTerminal, stdin},
+    path::Path,
+    process,
 };
 
-/// Evaluates a statement as a Chalk AST
-fn eval_statement(statement: &str) -> Option<Expr> {
-    let tokens = statement.tokenize();
+use args::DebugView;
+use chalk_core::{exec::AngleMode, tokenizer::TokenizerOptions};
+use session::Session;
+use style::Style;
 
-    if tokens.is_err() {
-        println!(
-            "The provided statement is invalid Chalk format, please only use mathematical notation"
-        );
-        return None;
-    }
+/// Runs a script file, warning about variables that look misused afterwards, and exiting the
+/// process if it can't be read or a line fails
+fn run_file(path: &Path, quiet: bool, session: &mut Session) {
+    let source = match fs::read_to_string(path) {
+        Ok(source) => source,
+        Err(err) => {
+            eprintln!("Failed to read `{}`: {err}", path.display());
+            process::exit(1);
+        }
+    };
 
-    let tokens = tokens.unwrap();
-    let mut parser = Parser::new(tokens);
+    let warnings = script::lint(&source, session);
+    let mut last = None;
+    let result = script::run(&source, session, |session, val, eval| {
+        let shown = session.display_statement(val, &eval);
+        if quiet {
+            last = Some(shown);
+        } else {
+            println!("{shown}");
+        }
+    });
 
-    let ast = parser.parse();
+    if let Some(last) = last {
+        println!("{last}");
+    }
 
-    if ast.is_err() {
-        println!(
-            "The provided statement is invalid Chalk format, please only use mathematical notation"
-        );
-        return None;
+    for warning in warnings {
+        let line = warning
+            .line
+            .map(|line| format!("{line}:"))
+            .unwrap_or_default();
+        eprintln!("{}:{line} warning: {}", path.display(), warning.kind);
     }
 
-    let ast = ast.unwrap();
-    Some(ast)
+    if let Err(err) = result {
+        let message = format!("{}:{}: {}", path.display(), err.line, err.kind);
+        eprintln!("{}", session.style.error(&message));
+        process::exit(err.kind.exit_code());
+    }
 }
 
 fn main() {
-    let statement = env::args().skip(1).collect::<String>();
-    let mut executor = Evaluator::default();
-
-    if !statement.is_empty() {
-        if let Some(val) = eval_statement(&statement) {
-            if let Ok(eval) = executor.exec(&val) {
-                println!("`{val}` = {eval}");
-            } else {
-                panic!("Runtime error has occurred on expression `{val}`")
-            }
+    let args = match args::parse(env::args().skip(1)) {
+        Ok(args) => args,
+        Err(err) => {
+            eprintln!("{err}");
+            process::exit(1);
         }
+    };
+    if args.help {
+        println!("{}", args::USAGE);
+        return;
+    }
+    if args.list_functions {
+        println!("{}", commands::list_functions());
+        return;
+    }
+
+    let no_color = env::var_os("NO_COLOR");
+    let colors = |tty| style::colors_enabled(args.no_color, no_color.as_deref(), tty);
+    let mut session = Session {
+        format: args.format(),
+        input: TokenizerOptions {
+            digit_group_char: args.input_grouping,
+            decimal_comma: args.decimal_comma,
+            lenient: !args.strict_input,
+        },
+        time: args.time,
+        style: Style {
+            enabled: colors(io::stdout().is_terminal()),
+            errors: colors(io::stderr().is_terminal()),
+        },
+        ..Default::default()
+    };
+
+    if args.degrees {
+        session.executor.set_angle_mode(AngleMode::Degrees);
+    }
+    if let Some(seed) = args.seed {
+        session.executor.seed(seed);
+    }
+    session.executor.set_numeric_mode(args.numeric_mode());
 
+    for (var, value) in &args.defines {
+        if let Err(err) = statement::define(*var, value, &mut session.executor) {
+            eprintln!("Invalid definition `-D {var}={value}`: {err}");
+            process::exit(err.exit_code());
+        }
+    }
+
+    if args.serve {
+        if let Err(err) = serve::serve(stdin().lines(), &mut session, &mut io::stdout()) {
+            eprintln!("Failed to serve requests: {err}");
+            process::exit(1);
+        }
         return;
     }
 
-    println!("Welcome to the Chalk Repl\n");
-    loop {
-        print!("->> ");
-        let _ = std::io::stdout().flush();
-        let mut buf = String::new();
-        stdin().read_line(&mut buf).expect("Failed to read StdIn");
+    if let Some(path) = &args.file {
+        if args.watch {
+            let mut env = watch::FileSystem { path: path.clone() };
+            let watched = watch::watch(
+                path,
+                &session,
+                &mut env,
+                &mut io::stdout(),
+                &mut io::stderr(),
+            );
+            if let Err(err) = watched {
+                eprintln!("Failed to watch `{}`: {err}", path.display());
+                process::exit(1);
+            }
+            return;
+        }
+
+        run_file(path, args.quiet, &mut session);
 
-        let statement = buf.trim();
+        if !args.interactive {
+            return;
+        }
+    } else if let Some(view) = args.debug {
+        let shown = match view {
+            DebugView::Tokens => statement::show_tokens(&args.expression),
+            DebugView::Ast => statement::show_ast(&args.expression),
+            DebugView::Dot => statement::show_dot(&args.expression),
+        };
+
+        match shown {
+            Ok(shown) => println!("{shown}"),
+            Err(err) => {
+                eprintln!("{err}");
+                process::exit(err.exit_code());
+            }
+        }
+
+        return;
+    } else if !args.expression.is_empty() {
+        if let Some(note) = session.normalized_note(&args.expression) {
+            eprintln!("{note}");
+        }
+        let (result, timings) =
+            statement::eval_timed(&args.expression, &mut session.executor, session.input);
+        if session.time {
+            eprintln!("{timings}");
+        }
 
-        if let Some(val) = eval_statement(statement) {
-            if let Ok(eval) = executor.exec(&val) {
-                println!("`{val}` = {eval}\n");
-            } else {
-                println!("Runtime error has occurred on expression `{val}`")
+        match result {
+            Ok(Some((val, eval))) => println!("{}", session.display_statement(&val, &eval)),
+            Ok(None) => {}
+            Err(err) => {
+                eprintln!("{}", session.style.error(&err.to_string()));
+                process::exit(err.exit_code());
             }
         }
+
+        return;
+    }
+
+    if args.stdin || (!args.interactive && !stdin().is_terminal()) {
+        let success = script::pipe(
+            stdin().lines(),
+            &mut session,
+            &mut io::stdout(),
+            &mut io::stderr(),
+        )
+        .expect("Failed to read StdIn");
+
+        if !success {
+            process::exit(1);
+        }
+        return;
+    }
+
+    let result = repl::Editor::new().and_then(|mut editor| {
+        repl::run(
+            &mut editor,
+            &mut session,
+            &mut io::stdout(),
+            &mut io::stderr(),
+        )
+    });
+
+    if let Err(err) = result {
+        eprintln!("Failed to run the REPL: {err}");
+        process::exit(1);
     }
 }
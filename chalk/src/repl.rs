@@ -0,0 +1,433 @@
+//! The interactive read-eval-print loop and the line sources that feed it
+
+use std::{
+    env,
+    io::{self, Write},
+    path::PathBuf,
+};
+
+use chalk_core::{
+    exec::Evaluator,
+    suggest::{SuggestionKind, suggest},
+};
+use rustyline::{
+    Context, Helper,
+    completion::{Completer, Pair},
+    error::ReadlineError,
+    highlight::Highlighter,
+    hint::Hinter,
+    history::DefaultHistory,
+    validate::Validator,
+};
+
+use crate::{
+    commands::{self, Action},
+    session::Session,
+    statement,
+};
+
+/// Prompt shown before every statement
+const PROMPT: &str = "->> ";
+
+/// Prompt shown before the lines continuing an unfinished statement
+const CONTINUATION_PROMPT: &str = "... ";
+
+/// File in the home directory that history persists to between sessions
+const HISTORY_FILE: &str = ".chalk_history";
+
+/// The outcome of asking for a line of input
+#[derive(Clone, Debug, PartialEq)]
+pub enum Input {
+    /// A full line was entered
+    Line(String),
+    /// The current line was cancelled (Ctrl-C)
+    Interrupted,
+    /// No more input will arrive (Ctrl-D or end of stream)
+    Eof,
+}
+
+/// Somewhere the REPL can read lines from
+pub trait LineSource {
+    /// Reads the next line, showing the prompt if the source is interactive
+    fn read_line(&mut self, prompt: &str) -> io::Result<Input>;
+    /// Records a line so it can be recalled later
+    fn add_history(&mut self, _line: &str) {}
+    /// Updates the variables tab completion can suggest
+    fn set_variables(&mut self, _executor: &Evaluator) {}
+}
+
+/// Completes the name being typed at the cursor, giving where the name starts and what could
+/// replace it. Functions complete with their opening parenthesis
+pub fn complete(line: &str, pos: usize, executor: &Evaluator) -> (usize, Vec<Pair>) {
+    let start = line[..pos]
+        .char_indices()
+        .rev()
+        .take_while(|(_, c)| c.is_alphanumeric())
+        .last()
+        .map_or(pos, |(i, _)| i);
+    // Meta-commands and numbers aren't names
+    let prefix = &line[start..pos];
+    if prefix.is_empty() || line[..start].ends_with(':') || prefix.starts_with(char::is_numeric) {
+        return (pos, vec![]);
+    }
+
+    let candidates = suggest(prefix, executor)
+        .into_iter()
+        .map(|suggestion| Pair {
+            display: suggestion.to_string(),
+            replacement: match suggestion.kind {
+                SuggestionKind::Function => format!("{}(", suggestion.name),
+                SuggestionKind::Variable | SuggestionKind::Constant => suggestion.name,
+            },
+        })
+        .collect();
+
+    (start, candidates)
+}
+
+/// Tab completion of function, constant and variable names
+#[derive(Default)]
+struct Completions {
+    /// The session's variables as of the last line
+    executor: Evaluator,
+}
+
+impl Completer for Completions {
+    type Candidate = Pair;
+
+    fn complete(
+        &self,
+        line: &str,
+        pos: usize,
+        _ctx: &Context<'_>,
+    ) -> rustyline::Result<(usize, Vec<Pair>)> {
+        Ok(complete(line, pos, &self.executor))
+    }
+}
+
+impl Hinter for Completions {
+    type Hint = String;
+}
+
+impl Highlighter for Completions {}
+
+impl Validator for Completions {}
+
+impl Helper for Completions {}
+
+/// An interactive line editor with in-session history, cursor movement and tab completion
+pub struct Editor {
+    /// The underlying rustyline editor
+    editor: rustyline::Editor<Completions, DefaultHistory>,
+    /// Where history is persisted to, if a home directory could be found
+    history: Option<PathBuf>,
+}
+
+impl Editor {
+    /// Creates a new editor, loading any history persisted by a previous session
+    pub fn new() -> io::Result<Self> {
+        let mut editor = rustyline::Editor::new().map_err(io::Error::other)?;
+        editor.set_helper(Some(Completions::default()));
+        let history = env::var_os("HOME").map(|home| PathBuf::from(home).join(HISTORY_FILE));
+
+        if let Some(history) = &history {
+            // There's no history file on the very first run
+            let _ = editor.load_history(history);
+        }
+
+        Ok(Self { editor, history })
+    }
+}
+
+impl Drop for Editor {
+    fn drop(&mut self) {
+        if let Some(history) = &self.history {
+            let _ = self.editor.save_history(history);
+        }
+    }
+}
+
+impl LineSource for Editor {
+    fn read_line(&mut self, prompt: &str) -> io::Result<Input> {
+        match self.editor.readline(prompt) {
+            Ok(line) => Ok(Input::Line(line)),
+            Err(ReadlineError::Interrupted) => Ok(Input::Interrupted),
+            Err(ReadlineError::Eof) => Ok(Input::Eof),
+            Err(err) => Err(io::Error::other(err)),
+        }
+    }
+
+    fn add_history(&mut self, line: &str) {
+        let _ = self.editor.add_history_entry(line);
+    }
+
+    fn set_variables(&mut self, executor: &Evaluator) {
+        if let Some(completions) = self.editor.helper_mut() {
+            completions.executor = executor.clone();
+        }
+    }
+}
+
+/// Reads lines until they form a complete statement, joined by newlines. A line ending in an
+/// unclosed parenthesis or a trailing operator is continued on the next, as is any line ending in
+/// a backslash. Interrupting abandons the whole statement
+fn read_statement(input: &mut impl LineSource) -> io::Result<Input> {
+    let mut statement = String::new();
+
+    loop {
+        let prompt = if statement.is_empty() {
+            PROMPT
+        } else {
+            CONTINUATION_PROMPT
+        };
+        let line = match input.read_line(prompt)? {
+            Input::Line(line) => line,
+            // Whatever was unfinished is still evaluated, so what's wrong with it is shown
+            Input::Eof if !statement.is_empty() => return Ok(Input::Line(statement)),
+            other => return Ok(other),
+        };
+
+        if let Some(continued) = line.trim_end().strip_suffix('\\') {
+            statement.push_str(continued);
+            statement.push('\n');
+            continue;
+        }
+
+        statement.push_str(&line);
+        if statement.trim_start().starts_with(':') || !statement::is_incomplete(&statement) {
+            return Ok(Input::Line(statement));
+        }
+        statement.push('\n');
+    }
+}
+
+/// Runs the read-eval-print loop until the user quits or input ends
+pub fn run(
+    input: &mut impl LineSource,
+    session: &mut Session,
+    out: &mut impl Write,
+    err: &mut impl Write,
+) -> io::Result<()> {
+    writeln!(out, "Welcome to the Chalk Repl\n")?;
+
+    loop {
+        input.set_variables(&session.executor);
+        let line = match read_statement(input)? {
+            Input::Line(line) => line,
+            Input::Interrupted => continue,
+            Input::Eof => break,
+        };
+
+        let statement = line.trim();
+        if !statement.is_empty() {
+            input.add_history(statement);
+        }
+
+        match commands::dispatch(statement, session) {
+            Some(Action::Quit) => break,
+            Some(Action::Print(printed)) => {
+                writeln!(out, "{printed}\n")?;
+                continue;
+            }
+            None => {}
+        }
+
+        if let Some(note) = session.normalized_note(statement) {
+            writeln!(err, "{note}")?;
+        }
+        let (result, timings) =
+            statement::eval_timed(statement, &mut session.executor, session.input);
+        if session.time && !timings.0.is_empty() {
+            writeln!(err, "{timings}")?;
+        }
+
+        match result {
+            Ok(Some((val, eval))) => {
+                let shown = match session.executor.history().last() {
+                    Some(entry) => session.display_entry(entry.number, &val, &eval),
+                    None => session.display_statement(&val, &eval),
+                };
+                writeln!(out, "{shown}\n")?
+            }
+            Ok(None) => {}
+            Err(error) => writeln!(err, "{}\n", session.style.error(&error.to_string()))?,
+        }
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{collections::VecDeque, io};
+
+    use crate::session::Session;
+
+    use chalk_core::{ast::Expr, exec::Evaluator};
+
+    use super::{Input, LineSource, complete, run};
+
+    /// Feeds the REPL a fixed sequence of inputs
+    struct Scripted {
+        /// Inputs that haven't been read yet
+        inputs: VecDeque<Input>,
+        /// Lines recorded to history
+        history: Vec<String>,
+        /// Every prompt shown
+        prompts: Vec<String>,
+    }
+
+    impl Scripted {
+        fn new(lines: &[&str]) -> Self {
+            Self {
+                inputs: lines
+                    .iter()
+                    .map(|line| Input::Line(line.to_string()))
+                    .collect(),
+                history: vec![],
+                prompts: vec![],
+            }
+        }
+    }
+
+    impl LineSource for Scripted {
+        fn read_line(&mut self, prompt: &str) -> io::Result<Input> {
+            self.prompts.push(prompt.to_string());
+            Ok(self.inputs.pop_front().unwrap_or(Input::Eof))
+        }
+
+        fn add_history(&mut self, line: &str) {
+            self.history.push(line.to_string());
+        }
+    }
+
+    fn drive(input: &mut Scripted) -> (String, String) {
+        let mut out = vec![];
+        let mut err = vec![];
+        run(input, &mut Session::default(), &mut out, &mut err).expect("Run REPL");
+
+        (
+            String::from_utf8(out).unwrap(),
+            String::from_utf8(err).unwrap(),
+        )
+    }
+
+    #[test]
+    fn evaluates_until_eof() {
+        let mut input = Scripted::new(&["x = 2", "", "x ^ 3"]);
+        let (out, err) = drive(&mut input);
+
+        assert!(out.contains("x ← 2"));
+        assert!(out.contains("`x ^ 3` = 8"));
+        assert!(err.is_empty());
+        assert_eq!(input.history, ["x = 2", "x ^ 3"]);
+    }
+
+    #[test]
+    fn numbering_results() {
+        let mut input = Scripted::new(&["6 * 7", "1 $", "hist(1) + 1"]);
+        let (out, err) = drive(&mut input);
+
+        assert!(out.contains("[1] `6 * 7` = 42"), "{out}");
+        assert!(out.contains("[2] `hist(1) + 1` = 43"), "{out}");
+        assert!(err.contains("Invalid character `$` at position 2"), "{err}");
+    }
+
+    #[test]
+    fn interrupts_cancel_the_line_only() {
+        let mut input = Scripted::new(&["1 + 1"]);
+        input.inputs.push_front(Input::Interrupted);
+        let (out, _) = drive(&mut input);
+
+        assert!(out.contains("`1 + 1` = 2"));
+    }
+
+    #[test]
+    fn quitting_stops_reading() {
+        let mut input = Scripted::new(&["1 $", ":quit", "2 + 2"]);
+        let (out, err) = drive(&mut input);
+
+        assert!(!out.contains("`2 + 2`"));
+        assert!(err.contains("Invalid character `$` at position 2"), "{err}");
+    }
+
+    #[test]
+    fn continuing_unfinished_lines() {
+        let mut input = Scripted::new(&["(1 + # first", "  2) *", "3", "4 \\", "+ 1"]);
+        let (out, err) = drive(&mut input);
+
+        assert!(out.contains("= 9"), "{out}");
+        assert!(out.contains("= 5"), "{out}");
+        assert!(err.is_empty(), "{err}");
+        assert_eq!(
+            input.prompts,
+            ["->> ", "... ", "... ", "->> ", "... ", "->> "]
+        );
+
+        // Input ending mid-statement still reports the statement
+        let (_, err) = drive(&mut Scripted::new(&["gcd(4,"]));
+        assert!(err.contains("Expression ended early"), "{err}");
+
+        let mut input = Scripted::new(&["(1 +"]);
+        input.inputs.push_back(Input::Interrupted);
+        input.inputs.push_back(Input::Line("7".to_string()));
+        let (out, err) = drive(&mut input);
+        assert!(out.contains("`7` = 7"));
+        assert!(err.is_empty());
+    }
+
+    #[test]
+    fn noting_pasted_characters() {
+        let mut session = Session::default();
+        session.input.lenient = true;
+        let mut input = Scripted::new(&["“6 × 7”", "10 – 3", "1 + 1"]);
+        let (mut out, mut err) = (vec![], vec![]);
+        run(&mut input, &mut session, &mut out, &mut err).expect("Run REPL");
+
+        let (out, err) = (
+            String::from_utf8(out).unwrap(),
+            String::from_utf8(err).unwrap(),
+        );
+        assert!(out.contains("`6 * 7` = 42"));
+        assert!(out.contains("`10 - 3` = 7"));
+        assert_eq!(
+            err.lines().collect::<Vec<_>>(),
+            [
+                "note: normalized 3 pasted characters",
+                "note: read `–` as `-`"
+            ]
+        );
+    }
+
+    #[test]
+    fn timing_goes_to_stderr() {
+        let mut input = Scripted::new(&[":time on", "1 + 1"]);
+        let (out, err) = drive(&mut input);
+
+        assert!(out.contains("`1 + 1` = 2"));
+        assert!(err.contains("tokenize: "));
+        assert!(err.contains("eval: "));
+    }
+
+    #[test]
+    fn tab_completion() {
+        let mut executor = Evaluator::default();
+        executor.set('p', Expr::Integer(2));
+        let completed = |line: &str| {
+            let (start, pairs) = complete(line, line.len(), &executor);
+            let replacements: Vec<_> = pairs.into_iter().map(|pair| pair.replacement).collect();
+            (start, replacements)
+        };
+
+        assert_eq!(completed("1 + gc"), (4, vec!["gcd(".to_string()]));
+        assert_eq!(completed("3*ATAN2").1, ["atan2("]);
+        assert_eq!(completed("p").1[..2], ["p", "pi"]);
+        assert_eq!(completed("1 + ").1, Vec::<String>::new());
+        assert_eq!(completed(":cl").1, Vec::<String>::new());
+        assert_eq!(completed("12").1, Vec::<String>::new());
+
+        let (start, pairs) = complete("sq + 1", 2, &executor);
+        assert_eq!(start, 0);
+        assert_eq!(pairs[0].display, "sqrt(x)");
+    }
+}
@@ -0,0 +1,373 @@
+//! Line by line evaluation of chalk script files
+
+use std::{
+    error::Error,
+    fmt::Display,
+    io::{self, Write},
+};
+
+use chalk_core::{
+    ast::Expr,
+    exec::{EvalResult, Evaluator},
+};
+
+use crate::{
+    session::Session,
+    statement::{self, StatementError},
+};
+
+/// A line of a script failed, stopping the script
+#[derive(Debug, PartialEq)]
+pub struct ScriptError {
+    /// The 1-based line number that failed
+    pub line: usize,
+    /// What went wrong
+    pub kind: StatementError,
+}
+
+impl Display for ScriptError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "line {}: {}", self.line, self.kind)
+    }
+}
+
+impl Error for ScriptError {}
+
+/// Something about a script's variables that's likely a mistake, found without running it
+#[derive(Debug, PartialEq)]
+pub struct ScriptWarning {
+    /// The 1-based line the warning is about, unknown for a variable only assigned inside another
+    /// expression
+    pub line: Option<usize>,
+    /// What looks wrong
+    pub kind: WarningKind,
+}
+
+/// What a [`ScriptWarning`] is about
+#[derive(Debug, PartialEq)]
+pub enum WarningKind {
+    /// The variable is assigned, first on the warning's line, but nothing reads it afterwards
+    Unused(char),
+    /// The variable is read on the warning's line before anything assigns it
+    Undefined(char),
+}
+
+impl Display for WarningKind {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Unused(var) => write!(f, "`{var}` is assigned but never read"),
+            Self::Undefined(var) => write!(f, "`{var}` is read before it's assigned"),
+        }
+    }
+}
+
+/// Checks a script for variables read before they're assigned and variables assigned but never
+/// read, without evaluating it. Variables the session already binds count as assigned, and lines
+/// that don't parse are left for running the script to report
+pub fn lint(source: &str, session: &Session) -> Vec<ScriptWarning> {
+    let (lines, statements): (Vec<_>, Vec<_>) = source
+        .lines()
+        .enumerate()
+        .filter_map(|(idx, line)| {
+            Some((idx + 1, statement::parse_with(line, session.input).ok()??))
+        })
+        .unzip();
+    let report = session.executor.analyze(&statements);
+
+    let undefined = report
+        .undefined_uses
+        .into_iter()
+        .map(|(index, var)| ScriptWarning {
+            line: Some(lines[index]),
+            kind: WarningKind::Undefined(var),
+        });
+    let unused = report.unused.into_iter().map(|var| ScriptWarning {
+        line: statements
+            .iter()
+            .position(|statement| matches!(statement, Expr::Assignment(v, _) if *v == var))
+            .map(|index| lines[index]),
+        kind: WarningKind::Unused(var),
+    });
+    undefined.chain(unused).collect()
+}
+
+/// Evaluates every non-empty, non-comment line of a script in order with a shared session,
+/// handing each result to `on_result`. Stops at the first line that fails
+pub fn run(
+    source: &str,
+    session: &mut Session,
+    mut on_result: impl FnMut(&Session, &Expr, EvalResult),
+) -> Result<(), ScriptError> {
+    for (idx, line) in source.lines().enumerate() {
+        let evaluated =
+            statement::eval(line, &mut session.executor, session.input).map_err(|kind| {
+                ScriptError {
+                    line: idx + 1,
+                    kind,
+                }
+            })?;
+
+        if let Some((ast, result)) = evaluated {
+            on_result(session, &ast, result);
+        }
+    }
+
+    Ok(())
+}
+
+/// Evaluates a stream of lines for pipeline use, writing one bare result per line to `out` and any
+/// errors to `err`. Unlike [`run`], a failing line doesn't stop the lines after it. Returns whether
+/// every line succeeded
+pub fn pipe(
+    lines: impl IntoIterator<Item = io::Result<String>>,
+    session: &mut Session,
+    out: &mut impl Write,
+    err: &mut impl Write,
+) -> io::Result<bool> {
+    let mut success = true;
+
+    for (idx, line) in lines.into_iter().enumerate() {
+        let line = line?;
+        match statement::eval(&line, &mut session.executor, session.input) {
+            Ok(Some((_, result))) => writeln!(out, "{}", session.display(&result))?,
+            Ok(None) => {}
+            Err(kind) => {
+                success = false;
+                writeln!(
+                    err,
+                    "{}",
+                    ScriptError {
+                        line: idx + 1,
+                        kind
+                    }
+                )?;
+            }
+        }
+    }
+
+    Ok(success)
+}
+
+/// Every bound variable written as chalk source, one assignment per line sorted by variable, which
+/// [`load`] reads back to the same definitions
+pub fn save(executor: &Evaluator) -> String {
+    executor
+        .vars()
+        .into_iter()
+        .map(|(var, expr)| format!("{var} = {expr}\n"))
+        .collect()
+}
+
+/// Binds every assignment in `source` without evaluating them, so definitions can refer to ones
+/// later in the file. Blank and comment lines are skipped, and any other statement is an error.
+/// Nothing is bound unless every line is a valid definition, and with `replace` every existing
+/// variable is unbound first. Returns how many definitions were loaded
+pub fn load(source: &str, executor: &mut Evaluator, replace: bool) -> Result<usize, ScriptError> {
+    let mut definitions = vec![];
+    for (idx, line) in source.lines().enumerate() {
+        match statement::parse(line) {
+            Ok(Some(Expr::Assignment(var, expr))) => definitions.push((var, *expr)),
+            Ok(None) => {}
+            Ok(Some(_)) => {
+                return Err(ScriptError {
+                    line: idx + 1,
+                    kind: StatementError::Invalid,
+                });
+            }
+            Err(kind) => {
+                return Err(ScriptError {
+                    line: idx + 1,
+                    kind,
+                });
+            }
+        }
+    }
+
+    if replace {
+        executor.clear();
+    }
+    let loaded = definitions.len();
+    for (var, expr) in definitions {
+        executor.set(var, expr);
+    }
+
+    Ok(loaded)
+}
+
+#[cfg(test)]
+mod tests {
+    use chalk_core::{
+        ast::Expr,
+        exec::{EvalResult, Evaluator, RuntimeError},
+        format::FormatOptions,
+    };
+
+    use crate::{
+        session::Session,
+        statement::{self, StatementError},
+    };
+
+    use super::{WarningKind, lint, load, pipe, run, save};
+
+    #[test]
+    fn shared_context() {
+        let source = "# constants\ng = 9.81\n\nt = 2 // seconds\ng * t^2 / 2\n";
+        let mut session = Session::default();
+        let mut results = vec![];
+
+        run(source, &mut session, |_, _, res| results.push(res)).expect("Run script");
+
+        assert_eq!(results.len(), 3);
+        assert_eq!(results[2], EvalResult::Float(19.62));
+        assert!(session.executor.get('g').is_some());
+    }
+
+    #[test]
+    fn reports_failing_line() {
+        let mut session = Session::default();
+        let mut results = vec![];
+
+        let err = run("x = 1\n\ny +\nx", &mut session, |_, _, res| {
+            results.push(res)
+        })
+        .expect_err("Line 3 is invalid");
+
+        assert_eq!(err.line, 3);
+        assert!(matches!(err.kind, StatementError::Parse(_)));
+        assert_eq!(results, [EvalResult::Integer(1)]);
+    }
+
+    #[test]
+    fn reports_runtime_errors() {
+        let mut session = Session::default();
+        let err = run("1 + 1\nz * 2", &mut session, |_, _, _| {}).expect_err("z is unbound");
+
+        assert_eq!(err.line, 2);
+        assert!(matches!(
+            err.kind,
+            StatementError::Runtime(RuntimeError::Invalid, _)
+        ));
+    }
+
+    #[test]
+    fn linting_variables() {
+        let source = "r = 2\n# area\na = pi * r^2\nc = 2pi * q\nq = 1\n\nb = (t = 2) + 1";
+        let mut session = Session::default();
+        session.executor.set('z', Expr::Integer(0));
+
+        let warnings: Vec<_> = lint(source, &session)
+            .into_iter()
+            .map(|warning| (warning.line, warning.kind))
+            .collect();
+        assert_eq!(
+            warnings,
+            [
+                (Some(4), WarningKind::Undefined('q')),
+                (Some(3), WarningKind::Unused('a')),
+                (Some(7), WarningKind::Unused('b')),
+                (Some(4), WarningKind::Unused('c')),
+                (Some(5), WarningKind::Unused('q')),
+                (None, WarningKind::Unused('t')),
+            ]
+        );
+        assert_eq!(
+            WarningKind::Undefined('q').to_string(),
+            "`q` is read before it's assigned"
+        );
+    }
+
+    #[test]
+    fn piping_mixed_lines() {
+        let lines = ["2^10", "1 +", "", "x = 3", "x * y", "x!"].map(|line| Ok(line.to_string()));
+        let mut session = Session::default();
+        let mut out = vec![];
+        let mut err = vec![];
+
+        let success = pipe(lines, &mut session, &mut out, &mut err).expect("Write output");
+
+        assert!(!success);
+        assert_eq!(String::from_utf8(out).unwrap(), "1024\n3\n6\n");
+
+        let err = String::from_utf8(err).unwrap();
+        assert_eq!(err.lines().count(), 2);
+        assert!(err.starts_with("line 2:"));
+    }
+
+    #[test]
+    fn piping_all_good() {
+        let lines = ["1 + 1", "# comment"].map(|line| Ok(line.to_string()));
+        let mut out = vec![];
+        let mut err = vec![];
+
+        let success =
+            pipe(lines, &mut Session::default(), &mut out, &mut err).expect("Write output");
+
+        assert!(success);
+        assert!(err.is_empty());
+    }
+
+    #[test]
+    fn piping_formatted() {
+        let lines = ["1 / 3", "4 / 2"].map(|line| Ok(line.to_string()));
+        let mut session = Session {
+            format: FormatOptions::precision(2),
+            ..Default::default()
+        };
+        let mut out = vec![];
+
+        pipe(lines, &mut session, &mut out, &mut vec![]).expect("Write output");
+
+        assert_eq!(String::from_utf8(out).unwrap(), "0.33\n2\n");
+    }
+
+    #[test]
+    fn saving_and_loading_definitions() {
+        let mut session = Session::default();
+        run(
+            "r = 2.5\nd = 2r\na = pi * (d / 2)^2\nx = 0\nf = -(x - 1) * (x + 1)\nl = [1, 2, 3]",
+            &mut session,
+            |_, _, _| {},
+        )
+        .expect("Define");
+
+        let saved = save(&session.executor);
+        assert_eq!(
+            saved,
+            "a = pi * (d / 2) ^ 2\nd = 2 * r\nf = -(x - 1) * (x + 1)\nl = [1, 2, 3]\nr = 2.5\nx = 0\n"
+        );
+
+        // Definitions come before what they refer to, so loading mustn't evaluate them
+        let mut executor = Evaluator::default();
+        assert_eq!(load(&saved, &mut executor, false), Ok(6));
+        assert_eq!(executor.vars(), session.executor.vars());
+        for expression in ["a", "d * l[3]", "f", "sum(x, 1, 3, f)"] {
+            assert_eq!(
+                statement::eval(expression, &mut executor, Default::default()),
+                statement::eval(expression, &mut session.executor, Default::default()),
+                "{expression}"
+            );
+        }
+    }
+
+    #[test]
+    fn loading_merges_or_replaces() {
+        let mut executor = Evaluator::default();
+        load("x = 1\ny = 2", &mut executor, false).expect("Load");
+        load("# more\n\ny = 3\nz = x + y", &mut executor, false).expect("Load");
+        assert_eq!(save(&executor), "x = 1\ny = 3\nz = x + y\n");
+
+        load("w = 4", &mut executor, true).expect("Load");
+        assert_eq!(save(&executor), "w = 4\n");
+
+        // A failing line is reported and nothing from the file is bound
+        for (source, line) in [("a = 1\nb = \nc = 3", 2), ("a = 1\n\n2 + 2", 3)] {
+            let err = load(source, &mut executor, true).expect_err("Invalid definition");
+            assert_eq!(err.line, line, "{source}");
+            assert!(matches!(
+                err.kind,
+                StatementError::Invalid | StatementError::Parse(_)
+            ));
+        }
+        assert_eq!(save(&executor), "w = 4\n");
+    }
+}
@@ -0,0 +1,344 @@
+//! A line based JSON protocol for driving chalk from other programs over a pipe. Each request is
+//! one JSON object per line, like `{"id": 1, "op": "eval", "expr": "x = 3"}`, answered by one JSON
+//! response per line carrying the same id
+
+use std::io::{self, Write};
+
+use chalk_core::{ast::Expr, exec::EvalResult};
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
+
+use crate::{
+    session::Session,
+    statement::{self, StatementError},
+};
+
+/// What a request asks for, named by its `op`
+#[derive(Clone, Debug, Deserialize, PartialEq)]
+#[serde(tag = "op", rename_all = "lowercase")]
+pub enum Op {
+    /// Evaluates a statement, binding any assignment and naming the variable bound as `assigned`
+    Eval {
+        /// The statement
+        expr: String,
+    },
+    /// Lists every bound variable
+    Vars,
+    /// Unbinds every variable
+    Reset,
+    /// Checks whether a statement parses without evaluating it
+    Validate {
+        /// The statement
+        expr: String,
+    },
+}
+
+/// One line of input, `{ id, op, ... }`
+#[derive(Clone, Debug, Deserialize, PartialEq)]
+pub struct Request {
+    /// Echoed back in the response, so any JSON value works
+    #[serde(default)]
+    pub id: Value,
+    /// What's asked for
+    #[serde(flatten)]
+    pub op: Op,
+}
+
+/// Why a request failed
+#[derive(Clone, Copy, Debug, PartialEq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum FailureKind {
+    /// The line wasn't a request, because it wasn't JSON or had an unknown op or missing field
+    Request,
+    /// The statement couldn't be tokenized or parsed
+    Invalid,
+    /// The statement parsed but failed to evaluate
+    Runtime,
+}
+
+/// What went wrong with a request, serialized as `{ kind, message }`
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct Failure {
+    /// Which stage failed
+    pub kind: FailureKind,
+    /// Human readable description of the failure
+    pub message: String,
+}
+
+impl From<StatementError> for Failure {
+    fn from(err: StatementError) -> Self {
+        let kind = match err {
+            StatementError::Invalid | StatementError::Tokenize(_) | StatementError::Parse(_) => {
+                FailureKind::Invalid
+            }
+            StatementError::Runtime(..) => FailureKind::Runtime,
+        };
+        Self {
+            kind,
+            message: err.to_string(),
+        }
+    }
+}
+
+/// One line of output, `{ id, ok, result? }` on success or `{ id, ok, error? }` on failure
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct Response {
+    /// The id of the request answered, or null if it couldn't be read
+    pub id: Value,
+    /// Whether the request succeeded
+    pub ok: bool,
+    /// What the request gave on success
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub result: Option<Value>,
+    /// What went wrong on failure
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<Failure>,
+}
+
+impl Response {
+    /// A successful response
+    fn success(id: Value, result: Value) -> Self {
+        Self {
+            id,
+            ok: true,
+            result: Some(result),
+            error: None,
+        }
+    }
+
+    /// A failed response
+    fn failure(id: Value, error: Failure) -> Self {
+        Self {
+            id,
+            ok: false,
+            result: None,
+            error: Some(error),
+        }
+    }
+}
+
+/// A result as `{ type, value, shown }`, with its serialized form alongside how the session
+/// displays it
+fn result_json(session: &Session, result: &EvalResult) -> Value {
+    let mut json = serde_json::to_value(result).unwrap_or(Value::Null);
+    if let Value::Object(fields) = &mut json {
+        fields.insert("shown".to_string(), Value::from(session.display(result)));
+    }
+    json
+}
+
+/// Answers one line of input. Lines that aren't a valid request get a failed response rather
+/// than being skipped, with the id still echoed if it could be read
+pub fn handle(line: &str, session: &mut Session) -> Response {
+    let json: Value = match serde_json::from_str(line) {
+        Ok(json) => json,
+        Err(err) => {
+            return Response::failure(
+                Value::Null,
+                Failure {
+                    kind: FailureKind::Request,
+                    message: format!("Malformed JSON: {err}"),
+                },
+            );
+        }
+    };
+    let id = json.get("id").cloned().unwrap_or(Value::Null);
+    let request = match Request::deserialize(json) {
+        Ok(request) => request,
+        Err(err) => {
+            return Response::failure(
+                id,
+                Failure {
+                    kind: FailureKind::Request,
+                    message: format!("Invalid request: {err}"),
+                },
+            );
+        }
+    };
+
+    match request.op {
+        Op::Eval { expr } => match statement::eval(&expr, &mut session.executor, session.input) {
+            Ok(Some((expr, result))) => {
+                let mut json = result_json(session, &result);
+                if let (Expr::Assignment(var, _), Value::Object(fields)) = (&expr, &mut json) {
+                    fields.insert("assigned".to_string(), Value::from(var.to_string()));
+                }
+                Response::success(id, json)
+            }
+            Ok(None) => Response::success(id, Value::Null),
+            Err(err) => Response::failure(id, err.into()),
+        },
+        Op::Vars => {
+            let bound: Vec<_> = session
+                .executor
+                .vars()
+                .into_iter()
+                .map(|(var, expr)| (var, expr.clone()))
+                .collect();
+            let vars: Map<String, Value> = bound
+                .into_iter()
+                .map(|(var, expr)| {
+                    let value = match session.executor.value(var) {
+                        Some(Ok(value)) => result_json(session, &value),
+                        _ => Value::Null,
+                    };
+                    let entry = serde_json::json!({ "expr": expr.to_string(), "value": value });
+                    (var.to_string(), entry)
+                })
+                .collect();
+            Response::success(id, Value::Object(vars))
+        }
+        Op::Reset => {
+            session.executor.clear();
+            Response::success(id, Value::Null)
+        }
+        Op::Validate { expr } => {
+            let validation = match statement::parse(&expr) {
+                Ok(_) => serde_json::json!({ "valid": true }),
+                Err(err) => serde_json::json!({ "valid": false, "message": err.to_string() }),
+            };
+            Response::success(id, validation)
+        }
+    }
+}
+
+/// Answers every line of input in order with one line of JSON each, sharing one session, until
+/// the input ends
+pub fn serve(
+    lines: impl IntoIterator<Item = io::Result<String>>,
+    session: &mut Session,
+    out: &mut impl Write,
+) -> io::Result<()> {
+    for line in lines {
+        let response = handle(&line?, session);
+        let json = serde_json::to_string(&response).expect("Responses always serialize");
+        writeln!(out, "{json}")?;
+        out.flush()?;
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::{Value, json};
+
+    use crate::session::Session;
+
+    use super::{FailureKind, Op, Request, handle, serve};
+
+    #[test]
+    fn reading_requests() {
+        let request: Request =
+            serde_json::from_str(r#"{"id": 1, "op": "eval", "expr": "x=3"}"#).expect("Request");
+        assert_eq!(request.id, json!(1));
+        assert_eq!(
+            request.op,
+            Op::Eval {
+                expr: "x=3".to_string()
+            }
+        );
+
+        let request: Request = serde_json::from_str(r#"{"op": "vars"}"#).expect("Request");
+        assert_eq!((request.id, request.op), (Value::Null, Op::Vars));
+    }
+
+    #[test]
+    fn scripted_session() {
+        let lines = [
+            r#"{"id": 1, "op": "eval", "expr": "x=3"}"#,
+            r#"{"id": 2, "op": "eval", "expr": "x^2"}"#,
+            r#"{"id": 3, "op": "eval", "expr": "#,
+            r#"{"id": "four", "op": "shout"}"#,
+            r#"{"id": 5, "op": "eval", "expr": "1 / 4"}"#,
+            r#"{"id": 6, "op": "vars"}"#,
+            r#"{"id": 7, "op": "validate", "expr": "2 +"}"#,
+            r#"{"id": 8, "op": "eval", "expr": "y * 2"}"#,
+            r#"{"id": 9, "op": "reset"}"#,
+            r#"{"id": 10, "op": "eval", "expr": "x"}"#,
+        ]
+        .map(|line| Ok(line.to_string()));
+        let mut out = vec![];
+
+        serve(lines, &mut Session::default(), &mut out).expect("Write output");
+
+        let responses: Vec<Value> = String::from_utf8(out)
+            .unwrap()
+            .lines()
+            .map(|line| serde_json::from_str(line).expect("One response per line"))
+            .collect();
+        assert_eq!(responses.len(), 10);
+
+        assert_eq!(
+            responses[0],
+            json!({
+                "id": 1,
+                "ok": true,
+                "result": { "type": "integer", "value": 3, "shown": "3", "assigned": "x" }
+            })
+        );
+        assert_eq!(responses[1]["result"]["value"], json!(9));
+        assert_eq!(responses[1]["result"].get("assigned"), None);
+
+        // A malformed line in the middle is answered, and doesn't stop the ones after it
+        assert_eq!(responses[2]["id"], Value::Null);
+        assert_eq!(responses[2]["ok"], json!(false));
+        assert_eq!(responses[2]["error"]["kind"], json!("request"));
+        assert_eq!(responses[3]["id"], json!("four"));
+        assert_eq!(responses[3]["error"]["kind"], json!("request"));
+
+        assert_eq!(responses[4]["result"]["type"], json!("rational"));
+        assert_eq!(responses[4]["result"]["shown"], json!("1/4"));
+        assert_eq!(
+            responses[5]["result"],
+            json!({ "x": { "expr": "3", "value": { "type": "integer", "value": 3, "shown": "3" } } })
+        );
+        assert_eq!(responses[6]["result"]["valid"], json!(false));
+        assert_eq!(responses[7]["error"]["kind"], json!("runtime"));
+        assert_eq!(responses[8], json!({ "id": 9, "ok": true, "result": null }));
+        assert_eq!(responses[9]["error"]["kind"], json!("runtime"));
+    }
+
+    #[test]
+    fn big_integers() {
+        let mut session = Session::default();
+        let response = handle(
+            r#"{"id": 1, "op": "eval", "expr": "[2^200, 1]"}"#,
+            &mut session,
+        );
+        let big = "1606938044258990275541962092341162602522202993782792835301376";
+        assert_eq!(
+            response.result,
+            Some(json!({
+                "type": "list",
+                "value": [{ "type": "big_int", "value": big }, { "type": "integer", "value": 1 }],
+                "shown": format!("[{big}, 1]")
+            }))
+        );
+    }
+
+    #[test]
+    fn statement_failures() {
+        let mut session = Session::default();
+        let response = handle(r#"{"id": 1, "op": "eval", "expr": "1 $ 2"}"#, &mut session);
+        assert!(!response.ok);
+        assert_eq!(
+            response.error.map(|err| err.kind),
+            Some(FailureKind::Invalid)
+        );
+
+        // Comments evaluate to nothing
+        let response = handle(
+            r##"{"id": 2, "op": "eval", "expr": "# note"}"##,
+            &mut session,
+        );
+        assert_eq!(response.result, Some(Value::Null));
+
+        let response = handle(r#"{"id": 3, "op": "eval"}"#, &mut session);
+        assert_eq!(response.id, json!(3));
+        assert_eq!(
+            response.error.map(|err| err.kind),
+            Some(FailureKind::Request)
+        );
+    }
+}
@@ -0,0 +1,62 @@
+//! State shared across every statement the CLI evaluates
+
+use chalk_core::{
+    ast::Expr,
+    exec::{EvalResult, Evaluator},
+    format::FormatOptions,
+    tokenizer::{TokenizerOptions, normalizations},
+};
+
+use crate::style::Style;
+
+/// The evaluator along with how its results are displayed
+#[derive(Clone, Debug, Default)]
+pub struct Session {
+    /// Variable context carried between statements
+    pub executor: Evaluator,
+    /// How results are displayed
+    pub format: FormatOptions,
+    /// What statements are allowed to contain beyond the defaults
+    pub input: TokenizerOptions,
+    /// Whether per-phase timings are reported for each statement
+    pub time: bool,
+    /// How output is colored
+    pub style: Style,
+}
+
+impl Session {
+    /// Displays a result with the session's formatting
+    pub fn display(&self, result: &EvalResult) -> String {
+        result.format_with(&self.format)
+    }
+
+    /// Displays a statement alongside its result, colored by the session's style. Assignments
+    /// show the variable bound, like `x ← 4`
+    pub fn display_statement(&self, expr: &Expr, result: &EvalResult) -> String {
+        let shown = self.style.result(result, &self.display(result));
+        match expr {
+            Expr::Assignment(var, _) => format!("{} ← {shown}", self.style.echo(&var.to_string())),
+            expr => format!("{} = {shown}", self.style.echo(&format!("`{expr}`"))),
+        }
+    }
+
+    /// A note saying how many pasted characters in a statement were read as something else, like
+    /// `–` as `-`, or `None` if none were or input is strict
+    pub fn normalized_note(&self, statement: &str) -> Option<String> {
+        let normalized = match self.input.lenient {
+            true => normalizations(statement),
+            false => vec![],
+        };
+        match normalized.as_slice() {
+            [] => None,
+            [only] => Some(format!("note: read `{}` as `{}`", only.found, only.read_as)),
+            many => Some(format!("note: normalized {} pasted characters", many.len())),
+        }
+    }
+
+    /// Displays a statement and its result like [`Session::display_statement`], led by the
+    /// number `hist` recalls it with
+    pub fn display_entry(&self, number: usize, expr: &Expr, result: &EvalResult) -> String {
+        format!("[{number}] {}", self.display_statement(expr, result))
+    }
+}
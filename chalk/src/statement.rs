@@ -0,0 +1,481 @@
+//! Evaluation of a single chalk statement, shared by every CLI mode
+
+use std::{
+    error::Error,
+    fmt::Display,
+    time::{Duration, Instant},
+};
+
+use chalk_core::{
+    ast::{Expr, MAX_DEPTH, ParseError},
+    exec::{EvalResult, Evaluator, RuntimeError},
+    pipeline::{self, ChalkError, CheckResult},
+    simplify,
+    tokenizer::{InvalidToken, Tokenizable, TokenizerOptions},
+};
+
+/// Exit code for statements that fail to tokenize or parse
+pub const EXIT_INVALID: i32 = 2;
+/// Exit code for statements that fail while being evaluated
+pub const EXIT_RUNTIME: i32 = 3;
+
+/// Why a statement failed
+#[derive(Debug, PartialEq)]
+pub enum StatementError {
+    /// The statement, or what a command was given, isn't in a form it could be
+    Invalid,
+    /// A character of the statement isn't valid Chalk
+    Tokenize(InvalidToken),
+    /// The statement's tokens don't form a valid expression
+    Parse(ParseError),
+    /// The statement parsed but failed to evaluate, along with the expression
+    Runtime(RuntimeError, Expr),
+}
+
+impl StatementError {
+    /// The process exit code this error maps to
+    pub fn exit_code(&self) -> i32 {
+        match self {
+            Self::Invalid | Self::Tokenize(_) | Self::Parse(_) => EXIT_INVALID,
+            Self::Runtime(..) => EXIT_RUNTIME,
+        }
+    }
+}
+
+impl From<ChalkError> for StatementError {
+    fn from(err: ChalkError) -> Self {
+        match err {
+            ChalkError::Empty => Self::Invalid,
+            ChalkError::Tokenize(err) => Self::Tokenize(err),
+            ChalkError::Parse(err) => Self::Parse(err),
+            ChalkError::Runtime(err, expr) => Self::Runtime(err, expr),
+        }
+    }
+}
+
+impl Display for StatementError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Invalid => write!(
+                f,
+                "The provided statement is invalid Chalk format, please only use mathematical notation"
+            ),
+            Self::Tokenize(err) => write!(f, "{err}"),
+            Self::Parse(err) => write!(f, "{err}"),
+            Self::Runtime(err, _) => write!(f, "{err}"),
+        }
+    }
+}
+
+impl Error for StatementError {}
+
+/// Parses a statement as a Chalk AST, returning `None` for blank and comment-only statements
+pub fn parse(statement: &str) -> Result<Option<Expr>, StatementError> {
+    parse_with(statement, TokenizerOptions::default())
+}
+
+/// Parses a statement like [`parse`], allowing what `input` does beyond the defaults
+pub fn parse_with(
+    statement: &str,
+    input: TokenizerOptions,
+) -> Result<Option<Expr>, StatementError> {
+    match pipeline::check_with(statement, input, MAX_DEPTH) {
+        CheckResult::Ok(expr) => Ok(Some(expr)),
+        CheckResult::Empty => Ok(None),
+        CheckResult::TokenizeError(err) => Err(StatementError::Tokenize(err)),
+        CheckResult::ParseError(err) => Err(StatementError::Parse(err)),
+    }
+}
+
+/// Whether a statement ended before it was complete, like `(1 +`, so more lines could finish it.
+/// Blank statements and ones that are wrong no matter what follows aren't incomplete
+pub fn is_incomplete(statement: &str) -> bool {
+    !pipeline::check(statement).is_complete()
+}
+
+/// Renders the tokens of a statement, one per line
+pub fn show_tokens(statement: &str) -> Result<String, StatementError> {
+    let tokens = statement.tokenize().map_err(StatementError::Tokenize)?;
+
+    Ok(tokens
+        .iter()
+        .map(|token| format!("{token:?}"))
+        .collect::<Vec<_>>()
+        .join("\n"))
+}
+
+/// Renders the parsed tree of a statement, empty for blank and comment-only statements
+pub fn show_ast(statement: &str) -> Result<String, StatementError> {
+    Ok(parse(statement)?
+        .map(|ast| ast.pretty())
+        .unwrap_or_default())
+}
+
+/// Renders the parsed tree of a statement as a Graphviz digraph, empty for blank and comment-only
+/// statements
+pub fn show_dot(statement: &str) -> Result<String, StatementError> {
+    Ok(parse(statement)?
+        .map(|ast| ast.to_dot())
+        .unwrap_or_default())
+}
+
+/// Renders a statement after algebraic simplification, with constants folded in the executor's
+/// angle mode, empty for blank and comment-only statements
+pub fn simplify(statement: &str, executor: &Evaluator) -> Result<String, StatementError> {
+    Ok(parse(statement)?
+        .map(|ast| simplify::simplify_with(&ast, executor).to_string())
+        .unwrap_or_default())
+}
+
+/// Splits comma separated arguments, leaving commas nested in brackets inside the expressions,
+/// like in `gcd(a, b)`, alone
+pub fn split_args(args: &str) -> Vec<&str> {
+    let mut depth = 0;
+    args.split(|c| {
+        match c {
+            '(' | '[' => depth += 1,
+            ')' | ']' => depth -= 1,
+            _ => {}
+        }
+        c == ',' && depth == 0
+    })
+    .collect()
+}
+
+/// Renders `expression, x, replacement` with every use of `x` replaced, following bound variables
+/// so uses inside what they're bound to are replaced too
+pub fn substitute(args: &str, executor: &Evaluator) -> Result<String, StatementError> {
+    let parts = split_args(args);
+    let [expression, var, replacement] = parts[..] else {
+        return Err(StatementError::Invalid);
+    };
+    let Some(Expr::Variable(var)) = parse(var)? else {
+        return Err(StatementError::Invalid);
+    };
+    let expression = parse(expression)?.ok_or(StatementError::Invalid)?;
+    let replacement = parse(replacement)?.ok_or(StatementError::Invalid)?;
+
+    executor
+        .substitute(&expression, var, &replacement)
+        .map(|substituted| substituted.to_string())
+        .map_err(|err| StatementError::Runtime(err, expression))
+}
+
+/// Evaluates a statement, rendering every step of the evaluation one per line. Statements with
+/// nothing to work out just show their result
+pub fn show_steps(statement: &str, executor: &mut Evaluator) -> Result<String, StatementError> {
+    let Some(ast) = parse(statement)? else {
+        return Ok(String::new());
+    };
+
+    match executor.exec_traced(&ast) {
+        Ok((result, steps)) if steps.is_empty() => Ok(result.to_string()),
+        Ok((_, steps)) => Ok(steps
+            .iter()
+            .map(ToString::to_string)
+            .collect::<Vec<_>>()
+            .join("\n")),
+        Err(err) => Err(StatementError::Runtime(err, ast)),
+    }
+}
+
+/// Binds a variable to the parsed value expression without evaluating it
+pub fn define(var: char, value: &str, executor: &mut Evaluator) -> Result<(), StatementError> {
+    let expr = parse(value)?.ok_or(StatementError::Invalid)?;
+    executor.set(var, expr);
+    Ok(())
+}
+
+/// How long each phase of evaluating a statement took, in the order they ran. Phases after a
+/// failing one are missing
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Timings(pub Vec<(&'static str, Duration)>);
+
+impl Display for Timings {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let phases: Vec<_> = self
+            .0
+            .iter()
+            .map(|(phase, duration)| format!("{phase}: {}µs", duration.as_micros()))
+            .collect();
+
+        write!(f, "{}", phases.join(", "))
+    }
+}
+
+/// Parses and evaluates a statement, returning `None` for blank and comment-only statements.
+/// Statements that evaluate successfully are added to the executor's history
+pub fn eval(
+    statement: &str,
+    executor: &mut Evaluator,
+    input: TokenizerOptions,
+) -> Result<Option<(Expr, EvalResult)>, StatementError> {
+    match pipeline::eval_statement(statement, input, executor) {
+        Ok((ast, outcome)) => Ok(Some((ast, outcome.into_value()))),
+        Err(ChalkError::Empty) => Ok(None),
+        Err(err) => Err(err.into()),
+    }
+}
+
+/// Parses and evaluates a statement like [`eval`], also timing the tokenize, parse and eval phases
+pub fn eval_timed(
+    statement: &str,
+    executor: &mut Evaluator,
+    input: TokenizerOptions,
+) -> (Result<Option<(Expr, EvalResult)>, StatementError>, Timings) {
+    let mut timings = Timings::default();
+    let result = run_timed(statement, executor, input, &mut timings);
+    (result, timings)
+}
+
+/// Runs each stage of the pipeline in turn, recording how long it took as soon as it finishes.
+/// Statements that evaluate successfully are added to the executor's history
+fn run_timed(
+    statement: &str,
+    executor: &mut Evaluator,
+    input: TokenizerOptions,
+    timings: &mut Timings,
+) -> Result<Option<(Expr, EvalResult)>, StatementError> {
+    let start = Instant::now();
+    let tokens = pipeline::tokenize(statement, input);
+    timings.0.push(("tokenize", start.elapsed()));
+
+    let Some(tokens) = tokens.map_err(StatementError::Tokenize)? else {
+        return Ok(None);
+    };
+
+    let start = Instant::now();
+    let ast = pipeline::parse(tokens, executor.max_depth());
+    timings.0.push(("parse", start.elapsed()));
+
+    let ast = ast.map_err(StatementError::Parse)?;
+
+    let start = Instant::now();
+    let result = pipeline::execute(statement, ast, executor);
+    timings.0.push(("eval", start.elapsed()));
+
+    let (ast, outcome) = result?;
+    Ok(Some((ast, outcome.into_value())))
+}
+
+#[cfg(test)]
+mod tests {
+    use chalk_core::{
+        ast::ParseErrorKind,
+        exec::{EvalResult, Evaluator, RuntimeError},
+        tokenizer::TokenizerOptions,
+    };
+
+    use super::{
+        EXIT_INVALID, EXIT_RUNTIME, StatementError, define, eval, eval_timed, is_incomplete,
+        show_ast, show_dot, show_tokens,
+    };
+
+    #[test]
+    fn grouped_input() {
+        let mut executor = Evaluator::default();
+        let commas = TokenizerOptions {
+            digit_group_char: Some(','),
+            ..Default::default()
+        };
+
+        let (_, result) = eval("1,234,567 + gcd(12, 18)", &mut executor, commas)
+            .expect("Evaluate")
+            .expect("Not blank");
+        assert_eq!(result, EvalResult::Integer(1_234_573));
+        assert!(matches!(
+            eval("1,23,4", &mut executor, commas),
+            Err(StatementError::Tokenize(_) | StatementError::Parse(_))
+        ));
+        // Without grouping the comma separates arguments as usual
+        assert!(matches!(
+            eval("1,234", &mut executor, TokenizerOptions::default()),
+            Err(StatementError::Parse(_))
+        ));
+    }
+
+    #[test]
+    fn decimal_comma_input() {
+        let mut executor = Evaluator::default();
+        let decimal_comma = TokenizerOptions {
+            decimal_comma: true,
+            ..Default::default()
+        };
+
+        let (_, result) = eval("2,5 * 2 + gcd(12; 18)", &mut executor, decimal_comma)
+            .expect("Evaluate")
+            .expect("Not blank");
+        assert_eq!(result, EvalResult::Float(11.0));
+        assert!(matches!(
+            eval("gcd(12, 18)", &mut executor, decimal_comma),
+            Err(StatementError::Tokenize(_) | StatementError::Parse(_))
+        ));
+        // By default `2,5` is two numbers, which isn't a statement
+        assert!(matches!(
+            eval("2,5", &mut executor, TokenizerOptions::default()),
+            Err(StatementError::Parse(_))
+        ));
+    }
+
+    #[test]
+    fn successful_statement() {
+        let mut executor = Evaluator::default();
+        let (ast, result) = eval("2 ^ 3", &mut executor, TokenizerOptions::default())
+            .expect("Evaluate")
+            .expect("Not blank");
+
+        assert_eq!(format!("{ast}"), "2 ^ 3");
+        assert_eq!(result, EvalResult::Integer(8));
+        assert_eq!(executor.history()[0].source, "2 ^ 3");
+    }
+
+    #[test]
+    fn blank_statement() {
+        let mut executor = Evaluator::default();
+        assert_eq!(
+            eval("  # nothing", &mut executor, TokenizerOptions::default()),
+            Ok(None)
+        );
+    }
+
+    #[test]
+    fn invalid_statements() {
+        let mut executor = Evaluator::default();
+
+        for (statement, message) in [
+            ("1 +", "Expression ended early at position 3 :("),
+            ("1 $ 2", "Invalid character `$` at position 2 :("),
+            ("gcd(1)", "Parse error occurred at position 5 :("),
+        ] {
+            let err = eval(statement, &mut executor, TokenizerOptions::default())
+                .expect_err("Invalid statement");
+            assert_eq!(err.to_string(), message, "{statement}");
+            assert_eq!(err.exit_code(), EXIT_INVALID);
+        }
+    }
+
+    #[test]
+    fn incomplete_statements() {
+        for incomplete in ["(1 +", "1 + ", "sqrt(", "x = 2 *\n  (3"] {
+            assert!(is_incomplete(incomplete), "{incomplete}");
+        }
+        for complete in ["1 $", "1 + )", "(1 +\n 2)", "", "# just a comment", ":vars"] {
+            assert!(!is_incomplete(complete), "{complete}");
+        }
+    }
+
+    #[test]
+    fn runtime_errors() {
+        let mut executor = Evaluator::default();
+        let err = eval("(-1)!", &mut executor, TokenizerOptions::default())
+            .expect_err("Negative factorial");
+
+        assert!(matches!(err, StatementError::Runtime(_, _)));
+        assert_eq!(err.exit_code(), EXIT_RUNTIME);
+        assert!(executor.history().is_empty());
+
+        // Each failure says what went wrong rather than just that something did
+        for (statement, expected) in [
+            ("acos(2)", RuntimeError::OutOfDomain("acos")),
+            (
+                "[1, 2][5]",
+                RuntimeError::IndexOutOfBounds { index: 5, len: 2 },
+            ),
+            ("q + 1", RuntimeError::Invalid),
+        ] {
+            let err = eval(statement, &mut executor, TokenizerOptions::default())
+                .expect_err("Runtime error");
+            assert_eq!(err.to_string(), expected.to_string(), "{statement}");
+            assert!(matches!(err, StatementError::Runtime(found, _) if found == expected));
+        }
+    }
+
+    #[test]
+    fn showing_tokens() {
+        assert_eq!(
+            show_tokens("x^2").expect("Tokenize"),
+            "Variable('x')\nCaret\nInteger(2)\nEOF"
+        );
+        assert!(matches!(
+            show_tokens("1 $"),
+            Err(StatementError::Tokenize(_))
+        ));
+    }
+
+    #[test]
+    fn showing_ast() {
+        assert_eq!(
+            show_ast("1 - x").expect("Parse"),
+            "BinaryOp Subtract\n  Integer 1\n  Variable x"
+        );
+        assert!(matches!(show_ast("1 -"), Err(StatementError::Parse(_))));
+
+        let dot = show_dot("1 - x").expect("Parse");
+        assert!(dot.starts_with("digraph {"));
+        assert!(dot.contains("n0 -> n2;"));
+        assert_eq!(show_dot("# nothing"), Ok(String::new()));
+    }
+
+    #[test]
+    fn seeded_evaluation() {
+        let mut executor = Evaluator::default();
+        define('x', "3", &mut executor).expect("Define x");
+        define('y', "x + 1", &mut executor).expect("Define y");
+
+        let (_, result) = eval("x^2 + y^2", &mut executor, TokenizerOptions::default())
+            .expect("Evaluate")
+            .expect("Not blank");
+        assert_eq!(result, EvalResult::Integer(25));
+
+        assert!(matches!(
+            define('z', "1 +", &mut executor),
+            Err(StatementError::Parse(_))
+        ));
+        assert_eq!(
+            define('z', "# nothing", &mut executor),
+            Err(StatementError::Invalid)
+        );
+        assert!(executor.get('z').is_none());
+    }
+
+    #[test]
+    fn timing_every_phase() {
+        let mut executor = Evaluator::default();
+        let (result, timings) = eval_timed(
+            "gcd(987654, 123456)",
+            &mut executor,
+            TokenizerOptions::default(),
+        );
+
+        assert!(result.is_ok());
+        let phases: Vec<_> = timings.0.iter().map(|(phase, _)| *phase).collect();
+        assert_eq!(phases, ["tokenize", "parse", "eval"]);
+    }
+
+    #[test]
+    fn timing_stops_at_failure() {
+        let mut executor = Evaluator::default();
+        let (result, timings) = eval_timed("1 +", &mut executor, TokenizerOptions::default());
+
+        assert!(matches!(result, Err(StatementError::Parse(_))));
+        assert_eq!(timings.0.len(), 2);
+    }
+
+    #[test]
+    fn timing_matches_untimed() {
+        let mut executor = Evaluator::default();
+        executor.set_max_depth(3);
+        for statement in ["((((1))))", "x = 2", "x + $", "acos(2)", "  # nothing"] {
+            let (timed, _) = eval_timed(statement, &mut executor, TokenizerOptions::default());
+            let untimed = eval(statement, &mut executor, TokenizerOptions::default());
+            assert_eq!(timed, untimed, "{statement}");
+        }
+
+        let (result, _) = eval_timed("((((1))))", &mut executor, TokenizerOptions::default());
+        assert!(matches!(
+            result,
+            Err(StatementError::Parse(err)) if err.kind == ParseErrorKind::TooDeep
+        ));
+        assert_eq!(executor.history().len(), 2);
+    }
+}
@@ -0,0 +1,127 @@
+//! ANSI coloring of CLI output
+
+use std::ffi::OsStr;
+
+use chalk_core::exec::EvalResult;
+
+/// Dim text, for echoed expressions
+const DIM: &str = "\x1b[2m";
+/// Green text, for numeric results
+const GREEN: &str = "\x1b[32m";
+/// Yellow text, for boolean results
+const YELLOW: &str = "\x1b[33m";
+/// Red text, for errors
+const RED: &str = "\x1b[31m";
+/// Resets all styling
+const RESET: &str = "\x1b[0m";
+
+/// Decides whether output to a stream should be colored. Color is off if `--no-color` was passed,
+/// if `NO_COLOR` is set to anything non-empty, or if the stream isn't a terminal
+pub fn colors_enabled(no_color_flag: bool, no_color_env: Option<&OsStr>, tty: bool) -> bool {
+    let env_disabled = no_color_env.is_some_and(|value| !value.is_empty());
+    !no_color_flag && !env_disabled && tty
+}
+
+/// Colors pieces of output, or leaves them plain when disabled
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct Style {
+    /// Whether escape codes are emitted in echoes and results, which go to stdout
+    pub enabled: bool,
+    /// Whether escape codes are emitted in errors, which go to stderr
+    pub errors: bool,
+}
+
+impl Style {
+    /// Wraps text in a color code if `enabled`
+    fn paint(enabled: bool, color: &str, text: &str) -> String {
+        if enabled {
+            format!("{color}{text}{RESET}")
+        } else {
+            text.to_string()
+        }
+    }
+
+    /// Styles an echoed expression
+    pub fn echo(&self, text: &str) -> String {
+        Self::paint(self.enabled, DIM, text)
+    }
+
+    /// Styles an already formatted result based on its type
+    pub fn result(&self, result: &EvalResult, shown: &str) -> String {
+        match result {
+            EvalResult::Bool(_) => Self::paint(self.enabled, YELLOW, shown),
+            _ => Self::paint(self.enabled, GREEN, shown),
+        }
+    }
+
+    /// Styles an error message
+    pub fn error(&self, text: &str) -> String {
+        Self::paint(self.errors, RED, text)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::ffi::OsStr;
+
+    use chalk_core::exec::EvalResult;
+
+    use super::{Style, colors_enabled};
+
+    #[test]
+    fn color_selection() {
+        let empty = Some(OsStr::new(""));
+        let set = Some(OsStr::new("1"));
+
+        assert!(colors_enabled(false, None, true));
+        assert!(colors_enabled(false, empty, true));
+
+        assert!(!colors_enabled(true, None, true));
+        assert!(!colors_enabled(false, set, true));
+        assert!(!colors_enabled(false, None, false));
+        assert!(!colors_enabled(true, set, false));
+    }
+
+    #[test]
+    fn disabled_style_is_plain() {
+        let style = Style::default();
+        assert_eq!(style.echo("`1 + 1`"), "`1 + 1`");
+        assert_eq!(style.result(&EvalResult::Integer(2), "2"), "2");
+        assert_eq!(style.error("oops"), "oops");
+    }
+
+    #[test]
+    fn colors_by_result_type() {
+        let style = Style {
+            enabled: true,
+            errors: true,
+        };
+        assert_eq!(
+            style.result(&EvalResult::Integer(2), "2"),
+            "\x1b[32m2\x1b[0m"
+        );
+        assert_eq!(
+            style.result(&EvalResult::Bool(true), "true"),
+            "\x1b[33mtrue\x1b[0m"
+        );
+        assert_eq!(style.error("oops"), "\x1b[31moops\x1b[0m");
+    }
+
+    #[test]
+    fn errors_styled_separately() {
+        // Output piped into a file while errors still reach the terminal, and the other way round
+        let piped = Style {
+            enabled: false,
+            errors: true,
+        };
+        assert_eq!(piped.result(&EvalResult::Integer(2), "2"), "2");
+        assert_eq!(piped.error("oops"), "\x1b[31moops\x1b[0m");
+
+        let redirected = Style {
+            enabled: true,
+            errors: false,
+        };
+        assert_eq!(redirected.echo("x"), "\x1b[2mx\x1b[0m");
+        assert_eq!(redirected.error("oops"), "oops");
+    }
+}
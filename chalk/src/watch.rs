@@ -0,0 +1,275 @@
+//! Re-running a script file whenever it changes, for iterating on a worksheet
+
+use std::{
+    fs,
+    io::{self, Write},
+    path::{Path, PathBuf},
+    thread,
+    time::{Duration, Instant, SystemTime},
+};
+
+use crate::{script, session::Session};
+
+/// How often the file is checked for changes
+const POLL_INTERVAL: Duration = Duration::from_millis(50);
+
+/// How long a change must settle before the file is run again, so an editor saving in several
+/// writes only causes one run
+const DEBOUNCE: Duration = Duration::from_millis(200);
+
+/// Clears the terminal and moves the cursor to the top left
+const CLEAR_SCREEN: &str = "\x1b[2J\x1b[H";
+
+/// Where the watch loop gets the file and the time from, so tests can stand in for both
+pub trait Environment {
+    /// When the file was last modified, or `None` if that can't be read
+    fn modified(&mut self) -> Option<SystemTime>;
+    /// Reads the whole file
+    fn read(&mut self) -> io::Result<String>;
+    /// The current time
+    fn now(&mut self) -> Instant;
+    /// Waits until the file should be checked again, returning whether to keep watching
+    fn wait(&mut self) -> bool;
+}
+
+/// A file on disk, checked every [`POLL_INTERVAL`] until the process is interrupted
+pub struct FileSystem {
+    /// The file watched
+    pub path: PathBuf,
+}
+
+impl Environment for FileSystem {
+    fn modified(&mut self) -> Option<SystemTime> {
+        fs::metadata(&self.path)
+            .and_then(|metadata| metadata.modified())
+            .ok()
+    }
+
+    fn read(&mut self) -> io::Result<String> {
+        fs::read_to_string(&self.path)
+    }
+
+    fn now(&mut self) -> Instant {
+        Instant::now()
+    }
+
+    fn wait(&mut self) -> bool {
+        thread::sleep(POLL_INTERVAL);
+        true
+    }
+}
+
+/// Decides when a watched file should be run, from its modification time at each check
+#[derive(Debug, Default)]
+pub struct Watcher {
+    /// Whether the file has been run at all yet
+    started: bool,
+    /// The modification time of the version last run
+    ran: Option<SystemTime>,
+    /// A modification time that hasn't been run yet, and when it was first seen
+    pending: Option<(Option<SystemTime>, Instant)>,
+}
+
+impl Watcher {
+    /// Whether the file should be run now. The first check always runs it, and after that a new
+    /// modification time runs it once it has stayed the same for [`DEBOUNCE`]
+    pub fn poll(&mut self, modified: Option<SystemTime>, now: Instant) -> bool {
+        if !self.started {
+            self.started = true;
+            self.ran = modified;
+            return true;
+        }
+        if modified == self.ran {
+            self.pending = None;
+            return false;
+        }
+
+        match self.pending {
+            Some((seen, since)) if seen == modified => {
+                if now.duration_since(since) < DEBOUNCE {
+                    return false;
+                }
+                self.ran = modified;
+                self.pending = None;
+                true
+            }
+            _ => {
+                self.pending = Some((modified, now));
+                false
+            }
+        }
+    }
+}
+
+/// Runs the file at `path` whenever it changes until the environment stops waiting, clearing the
+/// screen first. Every run starts from a copy of `session`, so bindings from an earlier version
+/// of the file never linger. A failing line is reported with the file and line like running a
+/// script, and watching carries on
+pub fn watch(
+    path: &Path,
+    session: &Session,
+    env: &mut impl Environment,
+    out: &mut impl Write,
+    err: &mut impl Write,
+) -> io::Result<()> {
+    let mut watcher = Watcher::default();
+    loop {
+        let modified = env.modified();
+        if watcher.poll(modified, env.now()) {
+            write!(out, "{CLEAR_SCREEN}")?;
+            match env.read() {
+                Ok(source) => run(path, &source, session.clone(), out, err)?,
+                Err(why) => writeln!(err, "Failed to read `{}`: {why}", path.display())?,
+            }
+            out.flush()?;
+        }
+
+        if !env.wait() {
+            return Ok(());
+        }
+    }
+}
+
+/// Runs one version of the file, writing each result and then the failing line if there is one
+fn run(
+    path: &Path,
+    source: &str,
+    mut session: Session,
+    out: &mut impl Write,
+    err: &mut impl Write,
+) -> io::Result<()> {
+    let mut shown = vec![];
+    let result = script::run(source, &mut session, |session, val, eval| {
+        shown.push(session.display_statement(val, &eval));
+    });
+
+    for line in shown {
+        writeln!(out, "{line}")?;
+    }
+    if let Err(failure) = result {
+        let message = format!("{}:{}: {}", path.display(), failure.line, failure.kind);
+        writeln!(err, "{}", session.style.error(&message))?;
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        io,
+        path::Path,
+        time::{Duration, Instant, SystemTime},
+    };
+
+    use chalk_core::ast::Expr;
+
+    use crate::session::Session;
+
+    use super::{CLEAR_SCREEN, DEBOUNCE, Environment, POLL_INTERVAL, Watcher, watch};
+
+    /// A file that changes to each version at the given check, with time passing by
+    /// [`POLL_INTERVAL`] between checks
+    struct Scripted {
+        /// When watching started
+        start: Instant,
+        /// How many checks have happened
+        checks: u32,
+        /// How many checks to make before stopping
+        last: u32,
+        /// The check each version appears at, and its contents
+        versions: Vec<(u32, &'static str)>,
+    }
+
+    impl Scripted {
+        /// Which version the file is at now
+        fn version(&self) -> usize {
+            self.versions
+                .iter()
+                .rposition(|(at, _)| *at <= self.checks)
+                .expect("A version from the start")
+        }
+    }
+
+    impl Environment for Scripted {
+        fn modified(&mut self) -> Option<SystemTime> {
+            Some(SystemTime::UNIX_EPOCH + Duration::from_secs(self.version() as u64))
+        }
+
+        fn read(&mut self) -> io::Result<String> {
+            Ok(self.versions[self.version()].1.to_string())
+        }
+
+        fn now(&mut self) -> Instant {
+            self.start + POLL_INTERVAL * self.checks
+        }
+
+        fn wait(&mut self) -> bool {
+            self.checks += 1;
+            self.checks < self.last
+        }
+    }
+
+    #[test]
+    fn debouncing_changes() {
+        let start = Instant::now();
+        let (first, second) = (
+            Some(SystemTime::UNIX_EPOCH),
+            Some(SystemTime::UNIX_EPOCH + Duration::from_secs(1)),
+        );
+        let mut watcher = Watcher::default();
+
+        assert!(watcher.poll(first, start), "runs straight away");
+        assert!(!watcher.poll(first, start + DEBOUNCE * 2));
+
+        // A change waits to settle, starting over if it changes again
+        let changed = start + DEBOUNCE * 3;
+        assert!(!watcher.poll(second, changed));
+        assert!(!watcher.poll(second, changed + DEBOUNCE / 2));
+        assert!(!watcher.poll(None, changed + DEBOUNCE / 2));
+        assert!(!watcher.poll(second, changed + DEBOUNCE));
+        assert!(!watcher.poll(second, changed + DEBOUNCE * 2 - POLL_INTERVAL));
+        assert!(watcher.poll(second, changed + DEBOUNCE * 2));
+        assert!(!watcher.poll(second, changed + DEBOUNCE * 5));
+
+        // Changing back to what was last run isn't a change
+        assert!(!watcher.poll(first, changed + DEBOUNCE * 6));
+        assert!(!watcher.poll(second, changed + DEBOUNCE * 8));
+    }
+
+    #[test]
+    fn rerunning_on_change() {
+        let mut env = Scripted {
+            start: Instant::now(),
+            checks: 0,
+            last: 20,
+            versions: vec![
+                (0, "x = 2\nx * 3"),
+                (5, "y = 4\n1 +\ny"),
+                (6, "y = 5\nx + y"),
+            ],
+        };
+        let mut session = Session::default();
+        session.executor.set('z', Expr::Integer(1));
+        let (mut out, mut err) = (vec![], vec![]);
+
+        watch(
+            Path::new("sheet.chalk"),
+            &session,
+            &mut env,
+            &mut out,
+            &mut err,
+        )
+        .expect("Write output");
+
+        // The version in between never settled, and `x` is gone once the file stops binding it
+        assert_eq!(
+            String::from_utf8(out).unwrap(),
+            format!("{CLEAR_SCREEN}x ← 2\n`x * 3` = 6\n{CLEAR_SCREEN}y ← 5\n")
+        );
+        let err = String::from_utf8(err).unwrap();
+        assert!(err.starts_with("sheet.chalk:2: "), "{err}");
+        assert_eq!(err.lines().count(), 1);
+        assert_eq!(session.executor.vars(), [('z', &Expr::Integer(1))]);
+    }
+}